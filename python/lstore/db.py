@@ -3,8 +3,21 @@ from lstore._core import CoreDatabase
 
 class Database:
 
-    def __init__(self):
-        self._core = CoreDatabase()
+    def __init__(self, buffer_pool_frames=None, buffer_pool_bytes=None,
+                 flush_interval_ms=None, checkpoint_interval_ms=None,
+                 eviction=None, read_ahead=None, merge_interval_ms=None):
+        """The buffer pool is sized by a frame count or a memory budget in
+        bytes, not both; by default it has 256 frames. Dirty pages are
+        written back every 50 ms and a checkpoint is taken every 30 s unless
+        the intervals say otherwise; 0 turns either off. The pool evicts by
+        `eviction`: 'arc' (the default), 'clock', 'lru' or 'lru-<k>'. Scans
+        have the pool read `read_ahead` page collections ahead of them (2 by
+        default; 0 turns it off). Tail records are merged into their base
+        records in the background, checked for every `merge_interval_ms`
+        (100 by default; 0 turns it off)."""
+        self._core = CoreDatabase(buffer_pool_frames, buffer_pool_bytes,
+                                  flush_interval_ms, checkpoint_interval_ms,
+                                  eviction, read_ahead, merge_interval_ms)
         self._tables = {}
 
     def open(self, path):
@@ -13,23 +26,131 @@ class Database:
     def close(self):
         self._core.close()
 
-    def create_table(self, name, num_columns, key_index):
-        self._core.create_table(name, num_columns, key_index)
-        table = Table(name, num_columns, key_index, self._core)
-        self._tables[name] = table
-        return table
+    def set_corruption_policy(self, policy):
+        """'fail' (the default) or 'quarantine'."""
+        self._core.set_corruption_policy(policy)
+
+    def quarantined(self):
+        return self._core.quarantined()
+
+    def checkpoint(self):
+        self._core.checkpoint()
+
+    def background_stats(self):
+        return self._core.background_stats()
+
+    def access_stats(self):
+        return self._core.access_stats()
+
+    def stats(self):
+        """Buffer pool, disk, checkpoint and merge counters in one dict: the
+        hit rate, evictions, pages written back and read from or written to
+        disk, among others. `reset_stats` zeroes the counters."""
+        return self._core.stats()
+
+    def reset_stats(self):
+        self._core.reset_stats()
+
+    def buffer_pool_frames(self):
+        return self._core.buffer_pool_frames()
+
+    def resize_buffer_pool(self, frames=None, bytes=None):
+        self._core.resize_buffer_pool(frames, bytes)
+
+    def create_table(self, name, num_columns, key_index,
+                     reserved_frames=None, max_frames=None, types=None,
+                     names=None, nullable=None, defaults=None):
+        """`reserved_frames` and `max_frames` set the table's buffer pool
+        quota as `set_table_quota` does. `types` gives each column a type:
+        'int32', 'int64', 'float64', 'bool', 'timestamp', 'str(n)',
+        'fixed_str(n)', 'bytes(n)', 'fixed_bytes(n)', 'text' or 'blob',
+        where n is the most bytes a value takes. Queries then take and return
+        ints, floats, bools, datetimes, strs and bytes. Without `types` every
+        column is an 'int64'; the key column has to be an integer or
+        timestamp either way.
+
+        `names` names the columns, which are otherwise named '0', '1' and so
+        on, so that queries can refer to them by name. `nullable` says which
+        columns an insert may leave as None, and `defaults` what an insert
+        that does gets instead (None for no default). All of these are saved
+        with the table. A table already named `name` is an error, and is left
+        as it is."""
+        if self._core.table_exists(name):
+            raise RuntimeError("a table is already named {}".format(name))
+        if reserved_frames is not None or max_frames is not None:
+            self.set_table_quota(name, reserved_frames or 0, max_frames)
+        self._core.create_table(name, num_columns, key_index, types,
+                                names, nullable, defaults)
+        return self.get_table(name)
+
+    def set_table_quota(self, name, reserved_frames=0, max_frames=None):
+        """Keeps `reserved_frames` of the table's pages in the buffer pool
+        whatever other tables load, and lets the table take no more than
+        `max_frames` frames (None for no limit). Applies to a table created
+        or opened under `name` later too, but is not saved with the
+        database."""
+        self._core.set_table_quota(name, reserved_frames, max_frames)
+
+    def table_quota(self, name):
+        """`(reserved_frames, max_frames)`."""
+        return self._core.table_quota(name)
+
+    def table_frames(self, name):
+        """Frames holding the table's pages right now."""
+        return self._core.table_frames(name)
 
     def drop_table(self, name):
         self._core.drop_table(name)
         self._tables.pop(name, None)
 
+    def alter_table_add_column(self, name, column, type='int64',
+                               nullable=True, default=None):
+        """Adds a column named `column` of `type` after the table's last
+        column, with `nullable` and `default` as `create_table` takes them.
+        The records already there read `default`, or None without one; none
+        of them is rewritten. Returns False if there is no such table."""
+        if not self._core.alter_table_add_column(name, column, type,
+                                                 nullable, default):
+            return False
+        self._reload(name)
+        return True
+
+    def alter_table_drop_column(self, name, column):
+        """Drops the column named `column`, which cannot be the key. Returns
+        False if there is no such table."""
+        if not self._core.alter_table_drop_column(name, column):
+            return False
+        self._reload(name)
+        return True
+
+    def vacuum(self, name, keep_versions=None):
+        """Rewrites the table compactly without its deleted records, keeping
+        the newest `keep_versions` older versions of each record for
+        select_version, or all of them for None, and takes a checkpoint.
+        Returns a dict of the records rewritten, the deleted ones and older
+        versions left out, the table's pages before and after and the bytes
+        that checkpoint freed, or None if there is no such table."""
+        return self._core.vacuum(name, keep_versions)
+
+    def _reload(self, name):
+        """Brings a table already handed out up to date with its columns."""
+        table = self._tables.get(name)
+        info = self._core.get_table(name)
+        if table is None or info is None:
+            return
+        table.num_columns = info['num_columns']
+        table.key_index = info['key_index']
+        table.columns = info['columns']
+        table.types = [column['type'] for column in info['columns']]
+        table.names = [column['name'] for column in info['columns']]
+
     def get_table(self, name):
         if name in self._tables:
             return self._tables[name]
         info = self._core.get_table(name)
         if info is None:
             return None
-        num_columns, key_index = info
-        table = Table(name, num_columns, key_index, self._core)
+        table = Table(name, info['num_columns'], info['key_index'], self._core,
+                      columns=info['columns'])
         self._tables[name] = table
         return table
@@ -41,14 +41,15 @@ class Query:
     """
     # Read matching record with specified search key
     # :param search_key: the value you want to search based on
-    # :param search_key_index: the column index you want to search based on
+    # :param search_key_index: the column index or name you want to search based on
     # :param projected_columns_index: what columns to return. array of 1 or 0 values.
+    # :param columns: names of the columns to return instead, in the order they come back
     # Returns a list of Record objects upon success
     # Returns False if record locked by TPL
     # Assume that select will never be called on a key that doesn't exist
     """
-    def select(self, search_key, search_key_index, projected_columns_index):
-        rows = self._core.select(search_key, search_key_index, projected_columns_index)
+    def select(self, search_key, search_key_index, projected_columns_index=None, columns=None):
+        rows = self._core.select(search_key, search_key_index, projected_columns_index, columns)
         return [Record(0, search_key, row) for row in rows]
 
     
@@ -12,12 +12,20 @@ class Table:
 
     """
     :param name: string         #Table name
-    :param num_columns: int     #Number of Columns: all columns are integer
+    :param num_columns: int     #Number of Columns
     :param key: int             #Index of table key in columns
+    :param types: list          #Type of each column, 'int64' for all by default
+    :param columns: list        #A dict of name, type, nullable and default for each column
     """
-    def __init__(self, name, num_columns, key_index, core_db=None):
+    def __init__(self, name, num_columns, key_index, core_db=None, types=None, columns=None):
         self.name = name
         self.num_columns = num_columns
         self.key_index = key_index
+        if columns is None:
+            columns = [{'name': str(i), 'type': ty, 'nullable': True, 'default': None}
+                       for i, ty in enumerate(types or ['int64'] * num_columns)]
+        self.columns = columns
+        self.types = [column['type'] for column in columns]
+        self.names = [column['name'] for column in columns]
         self._core_db = core_db
         self.index = Index(self)
@@ -0,0 +1,47 @@
+//! Checks a database directory for consistency.
+//!
+//! Usage: `fsck <database dir> [--repair]`
+//!
+//! Exits 0 when the database is consistent (or was fully repaired), 1 when
+//! problems remain, and 2 when the check could not run.
+
+use firstplace_db::check::check;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let mut path = None;
+    let mut repair = false;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--repair" => repair = true,
+            _ if path.is_none() => path = Some(PathBuf::from(arg)),
+            _ => {
+                eprintln!("usage: fsck <database dir> [--repair]");
+                return ExitCode::from(2);
+            }
+        }
+    }
+    let Some(path) = path else {
+        eprintln!("usage: fsck <database dir> [--repair]");
+        return ExitCode::from(2);
+    };
+
+    match check(&path, repair) {
+        Ok(report) => {
+            print!("{}", report);
+            if repair && !report.clean_shutdown {
+                println!("not repaired: open the database once to recover it first");
+            }
+            if report.is_consistent() {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::from(1)
+            }
+        }
+        Err(e) => {
+            eprintln!("fsck: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
@@ -1,8 +1,16 @@
-use crate::db::Database;
+use crate::config::{DatabaseConfig, EvictionKind, FlusherConfig, MergeConfig, PoolSize, TableQuota};
+use crate::db::{Database, DbStats};
+use crate::errors::DbError;
+use crate::bindings::values::{from_py, schema_to_py};
+use crate::schema::{ColumnDef, ColumnType, Schema};
+use crate::vacuum::VacuumOptions;
 use parking_lot::RwLock;
-use pyo3::exceptions::PyRuntimeError;
+use crate::disk_manager::CorruptionPolicy;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
 use pyo3::prelude::*;
+use pyo3::types::PyDict;
 use std::sync::Arc;
+use std::time::Duration;
 
 #[pyclass]
 pub struct CoreDatabase {
@@ -12,10 +20,47 @@ pub struct CoreDatabase {
 #[pymethods]
 impl CoreDatabase {
     #[new]
-    fn new() -> Self {
-        Self {
-            inner: Arc::new(RwLock::new(Database::new()))
+    /// An interval of 0 turns the background writer, checkpointer or merge
+    /// off.
+    #[pyo3(signature = (buffer_pool_frames=None, buffer_pool_bytes=None, flush_interval_ms=None, checkpoint_interval_ms=None, eviction=None, read_ahead=None, merge_interval_ms=None))]
+    fn new(
+        buffer_pool_frames: Option<usize>,
+        buffer_pool_bytes: Option<usize>,
+        flush_interval_ms: Option<u64>,
+        checkpoint_interval_ms: Option<u64>,
+        eviction: Option<&str>,
+        read_ahead: Option<usize>,
+        merge_interval_ms: Option<u64>,
+    ) -> PyResult<Self> {
+        let mut config = DatabaseConfig::default();
+        if let Some(size) = pool_size(buffer_pool_frames, buffer_pool_bytes)? {
+            config.buffer_pool = size;
         }
+        if let Some(eviction) = eviction {
+            config.eviction = eviction_kind(eviction)?;
+        }
+        if let Some(collections) = read_ahead {
+            config.read_ahead = collections;
+        }
+        if let Some(ms) = flush_interval_ms {
+            config.flusher = (ms > 0).then(|| FlusherConfig {
+                interval: Duration::from_millis(ms),
+                ..FlusherConfig::default()
+            });
+        }
+        if let Some(ms) = checkpoint_interval_ms {
+            config.checkpoint_interval = (ms > 0).then(|| Duration::from_millis(ms));
+        }
+        if let Some(ms) = merge_interval_ms {
+            config.merge = (ms > 0).then(|| MergeConfig {
+                interval: Duration::from_millis(ms),
+                ..MergeConfig::default()
+            });
+        }
+        let db = Database::with_config(config).map_err(config_err)?;
+        Ok(Self {
+            inner: Arc::new(RwLock::new(db))
+        })
     }
 
     fn open(&mut self, path: &str) -> PyResult<()> {
@@ -28,20 +73,282 @@ impl CoreDatabase {
             .map_err(|e| PyRuntimeError::new_err(e.to_string()))
     }
 
-    fn create_table(&self, name: String, num_columns: usize, key_index: usize) {
-        self.inner.read().create_table(name, num_columns, key_index)
+    fn checkpoint(&self) -> PyResult<()> {
+        self.inner.read()
+            .checkpoint()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// `types` names each column's type as `ColumnType` displays it, e.g.
+    /// `"int64"` or `"str(32)"`; without it every column is an `int64`.
+    /// `names`, `nullable` and `defaults` have an entry for each column too,
+    /// a `None` default being none at all. Columns are named after their
+    /// number and nullable unless said otherwise.
+    #[pyo3(signature = (name, num_columns, key_index, types=None, names=None, nullable=None, defaults=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn create_table(
+        &self,
+        name: String,
+        num_columns: usize,
+        key_index: usize,
+        types: Option<Vec<String>>,
+        names: Option<Vec<String>>,
+        nullable: Option<Vec<bool>>,
+        defaults: Option<Vec<Bound<PyAny>>>,
+    ) -> PyResult<()> {
+        let db = self.inner.read();
+        let lengths = [types.as_ref().map(Vec::len), names.as_ref().map(Vec::len), nullable.as_ref().map(Vec::len), defaults.as_ref().map(Vec::len)];
+        if let Some(len) = lengths.into_iter().flatten().find(|&len| len != num_columns) {
+            return Err(PyValueError::new_err(format!("{} entries for {} columns", len, num_columns)));
+        }
+        let types = match types {
+            Some(types) => types.iter().map(|ty| ty.parse::<ColumnType>()).collect::<Result<_, _>>().map_err(config_err)?,
+            None if names.is_none() && nullable.is_none() && defaults.is_none() => {
+                return db.create_table(name, num_columns, key_index).map_err(|e| PyRuntimeError::new_err(e.to_string()));
+            }
+            None => vec![ColumnType::I64; num_columns],
+        };
+        if names.is_none() && nullable.is_none() && defaults.is_none() {
+            return db.create_typed_table(name, types, key_index).map_err(config_err);
+        }
+        let schema = Schema::new(types).map_err(config_err)?;
+        let mut columns = schema.defs();
+        for (col, column) in columns.iter_mut().enumerate() {
+            if let Some(names) = &names {
+                column.name = names[col].clone();
+            }
+            if let Some(nullable) = &nullable {
+                column.nullable = nullable[col];
+            }
+            if let Some(defaults) = &defaults {
+                column.default = from_py(&schema, col, &defaults[col])?;
+            }
+        }
+        db.create_table_with_columns(name, columns, key_index).map_err(config_err)
+    }
+
+    fn drop_table(&self, name: String) -> PyResult<()> {
+        self.inner.write()
+            .drop_table(name.as_str())
+            .map(|_| ())
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// Adds a column of `ty`, named `column`, after the table's last; the
+    /// records there read `default`, or None without one. Returns false if
+    /// there is no such table.
+    #[pyo3(signature = (name, column, ty="int64", nullable=true, default=None))]
+    fn alter_table_add_column(
+        &self,
+        name: String,
+        column: String,
+        ty: &str,
+        nullable: bool,
+        default: Option<Bound<PyAny>>,
+    ) -> PyResult<bool> {
+        let ty = ty.parse::<ColumnType>().map_err(config_err)?;
+        let schema = Schema::new(vec![ty]).map_err(config_err)?;
+        let default = match &default {
+            Some(default) => from_py(&schema, 0, default)?,
+            None => None,
+        };
+        let def = ColumnDef { name: column, ty, nullable, default };
+        self.inner.read().alter_table_add_column(&name, def).map_err(config_err)
+    }
+
+    fn alter_table_drop_column(&self, name: String, column: String) -> PyResult<bool> {
+        self.inner.read().alter_table_drop_column(&name, &column).map_err(config_err)
+    }
+
+    /// What the rewrite did as a dict, or None if there is no such table.
+    #[pyo3(signature = (name, keep_versions=None))]
+    fn vacuum<'py>(
+        &self,
+        py: Python<'py>,
+        name: String,
+        keep_versions: Option<usize>,
+    ) -> PyResult<Option<Bound<'py, PyDict>>> {
+        let inner = self.inner.clone();
+        let vacuumed = py
+            .detach(move || inner.read().vacuum(&name, VacuumOptions { keep_versions }))
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        let Some(vacuumed) = vacuumed else {
+            return Ok(None);
+        };
+        let stats = PyDict::new(py);
+        stats.set_item("records", vacuumed.records)?;
+        stats.set_item("deleted", vacuumed.deleted)?;
+        stats.set_item("discarded_versions", vacuumed.discarded_versions)?;
+        stats.set_item("pages_before", vacuumed.pages_before)?;
+        stats.set_item("pages_after", vacuumed.pages_after)?;
+        stats.set_item("bytes_reclaimed", vacuumed.bytes_reclaimed)?;
+        Ok(Some(stats))
+    }
+
+    #[pyo3(signature = (name, reserved=0, limit=None))]
+    fn set_table_quota(&self, name: String, reserved: usize, limit: Option<usize>) -> PyResult<()> {
+        self.inner.read()
+            .set_table_quota(&name, TableQuota { reserved, limit })
+            .map_err(config_err)
+    }
+
+    /// `(reserved, limit)`.
+    fn table_quota(&self, name: String) -> (usize, Option<usize>) {
+        let quota = self.inner.read().table_quota(&name);
+        (quota.reserved, quota.limit)
+    }
+
+    fn table_frames(&self, name: String) -> Option<usize> {
+        self.inner.read().table_frames(&name)
     }
 
-    fn drop_table(&self, name: String) {
-        self.inner.write().drop_table(name.as_str());
+    /// `num_columns`, `key_index` and `columns`, a dict of `name`, `type`,
+    /// `nullable` and `default` for each column.
+    fn get_table<'py>(&self, py: Python<'py>, name: String) -> PyResult<Option<Bound<'py, PyDict>>> {
+        let Some(table) = self.inner.read().get_table(&name) else {
+            return Ok(None);
+        };
+        let info = PyDict::new(py);
+        info.set_item("num_columns", table.schema().len())?;
+        info.set_item("key_index", table.schema().column_of(table.key_index))?;
+        info.set_item("columns", schema_to_py(py, &table.schema())?)?;
+        Ok(Some(info))
     }
 
-    fn get_table(&self, name: String) -> Option<(usize, usize)> {
-        self.inner.read().get_table(&name).map(|t| (t.num_data_columns, t.key_index))
+    fn table_types(&self, name: String) -> Option<Vec<String>> {
+        let table = self.inner.read().get_table(&name)?;
+        Some(table.schema().columns().iter().map(|ty| ty.to_string()).collect())
     }
 
     fn table_exists(&self, name: String) -> bool {
         self.inner.read().table_exists(name.as_str())
     }
-    
+
+    /// `"fail"` or `"quarantine"`.
+    fn set_corruption_policy(&self, policy: &str) -> PyResult<()> {
+        let policy = match policy {
+            "fail" => CorruptionPolicy::Fail,
+            "quarantine" => CorruptionPolicy::Quarantine,
+            other => return Err(PyValueError::new_err(format!("unknown corruption policy: {}", other))),
+        };
+        self.inner.write().set_corruption_policy(policy);
+        Ok(())
+    }
+
+    fn quarantined(&self) -> Vec<String> {
+        self.inner.read().quarantined().iter().map(|c| c.to_string()).collect()
+    }
+
+    /// Counters of the background writer, checkpointer and merge.
+    fn background_stats<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let db = self.inner.read();
+        let (flush, checkpoints, merges) = (db.flush_stats(), db.checkpoint_stats(), db.merge_stats());
+        let stats = PyDict::new(py);
+        stats.set_item("victim_writes", flush.victim_writes)?;
+        stats.set_item("background_writes", flush.background_writes)?;
+        stats.set_item("background_failures", flush.background_failures)?;
+        stats.set_item("checkpoints", checkpoints.taken)?;
+        stats.set_item("failed_checkpoints", checkpoints.failed)?;
+        stats.set_item("reclaimed_pages", checkpoints.reclaimed_pages)?;
+        stats.set_item("merged_records", merges.records)?;
+        stats.set_item("failed_merges", merges.failed)?;
+        Ok(stats)
+    }
+
+    /// Buffer pool hits and loads, in total and for sequential scans, and how
+    /// many pages were read ahead and then used.
+    fn access_stats<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let access = self.inner.read().access_stats();
+        let stats = PyDict::new(py);
+        stats.set_item("hits", access.hits)?;
+        stats.set_item("loads", access.loads)?;
+        stats.set_item("sequential_hits", access.sequential_hits)?;
+        stats.set_item("sequential_loads", access.sequential_loads)?;
+        stats.set_item("prefetched", access.prefetched)?;
+        stats.set_item("prefetch_hits", access.prefetch_hits)?;
+        Ok(stats)
+    }
+
+    /// Everything in `Database::stats`, in one flat dict.
+    fn stats<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let DbStats { pool, io, checkpoints, merges } = self.inner.read().stats();
+        let stats = PyDict::new(py);
+        stats.set_item("frames", pool.frames)?;
+        stats.set_item("resident_pages", pool.resident)?;
+        stats.set_item("dirty_pages", pool.dirty)?;
+        stats.set_item("pinned_pages", pool.pinned)?;
+        stats.set_item("recency_target", pool.recency_target)?;
+        stats.set_item("hits", pool.access.hits)?;
+        stats.set_item("loads", pool.access.loads)?;
+        stats.set_item("hit_rate", pool.access.hit_rate())?;
+        stats.set_item("sequential_hits", pool.access.sequential_hits)?;
+        stats.set_item("sequential_loads", pool.access.sequential_loads)?;
+        stats.set_item("prefetched", pool.access.prefetched)?;
+        stats.set_item("prefetch_hits", pool.access.prefetch_hits)?;
+        stats.set_item("evictions", pool.evictions)?;
+        stats.set_item("victim_writes", pool.flush.victim_writes)?;
+        stats.set_item("background_writes", pool.flush.background_writes)?;
+        stats.set_item("background_failures", pool.flush.background_failures)?;
+        stats.set_item("flush_all_writes", pool.flush.flush_all_writes)?;
+        stats.set_item("page_reads", io.page_reads)?;
+        stats.set_item("page_writes", io.page_writes)?;
+        stats.set_item("metadata_reads", io.metadata_reads)?;
+        stats.set_item("metadata_writes", io.metadata_writes)?;
+        stats.set_item("bytes_read", io.bytes_read)?;
+        stats.set_item("bytes_written", io.bytes_written)?;
+        stats.set_item("syncs", io.syncs)?;
+        stats.set_item("checkpoints", checkpoints.taken)?;
+        stats.set_item("failed_checkpoints", checkpoints.failed)?;
+        stats.set_item("reclaimed_pages", checkpoints.reclaimed_pages)?;
+        stats.set_item("merged_records", merges.records)?;
+        stats.set_item("failed_merges", merges.failed)?;
+        Ok(stats)
+    }
+
+    fn reset_stats(&self) {
+        self.inner.read().reset_stats();
+    }
+
+    fn buffer_pool_frames(&self) -> usize {
+        self.inner.read().buffer_pool_frames()
+    }
+
+    /// Takes either a number of frames or a memory budget in bytes.
+    #[pyo3(signature = (frames=None, bytes=None))]
+    fn resize_buffer_pool(&self, frames: Option<usize>, bytes: Option<usize>) -> PyResult<()> {
+        let size = pool_size(frames, bytes)?
+            .ok_or_else(|| PyValueError::new_err("give either frames or bytes"))?;
+        self.inner.write().resize_buffer_pool(size).map_err(config_err)
+    }
+
+}
+
+fn pool_size(frames: Option<usize>, bytes: Option<usize>) -> PyResult<Option<PoolSize>> {
+    match (frames, bytes) {
+        (Some(_), Some(_)) => Err(PyValueError::new_err("give either a frame count or a byte budget, not both")),
+        (Some(frames), None) => Ok(Some(PoolSize::Frames(frames))),
+        (None, Some(bytes)) => Ok(Some(PoolSize::Bytes(bytes))),
+        (None, None) => Ok(None),
+    }
+}
+
+/// "arc", "clock", "lru", or "lru-<k>" for LRU-K.
+fn eviction_kind(name: &str) -> PyResult<EvictionKind> {
+    match name {
+        "arc" => Ok(EvictionKind::Arc),
+        "clock" => Ok(EvictionKind::Clock),
+        "lru" => Ok(EvictionKind::LruK(1)),
+        other => other
+            .strip_prefix("lru-")
+            .and_then(|k| k.parse().ok())
+            .map(EvictionKind::LruK)
+            .ok_or_else(|| PyValueError::new_err(format!("unknown eviction policy: {}", other))),
+    }
+}
+
+fn config_err(e: DbError) -> PyErr {
+    match e {
+        DbError::InvalidConfig(_) | DbError::InvalidSchema(_) | DbError::UnknownColumn(_) => PyValueError::new_err(e.to_string()),
+        e => PyRuntimeError::new_err(e.to_string()),
+    }
 }
@@ -1,3 +1,4 @@
+use crate::bindings::values::index_slot;
 use crate::table::Table;
 use std::sync::Arc;
 use pyo3::prelude::*;
@@ -10,13 +11,13 @@ pub struct CoreIndex {
 #[pymethods]
 impl CoreIndex {
     pub fn create_index(&self, col: usize) {
-        if col < self.table.num_data_columns {
+        if let Some(col) = index_slot(&self.table, col) {
             self.table.indices[col].enable()
         }
     }
 
     pub fn drop_index(&self, col: usize) {
-        if col < self.table.num_data_columns {
+        if let Some(col) = index_slot(&self.table, col) {
             self.table.indices[col].disable()
         }
     }
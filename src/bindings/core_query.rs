@@ -1,7 +1,12 @@
+use crate::bindings::values::{column_from_py, from_py, index_slot, key_from_py, record_from_py, records_to_py, to_py};
 use crate::bindings::CoreDatabase;
+use crate::bufferpool::AccessHint;
+use crate::errors::DbError;
 use crate::query::Query;
-use pyo3::exceptions::PyRuntimeError;
+use crate::schema::Value;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
 use pyo3::prelude::*;
+use pyo3::types::PyTuple;
 
 #[pyclass]
 pub struct CoreQuery {
@@ -22,73 +27,99 @@ impl CoreQuery {
     }
 
     #[pyo3(signature = (*columns))]
-    fn insert(&mut self, columns: Vec<i64>) -> bool {
-        let mut nullable_rec = Vec::with_capacity(columns.len() + 4);
-        nullable_rec.extend(columns.into_iter().map(Some));
-        self.inner.insert(nullable_rec).unwrap_or(false)
+    fn insert(&mut self, columns: &Bound<PyTuple>) -> bool {
+        record_from_py(&self.inner.table.schema(), columns)
+            .is_ok_and(|record| self.inner.insert_values(record).unwrap_or(false))
     }
 
+    /// Searches the column `search_key_index` numbers or names. With
+    /// `columns`, a list of names, only those columns come back, in that
+    /// order, and `projected_columns_index` is not needed.
+    #[pyo3(signature = (search_key, search_key_index, projected_columns_index=None, columns=None))]
     fn select(
         &self,
-        search_key: i64,
-        search_key_index: usize,
-        projected_columns_index: Vec<i64>,
-    ) -> PyResult<Vec<Vec<Option<i64>>>> {
-        self.inner
-            .select(search_key, search_key_index, &projected_columns_index)
-            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+        py: Python<'_>,
+        search_key: &Bound<PyAny>,
+        search_key_index: &Bound<PyAny>,
+        projected_columns_index: Option<Vec<i64>>,
+        columns: Option<Vec<String>>,
+    ) -> PyResult<Vec<Vec<Py<PyAny>>>> {
+        let schema = &self.inner.table.schema();
+        let search_col = column_from_py(schema, search_key_index)?;
+        let key = from_py(schema, search_col, search_key)?
+            .ok_or_else(|| PyRuntimeError::new_err(DbError::NullValue(search_col).to_string()))?;
+        let records = match (columns, projected_columns_index) {
+            (Some(columns), _) => {
+                let names: Vec<&str> = columns.iter().map(String::as_str).collect();
+                self.inner.select_columns(&key, &schema.names()[search_col], &names)
+            }
+            (None, Some(projected)) => self.inner.select_values(&key, search_col, &projected),
+            (None, None) => return Err(PyValueError::new_err("give projected_columns_index or columns")),
+        };
+        records_to_py(py, records.map_err(|e| PyRuntimeError::new_err(e.to_string()))?)
     }
 
     fn select_version(
         &self,
-        search_key: i64,
-        search_key_index: usize,
+        py: Python<'_>,
+        search_key: &Bound<PyAny>,
+        _search_key_index: usize,
         projected_columns_index: Vec<i64>,
         relative_version: i64
-    ) -> PyResult<Vec<Vec<Option<i64>>>> {
-        self.inner
-            .select_version(search_key, search_key_index, &projected_columns_index,relative_version)
-            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    ) -> PyResult<Vec<Vec<Py<PyAny>>>> {
+        let key = key_from_py(&self.inner.table, search_key)?;
+        let records = self.inner
+            .select_version_values(&Value::I64(key), &projected_columns_index, relative_version)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        records_to_py(py, records)
     }
 
     #[pyo3(signature = (primary_key, *columns))]
-    fn update(&mut self, primary_key: i64, columns: Vec<Option<i64>>) -> bool {
-        self.inner.update(primary_key, columns).unwrap_or(false)
+    fn update(&mut self, primary_key: &Bound<PyAny>, columns: &Bound<PyTuple>) -> bool {
+        let table = &self.inner.table;
+        match (key_from_py(table, primary_key), record_from_py(&table.schema(), columns)) {
+            (Ok(key), Ok(record)) => self.inner.update_values(key, record).unwrap_or(false),
+            _ => false,
+        }
     }
 
-    fn delete(&mut self, key: i64) -> bool {
-        self.inner.delete(key).unwrap_or(false)
+    fn delete(&mut self, key: &Bound<PyAny>) -> bool {
+        key_from_py(&self.inner.table, key).is_ok_and(|key| self.inner.delete(key).unwrap_or(false))
     }
 
-    fn sum(&self, start_range: i64, end_range: i64, col: usize) -> PyResult<i64> {
-        self.inner
-            .sum(start_range, end_range, col)
-            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    fn sum(&self, py: Python<'_>, start_range: &Bound<PyAny>, end_range: &Bound<PyAny>, col: usize) -> PyResult<Py<PyAny>> {
+        let (start, end) = (key_from_py(&self.inner.table, start_range)?, key_from_py(&self.inner.table, end_range)?);
+        let sum = self.inner
+            .sum_values(start, end, col)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        to_py(py, Some(sum))
     }
-    fn sum_version(&self, start_range: i64, end_range: i64, column: usize, relative_version: i64) -> PyResult<i64>{
-        self.inner.sum_version(start_range, end_range, column, relative_version)
-            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    fn sum_version(&self, py: Python<'_>, start_range: &Bound<PyAny>, end_range: &Bound<PyAny>, column: usize, relative_version: i64) -> PyResult<Py<PyAny>>{
+        let (start, end) = (key_from_py(&self.inner.table, start_range)?, key_from_py(&self.inner.table, end_range)?);
+        let sum = self.inner.sum_version_values(start, end, column, relative_version)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        to_py(py, Some(sum))
     }
 
-    fn increment(&mut self, key: i64, column: usize) -> bool {
-        self.inner.increment(key, column).unwrap_or(false)
+    fn increment(&mut self, key: &Bound<PyAny>, column: usize) -> bool {
+        key_from_py(&self.inner.table, key).is_ok_and(|key| self.inner.increment_values(key, column).unwrap_or(false))
     }
 
     fn create_index(&self, col: usize) {
-        if col >= self.inner.table.num_data_columns || col == self.inner.table.key_index {
-            return;
-        }
         let table = &self.inner.table;
+        let Some(col) = index_slot(table, col).filter(|&slot| slot != table.key_index) else {
+            return;
+        };
         table.indices[col].enable();
         for (_,rid) in table.indices[table.key_index].all_pairs() {
-            if let Ok(Some(val)) = table.read_latest_single(rid, col) {
+            if let Ok(Some(val)) = table.read_latest_single_hinted(rid, col, AccessHint::Sequential) {
                 table.indices[col].insert(val, rid);
             }
         }
     }
 
     fn drop_index(&self, col: usize) {
-        if col < self.inner.table.num_data_columns {
+        if let Some(col) = index_slot(&self.inner.table, col) {
             self.inner.table.indices[col].disable();
         }
     }
@@ -12,12 +12,12 @@ pub struct CoreTable {
 impl CoreTable {
     #[getter]
     fn num_columns(&self) -> usize {
-        self.inner.num_data_columns
+        self.inner.schema().len()
     }
 
     #[getter]
     fn key_index(&self) -> usize {
-        self.inner.key_index
+        self.inner.schema().column_of(self.inner.key_index)
     }
 
     #[getter]
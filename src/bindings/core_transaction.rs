@@ -1,6 +1,8 @@
+use crate::bindings::values::{from_py, key_from_py, record_from_py};
 use crate::bindings::CoreQuery;
+use crate::schema::Value;
 use crate::transaction::{QueryOp, Transaction};
-use pyo3::exceptions::PyRuntimeError;
+use pyo3::exceptions::{PyRuntimeError, PyTypeError, PyValueError};
 use pyo3::prelude::*;
 use pyo3::types::PyTuple;
 
@@ -33,52 +35,77 @@ impl CoreTransaction {
                 .cast_into::<CoreQuery>()?;
             let table = core_q.borrow().inner.table.clone();
 
+            // The ops work on slots, so columns and values go through the
+            // table's schema here.
+            let schema = table.schema();
+            let slot = |i: usize| -> PyResult<usize> {
+                let col: usize = args.get_item(i)?.extract()?;
+                if col >= schema.len() {
+                    return Err(PyValueError::new_err(format!("there is no column {}", col)));
+                }
+                Ok(schema.slots(col).start)
+            };
+            let proj = |i: usize| -> PyResult<Vec<i64>> { Ok(schema.project(&args.get_item(i)?.extract::<Vec<i64>>()?)) };
+            let encode = |record: Vec<Option<Value>>| table.encode(&record).map_err(|e| PyTypeError::new_err(e.to_string()));
+
             let op = match fn_name.as_str() {
                 "insert" => {
-                    let raw: Vec<i64> = args.extract()?;
-                    QueryOp::Insert { table, args: raw.into_iter().map(Some).collect() }
+                    let mut record = record_from_py(&schema, args)?;
+                    schema.fill_defaults(&mut record).map_err(|e| PyValueError::new_err(e.to_string()))?;
+                    let args = encode(record)?;
+                    QueryOp::Insert { table, args }
                 }
                 "update" => {
-                    let key: i64 = args.get_item(0)?.extract()?;
-                    let cols = (1..args.len())
-                        .map(|i| args.get_item(i)?.extract::<Option<i64>>())
-                        .collect::<PyResult<_>>()?;
+                    let key = key_from_py(&table, &args.get_item(0)?)?;
+                    let cols = encode(record_from_py(&schema, &args.get_slice(1, args.len()))?)?;
                     QueryOp::Update { table, key, cols }
                 }
                 "delete" => {
-                    QueryOp::Delete { table, key: args.get_item(0)?.extract()? }
+                    QueryOp::Delete { key: key_from_py(&table, &args.get_item(0)?)?, table }
+                }
+                "select" => {
+                    let col: usize = args.get_item(1)?.extract()?;
+                    let value = from_py(&schema, col, &args.get_item(0)?)?
+                        .ok_or_else(|| PyValueError::new_err("cannot select by a null"))?;
+                    // Text and blob slots say where a value is, not what it is.
+                    let slots = if schema.columns()[col].is_out_of_line() {
+                        vec![]
+                    } else {
+                        schema.encode_column(col, &value).map_err(|e| PyTypeError::new_err(e.to_string()))?
+                    };
+                    let key = match slots[..] {
+                        [Some(key)] => key,
+                        _ => return Err(PyValueError::new_err(format!("a transaction cannot select by a {} column", schema.columns()[col]))),
+                    };
+                    QueryOp::Select { key, search_col: slot(1)?, proj: proj(2)?, table }
                 }
-                "select" => QueryOp::Select {
-                    table,
-                    key:        args.get_item(0)?.extract()?,
-                    search_col: args.get_item(1)?.extract()?,
-                    proj:       args.get_item(2)?.extract()?,
-                },
                 "select_version" => QueryOp::SelectVersion {
-                    table,
-                    key:        args.get_item(0)?.extract()?,
-                    search_col: args.get_item(1)?.extract()?,
-                    proj:       args.get_item(2)?.extract()?,
+                    key:        key_from_py(&table, &args.get_item(0)?)?,
+                    search_col: slot(1)?,
+                    proj:       proj(2)?,
                     version:    args.get_item(3)?.extract()?,
+                    table,
                 },
                 "sum" => QueryOp::Sum {
+                    start: key_from_py(&table, &args.get_item(0)?)?,
+                    end:   key_from_py(&table, &args.get_item(1)?)?,
+                    col:   slot(2)?,
                     table,
-                    start: args.get_item(0)?.extract()?,
-                    end:   args.get_item(1)?.extract()?,
-                    col:   args.get_item(2)?.extract()?,
                 },
                 "sum_version" => QueryOp::SumVersion {
-                    table,
-                    start:   args.get_item(0)?.extract()?,
-                    end:     args.get_item(1)?.extract()?,
-                    col:     args.get_item(2)?.extract()?,
+                    start:   key_from_py(&table, &args.get_item(0)?)?,
+                    end:     key_from_py(&table, &args.get_item(1)?)?,
+                    col:     slot(2)?,
                     version: args.get_item(3)?.extract()?,
-                },
-                "increment" => QueryOp::Increment {
                     table,
-                    key: args.get_item(0)?.extract()?,
-                    col: args.get_item(1)?.extract()?,
                 },
+                "increment" => {
+                    let col: usize = args.get_item(1)?.extract()?;
+                    if !schema.columns().get(col).is_some_and(|ty| ty.is_integer()) {
+                        return Err(PyValueError::new_err(format!("column {} cannot be incremented", col)));
+                    }
+                    QueryOp::Increment { key: key_from_py(&table, &args.get_item(0)?)?, col: slot(1)?, table }
+                }
                 other => return Err(PyRuntimeError::new_err(format!("unknown query op: {}", other))),
             };
 
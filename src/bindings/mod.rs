@@ -4,6 +4,7 @@ mod core_table;
 mod core_index;
 mod core_transaction;
 mod core_transaction_worker;
+mod values;
 
 pub use core_db::CoreDatabase;
 pub use core_query::CoreQuery;
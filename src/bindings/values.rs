@@ -0,0 +1,138 @@
+use crate::errors::DbError;
+use crate::schema::{ColumnType, Schema, Value};
+use crate::table::Table;
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyTuple};
+
+/// The value of Python `obj` for column `col`: an int for an integer
+/// column, a float for `float64`, a bool, a `datetime` or int of
+/// microseconds for `timestamp`, a str or bytes. `None` is null.
+pub(crate) fn from_py(schema: &Schema, col: usize, obj: &Bound<PyAny>) -> PyResult<Option<Value>> {
+    if obj.is_none() {
+        return Ok(None);
+    }
+    let ty = *schema
+        .columns()
+        .get(col)
+        .ok_or_else(|| PyTypeError::new_err(DbError::ColumnCount(col + 1).to_string()))?;
+    let wrong = |_| PyTypeError::new_err(DbError::WrongType(col, ty).to_string());
+    let value = match ty {
+        ColumnType::I32 => Value::I32(obj.extract().map_err(wrong)?),
+        ColumnType::I64 => Value::I64(obj.extract().map_err(wrong)?),
+        ColumnType::F64 => Value::F64(obj.extract().map_err(wrong)?),
+        ColumnType::Bool => Value::Bool(obj.extract().map_err(wrong)?),
+        ColumnType::Timestamp => Value::Timestamp(match obj.extract::<i64>() {
+            Ok(micros) => micros,
+            Err(_) => datetime_micros(obj).map_err(wrong)?,
+        }),
+        ColumnType::FixedStr(_) | ColumnType::Str(_) | ColumnType::Text => Value::Str(obj.extract().map_err(wrong)?),
+        ColumnType::FixedBytes(_) | ColumnType::Bytes(_) | ColumnType::Blob => {
+            Value::Bytes(obj.cast::<PyBytes>().map_err(|e| wrong(e.into()))?.as_bytes().to_vec())
+        }
+    };
+    Ok(Some(value))
+}
+
+/// One value for each column of the table.
+pub(crate) fn record_from_py(schema: &Schema, objs: &Bound<PyTuple>) -> PyResult<Vec<Option<Value>>> {
+    if objs.len() != schema.len() {
+        return Err(PyTypeError::new_err(DbError::ColumnCount(objs.len()).to_string()));
+    }
+    objs.iter().enumerate().map(|(col, obj)| from_py(schema, col, &obj)).collect()
+}
+
+/// A primary key, as the `i64` it is stored as.
+pub(crate) fn key_from_py(table: &Table, obj: &Bound<PyAny>) -> PyResult<i64> {
+    let col = table.schema().column_of(table.key_index);
+    from_py(&table.schema(), col, obj)?
+        .and_then(|key| key.as_i64())
+        .ok_or_else(|| PyTypeError::new_err(DbError::NullValue(col).to_string()))
+}
+
+pub(crate) fn to_py(py: Python<'_>, value: Option<Value>) -> PyResult<Py<PyAny>> {
+    Ok(match value {
+        None => py.None(),
+        Some(Value::I32(v)) => v.into_pyobject(py)?.into_any().unbind(),
+        Some(Value::I64(v)) => v.into_pyobject(py)?.into_any().unbind(),
+        Some(Value::F64(v)) => v.into_pyobject(py)?.into_any().unbind(),
+        Some(Value::Bool(v)) => v.into_pyobject(py)?.to_owned().into_any().unbind(),
+        Some(Value::Timestamp(micros)) => {
+            let datetime = py.import("datetime")?;
+            let delta = datetime.getattr("timedelta")?.call((0, 0, micros), None)?;
+            epoch(py)?.add(delta)?.unbind()
+        }
+        Some(Value::Str(s)) => s.into_pyobject(py)?.into_any().unbind(),
+        Some(Value::Bytes(b)) => PyBytes::new(py, &b).into_any().unbind(),
+    })
+}
+
+pub(crate) fn records_to_py(py: Python<'_>, records: Vec<Vec<Option<Value>>>) -> PyResult<Vec<Vec<Py<PyAny>>>> {
+    records
+        .into_iter()
+        .map(|record| record.into_iter().map(|value| to_py(py, value)).collect())
+        .collect()
+}
+
+/// A column given by its number or its name.
+pub(crate) fn column_from_py(schema: &Schema, obj: &Bound<PyAny>) -> PyResult<usize> {
+    let col = match obj.extract::<usize>() {
+        Ok(col) => col,
+        Err(_) => schema
+            .column(&obj.extract::<String>()?)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?,
+    };
+    if col >= schema.len() {
+        return Err(PyValueError::new_err(format!("there is no column {}", col)));
+    }
+    Ok(col)
+}
+
+/// A dict of `name`, `type`, `nullable` and `default` for each column.
+pub(crate) fn schema_to_py<'py>(py: Python<'py>, schema: &Schema) -> PyResult<Vec<Bound<'py, PyDict>>> {
+    schema
+        .defs()
+        .into_iter()
+        .map(|def| {
+            let column = PyDict::new(py);
+            column.set_item("name", def.name)?;
+            column.set_item("type", def.ty.to_string())?;
+            column.set_item("nullable", def.nullable)?;
+            column.set_item("default", to_py(py, def.default)?)?;
+            Ok(column)
+        })
+        .collect()
+}
+
+/// The slot an index on column `col` would cover, if the column has an
+/// index: one that takes more than a single slot does not, and neither does
+/// text or a blob, whose slot only says where the value is.
+pub(crate) fn index_slot(table: &Table, col: usize) -> Option<usize> {
+    let schema = &table.schema();
+    (col < schema.len() && schema.slots(col).len() == 1 && !schema.columns()[col].is_out_of_line())
+        .then(|| schema.slots(col).start)
+}
+
+/// 1970-01-01 in UTC.
+fn epoch(py: Python<'_>) -> PyResult<Bound<'_, PyAny>> {
+    let datetime = py.import("datetime")?;
+    let utc = datetime.getattr("timezone")?.getattr("utc")?;
+    datetime.getattr("datetime")?.call((1970, 1, 1, 0, 0, 0, 0, utc), None)
+}
+
+/// Microseconds from the epoch to `datetime`, which is taken to be in UTC if
+/// it has no time zone.
+fn datetime_micros(obj: &Bound<PyAny>) -> PyResult<i64> {
+    let py = obj.py();
+    let datetime = py.import("datetime")?;
+    let utc = datetime.getattr("timezone")?.getattr("utc")?;
+    let aware = if obj.getattr("tzinfo")?.is_none() {
+        let kwargs = pyo3::types::PyDict::new(py);
+        kwargs.set_item("tzinfo", utc)?;
+        obj.call_method("replace", (), Some(&kwargs))?
+    } else {
+        obj.clone()
+    };
+    let one = datetime.getattr("timedelta")?.call((0, 0, 1), None)?;
+    aware.sub(epoch(py)?)?.floor_div(one)?.extract()
+}
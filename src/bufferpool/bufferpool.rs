@@ -1,18 +1,20 @@
 use crate::bufferpool::bufferpool_worker::{BufferPoolOp, BufferPoolWorker};
 use crate::bufferpool::errors::BufferPoolError;
-use crate::bufferpool::eviction_policy::EvictionPolicy;
-use crate::disk_manager::DiskManager;
+use crate::bufferpool::eviction_policy::{ColdRing, Eviction};
+use crate::config::{EvictionKind, FlusherConfig, PoolSize, TableQuota};
 use crate::page::Page;
 use crate::page_collection::PageId;
+use crate::storage::StorageBackend;
 use dashmap::DashMap;
-use parking_lot::{Mutex, RwLock};
-use std::sync::atomic::{AtomicBool, Ordering};
+use parking_lot::{MappedRwLockReadGuard, MappedRwLockWriteGuard, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::{mpsc, Arc};
 use std::thread;
 
 pub type FrameId = usize;
 // What the TA ended up using
 
+/// Frames in a pool built with `BufferPool::new`.
 pub const BP_CAP: usize = 256;
 
 struct InnerFrame {
@@ -22,6 +24,13 @@ struct InnerFrame {
 
 pub struct Frame {
     dirty: AtomicBool,
+    /// Loaded by read-ahead and not used since.
+    prefetched: AtomicBool,
+    /// Live `PageGuard`s on the frame. A hit pins first and then checks the
+    /// page table still maps its page here; an eviction unmaps the page first
+    /// and then checks for pins. One of the two always sees the other, so a
+    /// page is never evicted from under a guard.
+    pins: AtomicUsize,
     inner: RwLock<InnerFrame>,
 }
 
@@ -29,6 +38,8 @@ impl Frame {
     pub fn new() -> Self {
         Self {
             dirty: AtomicBool::new(false),
+            prefetched: AtomicBool::new(false),
+            pins: AtomicUsize::new(0),
             inner: RwLock::new(InnerFrame {
                 page: Default::default(),
                 pid: None,
@@ -41,6 +52,7 @@ impl Frame {
         guard.page = page;
         guard.pid = Some(pid);
         self.dirty.store(false, Ordering::Release);
+        self.prefetched.store(false, Ordering::Relaxed);
     }
 
     pub fn init(&self, pid: PageId) {
@@ -48,90 +60,474 @@ impl Frame {
         guard.pid = Some(pid);
         guard.page = Page::default();
         self.dirty.store(false, Ordering::Release);
-    }
-
-    pub fn get_page_copy(&self) -> Page {
-        let guard = self.inner.read();
-        guard.page.clone()
+        self.prefetched.store(false, Ordering::Relaxed);
     }
 
     pub fn release(&self) {
         let mut guard = self.inner.write();
         guard.pid = None;
         self.dirty.store(false, Ordering::Release);
+        self.prefetched.store(false, Ordering::Relaxed);
     }
 
-    pub fn is_dirty(&self) -> bool {
-        self.dirty.load(Ordering::Acquire)
+    pub fn is_pinned(&self) -> bool {
+        self.pins.load(Ordering::SeqCst) > 0
     }
 
-    pub fn clear_dirty(&self) {
-        self.dirty.store(false, Ordering::Release);
+    /// Writes the page back if it is dirty, and says whether it was. The frame
+    /// stays read-locked until the write is done, so its page can be neither
+    /// changed nor swapped for another one halfway through.
+    pub fn write_back(&self, storage: &dyn StorageBackend) -> Result<bool, BufferPoolError> {
+        let guard = self.inner.read();
+        let Some(pid) = guard.pid else {
+            return Ok(false);
+        };
+        // Writers set the flag with the frame write-locked, so none of them
+        // can slip in between clearing it and the write.
+        if !self.dirty.swap(false, Ordering::AcqRel) {
+            return Ok(false);
+        }
+        if let Err(e) = storage.write_page(pid, &guard.page) {
+            self.dirty.store(true, Ordering::Release);
+            return Err(e.into());
+        }
+        Ok(true)
     }
+
     pub fn pid(&self) -> Option<PageId> {
         self.inner.read().pid
     }
 }
 
-pub struct BufferPool {
+/// A page held in the buffer pool. The frame it is in stays pinned until the
+/// guard is dropped: eviction passes it over and `resize` will not drop it, so
+/// the guard can be read and written any number of times without looking the
+/// page up again.
+pub struct PageGuard {
+    pid: PageId,
+    frame: Arc<Frame>,
+}
+
+impl PageGuard {
+    pub fn pid(&self) -> PageId {
+        self.pid
+    }
+
+    /// Shared access to the page; blocks while someone holds `write`.
+    pub fn read(&self) -> MappedRwLockReadGuard<'_, Page> {
+        RwLockReadGuard::map(self.frame.inner.read(), |inner| &inner.page)
+    }
+
+    /// Exclusive access to the page, which is marked dirty.
+    pub fn write(&self) -> MappedRwLockWriteGuard<'_, Page> {
+        let guard = self.frame.inner.write();
+        self.frame.dirty.store(true, Ordering::Release);
+        RwLockWriteGuard::map(guard, |inner| &mut inner.page)
+    }
+}
+
+impl Drop for PageGuard {
+    fn drop(&mut self) {
+        self.frame.pins.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// How the pool's dirty pages have been written back so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FlushStats {
+    /// Written by a load that needed the frame, on the loading thread.
+    pub victim_writes: u64,
+    /// Written ahead of time by the background writer.
+    pub background_writes: u64,
+    /// Background writes that failed; those pages stay dirty.
+    pub background_failures: u64,
+    /// Written by `flush_all`, for a checkpoint or before the pool empties.
+    pub flush_all_writes: u64,
+}
+
+/// How pages have been looked up in the pool so far. The totals include the
+/// sequential lookups.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AccessStats {
+    /// Lookups that found the page in the pool.
+    pub hits: u64,
+    /// Lookups that had to load the page.
+    pub loads: u64,
+    pub sequential_hits: u64,
+    pub sequential_loads: u64,
+    /// Pages read ahead by the worker, and how many of those were used
+    /// before they were evicted.
+    pub prefetched: u64,
+    pub prefetch_hits: u64,
+}
+
+impl AccessStats {
+    /// The share of lookups that found their page in the pool, or `None`
+    /// before the first lookup.
+    pub fn hit_rate(&self) -> Option<f64> {
+        let lookups = self.hits + self.loads;
+        (lookups > 0).then(|| self.hits as f64 / lookups as f64)
+    }
+}
+
+/// What the pool holds right now, and everything it has counted since it was
+/// built or its stats were last reset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    pub frames: usize,
+    /// Frames holding a page, and how many of those are dirty or pinned.
+    pub resident: usize,
+    pub dirty: usize,
+    pub pinned: usize,
+    /// Pages evicted, whether to make room for a load, to shrink the pool or
+    /// to empty it.
+    pub evictions: u64,
+    /// The eviction policy's `Eviction::recency_target`, ARC's `p`.
+    pub recency_target: Option<usize>,
+    pub access: AccessStats,
+    pub flush: FlushStats,
+}
+
+/// How a caller expects to use the page it fetches.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AccessHint {
+    #[default]
+    Normal,
+    /// Once, as part of a pass over many pages, like a scan. A hit leaves the
+    /// eviction policy as it was, and a page that has to be loaded goes in
+    /// cold, so the pass does not push out the pages used over and over.
+    Sequential,
+}
+
+#[derive(Default)]
+struct AccessCounters {
+    hits: AtomicU64,
+    loads: AtomicU64,
+    sequential_hits: AtomicU64,
+    sequential_loads: AtomicU64,
+    prefetched: AtomicU64,
+    prefetch_hits: AtomicU64,
+}
+
+impl AccessCounters {
+    fn reset(&self) {
+        for counter in [
+            &self.hits,
+            &self.loads,
+            &self.sequential_hits,
+            &self.sequential_loads,
+            &self.prefetched,
+            &self.prefetch_hits,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn record(&self, hint: AccessHint, hit: bool) {
+        let (total, sequential) = match hit {
+            true => (&self.hits, &self.sequential_hits),
+            false => (&self.loads, &self.sequential_loads),
+        };
+        total.fetch_add(1, Ordering::Relaxed);
+        if hint == AccessHint::Sequential {
+            sequential.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct FlushCounters {
+    pub(crate) victim_writes: AtomicU64,
+    pub(crate) background_writes: AtomicU64,
+    pub(crate) background_failures: AtomicU64,
+    pub(crate) flush_all_writes: AtomicU64,
+    pub(crate) evictions: AtomicU64,
+}
+
+impl FlushCounters {
+    pub(crate) fn bump(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn reset(&self) {
+        for counter in [
+            &self.victim_writes,
+            &self.background_writes,
+            &self.background_failures,
+            &self.flush_all_writes,
+            &self.evictions,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// What the pool and its worker share: the frames and everything that says
+/// which page is in which.
+pub(crate) struct PoolCore {
     page_table: DashMap<PageId, FrameId>,
-    frames: Vec<Frame>,
-    eviction_policy: Mutex<EvictionPolicy>,
-    disk_manager: Arc<RwLock<DiskManager>>,
+    /// Only grows or shrinks under `load_lock`, so a frame the policy knows
+    /// of is always here.
+    pub(crate) frames: RwLock<Vec<Arc<Frame>>>,
+    pub(crate) eviction_policy: Arc<dyn Eviction>,
+    /// Held by loads, evictions and resizes, one at a time. Hits go without
+    /// it.
+    load_lock: Mutex<()>,
+    pub(crate) storage: Arc<dyn StorageBackend>,
+    pub(crate) counters: FlushCounters,
+    accesses: AccessCounters,
+    /// The tables with a quota, and the frames each table's pages take up.
+    /// Both change only under `load_lock`.
+    quotas: DashMap<usize, TableQuota>,
+    table_frames: DashMap<usize, usize>,
+}
+
+pub struct BufferPool {
+    core: Arc<PoolCore>,
+    /// Page collections a scan reads ahead of the one it is in.
+    read_ahead: AtomicUsize,
     command_tx: mpsc::Sender<BufferPoolOp>,
     _bg_thread: thread::JoinHandle<()>,
 }
 
 impl BufferPool {
-    pub fn new(disk_manager: Arc<RwLock<DiskManager>>) -> BufferPool {
-        let worker_dm = Arc::clone(&disk_manager);
+    /// Memory one frame takes up.
+    pub const FRAME_BYTES: usize = std::mem::size_of::<Frame>();
+
+    pub fn new(storage: Arc<dyn StorageBackend>) -> BufferPool {
+        Self::with_capacity(storage, BP_CAP)
+    }
+
+    pub fn with_capacity(storage: Arc<dyn StorageBackend>, capacity: usize) -> BufferPool {
+        Self::with_options(storage, capacity, EvictionKind::default(), None)
+    }
+
+    /// A pool that evicts by `eviction`, and whose worker also writes pages
+    /// back in the background if given a `flusher`.
+    pub fn with_options(
+        storage: Arc<dyn StorageBackend>,
+        capacity: usize,
+        eviction: EvictionKind,
+        flusher: Option<FlusherConfig>,
+    ) -> BufferPool {
+        let core = Arc::new(PoolCore {
+            page_table: DashMap::new(),
+            frames: RwLock::new((0..capacity).map(|_| Arc::new(Frame::new())).collect()),
+            eviction_policy: eviction.build(capacity),
+            load_lock: Mutex::new(()),
+            storage,
+            counters: FlushCounters::default(),
+            accesses: AccessCounters::default(),
+            quotas: DashMap::new(),
+            table_frames: DashMap::new(),
+        });
         let (tx, rx) = mpsc::channel(); // unbounded
-        let handle = thread::spawn(move || BufferPoolWorker::new(rx, worker_dm).run());
+        let worker = BufferPoolWorker::new(rx, Arc::clone(&core), flusher);
+        let handle = thread::spawn(move || worker.run());
         Self {
-            page_table: DashMap::new(),
-            frames: (0..BP_CAP).map(|_| Frame::new()).collect(),
-            eviction_policy: Mutex::new(EvictionPolicy::new(BP_CAP)),
-            disk_manager,
+            core,
+            read_ahead: AtomicUsize::new(0),
             command_tx: tx,
             _bg_thread: handle,
         }
     }
 
-    pub fn evict_all(&self) -> Result<(), BufferPoolError> {
-        let pages: Vec<(PageId, Page)> = self
-            .page_table
-            .iter()
-            .filter_map(|entry| {
-                let pid = *entry.key();
-                let fid = *entry.value();
-                if self.frames[fid].is_dirty() {
-                    Some((pid, self.frames[fid].get_page_copy()))
-                } else {
-                    None
+    pub fn flush_stats(&self) -> FlushStats {
+        let counters = &self.core.counters;
+        FlushStats {
+            victim_writes: counters.victim_writes.load(Ordering::Relaxed),
+            background_writes: counters.background_writes.load(Ordering::Relaxed),
+            background_failures: counters.background_failures.load(Ordering::Relaxed),
+            flush_all_writes: counters.flush_all_writes.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn access_stats(&self) -> AccessStats {
+        let accesses = &self.core.accesses;
+        AccessStats {
+            hits: accesses.hits.load(Ordering::Relaxed),
+            loads: accesses.loads.load(Ordering::Relaxed),
+            sequential_hits: accesses.sequential_hits.load(Ordering::Relaxed),
+            sequential_loads: accesses.sequential_loads.load(Ordering::Relaxed),
+            prefetched: accesses.prefetched.load(Ordering::Relaxed),
+            prefetch_hits: accesses.prefetch_hits.load(Ordering::Relaxed),
+        }
+    }
+
+    /// The counters are read one at a time, with lookups going on, so they
+    /// need not add up exactly.
+    pub fn stats(&self) -> PoolStats {
+        let frames = self.core.frames.read().clone();
+        let resident: Vec<_> = frames.iter().filter(|f| f.pid().is_some()).collect();
+        PoolStats {
+            frames: frames.len(),
+            resident: resident.len(),
+            dirty: resident.iter().filter(|f| f.dirty.load(Ordering::Acquire)).count(),
+            pinned: resident.iter().filter(|f| f.is_pinned()).count(),
+            evictions: self.core.counters.evictions.load(Ordering::Relaxed),
+            recency_target: self.core.eviction_policy.recency_target(),
+            access: self.access_stats(),
+            flush: self.flush_stats(),
+        }
+    }
+
+    /// Sets every counter back to zero. What the pool holds is left alone.
+    pub fn reset_stats(&self) {
+        self.core.accesses.reset();
+        self.core.counters.reset();
+    }
+
+    pub fn read_ahead(&self) -> usize {
+        self.read_ahead.load(Ordering::Relaxed)
+    }
+
+    /// How many page collections past the one a scan is in get loaded ahead
+    /// of it; 0 turns read-ahead off.
+    pub fn set_read_ahead(&self, collections: usize) {
+        self.read_ahead.store(collections, Ordering::Relaxed);
+    }
+
+    /// Has the worker load `pids` in the background, cold, so that a scan
+    /// about to read them finds them in the pool. Pages already in are
+    /// skipped, and so is any that cannot be loaded: this is only a hint.
+    /// Cold pages share a small ring, so only the first pages that fit in
+    /// half of it are loaded; the other half holds what the scan is reading.
+    pub fn prefetch(&self, mut pids: Vec<PageId>) {
+        pids.truncate(ColdRing::size_for(self.capacity()) / 2);
+        let _ = self.command_tx.send(BufferPoolOp::Prefetch { pids });
+    }
+
+    /// Fails if a table new to the pool could not be given `quota`, its
+    /// reservation leaving too little of the pool to the others.
+    pub fn check_new_quota(&self, quota: TableQuota) -> Result<(), BufferPoolError> {
+        let core = &self.core;
+        let _loading = core.load_lock.lock();
+        core.check_reservations(self.capacity(), Some((usize::MAX, quota.reserved)))
+    }
+
+    /// Gives `table_id` its share of the pool, replacing the one it had;
+    /// `TableQuota::default()` takes it away. A table already over its new
+    /// limit has the excess evicted right away, save for pinned pages.
+    pub fn set_quota(&self, table_id: usize, quota: TableQuota) -> Result<(), BufferPoolError> {
+        let core = &self.core;
+        let _loading = core.load_lock.lock();
+        core.check_reservations(self.capacity(), Some((table_id, quota.reserved)))?;
+        if quota == TableQuota::default() {
+            core.quotas.remove(&table_id);
+        } else {
+            core.quotas.insert(table_id, quota);
+        }
+        if let Some(limit) = quota.limit {
+            while core.frames_of(table_id) > limit {
+                match core.evict_own(table_id) {
+                    Ok(()) => {}
+                    Err(BufferPoolError::AllFramesPinned) => break,
+                    Err(e) => return Err(e),
                 }
-            })
-            .collect();
-        self.flush_pages(pages)?;
-
-        let mut ev = self.eviction_policy.lock();
-        let pids: Vec<PageId> = self.page_table.iter().map(|e| *e.key()).collect();
-        for pid in pids {
-            if let Some((_, fid)) = self.page_table.remove(&pid) {
-                self.frames[fid].release();
-                ev.release_frame(fid)
             }
         }
         Ok(())
     }
 
-    pub fn read(&self, pid: PageId, offset: usize) -> Result<Option<i64>, BufferPoolError> {
-        loop {
-            let fid = self.resolve_or_load(pid)?;
-            let guard = self.frames[fid].inner.read();
-            if guard.pid == Some(pid) { //pathological edge case, loop if the race occurs
-                return Ok(guard.page.read(offset)?);
+    pub fn quota(&self, table_id: usize) -> TableQuota {
+        self.core.quotas.get(&table_id).map(|q| *q).unwrap_or_default()
+    }
+
+    /// Frames holding a page of `table_id`.
+    pub fn table_frames(&self, table_id: usize) -> usize {
+        self.core.frames_of(table_id)
+    }
+
+    pub fn evict_all(&self) -> Result<(), BufferPoolError> {
+        self.flush_all()?;
+
+        let core = &self.core;
+        let _loading = core.load_lock.lock();
+        let fids: Vec<FrameId> = core.page_table.iter().map(|e| *e.value()).collect();
+        for fid in fids {
+            match core.evict_frame(fid) {
+                Ok(()) => core.eviction_policy.release_frame(fid),
+                // Pinned pages were written back too, and stay where they are.
+                Err(BufferPoolError::PagePinned(_)) => {}
+                Err(e) => return Err(e),
             }
         }
+        Ok(())
+    }
+
+    /// Drops `pid` from the pool without writing it back and deletes it
+    /// from storage, for a page nothing is going to read again. Fails if
+    /// it is pinned.
+    pub fn delete_page(&self, pid: PageId) -> Result<(), BufferPoolError> {
+        let core = &self.core;
+        let _loading = core.load_lock.lock();
+        if let Some((_, fid)) = core.page_table.remove(&pid) {
+            let frame = core.frames.read()[fid].clone();
+            if frame.is_pinned() {
+                core.page_table.insert(pid, fid);
+                return Err(BufferPoolError::PagePinned(pid));
+            }
+            frame.release();
+            if let Some(mut frames) = core.table_frames.get_mut(&pid.table_id) {
+                *frames -= 1;
+            }
+            core.eviction_policy.release_frame(fid);
+        }
+        core.storage.delete_page(pid)?;
+        Ok(())
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.core.frames.read().len()
+    }
+
+    /// Grows or shrinks the pool to `capacity` frames. Shrinking writes back
+    /// and drops the pages held by the frames that go; if one of them is
+    /// pinned or a write fails, the pool keeps its old size.
+    pub fn resize(&self, capacity: usize) -> Result<(), BufferPoolError> {
+        let core = &self.core;
+        let _loading = core.load_lock.lock();
+        let current = self.capacity();
+        core.check_reservations(capacity, None)?;
+        if let Some(pid) = core.frames.read().iter().skip(capacity).find(|f| f.is_pinned()).and_then(|f| f.pid()) {
+            return Err(BufferPoolError::PagePinned(pid));
+        }
+        if capacity > current {
+            core.frames
+                .write()
+                .extend((current..capacity).map(|_| Arc::new(Frame::new())));
+        }
+        for fid in capacity..current {
+            if core.frames.read()[fid].pid().is_some() {
+                core.evict_frame(fid)?;
+                core.eviction_policy.release_frame(fid);
+            }
+        }
+        core.eviction_policy.resize(capacity);
+        core.frames.write().truncate(capacity);
+        Ok(())
+    }
+
+    /// Pins the page `pid`, loading it first if it is not in the pool.
+    /// Fails with `AllFramesPinned` if it is not and every frame is pinned.
+    pub fn fetch_page(&self, pid: PageId) -> Result<PageGuard, BufferPoolError> {
+        self.fetch_page_hinted(pid, AccessHint::Normal)
+    }
+
+    /// `fetch_page`, telling the eviction policy how the page will be used.
+    pub fn fetch_page_hinted(&self, pid: PageId, hint: AccessHint) -> Result<PageGuard, BufferPoolError> {
+        let frame = self.core.pin(pid, hint)?;
+        Ok(PageGuard { pid, frame })
+    }
+
+    pub fn read(&self, pid: PageId, offset: usize) -> Result<Option<i64>, BufferPoolError> {
+        self.read_hinted(pid, offset, AccessHint::Normal)
+    }
+
+    pub fn read_hinted(&self, pid: PageId, offset: usize, hint: AccessHint) -> Result<Option<i64>, BufferPoolError> {
+        let page = self.fetch_page_hinted(pid, hint)?;
+        let value = page.read().read(offset)?;
+        Ok(value)
     }
 
     pub fn write(
@@ -140,15 +536,9 @@ impl BufferPool {
         val: Option<i64>,
         offset: usize,
     ) -> Result<(), BufferPoolError> {
-        loop {
-            let fid = self.resolve_or_load(pid)?;
-            let mut guard = self.frames[fid].inner.write();
-            if guard.pid == Some(pid) { //pathological edge case, loop if the race occurs
-                guard.page.write(val, offset)?;
-                self.frames[fid].dirty.store(true, Ordering::Release);
-                return Ok(());
-            }
-        }
+        let page = self.fetch_page(pid)?;
+        page.write().write(val, offset)?;
+        Ok(())
     }
 
     pub fn update(
@@ -157,15 +547,9 @@ impl BufferPool {
         offset: usize,
         val: Option<i64>,
     ) -> Result<(), BufferPoolError> {
-        loop {
-            let fid = self.resolve_or_load(pid)?;
-            let mut guard = self.frames[fid].inner.write();
-            if guard.pid == Some(pid) { //pathological edge case, loop if the race occurs
-                guard.page.update(offset, val)?;
-                self.frames[fid].dirty.store(true, Ordering::Release);
-                return Ok(())
-            }
-        }
+        let page = self.fetch_page(pid)?;
+        page.write().update(offset, val)?;
+        Ok(())
     }
 
     // TODO wire up for transaction
@@ -182,10 +566,11 @@ impl BufferPool {
     //So two channels: one for bp (tx) --> worker (rx) passed on construction and one for worker (tx) -> bp (rx) passed
     // on function call.
     //There may be a better way to do this.
-    pub fn flush_pages(&self, pages: Vec<(PageId, Page)>) -> Result<(), BufferPoolError> {
+    /// Writes back every dirty page, keeping them all in the pool.
+    pub fn flush_all(&self) -> Result<(), BufferPoolError> {
         let (res_tx, res_rx) = mpsc::sync_channel(1);
         self.command_tx
-            .send(BufferPoolOp::FlushPages { pages, res_tx })
+            .send(BufferPoolOp::FlushAll { res_tx })
             .map_err(|_| BufferPoolError::BackgroundWorkerDead)?;
         res_rx
             .recv()
@@ -201,85 +586,219 @@ impl BufferPool {
     //         })
     //         .map_err(|_| BufferPoolError::BackgroundWorkerDead)
     // }
+}
 
+impl PoolCore {
     //Todo: A thought,
-    fn flush_frame(&self, pid: PageId, fid: FrameId) -> Result<(), BufferPoolError> {
-        if self.frames[fid].is_dirty() {
-            let page = self.frames[fid].get_page_copy();
-            self.disk_manager.read().write_page(pid, &page)?;
-            self.frames[fid].clear_dirty();
+    fn flush_frame(&self, fid: FrameId) -> Result<(), BufferPoolError> {
+        let frame = self.frames.read()[fid].clone();
+        if frame.write_back(self.storage.as_ref())? {
+            FlushCounters::bump(&self.counters.victim_writes);
         }
         Ok(())
     }
 
-    fn check_cache_hit(&self, pid: PageId) -> Option<FrameId> {
-        if let Some(entry) = self.page_table.get(&pid) {
-            let fid = *entry;
-            drop(entry);
-            self.eviction_policy.lock().on_access(fid);
-            return Some(fid)
+    /// Unmaps the page in `fid` and writes it back, leaving the frame free.
+    /// The page stays put if it turns out to be pinned or the write fails.
+    /// Callers hold `load_lock`.
+    fn evict_frame(&self, fid: FrameId) -> Result<(), BufferPoolError> {
+        let frame = self.frames.read()[fid].clone();
+        let victim_pid = frame
+            .pid()
+            .ok_or(BufferPoolError::PidNotInFrame)?;
+        self.page_table.remove(&victim_pid);
+        if frame.is_pinned() {
+            // Pinned before the page was unmapped; the guard keeps it.
+            self.page_table.insert(victim_pid, fid);
+            return Err(BufferPoolError::PagePinned(victim_pid));
+        }
+        if let Err(e) = self.flush_frame(fid) {
+            // Keep the victim, still dirty, rather than lose its writes.
+            self.page_table.insert(victim_pid, fid);
+            return Err(e);
         }
-        None
+        frame.release();
+        if let Some(mut frames) = self.table_frames.get_mut(&victim_pid.table_id) {
+            *frames -= 1;
+        }
+        FlushCounters::bump(&self.counters.evictions);
+        Ok(())
     }
 
-    fn check_cache_race(&self, pid: PageId, policy: &mut EvictionPolicy) -> Option<FrameId> {
-        if let Some(entry) = self.page_table.get(&pid) {
-            let fid = *entry;
-            drop(entry);
-            policy.on_access(fid);
-            return Some(fid)
-        }
-        None
+    fn frames_of(&self, table_id: usize) -> usize {
+        self.table_frames.get(&table_id).map_or(0, |frames| *frames)
     }
 
-    fn evict_frame(&self, fid: FrameId) -> Result<(), BufferPoolError> {
-        let victim_pid = self.frames[fid]
-            .pid()
-            .ok_or(BufferPoolError::PidNotInFrame)?;
-        self.page_table.remove(&victim_pid);
-        self.flush_frame(victim_pid, fid)?;
-        self.frames[fid].release();
+    /// Fails if the reservations, with `table_id`'s changed to `reserved`,
+    /// leave too little of a pool of `capacity` frames to the other tables.
+    fn check_reservations(&self, capacity: usize, change: Option<(usize, usize)>) -> Result<(), BufferPoolError> {
+        let others: usize = self
+            .quotas
+            .iter()
+            .filter(|q| change.is_none_or(|(table_id, _)| *q.key() != table_id))
+            .map(|q| q.reserved)
+            .sum();
+        let reserved = others + change.map_or(0, |(_, reserved)| reserved);
+        if capacity.saturating_sub(reserved) < PoolSize::MIN_FRAMES {
+            return Err(BufferPoolError::Overcommitted { reserved, capacity });
+        }
         Ok(())
     }
 
+    /// Tables other than `loading` that are down to their reservation, so
+    /// that a load of `loading` may not evict their pages.
+    fn reserved_tables(&self, loading: usize) -> Vec<usize> {
+        self.quotas
+            .iter()
+            .filter(|q| *q.key() != loading && q.reserved > 0 && self.frames_of(*q.key()) <= q.reserved)
+            .map(|q| *q.key())
+            .collect()
+    }
+
+    /// Evicts one of `table_id`'s pages, leaving its frame free. Callers hold
+    /// `load_lock`.
+    fn evict_own(&self, table_id: usize) -> Result<(), BufferPoolError> {
+        loop {
+            let frames = self.frames.read();
+            let fid = self
+                .eviction_policy
+                .acquire_victim(&|fid| {
+                    frames[fid].is_pinned() || frames[fid].pid().is_none_or(|pid| pid.table_id != table_id)
+                })
+                .ok_or(BufferPoolError::AllFramesPinned)?;
+            drop(frames);
+
+            match self.evict_frame(fid) {
+                Ok(()) => {
+                    self.eviction_policy.release_frame(fid);
+                    return Ok(());
+                }
+                Err(BufferPoolError::PagePinned(_)) => self.eviction_policy.on_insert(fid),
+                Err(e) => {
+                    self.eviction_policy.on_insert(fid);
+                    return Err(e);
+                }
+            }
+        }
+    }
+
     fn read_or_init_page(&self, pid: PageId, fid: FrameId) -> Result<(), BufferPoolError> {
-        if self.disk_manager.read().page_exists(pid) {
-            let page = self.disk_manager.read().read_page(pid)?;
-            self.frames[fid].load(pid, page);
+        let frame = self.frames.read()[fid].clone();
+        if self.storage.page_exists(pid) {
+            frame.load(pid, self.storage.read_page(pid)?);
         } else {
-            self.frames[fid].init(pid);
+            frame.init(pid);
         }
         Ok(())
     }
 
-    fn resolve_or_load(&self, pid: PageId) -> Result<FrameId, BufferPoolError> {
-        if let Some(fid) = self.check_cache_hit(pid) {
-            return Ok(fid);
+    /// The frame holding `pid`, with one more pin on it.
+    fn pin(&self, pid: PageId, hint: AccessHint) -> Result<Arc<Frame>, BufferPoolError> {
+        if let Some(frame) = self.pin_resident(pid, hint) {
+            self.accesses.record(hint, true);
+            return Ok(frame);
         }
-
-        let mut policy = self.eviction_policy.lock();
-
-        if let Some(fid) = self.check_cache_race(pid, &mut policy) {
-            return Ok(fid);
+        let _loading = self.load_lock.lock();
+        // Someone else may have loaded it while we waited.
+        if let Some(frame) = self.pin_resident(pid, hint) {
+            self.accesses.record(hint, true);
+            return Ok(frame);
         }
+        let frame = self.load(pid, hint)?;
+        self.accesses.record(hint, false);
+        Ok(frame)
+    }
 
-        let (fid, was_evicted) = policy
-            .acquire_frame()
-            .ok_or(BufferPoolError::AllFramesPinned)?;
+    /// Pins `pid` if it is in the pool, without taking `load_lock`.
+    fn pin_resident(&self, pid: PageId, hint: AccessHint) -> Option<Arc<Frame>> {
+        let fid = *self.page_table.get(&pid)?;
+        let frame = self.frames.read().get(fid)?.clone();
+        frame.pins.fetch_add(1, Ordering::SeqCst);
+        // An eviction that unmapped the page before we pinned it may go on
+        // without seeing the pin, so the page has to still be mapped here.
+        let still_here = self.page_table.get(&pid).is_some_and(|e| *e == fid)
+            && self.frames.read().get(fid).is_some_and(|f| Arc::ptr_eq(f, &frame));
+        if !still_here {
+            frame.pins.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+        if hint == AccessHint::Normal {
+            self.eviction_policy.on_access(fid);
+        }
+        if frame.prefetched.load(Ordering::Relaxed) && frame.prefetched.swap(false, Ordering::Relaxed) {
+            self.accesses.prefetch_hits.fetch_add(1, Ordering::Relaxed);
+        }
+        Some(frame)
+    }
 
-        // DO NOT drop(policy) here!
+    /// Loads `pid` cold and unpinned, unless it is in the pool already.
+    pub(crate) fn prefetch(&self, pid: PageId) {
+        if self.page_table.contains_key(&pid) {
+            return;
+        }
+        let _loading = self.load_lock.lock();
+        if self.page_table.contains_key(&pid) {
+            return;
+        }
+        if let Ok(frame) = self.load(pid, AccessHint::Sequential) {
+            frame.prefetched.store(true, Ordering::Relaxed);
+            frame.pins.fetch_sub(1, Ordering::SeqCst);
+            self.accesses.prefetched.fetch_add(1, Ordering::Relaxed);
+        }
+    }
 
-        if was_evicted {
-            self.evict_frame(fid)?;
+    /// Loads `pid` into a frame and pins it. Callers hold `load_lock`.
+    fn load(&self, pid: PageId, hint: AccessHint) -> Result<Arc<Frame>, BufferPoolError> {
+        let limit = self.quotas.get(&pid.table_id).and_then(|q| q.limit);
+        if limit.is_some_and(|limit| self.frames_of(pid.table_id) >= limit) {
+            // The frame this frees is the next one handed out.
+            self.evict_own(pid.table_id)?;
         }
+        let reserved = self.reserved_tables(pid.table_id);
+        let fid = loop {
+            let frames = self.frames.read();
+            let (fid, was_evicted) = self
+                .eviction_policy
+                .acquire_frame(&|fid| {
+                    frames[fid].is_pinned()
+                        || (!reserved.is_empty()
+                            && frames[fid].pid().is_some_and(|pid| reserved.contains(&pid.table_id)))
+                })
+                .ok_or(BufferPoolError::AllFramesPinned)?;
+            drop(frames);
+
+            if was_evicted {
+                match self.evict_frame(fid) {
+                    Ok(()) => {}
+                    // Pinned by a hit since the policy chose it; pick again.
+                    Err(BufferPoolError::PagePinned(_)) => {
+                        self.eviction_policy.on_insert(fid);
+                        continue;
+                    }
+                    Err(e) => {
+                        self.eviction_policy.on_insert(fid);
+                        return Err(e);
+                    }
+                }
+            }
+            break fid;
+        };
 
-        self.read_or_init_page(pid, fid)?;
+        if let Err(e) = self.read_or_init_page(pid, fid) {
+            self.frames.read()[fid].release();
+            self.eviction_policy.release_frame(fid);
+            return Err(e);
+        }
 
+        let frame = self.frames.read()[fid].clone();
+        frame.pins.fetch_add(1, Ordering::SeqCst);
         self.page_table.insert(pid, fid);
-        policy.on_insert(fid);
-        drop(policy);  // release lock AFTER page_table is updated
-
-        Ok(fid)
+        *self.table_frames.entry(pid.table_id).or_default() += 1;
+        match hint {
+            AccessHint::Normal => self.eviction_policy.on_insert(fid),
+            AccessHint::Sequential => self.eviction_policy.on_insert_cold(fid),
+        }
+        Ok(frame)
     }
 }
 
@@ -1,40 +1,68 @@
-use std::sync::mpsc::Receiver;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
 use std::sync::{mpsc, Arc};
-use parking_lot::RwLock;
-use crate::disk_manager::DiskManager;
+use crate::bufferpool::bufferpool::{FlushCounters, PoolCore};
 use crate::bufferpool::errors::BufferPoolError;
-use crate::page::Page;
+use crate::config::FlusherConfig;
 use crate::page_collection::PageId;
 
 pub enum BufferPoolOp {
-    FlushPages {
-        pages: Vec<(PageId, Page)>,
+    /// Writes back every dirty frame.
+    FlushAll {
         res_tx: mpsc::SyncSender<Result<(), BufferPoolError>>,
     },
 
+    /// Loads pages ahead of a scan; nobody waits for it.
+    Prefetch {
+        pids: Vec<PageId>,
+    },
+
     Shutdown,
 }
 
+/// The pool's writer thread. It runs the pool's `FlushAll` commands and, with
+/// a `FlusherConfig`, keeps the frames that are about to be evicted clean in
+/// between.
 pub struct BufferPoolWorker {
     cmd_rx: Receiver<BufferPoolOp>,
-    disk_manager: Arc<RwLock<DiskManager>>,
+    core: Arc<PoolCore>,
+    flusher: Option<FlusherConfig>,
 }
 
 impl BufferPoolWorker {
-    pub fn new(receiver: Receiver<BufferPoolOp>, disk_manager: Arc<RwLock<DiskManager>>) -> Self {
+    pub(crate) fn new(receiver: Receiver<BufferPoolOp>, core: Arc<PoolCore>, flusher: Option<FlusherConfig>) -> Self {
         Self {
             cmd_rx: receiver,
-            disk_manager,
+            core,
+            flusher,
         }
     }
 
     pub(crate) fn run(self) {
-        while let Ok(op) = self.cmd_rx.recv() {
+        loop {
+            let op = match self.flusher {
+                Some(flusher) => match self.cmd_rx.recv_timeout(flusher.interval) {
+                    Ok(op) => op,
+                    Err(RecvTimeoutError::Timeout) => {
+                        self.flush_victims(flusher);
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => break,
+                },
+                None => match self.cmd_rx.recv() {
+                    Ok(op) => op,
+                    Err(_) => break,
+                },
+            };
             match op {
-                BufferPoolOp::FlushPages { pages, res_tx } => {
-                    let result = self.handle_evict(pages);
+                BufferPoolOp::FlushAll { res_tx } => {
+                    let result = self.flush_all();
                     res_tx.send(result).unwrap();
                 }
+                BufferPoolOp::Prefetch { pids } => {
+                    for pid in pids {
+                        self.core.prefetch(pid);
+                    }
+                }
                 BufferPoolOp::Shutdown => {
                     break;
                 }
@@ -42,10 +70,38 @@ impl BufferPoolWorker {
         }
     }
 
-    fn handle_evict(&self, pages: Vec<(PageId, Page)>) -> Result<(), BufferPoolError> {
-        for (pid, page) in pages {
-            self.disk_manager.read().write_page(pid, &page)?;
+    fn flush_all(&self) -> Result<(), BufferPoolError> {
+        // A copy, so the pool can resize while pages are being written.
+        let frames = self.core.frames.read().clone();
+        for frame in frames {
+            if frame.write_back(self.core.storage.as_ref())? {
+                FlushCounters::bump(&self.core.counters.flush_all_writes);
+            }
         }
         Ok(())
     }
+
+    /// Writes back the dirty pages among the next victims. A page that fails
+    /// stays dirty, for the next round or the eviction to try again.
+    fn flush_victims(&self, flusher: FlusherConfig) {
+        let victims = self.core.eviction_policy.next_victims(flusher.clean_frames);
+        let frames = self.core.frames.read().clone();
+        let mut written = 0;
+        for fid in victims {
+            if written == flusher.max_pages {
+                break;
+            }
+            let Some(frame) = frames.get(fid) else {
+                continue; // the pool shrank in between
+            };
+            match frame.write_back(self.core.storage.as_ref()) {
+                Ok(true) => {
+                    written += 1;
+                    FlushCounters::bump(&self.core.counters.background_writes);
+                }
+                Ok(false) => {}
+                Err(_) => FlushCounters::bump(&self.core.counters.background_failures),
+            }
+        }
+    }
 }
@@ -0,0 +1,145 @@
+use parking_lot::{Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::bufferpool::bufferpool::FrameId;
+use crate::bufferpool::eviction_policy::{ColdRing, Eviction};
+
+/// Second chance: a hand sweeps the frames and evicts the first one not used
+/// since the hand last passed it. A hit only sets the frame's reference bit,
+/// without taking any lock the loads take.
+pub struct ClockPolicy {
+    referenced: RwLock<Vec<AtomicBool>>,
+    hand: Mutex<Hand>,
+}
+
+struct Hand {
+    position: FrameId,
+    /// Frames holding a page that has not been picked as a victim since.
+    resident: Vec<bool>,
+    free_list: Vec<FrameId>,
+    /// Frames loaded cold. The hand passes over them; they are reused in
+    /// the order they were loaded.
+    cold: ColdRing,
+}
+
+impl ClockPolicy {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            referenced: RwLock::new((0..capacity).map(|_| AtomicBool::new(false)).collect()),
+            hand: Mutex::new(Hand {
+                position: 0,
+                resident: vec![false; capacity],
+                free_list: (0..capacity).collect(),
+                cold: ColdRing::new(capacity),
+            }),
+        }
+    }
+}
+
+impl ClockPolicy {
+    fn sweep(&self, hand: &mut Hand, pinned: &dyn Fn(FrameId) -> bool) -> Option<FrameId> {
+        let referenced = self.referenced.read();
+        // A page in the ring that was hit since is kept, and swept like any
+        // other from then on.
+        let used = |fid: FrameId| referenced[fid].load(Ordering::Relaxed);
+        if hand.cold.is_full() && let Some(fid) = hand.cold.pop(pinned, &used) {
+            hand.resident[fid] = false;
+            return Some(fid);
+        }
+        let capacity = hand.resident.len();
+        // The first pass may only clear reference bits; by the end of the
+        // second, every frame that can go has been offered.
+        for _ in 0..2 * capacity {
+            let fid = hand.position;
+            hand.position = (fid + 1) % capacity;
+            if !hand.resident[fid] || pinned(fid) || hand.cold.contains(fid) {
+                continue;
+            }
+            if !referenced[fid].swap(false, Ordering::Relaxed) {
+                hand.resident[fid] = false;
+                return Some(fid);
+            }
+        }
+        // Every page left is cold, or pinned.
+        let fid = hand.cold.pop(pinned, &|_| false)?;
+        hand.resident[fid] = false;
+        Some(fid)
+    }
+}
+
+impl Eviction for ClockPolicy {
+    fn acquire_frame(&self, pinned: &dyn Fn(FrameId) -> bool) -> Option<(FrameId, bool)> {
+        let mut hand = self.hand.lock();
+        if let Some(fid) = hand.free_list.pop() {
+            return Some((fid, false));
+        }
+        self.sweep(&mut hand, pinned).map(|fid| (fid, true))
+    }
+
+    fn acquire_victim(&self, pinned: &dyn Fn(FrameId) -> bool) -> Option<FrameId> {
+        self.sweep(&mut self.hand.lock(), pinned)
+    }
+
+    fn on_insert(&self, fid: FrameId) {
+        // A page is not referenced until it is used a second time, so a
+        // scan that touches every page once does not flush the hot ones out.
+        self.referenced.read()[fid].store(false, Ordering::Relaxed);
+        self.hand.lock().resident[fid] = true;
+    }
+
+    fn on_insert_cold(&self, fid: FrameId) {
+        self.referenced.read()[fid].store(false, Ordering::Relaxed);
+        let mut hand = self.hand.lock();
+        hand.resident[fid] = true;
+        hand.cold.push(fid);
+    }
+
+    fn on_access(&self, fid: FrameId) {
+        if let Some(bit) = self.referenced.read().get(fid) {
+            bit.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn release_frame(&self, fid: FrameId) {
+        let mut hand = self.hand.lock();
+        hand.resident[fid] = false;
+        hand.cold.remove(fid);
+        hand.free_list.push(fid);
+    }
+
+    fn resize(&self, capacity: usize) {
+        let mut hand = self.hand.lock();
+        let mut referenced = self.referenced.write();
+        let current = hand.resident.len();
+        debug_assert!(hand.resident.iter().skip(capacity).all(|r| !r));
+        if capacity > current {
+            referenced.extend((current..capacity).map(|_| AtomicBool::new(false)));
+            hand.free_list.extend(current..capacity);
+        } else {
+            referenced.truncate(capacity);
+            hand.free_list.retain(|fid| *fid < capacity);
+        }
+        hand.resident.resize(capacity, false);
+        hand.cold.resize(capacity);
+        if hand.position >= capacity {
+            hand.position = 0;
+        }
+    }
+
+    fn next_victims(&self, n: usize) -> Vec<FrameId> {
+        let hand = self.hand.lock();
+        let referenced = self.referenced.read();
+        let capacity = hand.resident.len();
+        let sweep = (0..capacity)
+            .map(|i| (hand.position + i) % capacity)
+            .filter(|fid| hand.resident[*fid] && !hand.cold.contains(*fid));
+        // Unreferenced frames go on the first pass, the rest on the second.
+        let (first, second): (Vec<FrameId>, Vec<FrameId>) =
+            sweep.partition(|fid| !referenced[*fid].load(Ordering::Relaxed));
+        hand.cold
+            .next_victims()
+            .chain(first)
+            .chain(second)
+            .take(n.saturating_sub(hand.free_list.len()))
+            .collect()
+    }
+}
@@ -1,5 +1,7 @@
+use crate::config::PoolSize;
 use crate::page::PageError;
 use crate::page_collection::PageId;
+use std::path::PathBuf;
 
 #[derive(Debug)]
 pub enum BufferPoolError {
@@ -15,7 +17,13 @@ pub enum BufferPoolError {
 
     AllFramesPinned,
 
+    PagePinned(PageId),
+
     PidNotInFrame,
+
+    /// Table reservations would leave fewer than `PoolSize::MIN_FRAMES` frames
+    /// for everything else.
+    Overcommitted { reserved: usize, capacity: usize },
 }
 
 impl std::fmt::Display for BufferPoolError {
@@ -27,7 +35,15 @@ impl std::fmt::Display for BufferPoolError {
             BufferPoolError::Page(e) => write!(f, "Page error: {:?}", e),
             BufferPoolError::BackgroundWorkerDead => write!(f, "Background worker thread has died"),
             BufferPoolError::AllFramesPinned => write!(f, "Every frame is pinned"),
+            BufferPoolError::PagePinned(pid) => write!(f, "Page {:?} is pinned", pid),
             BufferPoolError::PidNotInFrame => write!(f, "Pid "),
+            BufferPoolError::Overcommitted { reserved, capacity } => write!(
+                f,
+                "{} of the buffer pool's {} frames are reserved for tables, leaving fewer than {} for the rest",
+                reserved,
+                capacity,
+                PoolSize::MIN_FRAMES
+            ),
         }
     }
 }
@@ -51,6 +67,32 @@ pub enum DiskError {
     SerializationError,
     CorruptedPage(String),
     PageError(PageError),
+    ChecksumMismatch(Corruption),
+}
+
+/// A page or metadata file whose contents do not match their checksum.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Corruption {
+    /// `None` for files that belong to the whole database.
+    pub table_id: Option<usize>,
+    /// `None` for metadata files.
+    pub page_num: Option<usize>,
+    pub file: PathBuf,
+    pub expected: u32,
+    pub actual: u32,
+}
+
+impl std::fmt::Display for Corruption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "checksum mismatch in {}", self.file.display())?;
+        if let Some(table_id) = self.table_id {
+            write!(f, ", table_id={}", table_id)?;
+        }
+        if let Some(page_num) = self.page_num {
+            write!(f, ", page_num={}", page_num)?;
+        }
+        write!(f, ": expected {:#010x}, found {:#010x}", self.expected, self.actual)
+    }
 }
 
 impl std::fmt::Display for DiskError {
@@ -64,6 +106,7 @@ impl std::fmt::Display for DiskError {
             DiskError::SerializationError => write!(f, "Serialization error"),
             DiskError::CorruptedPage(msg) => write!(f, "Corrupted page: {}", msg),
             DiskError::PageError(e) => write!(f, "Page error: {:?}", e),
+            DiskError::ChecksumMismatch(c) => write!(f, "Corrupted data: {}", c),
         }
     }
 }
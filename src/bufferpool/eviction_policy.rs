@@ -1,28 +1,212 @@
 use lru::LruCache;
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::sync::Arc;
 use crate::bufferpool::bufferpool::FrameId;
+use crate::bufferpool::clock_policy::ClockPolicy;
+use crate::bufferpool::lru_k_policy::LruKPolicy;
+use crate::config::EvictionKind;
 
-pub struct EvictionPolicy {
+/// Decides which frame each page goes into, and so which page makes room.
+///
+/// The pool makes one call at a time, except for `on_access` and
+/// `next_victims`, which come from any thread at any moment; a policy makes
+/// those as cheap as it can.
+pub trait Eviction: Send + Sync {
+    /// A free frame, or else a victim that `keep` lets go, and whether it
+    /// holds a page that has to be evicted first. `keep` holds on to pinned
+    /// frames and to those a table's reservation needs. `None` if it holds on
+    /// to every frame.
+    fn acquire_frame(&self, keep: &dyn Fn(FrameId) -> bool) -> Option<(FrameId, bool)>;
+
+    /// Like `acquire_frame`, but a victim even while frames are free, for a
+    /// table that has to make room among its own pages.
+    fn acquire_victim(&self, keep: &dyn Fn(FrameId) -> bool) -> Option<FrameId>;
+
+    /// `fid` holds a page now: a newly loaded one, or the victim it held
+    /// before, kept because its eviction failed.
+    fn on_insert(&self, fid: FrameId);
+
+    /// `fid` holds a newly loaded page that is not expected to be used again,
+    /// such as one a scan passes over. It goes into the policy's `ColdRing`
+    /// rather than among the pages that are used over and over.
+    fn on_insert_cold(&self, fid: FrameId);
+
+    /// The page in `fid` was used again.
+    fn on_access(&self, fid: FrameId);
+
+    /// `fid` no longer holds a page.
+    fn release_frame(&self, fid: FrameId);
+
+    /// Changes the number of frames to `capacity`. Frames at or past a
+    /// smaller capacity must have been released first.
+    fn resize(&self, capacity: usize);
+
+    /// The frames the next `n` loads would evict, first victim first. Free
+    /// frames are used up before anything is evicted, so they count towards
+    /// `n`.
+    fn next_victims(&self, n: usize) -> Vec<FrameId>;
+
+    /// How many frames the policy aims to give pages seen only once, for
+    /// policies that adapt it as they go.
+    fn recency_target(&self) -> Option<usize> {
+        None
+    }
+}
+
+impl EvictionKind {
+    pub(crate) fn build(self, capacity: usize) -> Arc<dyn Eviction> {
+        match self {
+            EvictionKind::Arc => Arc::new(ArcPolicy::new(capacity)),
+            EvictionKind::Clock => Arc::new(ClockPolicy::new(capacity)),
+            EvictionKind::LruK(k) => Arc::new(LruKPolicy::new(capacity, k)),
+        }
+    }
+}
+
+/// The frames holding pages loaded cold, oldest first. Once there are `size`
+/// of them, every further cold load reuses the oldest, so a scan cycles
+/// through a few frames of its own instead of pushing out the rest of the
+/// pool. A page in the ring that gets used normally leaves it.
+pub(crate) struct ColdRing {
+    frames: VecDeque<FrameId>,
+    size: usize,
+}
+
+impl ColdRing {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            frames: VecDeque::new(),
+            size: Self::size_for(capacity),
+        }
+    }
+
+    /// An eighth of the pool, but enough for the few pages a scan reads
+    /// together.
+    pub(crate) fn size_for(capacity: usize) -> usize {
+        (capacity / 8).max(4)
+    }
+
+    pub(crate) fn push(&mut self, fid: FrameId) {
+        self.frames.push_back(fid);
+    }
+
+    /// Takes `fid` out of the ring, saying whether it was in it.
+    pub(crate) fn remove(&mut self, fid: FrameId) -> bool {
+        let before = self.frames.len();
+        self.frames.retain(|f| *f != fid);
+        self.frames.len() != before
+    }
+
+    pub(crate) fn contains(&self, fid: FrameId) -> bool {
+        self.frames.contains(&fid)
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.frames.len() >= self.size
+    }
+
+    /// Removes and returns the oldest frame that is not pinned. Frames that
+    /// `used` says were used since they were loaded leave the ring on the way.
+    pub(crate) fn pop(&mut self, pinned: &dyn Fn(FrameId) -> bool, used: &dyn Fn(FrameId) -> bool) -> Option<FrameId> {
+        self.frames.retain(|fid| !used(*fid));
+        let at = self.frames.iter().position(|fid| !pinned(*fid))?;
+        self.frames.remove(at)
+    }
+
+    /// The frames the next cold loads would reuse, or none while the ring is
+    /// still filling up.
+    pub(crate) fn next_victims(&self) -> impl Iterator<Item = FrameId> + '_ {
+        let reused = if self.is_full() { self.frames.len() } else { 0 };
+        self.frames.iter().copied().take(reused)
+    }
+
+    pub(crate) fn resize(&mut self, capacity: usize) {
+        self.frames.retain(|fid| *fid < capacity);
+        self.size = Self::size_for(capacity);
+    }
+}
+
+/// Adaptive replacement: recency and frequency lists whose split adapts to
+/// the ghost hits of pages evicted from either. Every call, hits included,
+/// takes one lock.
+pub struct ArcPolicy {
+    lists: Mutex<ArcLists>,
+}
+
+impl ArcPolicy {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            lists: Mutex::new(ArcLists::new(capacity)),
+        }
+    }
+}
+
+impl Eviction for ArcPolicy {
+    fn acquire_frame(&self, pinned: &dyn Fn(FrameId) -> bool) -> Option<(FrameId, bool)> {
+        self.lists.lock().acquire_frame(pinned)
+    }
+
+    fn acquire_victim(&self, pinned: &dyn Fn(FrameId) -> bool) -> Option<FrameId> {
+        self.lists.lock().acquire_victim(pinned)
+    }
+
+    fn on_insert(&self, fid: FrameId) {
+        self.lists.lock().on_insert(fid)
+    }
+
+    fn on_insert_cold(&self, fid: FrameId) {
+        self.lists.lock().on_insert_cold(fid)
+    }
+
+    fn on_access(&self, fid: FrameId) {
+        self.lists.lock().on_access(fid)
+    }
+
+    fn release_frame(&self, fid: FrameId) {
+        self.lists.lock().release_frame(fid)
+    }
+
+    fn resize(&self, capacity: usize) {
+        self.lists.lock().resize(capacity)
+    }
+
+    fn next_victims(&self, n: usize) -> Vec<FrameId> {
+        self.lists.lock().next_victims(n)
+    }
+
+    /// ARC's `p`, the share of the frames it aims to give `t1`.
+    fn recency_target(&self) -> Option<usize> {
+        Some(self.lists.lock().p)
+    }
+}
+
+struct ArcLists {
     t1: LruCache<FrameId, ()>,
     t2: LruCache<FrameId, ()>,
     b1: LruCache<FrameId, ()>,
     b2: LruCache<FrameId, ()>,
+    /// Pages loaded cold. They are in neither `t1` nor `t2`, leave no ghosts
+    /// and so never move `p`.
+    cold: ColdRing,
     p: usize,
     capacity: usize,
     ghost_cap: usize,
     free_list: Vec<FrameId>,
 }
 
-impl EvictionPolicy {
-    pub fn new(capacity: usize) -> Self {
+impl ArcLists {
+    fn new(capacity: usize) -> Self {
         Self::with_ghost_cap(capacity, capacity)
     }
 
-    pub fn with_ghost_cap(capacity: usize, ghost_cap: usize) -> Self {
+    fn with_ghost_cap(capacity: usize, ghost_cap: usize) -> Self {
         Self {
             t1: LruCache::unbounded(),
             t2: LruCache::unbounded(),
             b1: LruCache::unbounded(),
             b2: LruCache::unbounded(),
+            cold: ColdRing::new(capacity),
             p: 0,
             capacity,
             ghost_cap,
@@ -30,34 +214,74 @@ impl EvictionPolicy {
         }
     }
 
-    pub fn acquire_frame(&mut self) -> Option<(FrameId, bool)> {
+    fn acquire_frame(&mut self, pinned: &dyn Fn(FrameId) -> bool) -> Option<(FrameId, bool)> {
         if let Some(fid) = self.free_list.pop() {
             return Some((fid, false));
         }
-        let victim = self.evict_victim()?;
-        Some((victim, true))
+        self.acquire_victim(pinned).map(|fid| (fid, true))
+    }
+
+    fn acquire_victim(&mut self, pinned: &dyn Fn(FrameId) -> bool) -> Option<FrameId> {
+        if self.cold.is_full() && let Some(fid) = self.cold.pop(pinned, &|_| false) {
+            return Some(fid);
+        }
+        // Until the ring is full, or if every page left is cold.
+        self.evict_victim(pinned).or_else(|| self.cold.pop(pinned, &|_| false))
     }
 
-    pub fn release_frame(&mut self, fid: FrameId) {
+    fn release_frame(&mut self, fid: FrameId) {
         self.t1.pop(&fid);
         self.t2.pop(&fid);
+        self.cold.remove(fid);
         self.free_list.push(fid);
     }
 
-    fn replace(&mut self, prefer_t2: bool) -> Option<FrameId> {
+    fn next_victims(&self, n: usize) -> Vec<FrameId> {
+        let (first, second) = if self.t1.len() > self.p {
+            (&self.t1, &self.t2)
+        } else {
+            (&self.t2, &self.t1)
+        };
+        self.cold
+            .next_victims()
+            .chain(first.iter().rev().map(|(fid, _)| *fid))
+            .chain(second.iter().rev().map(|(fid, _)| *fid))
+            .take(n.saturating_sub(self.free_list.len()))
+            .collect()
+    }
+
+    fn resize(&mut self, capacity: usize) {
+        debug_assert!(self.t1.iter().chain(self.t2.iter()).all(|(fid, _)| *fid < capacity));
+        self.cold.resize(capacity);
+        if capacity > self.capacity {
+            self.free_list.extend(self.capacity..capacity);
+        } else {
+            self.free_list.retain(|fid| *fid < capacity);
+            for ghosts in [&mut self.b1, &mut self.b2] {
+                let gone: Vec<FrameId> = ghosts.iter().map(|(fid, _)| *fid).filter(|fid| *fid >= capacity).collect();
+                for fid in gone {
+                    ghosts.pop(&fid);
+                }
+            }
+        }
+        if self.ghost_cap == self.capacity {
+            self.ghost_cap = capacity;
+        }
+        self.capacity = capacity;
+        self.p = self.p.min(capacity);
+    }
+
+    fn replace(&mut self, prefer_t2: bool, pinned: &dyn Fn(FrameId) -> bool) -> Option<FrameId> {
         let evict_t1 = !self.t1.is_empty()
             && (self.t1.len() > self.p || (self.t1.len() == self.p && !prefer_t2));
 
-        if evict_t1 {
-            let (victim, _) = self.t1.pop_lru().unwrap();
+        if evict_t1 && let Some(victim) = Self::pop_unpinned(&mut self.t1, pinned) {
             self.push_b1(victim);
             Some(victim)
-        } else if !self.t2.is_empty() {
-            let (victim, _) = self.t2.pop_lru().unwrap();
+        } else if let Some(victim) = Self::pop_unpinned(&mut self.t2, pinned) {
             self.push_b2(victim);
             Some(victim)
-        } else if !self.t1.is_empty() {
-            let (victim, _) = self.t1.pop_lru().unwrap();
+        } else if let Some(victim) = Self::pop_unpinned(&mut self.t1, pinned) {
             self.push_b1(victim);
             Some(victim)
         } else {
@@ -65,6 +289,14 @@ impl EvictionPolicy {
         }
     }
 
+    /// Removes and returns the least recently used frame of `list` that is
+    /// not pinned.
+    fn pop_unpinned(list: &mut LruCache<FrameId, ()>, pinned: &dyn Fn(FrameId) -> bool) -> Option<FrameId> {
+        let victim = list.iter().rev().map(|(fid, _)| *fid).find(|fid| !pinned(*fid))?;
+        list.pop(&victim);
+        Some(victim)
+    }
+
     fn push_b1(&mut self, fid: FrameId) {
         if self.b1.len() >= self.ghost_cap {
             self.b1.pop_lru();
@@ -95,15 +327,18 @@ impl EvictionPolicy {
         }
     }
 
-    pub(crate) fn on_access(&mut self, fid: FrameId) {
-        if self.t1.pop(&fid).is_some() {
+    fn on_access(&mut self, fid: FrameId) {
+        // Used again after all: from here on it is like any other page.
+        if self.cold.remove(fid) {
+            self.t1.push(fid, ());
+        } else if self.t1.pop(&fid).is_some() {
             self.t2.push(fid, ());
         } else if self.t2.contains(&fid) {
             self.t2.promote(&fid);
         }
     }
 
-    pub(crate) fn on_insert(&mut self, fid: FrameId) {
+    fn on_insert(&mut self, fid: FrameId) {
         if self.b1.contains(&fid) {
             let delta = if !self.b1.is_empty() {
                 (self.b2.len() / self.b1.len()).max(1)
@@ -130,19 +365,24 @@ impl EvictionPolicy {
         self.t1.push(fid, ());
     }
 
-    pub(crate) fn evict_victim(&mut self) -> Option<FrameId> {
-        let cache_size = self.t1.len() + self.t2.len();
-        if cache_size < self.capacity {
+    fn on_insert_cold(&mut self, fid: FrameId) {
+        self.remove_b1(fid);
+        self.remove_b2(fid);
+        self.cold.push(fid);
+    }
+
+    fn evict_victim(&mut self, pinned: &dyn Fn(FrameId) -> bool) -> Option<FrameId> {
+        if self.t1.is_empty() && self.t2.is_empty() {
             return None;
         }
         if self.t1.len() < self.capacity {
-            let victim = self.replace(false);
+            let victim = self.replace(false, pinned);
             if self.b1.len() + self.b2.len() >= self.ghost_cap {
                 self.trim_ghosts();
             }
             victim
         } else {
-            let (victim, _) = self.t1.pop_lru()?;
+            let victim = Self::pop_unpinned(&mut self.t1, pinned)?;
             self.push_b1(victim);
             Some(victim)
         }
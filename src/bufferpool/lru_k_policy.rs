@@ -0,0 +1,157 @@
+use parking_lot::{Mutex, RwLock};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::bufferpool::bufferpool::FrameId;
+use crate::bufferpool::eviction_policy::{ColdRing, Eviction};
+
+/// LRU-K: evicts the page whose `k`-th most recent use lies furthest back.
+/// Pages used fewer than `k` times go first, least recently used first, so
+/// one pass over a table cannot push out pages that are used over and over.
+///
+/// Histories belong to frames and start over when a frame gets a new page.
+/// Picking a victim looks at every frame.
+pub struct LruKPolicy {
+    k: usize,
+    now: AtomicU64,
+    /// The times of up to the last `k` uses of each frame's page, oldest first.
+    history: RwLock<Vec<Mutex<VecDeque<u64>>>>,
+    frames: Mutex<Frames>,
+}
+
+struct Frames {
+    /// Frames holding a page that has not been picked as a victim since.
+    resident: Vec<bool>,
+    free_list: Vec<FrameId>,
+    /// Frames loaded cold, which only the ring reuses.
+    cold: ColdRing,
+}
+
+impl LruKPolicy {
+    pub fn new(capacity: usize, k: usize) -> Self {
+        Self {
+            k,
+            now: AtomicU64::new(0),
+            history: RwLock::new((0..capacity).map(|_| Mutex::new(VecDeque::with_capacity(k))).collect()),
+            frames: Mutex::new(Frames {
+                resident: vec![false; capacity],
+                free_list: (0..capacity).collect(),
+                cold: ColdRing::new(capacity),
+            }),
+        }
+    }
+
+    fn touch(&self, fid: FrameId, new_page: bool) {
+        let now = self.now.fetch_add(1, Ordering::Relaxed);
+        if let Some(history) = self.history.read().get(fid) {
+            let mut history = history.lock();
+            if new_page {
+                history.clear();
+            }
+            if history.len() == self.k {
+                history.pop_front();
+            }
+            history.push_back(now);
+        }
+    }
+
+    /// Orders frames by how soon they should go: lowest first.
+    fn eviction_key(&self, history: &VecDeque<u64>) -> (bool, u64) {
+        match history.len() >= self.k {
+            true => (true, history[0]),
+            false => (false, history.back().copied().unwrap_or(0)),
+        }
+    }
+
+    /// Resident frames in the order they would go.
+    fn ranked(&self, frames: &Frames) -> Vec<FrameId> {
+        let history = self.history.read();
+        let mut ranked: Vec<((bool, u64), FrameId)> = (0..frames.resident.len())
+            .filter(|fid| frames.resident[*fid] && !frames.cold.contains(*fid))
+            .map(|fid| (self.eviction_key(&history[fid].lock()), fid))
+            .collect();
+        ranked.sort_unstable();
+        frames.cold.next_victims().chain(ranked.into_iter().map(|(_, fid)| fid)).collect()
+    }
+
+    fn victim(&self, frames: &mut Frames, pinned: &dyn Fn(FrameId) -> bool) -> Option<FrameId> {
+        let history = self.history.read();
+        // A page in the ring that was hit since has a history, and is kept.
+        let used = |fid: FrameId| !history[fid].lock().is_empty();
+        let victim = match frames.cold.is_full() {
+            true => frames.cold.pop(pinned, &used),
+            false => None,
+        };
+        let victim = victim
+            .or_else(|| {
+                (0..frames.resident.len())
+                    .filter(|fid| frames.resident[*fid] && !pinned(*fid) && !frames.cold.contains(*fid))
+                    .min_by_key(|fid| self.eviction_key(&history[*fid].lock()))
+            })
+            .or_else(|| frames.cold.pop(pinned, &|_| false))?;
+        frames.resident[victim] = false;
+        Some(victim)
+    }
+}
+
+impl Eviction for LruKPolicy {
+    fn acquire_frame(&self, pinned: &dyn Fn(FrameId) -> bool) -> Option<(FrameId, bool)> {
+        let mut frames = self.frames.lock();
+        if let Some(fid) = frames.free_list.pop() {
+            return Some((fid, false));
+        }
+        self.victim(&mut frames, pinned).map(|fid| (fid, true))
+    }
+
+    fn acquire_victim(&self, pinned: &dyn Fn(FrameId) -> bool) -> Option<FrameId> {
+        self.victim(&mut self.frames.lock(), pinned)
+    }
+
+    fn on_insert(&self, fid: FrameId) {
+        self.frames.lock().resident[fid] = true;
+        self.touch(fid, true);
+    }
+
+    fn on_insert_cold(&self, fid: FrameId) {
+        let mut frames = self.frames.lock();
+        frames.resident[fid] = true;
+        frames.cold.push(fid);
+        // Its history starts with its first hit, if it gets one.
+        if let Some(history) = self.history.read().get(fid) {
+            history.lock().clear();
+        }
+    }
+
+    fn on_access(&self, fid: FrameId) {
+        self.touch(fid, false);
+    }
+
+    fn release_frame(&self, fid: FrameId) {
+        let mut frames = self.frames.lock();
+        frames.resident[fid] = false;
+        frames.cold.remove(fid);
+        frames.free_list.push(fid);
+    }
+
+    fn resize(&self, capacity: usize) {
+        let mut frames = self.frames.lock();
+        let mut history = self.history.write();
+        let current = frames.resident.len();
+        debug_assert!(frames.resident.iter().skip(capacity).all(|r| !r));
+        if capacity > current {
+            history.extend((current..capacity).map(|_| Mutex::new(VecDeque::with_capacity(self.k))));
+            frames.free_list.extend(current..capacity);
+        } else {
+            history.truncate(capacity);
+            frames.free_list.retain(|fid| *fid < capacity);
+        }
+        frames.resident.resize(capacity, false);
+        frames.cold.resize(capacity);
+    }
+
+    fn next_victims(&self, n: usize) -> Vec<FrameId> {
+        let frames = self.frames.lock();
+        let mut ranked = self.ranked(&frames);
+        ranked.truncate(n.saturating_sub(frames.free_list.len()));
+        ranked
+    }
+}
@@ -1,9 +1,12 @@
 mod bufferpool_worker;
 mod eviction_policy;
+mod clock_policy;
+mod lru_k_policy;
+#[allow(clippy::module_inception)]
 mod bufferpool;
 mod errors;
 
 #[allow(unused_imports)]
-pub use bufferpool::{BufferPool, BP_CAP};
+pub use bufferpool::{AccessHint, AccessStats, BufferPool, FlushStats, PageGuard, PoolStats, BP_CAP};
 pub use crate::disk_manager::DiskManager;
 pub use errors::*;
\ No newline at end of file
@@ -0,0 +1,719 @@
+//! Offline consistency check of a database directory, behind the `fsck`
+//! binary.
+//!
+//! Nothing is written unless a repair is asked for, and a repair only touches
+//! what can be rebuilt from the pages: the page directory and the primary
+//! index. It is published as a new metadata generation, so an interrupted
+//! repair leaves the directory as it found it.
+
+use crate::bufferpool::{AccessHint, BufferPool, DiskManager};
+use crate::disk_manager::{TableCounters, TableMeta};
+use crate::errors::DbError;
+use crate::iterators::PhysicalAddress;
+use crate::lock_manager::LockManager;
+use crate::page::Page;
+use crate::page_collection::{MetaPage, PageId};
+use crate::page_range::{CollectionMeta, WhichRange};
+use crate::storage::StorageBackend;
+use crate::table::Table;
+use crate::wal::{LogAnalysis, Wal};
+use dashmap::{DashMap, DashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    /// A metadata file could not be read or decoded.
+    Unreadable {
+        table_id: Option<usize>,
+        file: &'static str,
+        error: String,
+    },
+    NameWithoutTable {
+        name: String,
+        table_id: usize,
+    },
+    TableWithoutName {
+        table_id: usize,
+    },
+    /// The PID ranges in `counters` overlap, run past the PID counter, or are
+    /// too few for the slots handed out.
+    PidRanges {
+        table_id: usize,
+        detail: String,
+    },
+    /// A page of a collection that holds records is missing from the segment.
+    UnallocatedPage {
+        table_id: usize,
+        page_num: usize,
+    },
+    /// A page directory entry points at a slot that does not hold its RID.
+    DanglingDirectoryEntry {
+        table_id: usize,
+        rid: i64,
+        addr: PhysicalAddress,
+    },
+    /// A record slot whose RID the page directory does not know.
+    MissingDirectoryEntry {
+        table_id: usize,
+        rid: i64,
+    },
+    /// A primary index entry that does not lead to a live base record with
+    /// that key.
+    BadIndexEntry {
+        table_id: usize,
+        key: i64,
+        rid: i64,
+        detail: String,
+    },
+    MissingIndexEntry {
+        table_id: usize,
+        key: i64,
+        rid: i64,
+    },
+    /// Following a record's indirection chain reached a RID that is not a
+    /// tail record.
+    BrokenChain {
+        table_id: usize,
+        base_rid: i64,
+        at: i64,
+    },
+    /// A record's indirection chain never gets back to the base record.
+    CyclicChain {
+        table_id: usize,
+        base_rid: i64,
+    },
+    /// A merged base record's TPS is past every RID handed out.
+    TpsAhead {
+        table_id: usize,
+        rid: i64,
+        tps: i64,
+        next_rid: i64,
+    },
+}
+
+impl Problem {
+    pub fn table_id(&self) -> Option<usize> {
+        match self {
+            Problem::Unreadable { table_id, .. } => *table_id,
+            Problem::NameWithoutTable { .. } => None,
+            Problem::TableWithoutName { table_id }
+            | Problem::PidRanges { table_id, .. }
+            | Problem::UnallocatedPage { table_id, .. }
+            | Problem::DanglingDirectoryEntry { table_id, .. }
+            | Problem::MissingDirectoryEntry { table_id, .. }
+            | Problem::BadIndexEntry { table_id, .. }
+            | Problem::MissingIndexEntry { table_id, .. }
+            | Problem::BrokenChain { table_id, .. }
+            | Problem::CyclicChain { table_id, .. }
+            | Problem::TpsAhead { table_id, .. } => Some(*table_id),
+        }
+    }
+
+    /// Whether rebuilding the page directory and primary index fixes it.
+    pub fn repairable(&self) -> bool {
+        match self {
+            Problem::Unreadable { file, .. } => matches!(*file, "page_directory" | "primary_index"),
+            Problem::DanglingDirectoryEntry { .. }
+            | Problem::MissingDirectoryEntry { .. }
+            | Problem::BadIndexEntry { .. }
+            | Problem::MissingIndexEntry { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.table_id() {
+            Some(table_id) => write!(f, "table {}: ", table_id)?,
+            None => write!(f, "database: ")?,
+        }
+        match self {
+            Problem::Unreadable { file, error, .. } => write!(f, "{} unreadable: {}", file, error),
+            Problem::NameWithoutTable { name, table_id } => {
+                write!(
+                    f,
+                    "name {:?} refers to table {} which is not in the catalog",
+                    name, table_id
+                )
+            }
+            Problem::TableWithoutName { .. } => write!(f, "in the catalog but has no name"),
+            Problem::PidRanges { detail, .. } => write!(f, "PID ranges: {}", detail),
+            Problem::UnallocatedPage { page_num, .. } => {
+                write!(f, "page {} is not allocated", page_num)
+            }
+            Problem::DanglingDirectoryEntry { rid, addr, .. } => write!(
+                f,
+                "page directory sends RID {} to collection {} offset {}, which holds another record",
+                rid, addr.collection_num, addr.offset
+            ),
+            Problem::MissingDirectoryEntry { rid, .. } => {
+                write!(f, "RID {} is stored but not in the page directory", rid)
+            }
+            Problem::BadIndexEntry {
+                key, rid, detail, ..
+            } => {
+                write!(f, "primary index entry {} -> RID {}: {}", key, rid, detail)
+            }
+            Problem::MissingIndexEntry { key, rid, .. } => {
+                write!(
+                    f,
+                    "live RID {} with key {} is not in the primary index",
+                    rid, key
+                )
+            }
+            Problem::BrokenChain { base_rid, at, .. } => {
+                write!(
+                    f,
+                    "indirection chain of RID {} breaks at RID {}",
+                    base_rid, at
+                )
+            }
+            Problem::CyclicChain { base_rid, .. } => {
+                write!(
+                    f,
+                    "indirection chain of RID {} does not terminate",
+                    base_rid
+                )
+            }
+            Problem::TpsAhead {
+                rid,
+                tps,
+                next_rid,
+                ..
+            } => write!(
+                f,
+                "RID {} has TPS {} but RIDs only go up to {}",
+                rid,
+                tps,
+                next_rid - 1
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableSummary {
+    pub table_id: usize,
+    pub name: String,
+    pub base_records: usize,
+    pub tail_records: usize,
+    pub live_records: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckReport {
+    pub path: PathBuf,
+    /// `false` when the write-ahead log still holds work; the next
+    /// `Database::open` recovers it, so some problems may be expected.
+    pub clean_shutdown: bool,
+    pub tables: Vec<TableSummary>,
+    pub problems: Vec<Problem>,
+    /// Tables whose page directory and primary index were rebuilt.
+    pub repaired: Vec<usize>,
+}
+
+impl CheckReport {
+    /// No problems, or only ones that were repaired.
+    pub fn is_consistent(&self) -> bool {
+        self.problems
+            .iter()
+            .all(|p| p.repairable() && p.table_id().is_some_and(|t| self.repaired.contains(&t)))
+    }
+}
+
+impl fmt::Display for CheckReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "database: {}", self.path.display())?;
+        if self.clean_shutdown {
+            writeln!(f, "shutdown: clean")?;
+        } else {
+            writeln!(
+                f,
+                "shutdown: unclean, the log will be replayed on the next open"
+            )?;
+        }
+        for t in &self.tables {
+            writeln!(
+                f,
+                "table {} {:?}: {} base records ({} live), {} tail records",
+                t.table_id, t.name, t.base_records, t.live_records, t.tail_records
+            )?;
+        }
+        writeln!(f, "problems: {}", self.problems.len())?;
+        for p in &self.problems {
+            let note =
+                if self.repaired.contains(&p.table_id().unwrap_or(usize::MAX)) && p.repairable() {
+                    " [repaired]"
+                } else if p.repairable() {
+                    " [repairable]"
+                } else {
+                    ""
+                };
+            writeln!(f, "  {}{}", p, note)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks the database in `path`, the directory holding `MANIFEST`. With
+/// `repair`, rebuilds the page directory and primary index of every table
+/// that needs it; this is refused while the log holds unrecovered work.
+///
+/// Only problems that stop the check from running at all, such as an
+/// unreadable manifest, are returned as errors.
+pub fn check(path: &Path, repair: bool) -> Result<CheckReport, DbError> {
+    let dm = Arc::new(DiskManager::new(path)?);
+    let clean_shutdown = log_is_clean(&*dm)?;
+    let bufferpool = Arc::new(BufferPool::new(dm.clone()));
+
+    let mut report = CheckReport {
+        path: path.to_path_buf(),
+        clean_shutdown,
+        tables: vec![],
+        problems: vec![],
+        repaired: vec![],
+    };
+
+    let (metas, next_table_id) = match dm.read_tables() {
+        Ok(catalog) => catalog,
+        Err(e) => {
+            report.problems.push(unreadable(None, "catalog", e));
+            return Ok(report);
+        }
+    };
+    let names = match dm.read_table_names() {
+        Ok(names) => names,
+        Err(e) => {
+            report.problems.push(unreadable(None, "table_names", e));
+            return Ok(report);
+        }
+    };
+
+    for (name, table_id) in &names {
+        if !metas.iter().any(|m| m.table_id == *table_id) {
+            report.problems.push(Problem::NameWithoutTable {
+                name: name.clone(),
+                table_id: *table_id,
+            });
+        }
+    }
+
+    let tables: DashMap<usize, Arc<Table>> = DashMap::new();
+    let mut needs_repair = Vec::new();
+    for meta in &metas {
+        let Some((name, _)) = names.iter().find(|(_, id)| *id == meta.table_id) else {
+            report.problems.push(Problem::TableWithoutName {
+                table_id: meta.table_id,
+            });
+            continue;
+        };
+        let before = report.problems.len();
+        let Some(table) = load_table(&dm, &bufferpool, meta, name, &mut report.problems)
+        else {
+            continue;
+        };
+        report.tables.push(check_table(&table, &dm, &mut report.problems)?);
+        // A rebuild reads every record, which a table with damage beyond the
+        // directory and index may not survive.
+        let found = &report.problems[before..];
+        if !found.is_empty() && found.iter().all(Problem::repairable) {
+            needs_repair.push(meta.table_id);
+        }
+        tables.insert(meta.table_id, Arc::new(table));
+    }
+
+    let can_repair = clean_shutdown && tables.len() == metas.len();
+    if repair && can_repair && !needs_repair.is_empty() {
+        for table_id in &needs_repair {
+            let table = tables.get(table_id).unwrap().clone();
+            let rebuilt = Arc::new(rebuild(&dm, &bufferpool, &table)?);
+            tables.insert(*table_id, rebuilt);
+        }
+        bufferpool.evict_all()?;
+        dm.sync_pages()?;
+        write_generation(&dm, &tables, &names, next_table_id)?;
+        report.repaired = needs_repair;
+    }
+    Ok(report)
+}
+
+fn unreadable(table_id: Option<usize>, file: &'static str, err: impl fmt::Display) -> Problem {
+    Problem::Unreadable {
+        table_id,
+        file,
+        error: err.to_string(),
+    }
+}
+
+/// Whether the log is absent, empty, or holds only a clean shutdown marker.
+fn log_is_clean(dm: &dyn StorageBackend) -> Result<bool, DbError> {
+    let Some(path) = dm.log_path().filter(|path| path.exists()) else {
+        return Ok(true);
+    };
+    let wal = Wal::disabled();
+    wal.open(&path, dm.fence())?;
+    let records = wal.read_all()?;
+    Ok(records.is_empty() || LogAnalysis::new(&records).clean_shutdown)
+}
+
+fn load_table(
+    dm: &DiskManager,
+    bufferpool: &Arc<BufferPool>,
+    meta: &TableMeta,
+    name: &str,
+    problems: &mut Vec<Problem>,
+) -> Option<Table> {
+    let table_id = Some(meta.table_id);
+    let counters = match dm.read_table_counters(meta.table_id) {
+        Ok(counters) => counters,
+        Err(e) => {
+            problems.push(unreadable(table_id, "counters", e));
+            return None;
+        }
+    };
+    let page_dir = dm.read_page_directory(meta.table_id).unwrap_or_else(|e| {
+        problems.push(unreadable(table_id, "page_directory", e));
+        vec![]
+    });
+    let primary = dm.read_primary_index(meta.table_id).unwrap_or_else(|e| {
+        problems.push(unreadable(table_id, "primary_index", e));
+        vec![]
+    });
+    Some(restore(bufferpool, meta, name, page_dir, counters, primary))
+}
+
+fn restore(
+    bufferpool: &Arc<BufferPool>,
+    meta: &TableMeta,
+    name: &str,
+    page_dir: Vec<(i64, PhysicalAddress)>,
+    counters: TableCounters,
+    primary: Vec<(i64, i64)>,
+) -> Table {
+    Table::restore(
+        name.to_string(),
+        meta.schema.clone(),
+        meta.key_index,
+        meta.table_id,
+        bufferpool.clone(),
+        page_dir,
+        counters,
+        primary,
+        Arc::new(LockManager::new()),
+        Arc::new(Wal::disabled()),
+    )
+}
+
+fn check_table(
+    table: &Table,
+    dm: &DiskManager,
+    problems: &mut Vec<Problem>,
+) -> Result<TableSummary, DbError> {
+    let table_id = table.table_id;
+    let ranges = &table.page_ranges;
+    let next_rid = table.rid.current();
+
+    // PID ranges and page allocation.
+    let base_collections = ranges.base_collections();
+    let tail_collections = ranges.tail_collections();
+    let heap_ranges = table.heap.pid_ranges();
+    let mut all_ranges: Vec<(usize, usize)> = base_collections
+        .iter()
+        .chain(&tail_collections)
+        .map(|c| (c.start, c.end))
+        .chain(heap_ranges)
+        .chain(table.free_pid_ranges())
+        .collect();
+    all_ranges.sort_unstable();
+    for pair in all_ranges.windows(2) {
+        if pair[0].1 > pair[1].0 {
+            problems.push(Problem::PidRanges {
+                table_id,
+                detail: format!("{:?} overlaps {:?}", pair[0], pair[1]),
+            });
+        }
+    }
+    if let Some(&(_, end)) = all_ranges.iter().max_by_key(|(_, end)| *end)
+        && end > ranges.pid_next_start()
+    {
+        problems.push(Problem::PidRanges {
+            table_id,
+            detail: format!(
+                "PID {} is past the next free PID {}",
+                end - 1,
+                ranges.pid_next_start()
+            ),
+        });
+    }
+    // Collections made since a column was dropped have no pages for it.
+    let retired = ranges.slots().retired;
+    for (kind, collections, next_addr) in [
+        ("base", &base_collections, ranges.base_next_addr()),
+        ("tail", &tail_collections, ranges.tail_next_addr()),
+    ] {
+        // Only a sealed collection can have been retired.
+        let open = next_addr / Page::PAGE_SIZE;
+        if next_addr % Page::PAGE_SIZE != 0 && !collections.iter().any(|c| c.num == open) {
+            problems.push(Problem::PidRanges {
+                table_id,
+                detail: format!(
+                    "{} slots end in {} collection {}, which is missing",
+                    next_addr, kind, open
+                ),
+            });
+        }
+        let used = next_addr.div_ceil(Page::PAGE_SIZE);
+        for &CollectionMeta { start, end, .. } in collections.iter().filter(|c| c.num < used) {
+            let data_width = Table::collection_width(end - start);
+            for page_num in start..end {
+                let slot = page_num - start;
+                if slot < data_width && retired.contains(&slot) {
+                    continue;
+                }
+                if !dm.page_exists(PageId::new(page_num, table_id)) {
+                    problems.push(Problem::UnallocatedPage { table_id, page_num });
+                }
+            }
+        }
+    }
+    if problems
+        .iter()
+        .any(|p| matches!(p, Problem::PidRanges { table_id: t, .. } if *t == table_id))
+    {
+        // Without sound collections no slot can be read safely.
+        return Ok(TableSummary {
+            table_id,
+            name: table.name.clone(),
+            base_records: 0,
+            tail_records: 0,
+            live_records: 0,
+        });
+    }
+
+    // Page directory against the RIDs stored in the slots.
+    let base_slots = ranges.scan_base_rids()?;
+    let tail_slots = ranges.scan_tail_rids()?;
+    let base_rids: DashSet<i64> = base_slots.iter().map(|(rid, _)| *rid).collect();
+    let tail_rids: DashSet<i64> = tail_slots.iter().map(|(rid, _)| *rid).collect();
+    let holds = |addr: &PhysicalAddress,
+                 range: WhichRange,
+                 next_addr: usize,
+                 rid: i64|
+     -> Result<bool, DbError> {
+        if addr.collection_num * Page::PAGE_SIZE + addr.offset >= next_addr
+            || !ranges.has_collection(addr, range)
+        {
+            return Ok(false);
+        }
+        Ok(ranges.read_meta_col(addr, MetaPage::Rid, range)? == Some(rid))
+    };
+    for (rid, addr) in table.page_directory.snapshot() {
+        let ok = if base_rids.contains(&rid) {
+            holds(&addr, WhichRange::Base, ranges.base_next_addr(), rid)?
+        } else {
+            holds(&addr, WhichRange::Tail, ranges.tail_next_addr(), rid)?
+        };
+        if !ok {
+            problems.push(Problem::DanglingDirectoryEntry {
+                table_id,
+                rid,
+                addr,
+            });
+        }
+    }
+    let tail_addrs: DashMap<i64, PhysicalAddress> = tail_slots.iter().copied().collect();
+    // A deleted record a merge dropped has slots until their collections go,
+    // but nothing in the directory: one of its copies ends in a tombstone,
+    // or in a tail record freed already.
+    let dropped = DashSet::new();
+    for (rid, addr) in &base_slots {
+        let head = ranges.read_meta_col(addr, MetaPage::Indirection, WhichRange::Base)?;
+        let Some(head) = head.filter(|head| head != rid) else {
+            continue;
+        };
+        let deleted = match tail_addrs.get(&head).map(|a| *a) {
+            Some(tail) => ranges.read_meta_col(&tail, MetaPage::SchemaEncoding, WhichRange::Tail)?.is_none(),
+            None => true,
+        };
+        if deleted {
+            dropped.insert(*rid);
+        }
+    }
+    for rid in base_rids
+        .iter()
+        .map(|r| *r)
+        .filter(|rid| !dropped.contains(rid))
+        .chain(tail_rids.iter().map(|r| *r))
+    {
+        if table.page_directory.get(rid).is_err() {
+            problems.push(Problem::MissingDirectoryEntry { table_id, rid });
+        }
+    }
+
+    // Indirection chains and watermarks. Chains are followed through the slots
+    // themselves so that a bad page directory does not show up twice.
+    let live = DashMap::new();
+    let broken = DashSet::new();
+    for rid in base_rids.iter().map(|r| *r) {
+        let Ok(addr) = table.page_directory.get(rid) else {
+            continue;
+        };
+        if !holds(&addr, WhichRange::Base, ranges.base_next_addr(), rid)? {
+            continue;
+        }
+        let tps = ranges.read_tps(&addr, AccessHint::Normal)?;
+        if tps != i64::MIN && tps >= next_rid {
+            problems.push(Problem::TpsAhead {
+                table_id,
+                rid,
+                tps,
+                next_rid,
+            });
+        }
+        let Some(head) = follow_chain(table, rid, &addr, tps, &tail_addrs, problems)? else {
+            broken.insert(rid);
+            continue;
+        };
+        let schema = match head {
+            Some(tail) => {
+                ranges.read_meta_col(&tail, MetaPage::SchemaEncoding, WhichRange::Tail)?
+            }
+            None => ranges.read_meta_col(&addr, MetaPage::SchemaEncoding, WhichRange::Base)?,
+        };
+        if schema.is_none() {
+            continue;
+        }
+        if let Some(key) = ranges.read_single(table.key_index, &addr, WhichRange::Base)? {
+            live.insert(key, rid);
+        }
+    }
+    // Primary index against the live records.
+    let index = &table.indices[table.key_index];
+    for (key, rid) in index.all_pairs() {
+        if broken.contains(&rid) {
+            continue;
+        }
+        match live.get(&key).map(|r| *r) {
+            Some(live_rid) if live_rid == rid => {}
+            Some(live_rid) => problems.push(Problem::BadIndexEntry {
+                table_id,
+                key,
+                rid,
+                detail: format!("the live record with this key is RID {}", live_rid),
+            }),
+            None => problems.push(Problem::BadIndexEntry {
+                table_id,
+                key,
+                rid,
+                detail: "no live record has this key".into(),
+            }),
+        }
+    }
+    for entry in live.iter() {
+        if index.locate(*entry.key()) != Some(*entry.value()) {
+            problems.push(Problem::MissingIndexEntry {
+                table_id,
+                key: *entry.key(),
+                rid: *entry.value(),
+            });
+        }
+    }
+
+    Ok(TableSummary {
+        table_id,
+        name: table.name.clone(),
+        base_records: base_rids.len(),
+        tail_records: tail_rids.len(),
+        live_records: live.len(),
+    })
+}
+
+/// Walks the chain from the base record at `addr` through its tail records
+/// down to its watermark `tps`, past which they may have been freed.
+/// Returns the slot of the newest tail record not merged (`None` if there is
+/// none), or `None` at the outer level if the chain is broken.
+fn follow_chain(
+    table: &Table,
+    base_rid: i64,
+    addr: &PhysicalAddress,
+    tps: i64,
+    tail_addrs: &DashMap<i64, PhysicalAddress>,
+    problems: &mut Vec<Problem>,
+) -> Result<Option<Option<PhysicalAddress>>, DbError> {
+    let table_id = table.table_id;
+    let ranges = &table.page_ranges;
+    let head = ranges.read_meta_col(addr, MetaPage::Indirection, WhichRange::Base)?;
+    let mut rid = head.unwrap_or(base_rid);
+    let mut newest = None;
+    for _ in 0..=tail_addrs.len() {
+        if rid == base_rid || rid <= tps {
+            return Ok(Some(newest));
+        }
+        let Some(tail) = tail_addrs.get(&rid).map(|a| *a) else {
+            problems.push(Problem::BrokenChain {
+                table_id,
+                base_rid,
+                at: rid,
+            });
+            return Ok(None);
+        };
+        newest.get_or_insert(tail);
+        match ranges.read_meta_col(&tail, MetaPage::Indirection, WhichRange::Tail)? {
+            Some(next) => rid = next,
+            None => {
+                problems.push(Problem::BrokenChain {
+                    table_id,
+                    base_rid,
+                    at: rid,
+                });
+                return Ok(None);
+            }
+        }
+    }
+    problems.push(Problem::CyclicChain { table_id, base_rid });
+    Ok(None)
+}
+
+/// A copy of `table` with its page directory and primary index rebuilt from
+/// the pages.
+fn rebuild(
+    dm: &DiskManager,
+    bufferpool: &Arc<BufferPool>,
+    table: &Table,
+) -> Result<Table, DbError> {
+    let counters = dm.read_table_counters(table.table_id)?;
+    let meta = TableMeta {
+        table_id: table.table_id,
+        schema: (*table.schema()).clone(),
+        key_index: table.key_index,
+        next_rid: counters.next_rid,
+        name: table.name.clone(),
+    };
+    let rebuilt = restore(bufferpool, &meta, &table.name, vec![], counters, vec![]);
+    rebuilt.rebuild_page_directory()?;
+    rebuilt.rebuild_primary_index()?;
+    Ok(rebuilt)
+}
+
+fn write_generation(
+    dm: &DiskManager,
+    tables: &DashMap<usize, Arc<Table>>,
+    names: &[(String, usize)],
+    next_table_id: usize,
+) -> Result<(), DbError> {
+    dm.begin_generation();
+    let names: DashMap<String, usize> = names.iter().cloned().collect();
+    dm.write_table_names(&names)?;
+    dm.write_tables(tables, next_table_id)?;
+    for entry in tables.iter() {
+        let table = entry.value();
+        dm.write_page_directory(table.table_id, &table.page_directory.snapshot())?;
+        dm.write_table_counters(table.table_id, &TableCounters::of(table))?;
+        dm.write_primary_index(table.table_id, &table.indices[table.key_index].all_pairs())?;
+    }
+    dm.commit_generation()?;
+    Ok(())
+}
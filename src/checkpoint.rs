@@ -0,0 +1,164 @@
+use crate::bufferpool::BufferPool;
+use crate::disk_manager::TableCounters;
+use crate::errors::DbError;
+use crate::iterators::AtomicIterator;
+use crate::storage::StorageBackend;
+use crate::table::Table;
+use crate::wal::Wal;
+use dashmap::DashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Checkpoints taken since the database was created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CheckpointStats {
+    pub taken: u64,
+    /// Checkpoints that failed part way; the log was left alone, so nothing
+    /// was lost.
+    pub failed: u64,
+    /// Pages of collections whose records were all superseded, deleted
+    /// once no query could still read them.
+    pub reclaimed_pages: u64,
+}
+
+#[derive(Default)]
+pub(crate) struct CheckpointCounters {
+    taken: AtomicU64,
+    failed: AtomicU64,
+    reclaimed_pages: AtomicU64,
+}
+
+impl CheckpointCounters {
+    pub(crate) fn stats(&self) -> CheckpointStats {
+        CheckpointStats {
+            taken: self.taken.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            reclaimed_pages: self.reclaimed_pages.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn reset(&self) {
+        self.taken.store(0, Ordering::Relaxed);
+        self.failed.store(0, Ordering::Relaxed);
+        self.reclaimed_pages.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Everything a checkpoint writes out. Made of shared handles, so the
+/// background checkpointer can keep one of its own.
+pub(crate) struct Checkpointer {
+    pub(crate) tables: Arc<DashMap<usize, Arc<Table>>>,
+    pub(crate) table_names: Arc<DashMap<String, usize>>,
+    pub(crate) table_id: Arc<AtomicIterator<AtomicUsize>>,
+    pub(crate) bufferpool: Arc<BufferPool>,
+    pub(crate) storage: Arc<dyn StorageBackend>,
+    pub(crate) wal: Arc<Wal>,
+    pub(crate) counters: Arc<CheckpointCounters>,
+}
+
+impl Checkpointer {
+    /// Writes every dirty page and all table metadata, after which the log
+    /// holds nothing that is not already on disk and is cut.
+    ///
+    /// Most pages are written while queries keep running. Writers are held
+    /// off only for what is left: the pages dirtied in the meantime, the
+    /// metadata and the cut, none of which may see a write half done.
+    pub(crate) fn checkpoint(&self) -> Result<(), DbError> {
+        let result = self.bufferpool.flush_all().map_err(DbError::from).and_then(|_| {
+            let _quiet = self.wal.quiesce();
+            self.write_out()
+        });
+        let counter = if result.is_ok() { &self.counters.taken } else { &self.counters.failed };
+        counter.fetch_add(1, Ordering::Relaxed);
+        result
+    }
+
+    fn write_out(&self) -> Result<(), DbError> {
+        // The collections merges superseded are left out of the metadata
+        // from this generation on.
+        for entry in self.tables.iter() {
+            entry.value().retire_superseded()?;
+        }
+
+        // Pages first: the metadata must never describe records whose pages
+        // are not on disk yet.
+        self.bufferpool.flush_all()?;
+
+        let storage = &self.storage;
+        storage.sync_pages()?;
+        storage.begin_generation();
+
+        storage.write_table_names(&self.table_names)?;
+
+        storage.write_tables(&self.tables, self.table_id.current())?;
+
+        for entry in self.tables.iter() {
+            let table = entry.value();
+            let tid = table.table_id;
+
+            let page_dir = table.page_directory_pairs();
+            storage.write_page_directory(tid, &page_dir)?;
+
+            storage.write_table_counters(tid, &TableCounters::of(table))?;
+
+            let primary_pairs = table.indices[table.key_index].all_pairs();
+            storage.write_primary_index(tid, &primary_pairs)?;
+        }
+
+        // Until the manifest moves, a crash leaves the previous generation
+        // of metadata in charge.
+        storage.commit_generation()?;
+
+        // Heap space merges gave up is recorded as free now; no generation
+        // still in charge reaches it.
+        for entry in self.tables.iter() {
+            entry.value().heap.release_retired();
+        }
+        // As are the pages of retired collections, once no query that
+        // started before they were retired is left.
+        for entry in self.tables.iter() {
+            let pages = entry.value().free_retired()?;
+            self.counters.reclaimed_pages.fetch_add(pages as u64, Ordering::Relaxed);
+        }
+
+        self.wal.truncate()?;
+
+        Ok(())
+    }
+}
+
+/// Takes a checkpoint every `interval` on a thread of its own, until dropped.
+pub(crate) struct CheckpointThread {
+    stop_tx: Option<mpsc::Sender<()>>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl CheckpointThread {
+    pub(crate) fn spawn(checkpointer: Checkpointer, interval: Duration) -> Self {
+        let (stop_tx, stop_rx) = mpsc::channel::<()>();
+        let handle = thread::spawn(move || {
+            // Wakes up early, to exit, once the sender is dropped.
+            while let Err(RecvTimeoutError::Timeout) = stop_rx.recv_timeout(interval) {
+                // Counted; the next one tries again.
+                let _ = checkpointer.checkpoint();
+            }
+        });
+        Self {
+            stop_tx: Some(stop_tx),
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for CheckpointThread {
+    /// Waits for a checkpoint that is under way to finish.
+    fn drop(&mut self) {
+        drop(self.stop_tx.take());
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
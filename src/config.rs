@@ -0,0 +1,215 @@
+use crate::bufferpool::{BufferPool, BP_CAP};
+use crate::errors::DbError;
+use std::time::Duration;
+
+/// Settings a `Database` is created with.
+///
+/// The page size is not among them: it is part of the on-disk page layout,
+/// so changing it would need a format migration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DatabaseConfig {
+    pub buffer_pool: PoolSize,
+    pub eviction: EvictionKind,
+    /// How many page collections a scan has the buffer pool's worker load
+    /// ahead of the one it is reading, or 0 for none.
+    pub read_ahead: usize,
+    /// The buffer pool's background writer, or `None` to write pages back
+    /// only on eviction and checkpoints.
+    pub flusher: Option<FlusherConfig>,
+    /// How often a durable database takes a checkpoint while it is open, or
+    /// `None` to take one only on close. A checkpoint is what lets the log
+    /// be cut, so it bounds both the log's size and how much recovery has to
+    /// replay after a crash.
+    pub checkpoint_interval: Option<Duration>,
+    /// The background merge, or `None` to leave tail records unmerged.
+    pub merge: Option<MergeConfig>,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            buffer_pool: PoolSize::Frames(BP_CAP),
+            eviction: EvictionKind::default(),
+            read_ahead: 2,
+            flusher: Some(FlusherConfig::default()),
+            checkpoint_interval: Some(Duration::from_secs(30)),
+            merge: Some(MergeConfig::default()),
+        }
+    }
+}
+
+impl DatabaseConfig {
+    /// The defaults without any background work, so that every write
+    /// happens on the caller's thread at a predictable point.
+    pub fn synchronous() -> Self {
+        Self {
+            read_ahead: 0,
+            flusher: None,
+            checkpoint_interval: None,
+            merge: None,
+            ..Self::default()
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), DbError> {
+        self.buffer_pool.frames()?;
+        if self.eviction == EvictionKind::LruK(0) {
+            return Err(DbError::InvalidConfig("LRU-K needs k of at least 1".into()));
+        }
+        if let Some(flusher) = self.flusher {
+            flusher.validate()?;
+        }
+        if self.checkpoint_interval == Some(Duration::ZERO) {
+            return Err(DbError::InvalidConfig("the checkpoint interval must not be zero".into()));
+        }
+        if let Some(merge) = self.merge {
+            merge.validate()?;
+        }
+        Ok(())
+    }
+}
+
+/// Which policy picks the pages the buffer pool evicts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EvictionKind {
+    /// Adaptive replacement, balancing recently and frequently used pages.
+    #[default]
+    Arc,
+    /// Second chance. Hits cost next to nothing, at some loss in precision.
+    Clock,
+    /// LRU-K with the given `k`: resists scans, but costs a look at every
+    /// frame per eviction. `LruK(1)` is plain LRU.
+    LruK(usize),
+}
+
+/// How much of the buffer pool one table can count on, and how much it may
+/// take. The default reserves nothing and takes as much as it needs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TableQuota {
+    /// Frames the table keeps however much other tables load: pages of other
+    /// tables never evict its pages while it holds no more than this.
+    pub reserved: usize,
+    /// Frames the table may hold at most. Once it has them, it loads a page
+    /// by evicting one of its own.
+    pub limit: Option<usize>,
+}
+
+impl TableQuota {
+    pub fn validate(&self) -> Result<(), DbError> {
+        if self.limit.is_some_and(|limit| limit < self.reserved) {
+            return Err(DbError::InvalidConfig(format!(
+                "a table limited to {:?} frames cannot have {} reserved",
+                self.limit, self.reserved
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// How the buffer pool's background writer paces itself. Every `interval` it
+/// writes back up to `max_pages` dirty pages from among the next
+/// `clean_frames` the eviction policy would pick, so that loading a page
+/// seldom has to wait for its victim to be written first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlusherConfig {
+    pub interval: Duration,
+    pub max_pages: usize,
+    pub clean_frames: usize,
+}
+
+impl Default for FlusherConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_millis(50),
+            max_pages: 64,
+            clean_frames: 32,
+        }
+    }
+}
+
+impl FlusherConfig {
+    pub fn validate(&self) -> Result<(), DbError> {
+        if self.interval.is_zero() || self.max_pages == 0 {
+            return Err(DbError::InvalidConfig(format!(
+                "the flusher needs a non-zero interval and page budget, not {} pages every {:?}",
+                self.max_pages, self.interval
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// When the background merge folds a table's tail records into its base
+/// records. Every `interval` it merges each table that has `tail_records`
+/// unmerged tail records in all, or a record whose chain of them is
+/// `chain_length` long, as reads of it have that many to follow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MergeConfig {
+    pub interval: Duration,
+    pub tail_records: usize,
+    pub chain_length: usize,
+    /// How many older versions of a record a merge keeps for
+    /// `select_version`; reads further back get the oldest of them. The
+    /// tail records and text and blob values of the rest go.
+    pub keep_versions: usize,
+}
+
+impl Default for MergeConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_millis(100),
+            tail_records: 4096,
+            chain_length: 16,
+            keep_versions: 4,
+        }
+    }
+}
+
+impl MergeConfig {
+    pub fn validate(&self) -> Result<(), DbError> {
+        if self.interval.is_zero() || self.tail_records == 0 || self.chain_length == 0 {
+            return Err(DbError::InvalidConfig(format!(
+                "the merge needs a non-zero interval and thresholds, not {} tail records or chains of {} every {:?}",
+                self.tail_records, self.chain_length, self.interval
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// How big the buffer pool is: a number of frames, each holding one page, or
+/// a memory budget that is rounded down to whole frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolSize {
+    Frames(usize),
+    Bytes(usize),
+}
+
+impl PoolSize {
+    /// Fewer frames than this and a single wide record no longer fits.
+    pub const MIN_FRAMES: usize = 16;
+
+    /// The number of frames, if it is one the pool can be built with.
+    pub fn frames(self) -> Result<usize, DbError> {
+        let frames = match self {
+            PoolSize::Frames(frames) => frames,
+            PoolSize::Bytes(bytes) => bytes / BufferPool::FRAME_BYTES,
+        };
+        if frames < Self::MIN_FRAMES {
+            return Err(DbError::InvalidConfig(format!(
+                "a buffer pool of {:?} has {} frames, at least {} ({} bytes) are needed",
+                self,
+                frames,
+                Self::MIN_FRAMES,
+                Self::MIN_FRAMES * BufferPool::FRAME_BYTES,
+            )));
+        }
+        if frames.checked_mul(BufferPool::FRAME_BYTES).is_none_or(|bytes| bytes > isize::MAX as usize) {
+            return Err(DbError::InvalidConfig(format!(
+                "a buffer pool of {} frames does not fit in memory",
+                frames
+            )));
+        }
+        Ok(frames)
+    }
+}
@@ -4,58 +4,202 @@ use crate::errors::DbError;
 use crate::iterators::AtomicIterator;
 use crate::table::Table;
 use dashmap::{mapref::entry::Entry, DashMap};
-use parking_lot::RwLock;
 use sanitise_file_name::sanitize;
 use std::path::PathBuf;
 use std::sync::atomic::AtomicUsize;
 use std::sync::Arc;
-use crate::disk_manager::TableCounters;
+use crate::bufferpool::{AccessStats, Corruption, FlushStats, PoolStats};
+use crate::checkpoint::{CheckpointCounters, CheckpointStats, CheckpointThread, Checkpointer};
+use crate::config::{DatabaseConfig, PoolSize, TableQuota};
+use crate::disk_manager::{CorruptionPolicy, IoStats};
+use crate::merge::{MergeCounters, MergeStats, MergeThread};
+use crate::vacuum::{VacuumOptions, VacuumStats};
 use crate::lock_manager::LockManager;
+use crate::schema::{ColumnDef, ColumnType, Schema};
+use crate::schema_encoding::SchemaEncoding;
+use crate::storage::{MemoryBackend, StorageBackend};
+use crate::wal::{LogAnalysis, LogRecord, Wal};
+use parking_lot::Mutex;
+
+/// Everything the database counts, for sizing the buffer pool and finding
+/// out where a slow query spends its time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DbStats {
+    pub pool: PoolStats,
+    /// Always zero for a database that was never opened: its pages stay in
+    /// memory.
+    pub io: IoStats,
+    pub checkpoints: CheckpointStats,
+    pub merges: MergeStats,
+}
 
 pub(crate) struct Database {
-    pub(crate) tables: DashMap<usize, Arc<Table>>,
-    table_names: DashMap<String, usize>,
-    table_id: AtomicIterator<AtomicUsize>,
+    pub(crate) tables: Arc<DashMap<usize, Arc<Table>>>,
+    table_names: Arc<DashMap<String, usize>>,
+    table_id: Arc<AtomicIterator<AtomicUsize>>,
     bufferpool: Arc<BufferPool>,
     pub path: Option<PathBuf>,
-    disk_manager: Arc<RwLock<DiskManager>>,
+    storage: Arc<dyn StorageBackend>,
+    corruption_policy: CorruptionPolicy,
+    config: DatabaseConfig,
     lock_manager: Arc<LockManager>,
+    wal: Arc<Wal>,
+    checkpoints: Arc<CheckpointCounters>,
+    /// Buffer pool quotas by table name, for tables created or opened later
+    /// as well as those there are now.
+    table_quotas: DashMap<String, TableQuota>,
+    /// Runs while a durable database is open, if `config` asks for one.
+    checkpoint_thread: Mutex<Option<CheckpointThread>>,
+    merges: Arc<MergeCounters>,
+    /// Runs while the database is open, in memory or on disk, if `config`
+    /// asks for one.
+    merge_thread: Mutex<Option<MergeThread>>,
 }
 
 impl Database {
     pub const DEFAULT_PATH: &str = "db_data";
+    /// An ephemeral database kept in memory until `open` points it at a
+    /// directory.
     pub fn new() -> Self {
-        let temp_path = Self::create_temp_path();
-        let disk_manager = Arc::new(RwLock::new(DiskManager::new(temp_path).unwrap()));
-        Self {
-            tables: DashMap::new(),
-            table_names: DashMap::new(),
-            table_id: AtomicIterator::default(),
-            bufferpool: Arc::new(BufferPool::new(disk_manager.clone())),
+        Self::with_config(DatabaseConfig::default()).expect("the default configuration is valid")
+    }
+
+    /// Like `new`, with `config` in place of the defaults. It applies to
+    /// whatever the database opens later on too.
+    pub fn with_config(config: DatabaseConfig) -> Result<Self, DbError> {
+        config.validate()?;
+        let storage: Arc<dyn StorageBackend> = Arc::new(MemoryBackend::new());
+        let mut db = Self {
+            tables: Arc::new(DashMap::new()),
+            table_names: Arc::new(DashMap::new()),
+            table_id: Arc::new(AtomicIterator::default()),
+            bufferpool: new_bufferpool(&storage, &config)?,
             path: None,
-            disk_manager,
+            storage,
+            corruption_policy: CorruptionPolicy::default(),
+            config,
             lock_manager: Arc::new(LockManager::new()), //Could wrap in option, but I'm lazy
+            wal: Arc::new(Wal::disabled()), // nothing to recover until open() picks a path
+            checkpoints: Arc::new(CheckpointCounters::default()),
+            table_quotas: DashMap::new(),
+            checkpoint_thread: Mutex::new(None),
+            merges: Arc::new(MergeCounters::default()),
+            merge_thread: Mutex::new(None),
+        };
+        *db.merge_thread.get_mut() = db.merger();
+        Ok(db)
+    }
+
+    /// How the buffer pool's dirty pages were written back since the
+    /// database was last opened.
+    pub fn flush_stats(&self) -> FlushStats {
+        self.bufferpool.flush_stats()
+    }
+
+    /// How pages were looked up in the buffer pool since the database was
+    /// last opened.
+    pub fn access_stats(&self) -> AccessStats {
+        self.bufferpool.access_stats()
+    }
+
+    pub fn checkpoint_stats(&self) -> CheckpointStats {
+        self.checkpoints.stats()
+    }
+
+    pub fn merge_stats(&self) -> MergeStats {
+        self.merges.stats()
+    }
+
+    /// The pool's and the files' counters start over whenever the database
+    /// is opened, and all of them when `reset_stats` is called.
+    pub fn stats(&self) -> DbStats {
+        DbStats {
+            pool: self.bufferpool.stats(),
+            io: self.storage.io_stats(),
+            checkpoints: self.checkpoints.stats(),
+            merges: self.merges.stats(),
         }
     }
 
-    fn create_temp_path() -> PathBuf {
-        let temp_path = std::env::temp_dir().join(format!("firstplacedb_{}", std::process::id()));
-        std::fs::create_dir_all(&temp_path).expect("TODO: panic message");
-        temp_path
+    /// Sets every counter of `stats` back to zero, to measure a workload on
+    /// its own.
+    pub fn reset_stats(&self) {
+        self.bufferpool.reset_stats();
+        self.storage.reset_io_stats();
+        self.checkpoints.reset();
+        self.merges.reset();
+    }
+
+    /// Frames the buffer pool has right now.
+    pub fn buffer_pool_frames(&self) -> usize {
+        self.bufferpool.capacity()
+    }
+
+    /// Grows or shrinks the buffer pool of the open database. Pages in the
+    /// frames that go are written back first.
+    pub fn resize_buffer_pool(&mut self, size: PoolSize) -> Result<(), DbError> {
+        self.bufferpool.resize(size.frames()?)?;
+        self.config.buffer_pool = size;
+        Ok(())
+    }
+
+    pub fn create_table(&self, name: String, num_columns: usize, key_index: usize) -> Result<(), DbError> {
+        self.create_table_with_columns(name, Schema::ints(num_columns).defs(), key_index)
+    }
+
+    /// A table of `columns` whose primary key is column `key`, which has to
+    /// be an integer column.
+    pub fn create_typed_table(&self, name: String, columns: Vec<ColumnType>, key: usize) -> Result<(), DbError> {
+        self.create_table_with_columns(name, Schema::new(columns)?.defs(), key)
+    }
+
+    /// `create_typed_table` with names, nullability and defaults. The key
+    /// column cannot have a default: every record needs a key of its own.
+    pub fn create_table_with_columns(&self, name: String, columns: Vec<ColumnDef>, key: usize) -> Result<(), DbError> {
+        let schema = Schema::with_defs(columns)?;
+        match schema.columns().get(key) {
+            Some(_) if schema.default(key).is_some() => {
+                return Err(DbError::InvalidSchema("the key column cannot have a default".into()));
+            }
+            Some(ty) if ty.is_integer() => {}
+            Some(ty) => return Err(DbError::InvalidSchema(format!("a {} column cannot be the key", ty))),
+            None => return Err(DbError::InvalidSchema(format!("there is no column {}", key))),
+        }
+        let key_index = schema.slots(key).start;
+        self.create_table_with_schema(name, schema, key_index)
     }
 
-    pub fn create_table(&self, name: String, num_columns: usize, key_index: usize) {
+    fn create_table_with_schema(&self, name: String, schema: Schema, key_index: usize) -> Result<(), DbError> {
+        self.check_fits(schema.width())?;
+        let _writing = self.wal.begin_write();
         //atomic check table_names and return an entry
         match self.table_names.entry(name.clone()) {
             Entry::Vacant(vacant) => {
+                // Before the table takes an id, so that one it is refused
+                // leaves no gap.
+                let quota = self.table_quotas.get(&name).map(|quota| *quota);
+                if let Some(quota) = quota {
+                    check_quota(&quota, schema.width())?;
+                    self.bufferpool.check_new_quota(quota)?;
+                }
                 let table_id = self.table_id.next();
+                if let Some(quota) = quota {
+                    self.bufferpool.set_quota(table_id, quota)?;
+                }
+                self.wal.append(&LogRecord::CreateTable {
+                    table_id,
+                    name: name.clone(),
+                    schema: schema.clone(),
+                    key_index,
+                })?;
                 let table = Arc::new(Table::new(
                     name.clone(),
-                    num_columns,
+                    schema,
                     key_index,
                     table_id,
                     self.bufferpool.clone(),
                     self.lock_manager.clone(),
+                    self.wal.clone(),
                 ));
 
                 //insert into tables
@@ -64,8 +208,35 @@ impl Database {
                 //insert into table_names
                 vacant.insert(table_id);
             }
-            Entry::Occupied(_) => {} //Todo better error handling
+            Entry::Occupied(_) => return Err(DbError::TableExists(name)),
+        }
+        Ok(())
+    }
+
+    /// Gives table `name` a share of the buffer pool, so that loads into
+    /// other tables cannot push all of its pages out, or so that its own
+    /// loads cannot push out theirs. It applies to the table if it exists and
+    /// to one created or opened under the name later; `TableQuota::default()`
+    /// takes it away. Quotas are not saved with the database.
+    pub fn set_table_quota(&self, name: &str, quota: TableQuota) -> Result<(), DbError> {
+        if let Some(table) = self.get_table(name) {
+            check_quota(&quota, table.num_data_columns())?;
+            self.bufferpool.set_quota(table.table_id, quota)?;
+        } else {
+            quota.validate()?;
         }
+        self.table_quotas.insert(name.to_string(), quota);
+        Ok(())
+    }
+
+    pub fn table_quota(&self, name: &str) -> TableQuota {
+        self.table_quotas.get(name).map(|q| *q).unwrap_or_default()
+    }
+
+    /// Frames holding a page of table `name` right now.
+    pub fn table_frames(&self, name: &str) -> Option<usize> {
+        let id = *self.table_names.get(name)?.value();
+        Some(self.bufferpool.table_frames(id))
     }
 
     pub fn get_table(&self, name: &str) -> Option<Arc<Table>> {
@@ -73,35 +244,139 @@ impl Database {
         self.tables.get(&id).map(|t| t.value().clone())
     }
 
-    pub fn drop_table(&self, name: &str) -> bool {
+    /// Applies to the current storage and to whatever `open` opens next.
+    pub fn set_corruption_policy(&mut self, policy: CorruptionPolicy) {
+        self.corruption_policy = policy;
+        self.storage.set_corruption_policy(policy);
+    }
+
+    /// Pages and metadata files set aside under `CorruptionPolicy::Quarantine`
+    /// since the database was opened.
+    pub fn quarantined(&self) -> Vec<Corruption> {
+        self.storage.quarantined()
+    }
+
+    /// Write budget shared by every file this database touches; used to
+    /// simulate a crash at an exact write.
+    #[cfg(test)]
+    pub(crate) fn write_fence(&self) -> Arc<crate::disk_manager::WriteFence> {
+        self.storage.fence()
+    }
+
+    pub fn drop_table(&self, name: &str) -> Result<bool, DbError> {
+        let _writing = self.wal.begin_write();
         if let Some((_, table_id)) = self.table_names.remove(name) {
+            self.wal.append(&LogRecord::DropTable { table_id })?;
             self.tables.remove(&table_id);
-            true
+            self.bufferpool.set_quota(table_id, TableQuota::default())?;
+            Ok(true)
         } else {
-            false
+            Ok(false)
         }
     }
 
+    /// Adds `column` to table `name` after its last column, without
+    /// rewriting any record: the ones there read as having its default.
+    /// Writes wait while it happens. Returns false if there is no such table.
+    pub fn alter_table_add_column(&self, name: &str, column: ColumnDef) -> Result<bool, DbError> {
+        let Some(table) = self.get_table(name) else {
+            return Ok(false);
+        };
+        let width = table.num_data_columns() + column.ty.width();
+        self.check_fits(width)?;
+        if let Some(quota) = self.table_quotas.get(name) {
+            check_quota(&quota, width)?;
+        }
+        let _quiet = self.wal.quiesce();
+        table.add_column(column)?;
+        Ok(true)
+    }
+
+    /// Drops column `column` from table `name`. Its pages are no longer
+    /// written, and none are made for it in new collections; the primary key
+    /// cannot be dropped. Returns false if there is no such table.
+    pub fn alter_table_drop_column(&self, name: &str, column: &str) -> Result<bool, DbError> {
+        let Some(table) = self.get_table(name) else {
+            return Ok(false);
+        };
+        let _quiet = self.wal.quiesce();
+        let col = table.schema().column(column)?;
+        table.drop_column(col)?;
+        Ok(true)
+    }
+
+    /// Rewrites table `name` into collections of its own without its
+    /// deleted records, and older versions past `options.keep_versions`,
+    /// then takes a checkpoint, which makes the rewrite the table on disk
+    /// in one go. Writes wait until the rewrite is done; queries do not.
+    /// Returns `None` if there is no such table.
+    pub fn vacuum(&self, name: &str, options: VacuumOptions) -> Result<Option<VacuumStats>, DbError> {
+        let Some(table) = self.get_table(name) else {
+            return Ok(None);
+        };
+        let freed = table.freed_pages();
+        let mut stats = {
+            let _quiet = self.wal.quiesce();
+            table.vacuum(options)?
+        };
+        self.checkpoint()?;
+        stats.bytes_reclaimed = ((table.freed_pages() - freed) * DiskManager::PAGE_BYTES) as u64;
+        Ok(Some(stats))
+    }
+
+    /// Errs unless the buffer pool can hold every page of a record of
+    /// `width` slots at once, as writing one takes.
+    fn check_fits(&self, width: usize) -> Result<(), DbError> {
+        let pages = Table::collection_pages(width);
+        if pages > self.bufferpool.capacity() {
+            return Err(DbError::InvalidSchema(format!(
+                "a record of {} slots spans {} pages, more than the buffer pool's {} frames",
+                width,
+                pages,
+                self.bufferpool.capacity()
+            )));
+        }
+        Ok(())
+    }
+
     pub fn table_exists(&self, name: &str) -> bool {
         self.table_names.contains_key(name)
     }
 
     pub fn open(&mut self, path: &str) -> Result<(), DbError> {
-        let sanitized_path = Some(PathBuf::from(Self::DEFAULT_PATH).join(sanitize(path.trim_start_matches('.'))));
-        self.path = sanitized_path.clone();
-        self.disk_manager
-            .write()
-            .set_path(sanitized_path)?;
-
-        let (table_metas, next_table_id) = {
-            let dm = self.disk_manager.read();
-            dm.read_tables()?
-        };
+        let sanitized_path = PathBuf::from(Self::DEFAULT_PATH).join(sanitize(path.trim_start_matches('.')));
+        self.path = Some(sanitized_path.clone());
+        // The write budget carries over, so a crash can be armed before opening.
+        let disk_manager = DiskManager::with_fence(sanitized_path, self.storage.fence())?;
+        disk_manager.migrate_page_files()?;
+        self.open_storage(Arc::new(disk_manager))
+    }
 
-        let name_pairs = {
-            let dm = self.disk_manager.read();
-            dm.read_table_names()?
-        };
+    /// Loads the database kept in `storage`, recovering it first if it was
+    /// not closed cleanly. Whatever this database held before is let go.
+    pub(crate) fn open_storage(&mut self, storage: Arc<dyn StorageBackend>) -> Result<(), DbError> {
+        *self.checkpoint_thread.get_mut() = None;
+        *self.merge_thread.get_mut() = None;
+        self.tables.clear();
+        self.table_names.clear();
+        self.bufferpool = new_bufferpool(&storage, &self.config)?;
+        self.wal = Arc::new(Wal::disabled());
+        storage.set_corruption_policy(self.corruption_policy);
+        self.storage = storage;
+
+        let (table_metas, next_table_id) = self.storage.read_tables()?;
+        let name_pairs = self.storage.read_table_names()?;
+
+        if let Some(log_path) = self.storage.log_path() {
+            self.wal.open(&log_path, self.storage.fence())?;
+        }
+        let records = self.wal.read_all()?;
+        let analysis = LogAnalysis::new(&records);
+
+        // A database that was never closed has no marker but nothing to recover
+        // either. Otherwise a missing marker means the last close did not finish,
+        // so only the catalog and counters are trusted and the rest is rebuilt.
+        let clean = analysis.clean_shutdown || (records.is_empty() && table_metas.is_empty());
 
         self.table_id.set(next_table_id);
 
@@ -109,64 +384,280 @@ impl Database {
             self.table_names.insert(name.clone(), table_id);
 
             if let Some(meta) = table_metas.iter().find(|m| m.table_id == table_id) {
-                let dm = self.disk_manager.read();
+                let storage = &self.storage;
 
-                let page_dir_pairs = dm.read_page_directory(table_id)?;
-                let counters = dm.read_table_counters(table_id)?;
-                let primary_pairs = dm.read_primary_index(table_id)?;
-                drop(dm);
+                // Under `CorruptionPolicy::Quarantine` a damaged page directory
+                // or primary index is rebuilt like after a crash.
+                let (page_dir_pairs, primary_pairs) = if clean {
+                    (
+                        storage.salvage(storage.read_page_directory(table_id))?,
+                        storage.salvage(storage.read_primary_index(table_id))?,
+                    )
+                } else {
+                    (None, None)
+                };
+                let counters = storage.read_table_counters(table_id)?;
 
+                let rebuild_page_directory = page_dir_pairs.is_none();
+                let rebuild_primary_index = clean && primary_pairs.is_none();
                 let table = Arc::new(Table::restore(
                     name,
-                    meta.num_data_columns,
+                    meta.schema.clone(),
                     meta.key_index,
                     table_id,
                     self.bufferpool.clone(),
-                    page_dir_pairs,
+                    page_dir_pairs.unwrap_or_default(),
                     counters,
-                    primary_pairs,
+                    primary_pairs.unwrap_or_default(),
                     self.lock_manager.clone(),
+                    self.wal.clone(),
                 ));
+                // Left behind if the last run stopped before freeing what
+                // its last checkpoint retired.
+                table.page_ranges.delete_free_pages()?;
+                if rebuild_page_directory {
+                    table.rebuild_page_directory()?;
+                }
+                if rebuild_primary_index {
+                    table.rebuild_primary_index()?;
+                }
 
                 self.tables.insert(table_id, table);
             }
         }
+
+        if clean {
+            // The marker only vouches for the files as they are right now.
+            self.wal.truncate()?;
+        } else {
+            self.recover(&analysis, records)?;
+            self.checkpoint()?;
+        }
+
+        for entry in self.table_names.iter() {
+            if let Some(quota) = self.table_quotas.get(entry.key()) {
+                self.bufferpool.set_quota(*entry.value(), *quota)?;
+            }
+        }
+
+        // Only a log on disk has anything to gain from being cut.
+        if let (Some(interval), Some(_)) = (self.config.checkpoint_interval, self.storage.log_path()) {
+            *self.checkpoint_thread.get_mut() = Some(CheckpointThread::spawn(self.checkpointer(), interval));
+        }
+        *self.merge_thread.get_mut() = self.merger();
         Ok(())
     }
 
-    pub fn close(&self) -> Result<(), DbError> {
-        let dm = self.disk_manager.read();
+    /// ARIES-style restart after an unclean shutdown, on top of the state the
+    /// last checkpoint left on disk (records with a RID below a table's
+    /// checkpointed `next_rid` are already in its pages).
+    ///
+    /// Redo repeats the writes of committed and auto-committed work that the
+    /// checkpoint does not have. Writes of loser transactions are never
+    /// repeated; instead the indirection chains are rewired around their tail
+    /// records, and inserts the checkpoint already captured get a compensating
+    /// delete. Finally every touched base record gets its final indirection and
+    /// the primary index is rebuilt from the live base records.
+    fn recover(&self, analysis: &LogAnalysis, records: Vec<LogRecord>) -> Result<(), DbError> {
+        let checkpoint_rid: DashMap<usize, i64> = self
+            .tables
+            .iter()
+            .map(|e| (*e.key(), e.value().rid.current()))
+            .collect();
 
-        dm.write_table_names(&self.table_names)?;
+        // Final indirection of every base record the log touches, and for each
+        // loser tail record the record its successors should point at instead.
+        let heads: DashMap<(usize, i64), i64> = DashMap::new();
+        let aliases: DashMap<(usize, i64), i64> = DashMap::new();
+        let resolve = |table_id: usize, mut rid: i64| {
+            while let Some(prev) = aliases.get(&(table_id, rid)) {
+                rid = *prev;
+            }
+            rid
+        };
+        let mut loser_inserts: Vec<(usize, i64, i64)> = Vec::new();
 
-        dm.write_tables(&self.tables, self.table_id.current())?;
+        // Redo
+        for record in records {
+            match record {
+                LogRecord::CreateTable { table_id, name, schema, key_index } => {
+                    if self.tables.contains_key(&table_id) {
+                        continue;
+                    }
+                    self.table_id.set(self.table_id.current().max(table_id + 1));
+                    self.tables.insert(
+                        table_id,
+                        Arc::new(Table::new(
+                            name.clone(),
+                            schema,
+                            key_index,
+                            table_id,
+                            self.bufferpool.clone(),
+                            self.lock_manager.clone(),
+                            self.wal.clone(),
+                        )),
+                    );
+                    self.table_names.insert(name, table_id);
+                    checkpoint_rid.insert(table_id, 0);
+                }
+                LogRecord::DropTable { table_id } => {
+                    self.tables.remove(&table_id);
+                    self.table_names.retain(|_, id| *id != table_id);
+                }
+                LogRecord::Insert { txn, table_id, rid, cols } => {
+                    let Some(table) = self.tables.get(&table_id).map(|t| t.clone()) else {
+                        continue;
+                    };
+                    table.reserve_rid(rid);
+                    let in_checkpoint = rid < *checkpoint_rid.get(&table_id).unwrap();
+                    match (analysis.is_winner(txn), in_checkpoint) {
+                        (true, false) => table.redo_insert(rid, &cols)?,
+                        (false, true) => {
+                            let key = cols[table.key_index].ok_or(DbError::NullValue(table.key_index))?;
+                            loser_inserts.push((table_id, rid, key));
+                        }
+                        _ => {}
+                    }
+                }
+                LogRecord::Update { txn, table_id, base_rid, tail_rid, prev, .. }
+                | LogRecord::Delete { txn, table_id, base_rid, tail_rid, prev, .. } => {
+                    let Some(table) = self.tables.get(&table_id).map(|t| t.clone()) else {
+                        continue;
+                    };
+                    table.reserve_rid(tail_rid);
+                    let resolved = resolve(table_id, prev);
+                    if !analysis.is_winner(txn) || table.page_directory.get(base_rid).is_err() {
+                        aliases.insert((table_id, tail_rid), resolved);
+                        heads.insert((table_id, base_rid), resolved);
+                        continue;
+                    }
+                    heads.insert((table_id, base_rid), tail_rid);
+                    if tail_rid < *checkpoint_rid.get(&table_id).unwrap() {
+                        if resolved != prev {
+                            table.write_tail_indirection(tail_rid, resolved)?;
+                        }
+                        continue;
+                    }
+                    match record {
+                        LogRecord::Update { cols, .. } => {
+                            let schema = SchemaEncoding::of(&cols);
+                            table.redo_tail(base_rid, tail_rid, resolved, cols, Some(schema))?;
+                        }
+                        LogRecord::Delete { key, .. } => {
+                            let tombstone = vec![None; table.num_data_columns()];
+                            table.redo_tail(base_rid, tail_rid, resolved, tombstone, None)?;
+                            table.indices[table.key_index].remove(key, base_rid);
+                        }
+                        _ => unreachable!(),
+                    }
+                }
+                LogRecord::HeapWrite { table_id, addr, bytes } => {
+                    if let Some(table) = self.tables.get(&table_id).map(|t| t.clone()) {
+                        table.redo_heap_write(addr, &bytes)?;
+                    }
+                }
+                LogRecord::AlterTable { table_id, schema, fill } => {
+                    // Already in place if a checkpoint saw it.
+                    if let Some(table) = self.tables.get(&table_id).map(|t| t.clone())
+                        && *table.schema() != schema
+                    {
+                        table.alter(schema, fill);
+                    }
+                }
+                LogRecord::Commit { .. } | LogRecord::Shutdown => {}
+            }
+        }
 
-        for entry in self.tables.iter() {
-            let table = entry.value();
-            let tid = table.table_id;
-
-            let page_dir = table.page_directory.snapshot();
-            dm.write_page_directory(tid, &page_dir)?;
-
-            let counters = TableCounters {
-                next_rid: table.rid.current(),
-                base_next_addr: table.page_ranges.base_next_addr(),
-                tail_next_addr: table.page_ranges.tail_next_addr(),
-                pid_next_start: table.page_ranges.pid_next_start(),
-                base_collections: table.page_ranges.base_collection_pid_ranges(),
-                tail_collections: table.page_ranges.tail_collection_pid_ranges(),
+        // Undo
+        for (table_id, rid, key) in loser_inserts {
+            let Some(table) = self.tables.get(&table_id).map(|t| t.clone()) else {
+                continue;
             };
+            let prev = match heads.get(&(table_id, rid)) {
+                Some(head) => *head,
+                None => table.read_base_indirection(rid)?.unwrap_or(rid),
+            };
+            let tombstone_rid = table.rid.next();
+            let tombstone = vec![None; table.num_data_columns()];
+            table.redo_tail(rid, tombstone_rid, prev, tombstone, None)?;
+            table.indices[table.key_index].remove(key, rid);
+            heads.insert((table_id, rid), tombstone_rid);
+        }
+
+        for entry in heads.iter() {
+            let (table_id, base_rid) = *entry.key();
+            if let Some(table) = self.tables.get(&table_id) {
+                table.write_base_indirection(base_rid, *entry.value())?;
+            }
+        }
+
+        for entry in self.tables.iter() {
+            entry.value().rebuild_primary_index()?;
+        }
+        Ok(())
+    }
+
+    pub fn close(&self) -> Result<(), DbError> {
+        // Stopped for good: a checkpoint after the marker would cut it again,
+        // and a merge would write pages it does not cover.
+        *self.checkpoint_thread.lock() = None;
+        *self.merge_thread.lock() = None;
+        self.checkpoint()?;
+        self.wal.shutdown()?;
+        Ok(())
+    }
 
-            dm.write_table_counters(tid, &counters)?;
+    /// Takes a checkpoint now, like the background checkpointer does. Queries
+    /// may keep running meanwhile.
+    pub fn checkpoint(&self) -> Result<(), DbError> {
+        self.checkpointer().checkpoint()
+    }
 
-            let primary_pairs = table.indices[table.key_index].all_pairs();
-            dm.write_primary_index(tid, &primary_pairs)?;
+    fn merger(&self) -> Option<MergeThread> {
+        let config = self.config.merge?;
+        Some(MergeThread::spawn(self.tables.clone(), config, self.merges.clone()))
+    }
+
+    fn checkpointer(&self) -> Checkpointer {
+        Checkpointer {
+            tables: self.tables.clone(),
+            table_names: self.table_names.clone(),
+            table_id: self.table_id.clone(),
+            bufferpool: self.bufferpool.clone(),
+            storage: self.storage.clone(),
+            wal: self.wal.clone(),
+            counters: self.checkpoints.clone(),
         }
+    }
+}
 
-        drop(dm);
+/// A quota that `TableQuota::validate` accepts and whose limit leaves room
+/// for every page of a record of `num_columns` columns at once.
+fn check_quota(quota: &TableQuota, num_columns: usize) -> Result<(), DbError> {
+    quota.validate()?;
+    let width = Table::collection_pages(num_columns);
+    if quota.limit.is_some_and(|limit| limit < width) {
+        return Err(DbError::InvalidConfig(format!(
+            "a record of {} columns spans {} pages, more than a limit of {:?} frames",
+            num_columns, width, quota.limit
+        )));
+    }
+    Ok(())
+}
 
-        self.bufferpool.evict_all()?;
+fn new_bufferpool(storage: &Arc<dyn StorageBackend>, config: &DatabaseConfig) -> Result<Arc<BufferPool>, DbError> {
+    let pool = BufferPool::with_options(
+        storage.clone(),
+        config.buffer_pool.frames()?,
+        config.eviction,
+        config.flusher,
+    );
+    pool.set_read_ahead(config.read_ahead);
+    Ok(Arc::new(pool))
+}
 
-        Ok(())
+impl Default for Database {
+    fn default() -> Self {
+        Self::new()
     }
-}
\ No newline at end of file
+}
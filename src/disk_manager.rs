@@ -1,124 +1,416 @@
-use crate::bufferpool::DiskError;
+use crate::bufferpool::{Corruption, DiskError};
+use crate::checksum::{crc32c, Crc32c};
 use crate::page::Page;
 use crate::page_collection::PageId;
+use crate::schema::Schema;
+use crate::page_range::{CollectionMeta, PageRange};
 use crate::table::Table;
 use dashmap::DashMap;
 use std::fs::{self, File, OpenOptions};
 use std::io::{BufReader, BufWriter, Read, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use parking_lot::{Mutex, RwLock};
 use std::sync::Arc;
 use crate::iterators::PhysicalAddress;
-
+use crate::segment::Segment;
+use crate::storage::StorageBackend;
+
+/// Every file is replaced atomically: the new contents go to a temp file that
+/// is fsynced and renamed over the target, and the directory is fsynced so the
+/// rename itself survives a crash.
+///
+/// Table metadata (`catalog`, `table_names`, and each table's `page_directory`,
+/// `counters` and `primary_index`) is written as a whole generation at a time.
+/// A generation's files carry its number in their name and only become current
+/// once `MANIFEST` points at them, so a checkpoint that dies halfway leaves the
+/// previous generation intact. Generation 0 is the layout from before the
+/// manifest existed, with no number in the file names. From metadata format 1
+/// on, every metadata file ends in a CRC-32C of its contents; the manifest
+/// records which format its generation was written in.
+///
+/// Pages are the exception: each table keeps them in one `Segment` that is
+/// written in place and made durable by `sync_pages` at checkpoints.
 pub struct DiskManager {
     base_path: PathBuf,
+    fence: Arc<WriteFence>,
+    segments: DashMap<usize, Arc<Segment>>,
+    /// Generation the reads come from.
+    generation: AtomicU64,
+    /// Generation the metadata writes go to.
+    pending: AtomicU64,
+    /// Metadata format of `generation`.
+    format: AtomicU32,
+    policy: RwLock<CorruptionPolicy>,
+    quarantined: Mutex<Vec<Corruption>>,
+    io: IoCounters,
 }
 
-pub struct TableCounters {
-    pub next_rid: i64,
-    pub base_next_addr: usize,
-    pub tail_next_addr: usize,
-    pub pid_next_start: usize,
-    pub base_collections: Vec<(usize, usize)>,
-    pub tail_collections: Vec<(usize, usize)>,
+/// Reads and writes of the files since the disk manager was created or its
+/// stats were last reset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IoStats {
+    pub page_reads: u64,
+    pub page_writes: u64,
+    /// Whole metadata files, the manifest among them.
+    pub metadata_reads: u64,
+    pub metadata_writes: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    /// Calls to `sync_pages`, one per checkpoint.
+    pub syncs: u64,
 }
 
-impl DiskManager {
-    pub fn new<P: Into<PathBuf>>(base_path: P) -> Result<Self, DiskError> {
-        let base_path = base_path.into();
-        fs::create_dir_all(&base_path)?;
-        Ok(Self { base_path })
+#[derive(Default)]
+struct IoCounters {
+    page_reads: AtomicU64,
+    page_writes: AtomicU64,
+    metadata_reads: AtomicU64,
+    metadata_writes: AtomicU64,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    syncs: AtomicU64,
+}
+
+impl IoCounters {
+    fn read(&self, counter: &AtomicU64, bytes: usize) {
+        counter.fetch_add(1, Ordering::Relaxed);
+        self.bytes_read.fetch_add(bytes as u64, Ordering::Relaxed);
     }
 
-    pub fn set_path(&mut self, path: Option<PathBuf>) -> Result<(), DiskError> {
-        self.base_path = path.unwrap();
-        fs::create_dir_all(&self.base_path)?;
-        Ok(())
+    fn written(&self, counter: &AtomicU64, bytes: usize) {
+        counter.fetch_add(1, Ordering::Relaxed);
+        self.bytes_written.fetch_add(bytes as u64, Ordering::Relaxed);
     }
 
-    fn page_path(&self, pid: PageId) -> PathBuf {
-        self.base_path
-            .join("table")
-            .join(pid.table_id.to_string())
-            .join(pid.page_num.to_string())
+    fn counters(&self) -> [&AtomicU64; 7] {
+        [
+            &self.page_reads,
+            &self.page_writes,
+            &self.metadata_reads,
+            &self.metadata_writes,
+            &self.bytes_read,
+            &self.bytes_written,
+            &self.syncs,
+        ]
     }
+}
 
-    pub fn read_page(&self, pid: PageId) -> Result<Page, DiskError> {
-        let path = self.page_path(pid);
+/// What to do when a checksum does not match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CorruptionPolicy {
+    /// Return `DiskError::ChecksumMismatch` to the caller.
+    #[default]
+    Fail,
+    /// Copy the damaged bytes to `quarantine/`, remember the damage, and carry
+    /// on: a page reads as empty, and the page directory and primary index are
+    /// rebuilt from the pages. Damage to anything that cannot be rebuilt still
+    /// fails.
+    Quarantine,
+}
 
-        if !path.exists() {
-            return Err(DiskError::PageNotFound(pid));
+/// Budget of durable writes shared by the page files, the metadata files and
+/// the write-ahead log. Once armed, the write that exhausts it and every write
+/// after it fail as if the process had been killed right there, which is how
+/// the recovery tests crash the database at each write point in turn.
+pub struct WriteFence {
+    remaining: AtomicUsize,
+    performed: AtomicUsize,
+}
+
+impl WriteFence {
+    const UNLIMITED: usize = usize::MAX;
+
+    pub fn new() -> Self {
+        Self {
+            remaining: AtomicUsize::new(Self::UNLIMITED),
+            performed: AtomicUsize::new(0),
         }
+    }
 
-        let file = File::open(&path)?;
-        let mut reader = BufReader::new(file);
+    /// Lets `writes` more writes through, then fails all of them.
+    pub fn arm(&self, writes: usize) {
+        self.remaining.store(writes, Ordering::SeqCst);
+    }
 
-        // Read all bytes
-        let mut buffer = Vec::new();
-        reader.read_to_end(&mut buffer)?;
+    /// Number of writes that have been let through so far.
+    pub fn performed(&self) -> usize {
+        self.performed.load(Ordering::SeqCst)
+    }
 
-        // Deserialize
-        self.deserialize_page(&buffer)
+    pub fn check(&self) -> Result<(), DiskError> {
+        self.remaining
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| match n {
+                Self::UNLIMITED => Some(n),
+                0 => None,
+                n => Some(n - 1),
+            })
+            .map_err(|_| DiskError::IoError(std::io::Error::other("write fence reached")))?;
+        self.performed.fetch_add(1, Ordering::SeqCst);
+        Ok(())
     }
+}
 
-    pub fn write_page(&self, pid: PageId, page: &Page) -> Result<(), DiskError> {
-        let path = self.page_path(pid);
+impl Default for WriteFence {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct TableCounters {
+    pub next_rid: i64,
+    pub base_next_addr: usize,
+    pub tail_next_addr: usize,
+    pub pid_next_start: usize,
+    /// Collections not retired, with their live counts; those from before
+    /// the counts are numbered in order and never retired.
+    pub base_collections: Vec<CollectionMeta>,
+    pub tail_collections: Vec<CollectionMeta>,
+    /// PID ranges to hand out again, as starts and ends.
+    pub free_pids: Vec<(usize, usize)>,
+    /// The end of the value heap's space, its chunk numbers and first PIDs,
+    /// and its free extents as starts and lengths in words.
+    pub heap_next_word: usize,
+    pub heap_chunks: Vec<(usize, usize)>,
+    pub heap_free: Vec<(usize, usize)>,
+    /// Pages in each heap chunk; zero for counters from before it could be
+    /// other than the pages of a collection.
+    pub heap_pages_per_chunk: usize,
+    /// Slots of added columns and what older base records read in them.
+    pub fill: Vec<(usize, i64)>,
+}
 
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)?;
+impl TableCounters {
+    /// The counters of `table` as they stand.
+    pub fn of(table: &Table) -> Self {
+        let ranges = &table.page_ranges;
+        Self {
+            next_rid: table.rid.current(),
+            base_next_addr: ranges.base_next_addr(),
+            tail_next_addr: ranges.tail_next_addr(),
+            pid_next_start: ranges.pid_next_start(),
+            base_collections: ranges.base_collections(),
+            tail_collections: ranges.tail_collections(),
+            free_pids: table.free_pid_ranges(),
+            heap_next_word: table.heap.next_word(),
+            heap_chunks: table.heap.chunk_pids(),
+            heap_free: table.heap.free_extents(),
+            heap_pages_per_chunk: table.heap.pages_per_chunk(),
+            fill: ranges.slots().fill.into_iter().collect(),
         }
+    }
+}
 
-        let data = self.serialize_page(page)?;
+impl DiskManager {
+    pub fn new<P: Into<PathBuf>>(base_path: P) -> Result<Self, DiskError> {
+        Self::with_fence(base_path, Arc::new(WriteFence::new()))
+    }
 
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&path)?;
+    /// A disk manager whose writes count against `fence`.
+    pub fn with_fence<P: Into<PathBuf>>(base_path: P, fence: Arc<WriteFence>) -> Result<Self, DiskError> {
+        let base_path = base_path.into();
+        fs::create_dir_all(&base_path)?;
+        let (generation, format) = read_manifest(&base_path)?;
+        Ok(Self {
+            base_path,
+            fence,
+            segments: DashMap::new(),
+            generation: AtomicU64::new(generation),
+            pending: AtomicU64::new(generation),
+            format: AtomicU32::new(format),
+            policy: RwLock::new(CorruptionPolicy::default()),
+            quarantined: Mutex::new(Vec::new()),
+            io: IoCounters::default(),
+        })
+    }
 
-        let mut writer = BufWriter::new(file);
-        writer.write_all(&data)?;
-        writer.flush()?;
+    pub fn corruption_policy(&self) -> CorruptionPolicy {
+        *self.policy.read()
+    }
 
+    /// Under `CorruptionPolicy::Quarantine`, records a checksum mismatch and
+    /// sets `data` aside in `quarantine/`, returning `Ok`. Any other error, or
+    /// any error under `CorruptionPolicy::Fail`, is handed back.
+    fn quarantine_data(&self, err: DiskError, data: &[u8]) -> Result<(), DiskError> {
+        let DiskError::ChecksumMismatch(corruption) = err else {
+            return Err(err);
+        };
+        if self.corruption_policy() == CorruptionPolicy::Fail {
+            return Err(DiskError::ChecksumMismatch(corruption));
+        }
+        let dir = self.base_path.join("quarantine");
+        fs::create_dir_all(&dir)?;
+        let mut name = corruption
+            .table_id
+            .map_or("db".to_string(), |t| format!("table-{}", t));
+        match corruption.page_num {
+            Some(page_num) => name.push_str(&format!("-page-{}.bin", page_num)),
+            None => {
+                let file = corruption.file.file_name().unwrap_or_default().to_string_lossy();
+                name.push_str(&format!("-{}", file));
+            }
+        }
+        fs::write(dir.join(name), data)?;
+        self.quarantined.lock().push(corruption);
         Ok(())
     }
 
-    pub fn delete_page(&self, pid: PageId) -> Result<(), DiskError> {
-        let path = self.page_path(pid);
-
-        if path.exists() {
-            fs::remove_file(path)?;
+    /// Best effort: a leftover file is never read again, it only takes space.
+    fn remove_generation(&self, generation: u64) {
+        for name in ["catalog", "table_names"] {
+            let _ = fs::remove_file(generation_path(&self.base_path, name, generation));
+        }
+        let Ok(dirs) = fs::read_dir(self.base_path.join("table")) else {
+            return;
+        };
+        for dir in dirs.flatten() {
+            for name in ["page_directory", "counters", "primary_index"] {
+                let _ = fs::remove_file(generation_path(&dir.path(), name, generation));
+            }
         }
+    }
 
-        Ok(())
+    fn read_path(&self, dir: &Path, name: &str) -> PathBuf {
+        generation_path(dir, name, self.generation.load(Ordering::SeqCst))
     }
 
-    pub fn page_exists(&self, pid: PageId) -> bool {
-        self.page_path(pid).exists()
+    fn write_path(&self, dir: &Path, name: &str) -> PathBuf {
+        generation_path(dir, name, self.pending.load(Ordering::SeqCst))
     }
 
-    fn serialize_page(&self, page: &Page) -> Result<Vec<u8>, DiskError> {
-        let mut buffer = Vec::new();
+    fn segment(&self, table_id: usize) -> Result<Arc<Segment>, DiskError> {
+        if let Some(segment) = self.segments.get(&table_id) {
+            return Ok(segment.clone());
+        }
+        let dir = self.table_meta_dir(table_id);
+        fs::create_dir_all(&dir)?;
+        let segment = self
+            .segments
+            .entry(table_id)
+            .or_try_insert_with(|| Segment::open(&dir, Self::PAGE_BYTES).map(Arc::new))?;
+        Ok(segment.clone())
+    }
 
-        let len = page.len() as u64;
-        buffer.extend_from_slice(&len.to_be_bytes());
+    /// Moves pages from the old layout, one file per page at
+    /// `table/<table_id>/<page_num>`, into the table segments. The old files are
+    /// only removed once the segments are synced, and a migration that was cut
+    /// short simply runs again on the next open. Returns the number of pages moved.
+    pub fn migrate_page_files(&self) -> Result<usize, DiskError> {
+        let Ok(dirs) = fs::read_dir(self.base_path.join("table")) else {
+            return Ok(0);
+        };
+        let mut moved = Vec::new();
+        for dir in dirs {
+            let dir = dir?.path();
+            let Some(table_id) = file_number(&dir) else {
+                continue;
+            };
+            for entry in fs::read_dir(&dir)? {
+                let path = entry?.path();
+                let Some(page_num) = file_number(&path) else {
+                    continue;
+                };
+                let page = self.deserialize_page(&read_file(&path)?)?;
+                self.write_page(PageId { table_id, page_num }, &page)?;
+                moved.push(path);
+            }
+        }
+        self.sync_pages()?;
+        for path in &moved {
+            fs::remove_file(path)?;
+        }
+        Ok(moved.len())
+    }
 
-        for i in 0..page.len() {
-            let value = page.read(i).map_err(|_| DiskError::SerializationError)?;
+    /// Version 2 page layout, all little-endian:
+    ///
+    /// | offset | size | field                                     |
+    /// |--------|------|-------------------------------------------|
+    /// | 0      | 4    | format version                            |
+    /// | 4      | 4    | number of records                         |
+    /// | 8      | 4    | CRC-32C of the page with this field zeroed |
+    /// | 12     | 4    | reserved, 0                               |
+    /// | 16     | 64   | null bitmap, bit set = slot holds a value |
+    /// | 80     | 4096 | 512 `i64` values, 0 where null            |
+    ///
+    /// Version 1 is the same without the checksum and reserved fields. Version
+    /// 0 is the older variable-length encoding (a big-endian `u64` length, whose
+    /// high bytes read as version 0, then a tag and optional value per slot).
+    /// Both are still decoded so old pages can be read and migrated.
+    pub const PAGE_FORMAT_VERSION: u32 = 2;
+    const PAGE_HEADER_SIZE: usize = 16;
+    const PAGE_CHECKSUM: std::ops::Range<usize> = 8..12;
+    pub const PAGE_BYTES: usize = Self::PAGE_HEADER_SIZE + Page::BITMAP_BYTES + Page::PAGE_SIZE * 8;
 
-            match value {
-                Some(val) => {
-                    buffer.push(1); // Tag: Some(i64)
-                    buffer.extend_from_slice(&val.to_be_bytes());
-                }
-                None => {
-                    buffer.push(0); // Tag: None
-                }
-            }
+    fn serialize_page(&self, page: &Page) -> Result<Vec<u8>, DiskError> {
+        let mut buffer = Vec::with_capacity(Self::PAGE_BYTES);
+        buffer.extend_from_slice(&Self::PAGE_FORMAT_VERSION.to_le_bytes());
+        buffer.extend_from_slice(&(page.len() as u32).to_le_bytes());
+        buffer.extend_from_slice(&[0; 8]);
+        buffer.extend_from_slice(page.present());
+        for value in page.values() {
+            buffer.extend_from_slice(&value.to_le_bytes());
         }
+        let checksum = crc32c(&buffer);
+        buffer[Self::PAGE_CHECKSUM].copy_from_slice(&checksum.to_le_bytes());
         Ok(buffer)
     }
 
+    /// Checks the checksum of a page read from a segment. Only version 2
+    /// pages are written to one, so any other version is a damaged one too:
+    /// older versions carry no checksum, and are only read to migrate them.
+    fn verify_page(&self, pid: PageId, data: &[u8]) -> Result<(), DiskError> {
+        let expected = u32::from_le_bytes(data[Self::PAGE_CHECKSUM].try_into().unwrap());
+        let actual = Crc32c::new()
+            .update(&data[..Self::PAGE_CHECKSUM.start])
+            .update(&[0; 4])
+            .update(&data[Self::PAGE_CHECKSUM.end..Self::PAGE_BYTES])
+            .finish();
+        if data[0..4] != Self::PAGE_FORMAT_VERSION.to_le_bytes() || expected != actual {
+            return Err(DiskError::ChecksumMismatch(Corruption {
+                table_id: Some(pid.table_id),
+                page_num: Some(pid.page_num),
+                file: self.table_meta_dir(pid.table_id).join(Segment::DATA_FILE),
+                expected,
+                actual,
+            }));
+        }
+        Ok(())
+    }
+
     fn deserialize_page(&self, data: &[u8]) -> Result<Page, DiskError> {
+        if data.len() < 4 {
+            return Err(DiskError::CorruptedPage("Data too short".into()));
+        }
+        match u32::from_le_bytes(data[0..4].try_into().unwrap()) {
+            0 => self.deserialize_page_v0(data),
+            1 => self.deserialize_fixed_page(data, 8),
+            Self::PAGE_FORMAT_VERSION => self.deserialize_fixed_page(data, Self::PAGE_HEADER_SIZE),
+            other => Err(DiskError::CorruptedPage(format!("Unknown page format version: {}", other))),
+        }
+    }
+
+    fn deserialize_fixed_page(&self, data: &[u8], header_size: usize) -> Result<Page, DiskError> {
+        let bitmap_end = header_size + Page::BITMAP_BYTES;
+        let end = bitmap_end + Page::PAGE_SIZE * 8;
+        if data.len() < end {
+            return Err(DiskError::CorruptedPage("Data too short".into()));
+        }
+        let len = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+        if len > Page::PAGE_SIZE {
+            return Err(DiskError::CorruptedPage(format!("Invalid page length: {}", len)));
+        }
+
+        let present: [u8; Page::BITMAP_BYTES] = data[header_size..bitmap_end].try_into().unwrap();
+        let mut values = [0i64; Page::PAGE_SIZE];
+        for (value, bytes) in values.iter_mut().zip(data[bitmap_end..end].chunks_exact(8)) {
+            *value = i64::from_le_bytes(bytes.try_into().unwrap());
+        }
+        Ok(Page::from_parts(values, present, len))
+    }
+
+    fn deserialize_page_v0(&self, data: &[u8]) -> Result<Page, DiskError> {
         if data.len() < 8 {
             return Err(DiskError::CorruptedPage("Data too short".into()));
         }
@@ -169,12 +461,166 @@ impl DiskManager {
         Ok(page)
     }
 
-    pub fn write_tables(
+    fn table_meta_dir(&self, table_id: usize) -> PathBuf {
+        self.base_path.join("table").join(table_id.to_string())
+    }
+
+    /// The segment file holding `pid` and the offset of its slot.
+    #[cfg(test)]
+    pub(crate) fn page_location(&self, pid: PageId) -> (PathBuf, u64) {
+        let path = self.table_meta_dir(pid.table_id).join(Segment::DATA_FILE);
+        (path, (pid.page_num * Self::PAGE_BYTES) as u64)
+    }
+
+    /// Writes a metadata file with its checksum appended.
+    fn write_metadata(&self, path: &Path, mut data: Vec<u8>) -> Result<(), DiskError> {
+        let checksum = crc32c(&data);
+        data.extend_from_slice(&checksum.to_be_bytes());
+        self.write_file(path, &data)
+    }
+
+    /// Reads a metadata file of the current generation and, if its format has
+    /// one, verifies and strips the checksum.
+    fn read_metadata(&self, path: &Path, table_id: Option<usize>) -> Result<Vec<u8>, DiskError> {
+        let mut data = read_file(path)?;
+        self.io.read(&self.io.metadata_reads, data.len());
+        if self.format.load(Ordering::SeqCst) == 0 {
+            return Ok(data);
+        }
+        verify_trailer(&mut data, path, table_id)?;
+        Ok(data)
+    }
+
+    /// Replaces `path` with `data` so that after a crash the file holds either
+    /// the old or the new contents, never a mix.
+    fn write_file(&self, path: &Path, data: &[u8]) -> Result<(), DiskError> {
+        self.fence.check()?;
+        let tmp = path.with_extension("tmp");
+        let file = OpenOptions::new().write(true).create(true).truncate(true).open(&tmp)?;
+        let mut w = BufWriter::new(file);
+        w.write_all(data)?;
+        w.flush()?;
+        w.get_ref().sync_all()?;
+        fs::rename(&tmp, path)?;
+        sync_parent(path)?;
+        self.io.written(&self.io.metadata_writes, data.len());
+        Ok(())
+    }
+}
+
+impl StorageBackend for DiskManager {
+    fn fence(&self) -> Arc<WriteFence> {
+        self.fence.clone()
+    }
+
+    fn set_corruption_policy(&self, policy: CorruptionPolicy) {
+        *self.policy.write() = policy;
+    }
+
+    /// Everything quarantined since the database was opened.
+    fn quarantined(&self) -> Vec<Corruption> {
+        self.quarantined.lock().clone()
+    }
+
+    fn io_stats(&self) -> IoStats {
+        let [page_reads, page_writes, metadata_reads, metadata_writes, bytes_read, bytes_written, syncs] =
+            self.io.counters().map(|counter| counter.load(Ordering::Relaxed));
+        IoStats { page_reads, page_writes, metadata_reads, metadata_writes, bytes_read, bytes_written, syncs }
+    }
+
+    fn reset_io_stats(&self) {
+        for counter in self.io.counters() {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn quarantine(&self, err: DiskError) -> Result<(), DiskError> {
+        let data = match &err {
+            DiskError::ChecksumMismatch(corruption) => fs::read(&corruption.file).unwrap_or_default(),
+            _ => return Err(err),
+        };
+        self.quarantine_data(err, &data)
+    }
+
+    /// Directs the metadata writes that follow to a fresh generation. Until
+    /// `commit_generation` the reads still see the current one.
+    fn begin_generation(&self) {
+        self.pending
+            .store(self.generation.load(Ordering::SeqCst) + 1, Ordering::SeqCst);
+    }
+
+    /// Points `MANIFEST` at the generation written since `begin_generation`
+    /// and removes the files of the one it replaces.
+    fn commit_generation(&self) -> Result<(), DiskError> {
+        let pending = self.pending.load(Ordering::SeqCst);
+        let mut manifest = pending.to_be_bytes().to_vec();
+        manifest.extend_from_slice(&METADATA_FORMAT.to_be_bytes());
+        manifest.extend_from_slice(&crc32c(&manifest).to_be_bytes());
+        self.write_file(&self.base_path.join(MANIFEST), &manifest)?;
+        self.format.store(METADATA_FORMAT, Ordering::SeqCst);
+        let previous = self.generation.swap(pending, Ordering::SeqCst);
+        if previous != pending {
+            self.remove_generation(previous);
+        }
+        Ok(())
+    }
+
+    fn log_path(&self) -> Option<PathBuf> {
+        Some(self.base_path.join("wal.log"))
+    }
+
+    fn read_page(&self, pid: PageId) -> Result<Page, DiskError> {
+        let data = self
+            .segment(pid.table_id)?
+            .read(pid.page_num)?
+            .ok_or(DiskError::PageNotFound(pid))?;
+        self.io.read(&self.io.page_reads, data.len());
+        if let Err(err) = self.verify_page(pid, &data) {
+            self.quarantine_data(err, &data)?;
+            return Ok(Page::default());
+        }
+        self.deserialize_page(&data)
+    }
+
+    fn write_page(&self, pid: PageId, page: &Page) -> Result<(), DiskError> {
+        let data = self.serialize_page(page)?;
+
+        self.fence.check()?;
+        self.segment(pid.table_id)?.write(pid.page_num, &data)?;
+        self.io.written(&self.io.page_writes, data.len());
+        Ok(())
+    }
+
+    fn delete_page(&self, pid: PageId) -> Result<(), DiskError> {
+        let segment = self.segment(pid.table_id)?;
+        if segment.is_allocated(pid.page_num) {
+            self.fence.check()?;
+            segment.free(pid.page_num)?;
+        }
+
+        Ok(())
+    }
+
+    fn page_exists(&self, pid: PageId) -> bool {
+        self.segment(pid.table_id)
+            .is_ok_and(|segment| segment.is_allocated(pid.page_num))
+    }
+
+    /// Forces every page written so far to stable storage.
+    fn sync_pages(&self) -> Result<(), DiskError> {
+        for segment in self.segments.iter() {
+            segment.sync()?;
+        }
+        self.io.syncs.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn write_tables(
         &self,
         tables: &DashMap<usize, Arc<Table>>,
         next_table_id: usize,
     ) -> Result<(), DiskError> {
-        let path = self.base_path.join("catalog.bin");
+        let path = self.write_path(&self.base_path, "catalog");
 
         let mut buffer = Vec::new();
         buffer.extend_from_slice(&(next_table_id as u64).to_be_bytes());
@@ -182,23 +628,26 @@ impl DiskManager {
         for entry in tables.iter() {
             let t = entry.value();
             buffer.extend_from_slice(&(t.table_id as u64).to_be_bytes());
-            buffer.extend_from_slice(&(t.num_data_columns as u64).to_be_bytes());
+            buffer.extend_from_slice(&(t.num_data_columns() as u64).to_be_bytes());
             buffer.extend_from_slice(&(t.key_index as u64).to_be_bytes());
             buffer.extend_from_slice(&t.rid.current().to_be_bytes());
         }
+        // The schemas follow the entries, in the same order, so that the
+        // entries keep the layout of the formats before them, and the slots
+        // of their columns follow the schemas.
+        let schemas: Vec<Arc<Schema>> = tables.iter().map(|entry| entry.value().schema()).collect();
+        for schema in &schemas {
+            schema.serialize(&mut buffer);
+        }
+        for schema in &schemas {
+            schema.serialize_slots(&mut buffer);
+        }
 
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&path)?;
-        let mut w = BufWriter::new(file);
-        w.write_all(&buffer)?;
-        w.flush()?;
-        Ok(())
+        self.write_metadata(&path, buffer)
     }
-    pub fn write_table_names(&self, table_names: &DashMap<String, usize>) -> Result<(), DiskError> {
-        let path = self.base_path.join("table_names.bin");
+
+    fn write_table_names(&self, table_names: &DashMap<String, usize>) -> Result<(), DiskError> {
+        let path = self.write_path(&self.base_path, "table_names");
 
         let mut buffer = Vec::new();
         buffer.extend_from_slice(&(table_names.len() as u64).to_be_bytes());
@@ -209,25 +658,16 @@ impl DiskManager {
             buffer.extend_from_slice(&(*entry.value() as u64).to_be_bytes());
         }
 
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&path)?;
-        let mut w = BufWriter::new(file);
-        w.write_all(&buffer)?;
-        w.flush()?;
-        Ok(())
+        self.write_metadata(&path, buffer)
     }
 
-    pub fn read_tables(&self) -> Result<(Vec<TableMeta>, usize), DiskError> {
-        let path = self.base_path.join("catalog.bin");
+    fn read_tables(&self) -> Result<(Vec<TableMeta>, usize), DiskError> {
+        let path = self.read_path(&self.base_path, "catalog");
         if !path.exists() {
             return Ok((vec![], 0));
         }
 
-        let mut data = Vec::new();
-        BufReader::new(File::open(&path)?).read_to_end(&mut data)?;
+        let data = self.read_metadata(&path, None)?;
 
         if data.len() < 16 {
             return Err(DiskError::CorruptedPage("Catalog too short".into()));
@@ -280,24 +720,38 @@ impl DiskManager {
             file_offset += 8;
             tables.push(TableMeta {
                 table_id,
-                num_data_columns,
+                schema: Schema::ints(num_data_columns),
                 key_index,
                 next_rid,
                 name: String::new(),
             });
         }
+        let format = self.format.load(Ordering::SeqCst);
+        if format >= 2 {
+            let widths: Vec<usize> = tables.iter().map(|table| table.schema.width()).collect();
+            for table in &mut tables {
+                table.schema = Schema::deserialize(&data, &mut file_offset, format >= 4)?;
+            }
+            if format >= 5 {
+                for table in &mut tables {
+                    table.schema.deserialize_slots(&data, &mut file_offset)?;
+                }
+            }
+            if tables.iter().zip(widths).any(|(table, width)| table.schema.width() != width) {
+                return Err(DiskError::CorruptedPage("Schema does not match num_data_columns".into()));
+            }
+        }
 
         Ok((tables, next_table_id))
     }
 
-    pub fn read_table_names(&self) -> Result<Vec<(String, usize)>, DiskError> {
-        let path = self.base_path.join("table_names.bin");
+    fn read_table_names(&self) -> Result<Vec<(String, usize)>, DiskError> {
+        let path = self.read_path(&self.base_path, "table_names");
         if !path.exists() {
             return Ok(vec![]);
         }
 
-        let mut data = Vec::new();
-        BufReader::new(File::open(&path)?).read_to_end(&mut data)?;
+        let data = self.read_metadata(&path, None)?;
 
         if data.len() < 8 {
             return Err(DiskError::CorruptedPage("Table names too short".into()));
@@ -347,12 +801,9 @@ impl DiskManager {
 
         Ok(result)
     }
-    fn table_meta_dir(&self, table_id: usize) -> PathBuf {
-        self.base_path.join("table").join(table_id.to_string())
-    }
 
-    pub fn write_page_directory(&self, table_id: usize, pairs: &[(i64, PhysicalAddress)]) -> Result<(), DiskError> {
-        let path = self.table_meta_dir(table_id).join("page_directory.bin");
+    fn write_page_directory(&self, table_id: usize, pairs: &[(i64, PhysicalAddress)]) -> Result<(), DiskError> {
+        let path = self.write_path(&self.table_meta_dir(table_id), "page_directory");
         fs::create_dir_all(path.parent().unwrap())?;
 
         let mut buf = Vec::new();
@@ -362,15 +813,15 @@ impl DiskManager {
             buf.extend_from_slice(&(addr.offset as i64).to_be_bytes());
             buf.extend_from_slice(&(addr.collection_num as i64).to_be_bytes());
         }
-        write_file(&path, &buf)
+        self.write_metadata(&path, buf)
     }
 
-    pub fn read_page_directory(&self, table_id: usize) -> Result<Vec<(i64,PhysicalAddress)>, DiskError> {
-        let path = self.table_meta_dir(table_id).join("page_directory.bin");
+    fn read_page_directory(&self, table_id: usize) -> Result<Vec<(i64,PhysicalAddress)>, DiskError> {
+        let path = self.read_path(&self.table_meta_dir(table_id), "page_directory");
         if !path.exists() {
             return Ok(vec![]);
         }
-        let data = fs::read(path)?;
+        let data = self.read_metadata(&path, Some(table_id))?;
         let mut file_offset = 0;
         let len = read_u64(&data,&mut file_offset)?;
         let mut pairs = Vec::with_capacity(len as usize);
@@ -383,12 +834,12 @@ impl DiskManager {
     Ok(pairs)
     }
 
-    pub fn write_table_counters(
+    fn write_table_counters(
         &self,
         table_id: usize,
         c: &TableCounters,
     ) -> Result<(), DiskError> {
-        let path = self.table_meta_dir(table_id).join("counters.bin");
+        let path = self.write_path(&self.table_meta_dir(table_id), "counters");
         fs::create_dir_all(path.parent().unwrap())?;
 
         let mut buf = Vec::new();
@@ -397,67 +848,114 @@ impl DiskManager {
         buf.extend_from_slice(&(c.tail_next_addr as u64).to_be_bytes());
         buf.extend_from_slice(&(c.pid_next_start as u64).to_be_bytes());
 
-        buf.extend_from_slice(&(c.base_collections.len() as u64).to_be_bytes());
-        for (start, end) in &c.base_collections {
-            buf.extend_from_slice(&(*start as u64).to_be_bytes());
-            buf.extend_from_slice(&(*end as u64).to_be_bytes());
+        for collections in [&c.base_collections, &c.tail_collections] {
+            buf.extend_from_slice(&(collections.len() as u64).to_be_bytes());
+            for collection in collections {
+                buf.extend_from_slice(&(collection.start as u64).to_be_bytes());
+                buf.extend_from_slice(&(collection.end as u64).to_be_bytes());
+            }
+        }
+        buf.extend_from_slice(&(c.heap_next_word as u64).to_be_bytes());
+        for pairs in [&c.heap_chunks, &c.heap_free] {
+            buf.extend_from_slice(&(pairs.len() as u64).to_be_bytes());
+            for (a, b) in pairs {
+                buf.extend_from_slice(&(*a as u64).to_be_bytes());
+                buf.extend_from_slice(&(*b as u64).to_be_bytes());
+            }
+        }
+        buf.extend_from_slice(&(c.heap_pages_per_chunk as u64).to_be_bytes());
+        buf.extend_from_slice(&(c.fill.len() as u64).to_be_bytes());
+        for (slot, value) in &c.fill {
+            buf.extend_from_slice(&(*slot as u64).to_be_bytes());
+            buf.extend_from_slice(&value.to_be_bytes());
+        }
+        for collections in [&c.base_collections, &c.tail_collections] {
+            for collection in collections {
+                buf.extend_from_slice(&(collection.num as u64).to_be_bytes());
+                buf.extend_from_slice(&(collection.live as u64).to_be_bytes());
+            }
         }
-        buf.extend_from_slice(&(c.tail_collections.len() as u64).to_be_bytes());
-        for (start, end) in &c.tail_collections {
+        buf.extend_from_slice(&(c.free_pids.len() as u64).to_be_bytes());
+        for (start, end) in &c.free_pids {
             buf.extend_from_slice(&(*start as u64).to_be_bytes());
             buf.extend_from_slice(&(*end as u64).to_be_bytes());
         }
-        write_file(&path, &buf)
+        self.write_metadata(&path, buf)
     }
 
-    pub fn read_table_counters(&self, table_id: usize) -> Result<TableCounters, DiskError> {
-        let path = self.table_meta_dir(table_id).join("counters.bin");
+    fn read_table_counters(&self, table_id: usize) -> Result<TableCounters, DiskError> {
+        let path = self.read_path(&self.table_meta_dir(table_id), "counters");
         if !path.exists() {
-            return Ok(TableCounters {
-                next_rid: 0,
-                base_next_addr: 0,
-                tail_next_addr: 0,
-                pid_next_start: 0,
-                base_collections: vec![],
-                tail_collections: vec![],
-            });
+            return Ok(TableCounters::default());
         }
-        let data = read_file(&path)?;
+        let data = self.read_metadata(&path, Some(table_id))?;
         let mut offset = 0;
         let next_rid = read_i64(&data, &mut offset)?;
         let base_next_addr = read_u64(&data, &mut offset)? as usize;
         let tail_next_addr = read_u64(&data, &mut offset)? as usize;
         let pid_next_start = read_u64(&data, &mut offset)? as usize;
 
-        let base_count = read_u64(&data, &mut offset)? as usize;
-        let mut base_collections = Vec::with_capacity(base_count);
-        for _ in 0..base_count {
-            let start = read_u64(&data, &mut offset)? as usize;
-            let end = read_u64(&data, &mut offset)? as usize;
-            base_collections.push((start, end));
-        }
-        let tail_count = read_u64(&data, &mut offset)? as usize;
-        let mut tail_collections = Vec::with_capacity(tail_count);
-        for _ in 0..tail_count {
-            let start = read_u64(&data, &mut offset)? as usize;
-            let end = read_u64(&data, &mut offset)? as usize;
-            tail_collections.push((start, end));
-        }
-        Ok(TableCounters {
+        let mut ranges = [Vec::new(), Vec::new()];
+        for collections in &mut ranges {
+            let count = read_u64(&data, &mut offset)? as usize;
+            for num in 0..count {
+                let start = read_u64(&data, &mut offset)? as usize;
+                let end = read_u64(&data, &mut offset)? as usize;
+                collections.push(CollectionMeta { num, start, end, live: PageRange::UNCOUNTED });
+            }
+        }
+        let [base_collections, tail_collections] = ranges;
+        let mut counters = TableCounters {
             next_rid,
             base_next_addr,
             tail_next_addr,
             pid_next_start,
             base_collections,
             tail_collections,
-        })
+            ..Default::default()
+        };
+        if self.format.load(Ordering::SeqCst) >= 3 {
+            counters.heap_next_word = read_u64(&data, &mut offset)? as usize;
+            for pairs in [&mut counters.heap_chunks, &mut counters.heap_free] {
+                let count = read_u64(&data, &mut offset)? as usize;
+                for _ in 0..count {
+                    let a = read_u64(&data, &mut offset)? as usize;
+                    let b = read_u64(&data, &mut offset)? as usize;
+                    pairs.push((a, b));
+                }
+            }
+        }
+        if self.format.load(Ordering::SeqCst) >= 5 {
+            counters.heap_pages_per_chunk = read_u64(&data, &mut offset)? as usize;
+            let count = read_u64(&data, &mut offset)? as usize;
+            for _ in 0..count {
+                let slot = read_u64(&data, &mut offset)? as usize;
+                counters.fill.push((slot, read_i64(&data, &mut offset)?));
+            }
+        }
+        if self.format.load(Ordering::SeqCst) >= 6 {
+            for collections in [&mut counters.base_collections, &mut counters.tail_collections] {
+                for collection in collections.iter_mut() {
+                    collection.num = read_u64(&data, &mut offset)? as usize;
+                    collection.live = read_u64(&data, &mut offset)? as usize;
+                }
+            }
+            let count = read_u64(&data, &mut offset)? as usize;
+            for _ in 0..count {
+                let start = read_u64(&data, &mut offset)? as usize;
+                let end = read_u64(&data, &mut offset)? as usize;
+                counters.free_pids.push((start, end));
+            }
+        }
+        Ok(counters)
     }
-    pub fn write_primary_index(
+
+    fn write_primary_index(
         &self,
         table_id: usize,
         pairs: &[(i64, i64)],
     ) -> Result<(), DiskError> {
-        let path = self.table_meta_dir(table_id).join("primary_index.bin");
+        let path = self.write_path(&self.table_meta_dir(table_id), "primary_index");
         fs::create_dir_all(path.parent().unwrap())?;
         let mut buf = Vec::new();
         buf.extend_from_slice(&(pairs.len() as u64).to_be_bytes());
@@ -465,15 +963,15 @@ impl DiskManager {
             buf.extend_from_slice(&key.to_be_bytes());
             buf.extend_from_slice(&rid.to_be_bytes());
         }
-        write_file(&path, &buf)
+        self.write_metadata(&path, buf)
     }
 
-    pub fn read_primary_index(&self, table_id: usize) -> Result<Vec<(i64, i64)>, DiskError> {
-        let path = self.table_meta_dir(table_id).join("primary_index.bin");
+    fn read_primary_index(&self, table_id: usize) -> Result<Vec<(i64, i64)>, DiskError> {
+        let path = self.read_path(&self.table_meta_dir(table_id), "primary_index");
         if !path.exists() {
             return Ok(vec![]);
         }
-        let data = read_file(&path)?;
+        let data = self.read_metadata(&path, Some(table_id))?;
         let mut offset = 0;
         let count = read_u64(&data, &mut offset)? as usize;
         let mut pairs = Vec::with_capacity(count);
@@ -486,30 +984,99 @@ impl DiskManager {
     }
 }
 
+#[derive(Clone)]
 pub struct TableMeta {
     pub table_id: usize,
-    pub num_data_columns: usize,
+    pub schema: Schema,
     pub key_index: usize,
     pub next_rid: i64,
     pub name: String,
 }
 
-fn write_file(path: &PathBuf, data: &[u8]) -> Result<(), DiskError> {
-    let file = OpenOptions::new().write(true).create(true).truncate(true).open(path)?;
-    let mut w = BufWriter::new(file);
-    w.write_all(data)?;
-    w.flush()?;
+const MANIFEST: &str = "MANIFEST";
+
+/// Metadata files end in a big-endian CRC-32C of everything before it.
+/// Format 0, from before checksums, is only found in generation 0. Format 2
+/// adds each table's column types to the catalog; tables from before it have
+/// `Schema::ints`. Format 3 adds each table's value heap to its counters,
+/// format 4 column names, nullability and defaults to the schemas, and
+/// format 5 the slots of each column to the catalog and the heap's chunk
+/// size and added columns' fill to the counters, and format 6 the number
+/// and live count of each collection and the free PID ranges to the
+/// counters.
+const METADATA_FORMAT: u32 = 6;
+
+/// The number a file or directory is named after, if it is named after one.
+fn file_number(path: &Path) -> Option<usize> {
+    path.file_name()?.to_str()?.parse().ok()
+}
+
+fn generation_path(dir: &Path, name: &str, generation: u64) -> PathBuf {
+    match generation {
+        0 => dir.join(format!("{name}.bin")),
+        n => dir.join(format!("{name}.{n}.bin")),
+    }
+}
+
+/// The current generation and its metadata format. A manifest from before
+/// checksums holds just the generation.
+fn read_manifest(base_path: &Path) -> Result<(u64, u32), DiskError> {
+    let path = base_path.join(MANIFEST);
+    if !path.exists() {
+        return Ok((0, 0));
+    }
+    let mut data = read_file(&path)?;
+    if data.len() == 8 {
+        return Ok((read_u64(&data, &mut 0)?, 0));
+    }
+    verify_trailer(&mut data, &path, None)?;
+    let mut offset = 0;
+    let generation = read_u64(&data, &mut offset)?;
+    let format = u32::from_be_bytes(
+        data.get(offset..offset + 4)
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| DiskError::CorruptedPage("Manifest too short".into()))?,
+    );
+    Ok((generation, format))
+}
+
+/// Checks the CRC-32C at the end of `data` and removes it.
+fn verify_trailer(data: &mut Vec<u8>, path: &Path, table_id: Option<usize>) -> Result<(), DiskError> {
+    if data.len() < 4 {
+        return Err(DiskError::CorruptedPage(format!("{} too short", path.display())));
+    }
+    let body_len = data.len() - 4;
+    let expected = u32::from_be_bytes(data[body_len..].try_into().unwrap());
+    let actual = crc32c(&data[..body_len]);
+    if expected != actual {
+        return Err(DiskError::ChecksumMismatch(Corruption {
+            table_id,
+            page_num: None,
+            file: path.to_path_buf(),
+            expected,
+            actual,
+        }));
+    }
+    data.truncate(body_len);
+    Ok(())
+}
+
+/// Makes a rename or removal in `path`'s directory durable.
+fn sync_parent(path: &Path) -> Result<(), DiskError> {
+    if let Some(parent) = path.parent() {
+        File::open(parent)?.sync_all()?;
+    }
     Ok(())
 }
 
-fn read_file(path: &PathBuf) -> Result<Vec<u8>, DiskError> {
+fn read_file(path: &Path) -> Result<Vec<u8>, DiskError> {
     let mut data = Vec::new();
     BufReader::new(File::open(path)?).read_to_end(&mut data)?;
     Ok(data)
 }
 
 #[inline]
-fn read_u64(data: &[u8], offset: &mut usize) -> Result<u64, DiskError> {
+pub(crate) fn read_u64(data: &[u8], offset: &mut usize) -> Result<u64, DiskError> {
     if *offset + 8 > data.len() {
         return Err(DiskError::CorruptedPage("Unexpected end of data (u64)".into()));
     }
@@ -521,7 +1088,7 @@ fn read_u64(data: &[u8], offset: &mut usize) -> Result<u64, DiskError> {
 }
 
 #[inline]
-fn read_i64(data: &[u8], offset: &mut usize) -> Result<i64, DiskError> {
+pub(crate) fn read_i64(data: &[u8], offset: &mut usize) -> Result<i64, DiskError> {
     if *offset + 8 > data.len() {
         return Err(DiskError::CorruptedPage("Unexpected end of data (i64)".into()));
     }
@@ -0,0 +1,64 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Tells when no reader can still be holding an address it looked up
+/// before some point. Readers pin the epoch while they run; the epoch
+/// only moves on once nobody is pinned in the one before it, so every
+/// reader pinned at any time is in the current epoch or the one before.
+/// Something unreachable from epoch `e` on is past every reader once the
+/// epoch gets to `e + 2`.
+pub(crate) struct Epochs {
+    epoch: AtomicU64,
+    /// Readers pinned in each of the last three epochs, by epoch mod 3.
+    pinned: [AtomicUsize; 3],
+}
+
+/// A reader's pin on the epoch it started in, let go when dropped.
+pub(crate) struct EpochGuard<'a> {
+    epochs: &'a Epochs,
+    epoch: u64,
+}
+
+impl Epochs {
+    pub(crate) fn new() -> Self {
+        Self {
+            epoch: AtomicU64::new(0),
+            pinned: Default::default(),
+        }
+    }
+
+    pub(crate) fn current(&self) -> u64 {
+        self.epoch.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn pin(&self) -> EpochGuard<'_> {
+        loop {
+            let epoch = self.current();
+            let pinned = &self.pinned[(epoch % 3) as usize];
+            pinned.fetch_add(1, Ordering::SeqCst);
+            // The epoch may have moved on before the pin showed, and then
+            // the slot may already stand for the epoch after next.
+            if self.current() == epoch {
+                return EpochGuard { epochs: self, epoch };
+            }
+            pinned.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Moves on to the next epoch if no reader is left in the one before
+    /// the current one, and says whether it did.
+    pub(crate) fn try_advance(&self) -> bool {
+        let epoch = self.current();
+        if self.pinned[((epoch + 2) % 3) as usize].load(Ordering::SeqCst) != 0 {
+            return false;
+        }
+        self.epoch
+            .compare_exchange(epoch, epoch + 1, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    }
+}
+
+impl Drop for EpochGuard<'_> {
+    fn drop(&mut self) {
+        self.epochs.pinned[(self.epoch % 3) as usize].fetch_sub(1, Ordering::SeqCst);
+    }
+}
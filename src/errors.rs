@@ -1,6 +1,7 @@
 use crate::page::PageError;
 use std::fmt;
 use crate::bufferpool::{BufferPoolError, DiskError};
+use crate::schema::ColumnType;
 
 #[derive(Debug)]
 pub enum DbError {
@@ -12,6 +13,12 @@ pub enum DbError {
     DuplicateKey(i64),   // Insertion is done with duplicate primary key
     NullValue(usize),    // Column was None when value is expected
     WriteTableFailed,
+    InvalidConfig(String),
+    InvalidSchema(String),
+    WrongType(usize, ColumnType), // Value does not fit the column's type
+    ColumnCount(usize),           // Record has a value for this many columns, not the table's
+    UnknownColumn(String),        // No column has this name
+    TableExists(String),          // A table already has this name
 }
 
 impl fmt::Display for DbError {
@@ -25,6 +32,12 @@ impl fmt::Display for DbError {
             DbError::DuplicateKey(key) => write!(f, "duplicate key: {}", key),
             DbError::NullValue(col) => write!(f, "unexpected null in column {}", col),
             DbError::WriteTableFailed => write!(f, "write table failed"),
+            DbError::InvalidConfig(msg) => write!(f, "invalid configuration: {}", msg),
+            DbError::InvalidSchema(msg) => write!(f, "invalid schema: {}", msg),
+            DbError::WrongType(col, ty) => write!(f, "column {} takes {} values", col, ty),
+            DbError::ColumnCount(n) => write!(f, "{} values do not match the table's columns", n),
+            DbError::UnknownColumn(name) => write!(f, "no column is named {}", name),
+            DbError::TableExists(name) => write!(f, "a table is already named {}", name),
         }
     }
 }
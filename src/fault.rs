@@ -0,0 +1,285 @@
+//! A storage backend that fails on cue, for testing how the layers above a
+//! `DiskManager` cope with I/O errors.
+
+use crate::bufferpool::{Corruption, DiskError, DiskManager};
+use crate::disk_manager::{CorruptionPolicy, TableCounters, TableMeta, WriteFence};
+use crate::iterators::PhysicalAddress;
+use crate::page::Page;
+use crate::page_collection::PageId;
+use crate::storage::StorageBackend;
+use crate::table::Table;
+use dashmap::DashMap;
+use parking_lot::Mutex;
+use std::fs::OpenOptions;
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Fault {
+    /// The write fails with an I/O error and changes nothing.
+    FailWrite,
+    /// The write of a page over an older one stops halfway through, as if
+    /// the machine died there: the second half of the new page is on disk,
+    /// the first half of the slot reads back as zeros, and the write fails.
+    Tear,
+    /// The thread doing the write panics.
+    Panic,
+    /// The read comes up short, as if the file ended early.
+    ShortRead,
+    /// A byte of the page flips on disk right before it is read.
+    Corrupt,
+}
+
+#[derive(Default)]
+struct Script {
+    writes: usize,
+    reads: usize,
+    /// Faults keyed by the number of the write or read they hit.
+    pending_writes: Vec<(usize, Fault)>,
+    pending_reads: Vec<(usize, Fault)>,
+    stall: Duration,
+}
+
+impl Script {
+    fn take(pending: &mut Vec<(usize, Fault)>, n: usize) -> Option<Fault> {
+        let i = pending.iter().position(|(at, _)| *at == n)?;
+        Some(pending.swap_remove(i).1)
+    }
+}
+
+/// Wraps a `DiskManager` and injects faults into the page reads and into every
+/// write, pages and metadata alike. Faults are scheduled relative to the
+/// operations performed so far: `fail_write(0)` fails the very next write.
+/// Each fault fires once.
+pub struct FaultyBackend {
+    inner: Arc<DiskManager>,
+    script: Mutex<Script>,
+}
+
+impl FaultyBackend {
+    pub fn new(inner: Arc<DiskManager>) -> Self {
+        Self {
+            inner,
+            script: Mutex::new(Script::default()),
+        }
+    }
+
+    /// Fails the `nth` write from now.
+    pub fn fail_write(&self, nth: usize) {
+        self.schedule_write(nth, Fault::FailWrite);
+    }
+
+    /// Tears the first write of a page over an older one, from the `nth`
+    /// write from now on.
+    pub fn tear_write(&self, nth: usize) {
+        self.schedule_write(nth, Fault::Tear);
+    }
+
+    /// Panics in whichever thread performs the `nth` write from now.
+    pub fn panic_on_write(&self, nth: usize) {
+        self.schedule_write(nth, Fault::Panic);
+    }
+
+    /// Cuts the `nth` page read from now short.
+    pub fn short_read(&self, nth: usize) {
+        self.schedule_read(nth, Fault::ShortRead);
+    }
+
+    /// Flips a byte on disk of the page the `nth` page read from now reads.
+    /// The damage stays.
+    pub fn corrupt_read(&self, nth: usize) {
+        self.schedule_read(nth, Fault::Corrupt);
+    }
+
+    /// Delays every read and write by `stall`.
+    pub fn stall(&self, stall: Duration) {
+        self.script.lock().stall = stall;
+    }
+
+    /// Cancels every fault that has not fired yet and any stall.
+    pub fn heal(&self) {
+        let mut script = self.script.lock();
+        script.pending_writes.clear();
+        script.pending_reads.clear();
+        script.stall = Duration::ZERO;
+    }
+
+    fn schedule_write(&self, nth: usize, fault: Fault) {
+        let mut script = self.script.lock();
+        let at = script.writes + nth;
+        script.pending_writes.push((at, fault));
+    }
+
+    fn schedule_read(&self, nth: usize, fault: Fault) {
+        let mut script = self.script.lock();
+        let at = script.reads + nth;
+        script.pending_reads.push((at, fault));
+    }
+
+    fn before_write(&self) -> Result<(), DiskError> {
+        if self.next_write()? {
+            // Only a page overwrite can be torn.
+            self.schedule_write(0, Fault::Tear);
+        }
+        Ok(())
+    }
+
+    /// Whether the next write is to be torn, once any stall is over.
+    fn next_write(&self) -> Result<bool, DiskError> {
+        let (fault, stall) = {
+            let mut script = self.script.lock();
+            let n = script.writes;
+            script.writes += 1;
+            (Script::take(&mut script.pending_writes, n), script.stall)
+        };
+        thread::sleep(stall);
+        match fault {
+            Some(Fault::FailWrite) => Err(DiskError::IoError(io::Error::other("injected write failure"))),
+            Some(Fault::Panic) => panic!("injected panic on write"),
+            Some(Fault::Tear) => Ok(true),
+            _ => Ok(false),
+        }
+    }
+
+    fn before_read(&self, pid: PageId) -> Result<(), DiskError> {
+        let (fault, stall) = {
+            let mut script = self.script.lock();
+            let n = script.reads;
+            script.reads += 1;
+            (Script::take(&mut script.pending_reads, n), script.stall)
+        };
+        thread::sleep(stall);
+        match fault {
+            Some(Fault::ShortRead) => Err(DiskError::IoError(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "injected short read",
+            ))),
+            Some(Fault::Corrupt) => {
+                let (path, offset) = self.inner.page_location(pid);
+                let at = offset + DiskManager::PAGE_BYTES as u64 - 1;
+                let file = OpenOptions::new().read(true).write(true).open(path)?;
+                let mut byte = [0u8];
+                file.read_exact_at(&mut byte, at)?;
+                file.write_all_at(&[byte[0] ^ 0xFF], at)?;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl StorageBackend for FaultyBackend {
+    fn fence(&self) -> Arc<WriteFence> {
+        self.inner.fence()
+    }
+
+    fn log_path(&self) -> Option<PathBuf> {
+        self.inner.log_path()
+    }
+
+    fn read_page(&self, pid: PageId) -> Result<Page, DiskError> {
+        self.before_read(pid)?;
+        self.inner.read_page(pid)
+    }
+
+    fn write_page(&self, pid: PageId, page: &Page) -> Result<(), DiskError> {
+        if !self.next_write()? {
+            return self.inner.write_page(pid, page);
+        }
+        if !self.inner.page_exists(pid) {
+            self.schedule_write(0, Fault::Tear);
+            return self.inner.write_page(pid, page);
+        }
+        // The whole write goes through, and then the first half of the slot
+        // is lost, as a file system may leave blocks it never finished.
+        let (path, offset) = self.inner.page_location(pid);
+        let file = OpenOptions::new().write(true).open(path)?;
+        self.inner.write_page(pid, page)?;
+        file.write_all_at(&vec![0; DiskManager::PAGE_BYTES / 2], offset)?;
+        Err(DiskError::IoError(io::Error::other("injected torn write")))
+    }
+
+    fn delete_page(&self, pid: PageId) -> Result<(), DiskError> {
+        self.before_write()?;
+        self.inner.delete_page(pid)
+    }
+
+    fn page_exists(&self, pid: PageId) -> bool {
+        self.inner.page_exists(pid)
+    }
+
+    fn sync_pages(&self) -> Result<(), DiskError> {
+        self.before_write()?;
+        self.inner.sync_pages()
+    }
+
+    fn begin_generation(&self) {
+        self.inner.begin_generation()
+    }
+
+    fn commit_generation(&self) -> Result<(), DiskError> {
+        self.before_write()?;
+        self.inner.commit_generation()
+    }
+
+    fn write_tables(&self, tables: &DashMap<usize, Arc<Table>>, next_table_id: usize) -> Result<(), DiskError> {
+        self.before_write()?;
+        self.inner.write_tables(tables, next_table_id)
+    }
+
+    fn read_tables(&self) -> Result<(Vec<TableMeta>, usize), DiskError> {
+        self.inner.read_tables()
+    }
+
+    fn write_table_names(&self, table_names: &DashMap<String, usize>) -> Result<(), DiskError> {
+        self.before_write()?;
+        self.inner.write_table_names(table_names)
+    }
+
+    fn read_table_names(&self) -> Result<Vec<(String, usize)>, DiskError> {
+        self.inner.read_table_names()
+    }
+
+    fn write_page_directory(&self, table_id: usize, pairs: &[(i64, PhysicalAddress)]) -> Result<(), DiskError> {
+        self.before_write()?;
+        self.inner.write_page_directory(table_id, pairs)
+    }
+
+    fn read_page_directory(&self, table_id: usize) -> Result<Vec<(i64, PhysicalAddress)>, DiskError> {
+        self.inner.read_page_directory(table_id)
+    }
+
+    fn write_table_counters(&self, table_id: usize, counters: &TableCounters) -> Result<(), DiskError> {
+        self.before_write()?;
+        self.inner.write_table_counters(table_id, counters)
+    }
+
+    fn read_table_counters(&self, table_id: usize) -> Result<TableCounters, DiskError> {
+        self.inner.read_table_counters(table_id)
+    }
+
+    fn write_primary_index(&self, table_id: usize, pairs: &[(i64, i64)]) -> Result<(), DiskError> {
+        self.before_write()?;
+        self.inner.write_primary_index(table_id, pairs)
+    }
+
+    fn read_primary_index(&self, table_id: usize) -> Result<Vec<(i64, i64)>, DiskError> {
+        self.inner.read_primary_index(table_id)
+    }
+
+    fn set_corruption_policy(&self, policy: CorruptionPolicy) {
+        self.inner.set_corruption_policy(policy)
+    }
+
+    fn quarantined(&self) -> Vec<Corruption> {
+        self.inner.quarantined()
+    }
+
+    fn quarantine(&self, err: DiskError) -> Result<(), DiskError> {
+        self.inner.quarantine(err)
+    }
+}
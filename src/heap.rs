@@ -0,0 +1,282 @@
+use crate::bufferpool::{BufferPool, DiskError};
+use crate::errors::DbError;
+use crate::iterators::PidRangeIterator;
+use crate::page::Page;
+use crate::page_collection::PageId;
+use dashmap::DashMap;
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// A table's text and blob values, kept out of line in pages of their own.
+/// The slot of a `Text` or `Blob` column holds the word its value starts at:
+/// the length in bytes, then the bytes eight to a big-endian word. A value
+/// runs across pages as it needs to.
+///
+/// Pages are taken a chunk at a time from the PID ranges the table's
+/// collections are cut from. Space goes first fit to what merges gave back,
+/// then comes off the end. A merge only retires what it gives back, since
+/// the last checkpoint may still reach it; it is free once the next
+/// checkpoint is taken.
+pub struct ValueHeap {
+    table_id: usize,
+    bufferpool: Arc<BufferPool>,
+    pid_iterator: Arc<PidRangeIterator>,
+    pages_per_chunk: usize,
+    /// The first PID of each chunk, by chunk number.
+    chunks: DashMap<usize, usize>,
+    space: Mutex<Space>,
+    /// Extents merges gave back since the last checkpoint.
+    retired: Mutex<Vec<(usize, usize)>>,
+}
+
+#[derive(Default)]
+struct Space {
+    next_word: usize,
+    /// Start and length in words of every free extent, none of them touching.
+    free: BTreeMap<usize, usize>,
+}
+
+impl ValueHeap {
+    pub fn new(
+        table_id: usize,
+        bufferpool: Arc<BufferPool>,
+        pid_iterator: Arc<PidRangeIterator>,
+        pages_per_chunk: usize,
+    ) -> Self {
+        Self {
+            table_id,
+            bufferpool,
+            pid_iterator,
+            pages_per_chunk,
+            chunks: DashMap::new(),
+            space: Mutex::new(Space::default()),
+            retired: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// A heap as `TableCounters` left it: `chunks` of chunk numbers and
+    /// first PIDs, and `free` extents of starts and lengths.
+    pub fn restore(
+        table_id: usize,
+        bufferpool: Arc<BufferPool>,
+        pid_iterator: Arc<PidRangeIterator>,
+        pages_per_chunk: usize,
+        next_word: usize,
+        chunks: Vec<(usize, usize)>,
+        free: Vec<(usize, usize)>,
+    ) -> Self {
+        let mut heap = Self::new(table_id, bufferpool, pid_iterator, pages_per_chunk);
+        heap.chunks = chunks.into_iter().collect();
+        let space = heap.space.get_mut();
+        space.next_word = next_word;
+        for (start, words) in free {
+            space.release(start, words);
+        }
+        heap
+    }
+
+    /// Words a value of `len` bytes takes.
+    pub fn words_for(len: usize) -> usize {
+        1 + len.div_ceil(8)
+    }
+
+    /// Space for `words` words, which nothing else is handed until it is
+    /// released.
+    pub fn allocate(&self, words: usize) -> usize {
+        let mut space = self.space.lock();
+        let fit = space.free.iter().find(|&(_, &len)| len >= words).map(|(&start, &len)| (start, len));
+        match fit {
+            Some((start, len)) => {
+                space.free.remove(&start);
+                if len > words {
+                    space.free.insert(start + words, len - words);
+                }
+                start
+            }
+            None => {
+                let start = space.next_word;
+                space.next_word += words;
+                start
+            }
+        }
+    }
+
+    /// Takes `words` words at `start` out of the free space, for recovery to
+    /// replay a logged write where it first went.
+    pub fn claim(&self, start: usize, words: usize) {
+        let mut space = self.space.lock();
+        let end = start + words;
+        if end > space.next_word {
+            let next = space.next_word;
+            if start > next {
+                space.release(next, start - next);
+            }
+            space.next_word = end;
+        }
+        let overlapping: Vec<(usize, usize)> = space
+            .free
+            .range(..end)
+            .rev()
+            .take_while(|&(&s, &len)| s + len > start)
+            .map(|(&s, &len)| (s, len))
+            .collect();
+        for (s, len) in overlapping {
+            space.free.remove(&s);
+            if s < start {
+                space.free.insert(s, start - s);
+            }
+            if s + len > end {
+                space.free.insert(end, s + len - end);
+            }
+        }
+    }
+
+    /// Gives back space no value was written to.
+    pub fn release(&self, start: usize, words: usize) {
+        self.space.lock().release(start, words);
+    }
+
+    /// Sets the value at `addr` aside, to be released after the next
+    /// checkpoint.
+    pub fn retire(&self, addr: i64) -> Result<(), DbError> {
+        let len = self.read_word(addr as usize)?;
+        self.retired.lock().push((addr as usize, Self::words_for(len as usize)));
+        Ok(())
+    }
+
+    /// Releases everything retired, once a checkpoint no longer reaches it.
+    pub fn release_retired(&self) {
+        let retired = std::mem::take(&mut *self.retired.lock());
+        let mut space = self.space.lock();
+        for (start, words) in retired {
+            space.release(start, words);
+        }
+    }
+
+    /// Writes `bytes` to the space at `addr`.
+    pub fn write(&self, addr: usize, bytes: &[u8]) -> Result<(), DbError> {
+        let mut words = Vec::with_capacity(Self::words_for(bytes.len()));
+        words.push(bytes.len() as i64);
+        words.extend(bytes.chunks(8).map(|chunk| {
+            let mut word = [0u8; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            i64::from_be_bytes(word)
+        }));
+        let mut word = addr;
+        for run in self.runs(addr, words.len()) {
+            let page = self.bufferpool.fetch_page(self.pid(word, true)?)?;
+            let mut page = page.write();
+            for offset in run {
+                page.update(offset, Some(words[word - addr]))?;
+                word += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// The bytes of the value at `addr`.
+    pub fn load(&self, addr: i64) -> Result<Vec<u8>, DbError> {
+        let addr = addr as usize;
+        let len = self.read_word(addr)? as usize;
+        let mut bytes = Vec::with_capacity(len.div_ceil(8) * 8);
+        let mut word = addr + 1;
+        for run in self.runs(word, len.div_ceil(8)) {
+            let page = self.bufferpool.fetch_page(self.pid(word, false)?)?;
+            let page = page.read();
+            for offset in run.clone() {
+                bytes.extend_from_slice(&page.read(offset)?.unwrap_or(0).to_be_bytes());
+            }
+            word += run.len();
+        }
+        bytes.truncate(len);
+        Ok(bytes)
+    }
+
+    /// Pages a chunk takes, which the table's collections may outgrow: a
+    /// word's page follows from it.
+    pub fn pages_per_chunk(&self) -> usize {
+        self.pages_per_chunk
+    }
+
+    pub fn next_word(&self) -> usize {
+        self.space.lock().next_word
+    }
+
+    /// Chunk numbers and first PIDs, in chunk order.
+    pub fn chunk_pids(&self) -> Vec<(usize, usize)> {
+        let mut chunks: Vec<(usize, usize)> = self.chunks.iter().map(|e| (*e.key(), *e.value())).collect();
+        chunks.sort_unstable();
+        chunks
+    }
+
+    /// The PID ranges of every chunk, as the collections report theirs.
+    pub fn pid_ranges(&self) -> Vec<(usize, usize)> {
+        self.chunk_pids()
+            .into_iter()
+            .map(|(_, start)| (start, start + self.pages_per_chunk))
+            .collect()
+    }
+
+    /// Free extents for a checkpoint to record. What is retired goes with
+    /// them: once that checkpoint is in charge nothing reaches it.
+    pub fn free_extents(&self) -> Vec<(usize, usize)> {
+        let mut free: Vec<(usize, usize)> = self.space.lock().free.iter().map(|(&s, &len)| (s, len)).collect();
+        free.extend(self.retired.lock().iter().copied());
+        free.sort_unstable();
+        free
+    }
+
+    fn read_word(&self, word: usize) -> Result<i64, DbError> {
+        self.bufferpool
+            .read(self.pid(word, false)?, word % Page::PAGE_SIZE)?
+            .ok_or_else(|| DbError::Disk(DiskError::CorruptedPage(format!("no value at heap word {}", word))))
+    }
+
+    /// Offsets within each page that `words` words from `start` cover.
+    fn runs(&self, start: usize, words: usize) -> impl Iterator<Item = std::ops::Range<usize>> {
+        let end = start + words;
+        let first_page = start / Page::PAGE_SIZE;
+        let last_page = end.div_ceil(Page::PAGE_SIZE);
+        (first_page..last_page).map(move |page| {
+            let from = start.max(page * Page::PAGE_SIZE);
+            let to = end.min((page + 1) * Page::PAGE_SIZE);
+            from % Page::PAGE_SIZE..from % Page::PAGE_SIZE + (to - from)
+        })
+    }
+
+    /// The page `word` is on, taking a new chunk for it if `allocate`.
+    fn pid(&self, word: usize, allocate: bool) -> Result<PageId, DbError> {
+        let words_per_chunk = self.pages_per_chunk * Page::PAGE_SIZE;
+        let chunk = word / words_per_chunk;
+        let existing = self.chunks.get(&chunk).map(|start| *start);
+        let start = match existing {
+            Some(start) => start,
+            None if allocate => *self.chunks.entry(chunk).or_insert_with(|| self.pid_iterator.take(self.pages_per_chunk).start),
+            None => {
+                return Err(DbError::Disk(DiskError::CorruptedPage(format!(
+                    "heap word {} is past every chunk",
+                    word
+                ))));
+            }
+        };
+        Ok(PageId::new(start + (word % words_per_chunk) / Page::PAGE_SIZE, self.table_id))
+    }
+}
+
+impl Space {
+    fn release(&mut self, start: usize, words: usize) {
+        let (mut start, mut words) = (start, words);
+        if let Some((&prev, &len)) = self.free.range(..start).next_back()
+            && prev + len == start
+        {
+            self.free.remove(&prev);
+            start = prev;
+            words += len;
+        }
+        if let Some(len) = self.free.remove(&(start + words)) {
+            words += len;
+        }
+        self.free.insert(start, words);
+    }
+}
@@ -1,12 +1,58 @@
+use crate::schema::Schema;
 use crossbeam_skiplist::{SkipSet,SkipMap};
+use std::collections::BTreeSet;
 use std::ops::Bound;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
 
 pub struct Index {
     inner: IndexInner,
     enabled: AtomicBool
 }
 
+/// An index for every slot a table's records can come to have, made when
+/// it is first asked for. All but the primary key's start off and stay off
+/// until `Index::enable`; selects by those slots scan until then.
+pub struct Indices {
+    slots: Vec<OnceLock<Box<Index>>>,
+}
+
+impl Indices {
+    pub fn new(key_index: usize) -> Self {
+        let slots: Vec<OnceLock<Box<Index>>> = (0..Schema::MAX_WIDTH).map(|_| OnceLock::new()).collect();
+        let _ = slots[key_index].set(Box::new(Index::new_unique()));
+        Self { slots }
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// The indices there are and are on, by slot.
+    pub fn enabled(&self) -> impl Iterator<Item = (usize, &Index)> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, index)| Some((slot, &**index.get()?)).filter(|(_, index)| index.is_enabled()))
+    }
+}
+
+impl std::ops::Index<usize> for Indices {
+    type Output = Index;
+
+    fn index(&self, slot: usize) -> &Index {
+        self.slots[slot].get_or_init(|| {
+            let index = Index::new_non_unique();
+            index.disable();
+            Box::new(index)
+        })
+    }
+}
+
 enum IndexInner {
     Unique(SkipMap<i64, i64>),
     NonUnique(SkipSet<(i64, i64)>),
@@ -34,6 +80,28 @@ impl Index {
         self.enabled.store(false, Ordering::Release);
     }
 
+    /// Drops every entry, for the index of a column that is dropped.
+    pub fn clear(&self) {
+        match &self.inner {
+            IndexInner::Unique(map) => map.clear(),
+            IndexInner::NonUnique(set) => set.clear(),
+        }
+    }
+
+    /// Makes `pairs` all the index holds. Those already in it stay in it
+    /// throughout, so a lookup never misses one meanwhile.
+    pub fn rebuild(&self, pairs: &[(i64, i64)]) {
+        for &(key, rid) in pairs {
+            self.insert(key, rid);
+        }
+        let keep: BTreeSet<(i64, i64)> = pairs.iter().copied().collect();
+        for (key, rid) in self.all_pairs() {
+            if !keep.contains(&(key, rid)) {
+                self.remove(key, rid);
+            }
+        }
+    }
+
     pub fn is_enabled(&self) -> bool {
         self.enabled.load(Ordering::Acquire)
     }
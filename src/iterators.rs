@@ -1,10 +1,23 @@
 use crate::page::Page;
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
 use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
 #[derive(Hash, Eq, PartialEq, Copy, Clone, Debug, Default)]
 pub struct PhysicalAddress {
     pub(crate) offset: usize,
     pub(crate) collection_num: usize,
 }
+impl PhysicalAddress {
+    /// Address of the `index`-th slot handed out by a `PhysicalAddressIterator`.
+    #[inline]
+    pub fn from_index(index: usize) -> Self {
+        PhysicalAddress {
+            offset: index % Page::PAGE_SIZE,
+            collection_num: index / Page::PAGE_SIZE,
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct PhysicalAddressIterator {
     next: AtomicUsize,
@@ -20,10 +33,7 @@ impl PhysicalAddressIterator {
     pub fn next(&self) -> PhysicalAddress {
         let prev = self.next.fetch_add(1, Ordering::Relaxed);
 
-        PhysicalAddress {
-            offset: prev % Page::PAGE_SIZE,
-            collection_num: prev / Page::PAGE_SIZE,
-        }
+        PhysicalAddress::from_index(prev)
     }
     pub fn current(&self) -> usize {
         self.next.load(Ordering::Relaxed)
@@ -34,7 +44,7 @@ impl PhysicalAddressIterator {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone, Copy)]
 pub struct PidRange {
     pub(crate) start: usize, //inclusive
     pub(crate) end: usize, //exclusive
@@ -42,15 +52,17 @@ pub struct PidRange {
 
 pub struct PidRangeIterator {
     start: AtomicUsize,
-    pages_per_collection: usize,
+    /// Pages of the collections cut from now on; a column added to the
+    /// table makes it more.
+    pages_per_collection: AtomicUsize,
+    /// Ranges freed collections gave back, start to end, none of them
+    /// touching. Handed out again first fit.
+    free: Mutex<BTreeMap<usize, usize>>,
 }
 impl PidRangeIterator {
 
     pub fn new(pages_per_collection: usize) -> Self {
-        Self {
-            start: AtomicUsize::new(0),
-            pages_per_collection,
-        }
+        Self::restore(0, pages_per_collection, Vec::new())
     }
 
     pub fn current(&self) -> usize {
@@ -58,17 +70,67 @@ impl PidRangeIterator {
     }
     
     pub fn next(&self) -> PidRange {
-        let start = self.start.fetch_add(self.pages_per_collection, Ordering::Relaxed);
-        let end = start + self.pages_per_collection;
+        self.take(self.pages_per_collection())
+    }
+
+    /// A range of `pages` pages, whatever collections take.
+    pub fn take(&self, pages: usize) -> PidRange {
+        let mut free = self.free.lock();
+        let fit = free.iter().find(|&(&start, &end)| end - start >= pages).map(|(&start, &end)| (start, end));
+        if let Some((start, end)) = fit {
+            free.remove(&start);
+            if end - start > pages {
+                free.insert(start + pages, end);
+            }
+            return PidRange { start, end: start + pages };
+        }
+        drop(free);
+
+        let start = self.start.fetch_add(pages, Ordering::Relaxed);
+        let end = start + pages;
 
         PidRange { start, end }
     }
 
-    pub fn restore(start: usize, pages_per_collection: usize) -> Self{
-        Self {
+    /// Takes back `range`, whose pages are gone, to hand out again.
+    pub fn give_back(&self, range: PidRange) {
+        let (mut start, mut end) = (range.start, range.end);
+        let mut free = self.free.lock();
+        if let Some((&prev, &prev_end)) = free.range(..start).next_back()
+            && prev_end == start
+        {
+            free.remove(&prev);
+            start = prev;
+        }
+        if let Some(next_end) = free.remove(&end) {
+            end = next_end;
+        }
+        free.insert(start, end);
+    }
+
+    /// The ranges there are to hand out again, in order.
+    pub fn free_ranges(&self) -> Vec<(usize, usize)> {
+        self.free.lock().iter().map(|(&start, &end)| (start, end)).collect()
+    }
+
+    pub fn pages_per_collection(&self) -> usize {
+        self.pages_per_collection.load(Ordering::Acquire)
+    }
+
+    pub fn set_pages_per_collection(&self, pages: usize) {
+        self.pages_per_collection.store(pages, Ordering::Release);
+    }
+
+    pub fn restore(start: usize, pages_per_collection: usize, free: Vec<(usize, usize)>) -> Self{
+        let iterator = Self {
             start: AtomicUsize::new(start),
-            pages_per_collection,
+            pages_per_collection: AtomicUsize::new(pages_per_collection),
+            free: Mutex::new(BTreeMap::new()),
+        };
+        for (start, end) in free {
+            iterator.give_back(PidRange { start, end });
         }
+        iterator
     }
 }
 
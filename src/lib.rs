@@ -1,12 +1,17 @@
 use pyo3::prelude::*;
+pub mod check;
+pub mod config;
 pub mod db;
 pub mod errors;
+pub mod heap;
 pub mod index;
 mod page;
 mod page_collection;
 mod page_directory;
 mod page_range;
 pub mod query;
+pub mod schema;
+mod schema_encoding;
 pub mod table;
 
 #[cfg(test)]
@@ -18,6 +23,16 @@ mod disk_manager;
 mod transaction;
 mod transaction_worker;
 mod lock_manager;
+mod wal;
+mod segment;
+mod storage;
+mod checkpoint;
+mod merge;
+mod epoch;
+mod vacuum;
+#[cfg(test)]
+mod fault;
+mod checksum;
 
 /// A Python module implemented in Rust. The name of this module must match
 /// the `lib.name` setting in the `Cargo.toml`, else Python will not be able to
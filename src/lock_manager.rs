@@ -18,7 +18,7 @@ impl LockManager {
     pub fn acquire_shared(&self, table_id: usize, key: i64, txn_id: usize) -> bool {
         let mut entry = self.table
             .entry((table_id, key))
-            .or_insert_with(LockEntry::default);
+            .or_default();
 
         let ok = entry.exclusive.is_none() || entry.exclusive == Some(txn_id);
         if ok && !entry.shared.contains(&txn_id) {
@@ -30,10 +30,10 @@ impl LockManager {
     pub fn acquire_exclusive(&self, table_id: usize, key: i64, txn_id: usize) -> bool {
         let mut entry = self.table
             .entry((table_id, key))
-            .or_insert_with(LockEntry::default);
+            .or_default();
 
         let other_shared = entry.shared.iter().any(|&id| id != txn_id);
-        let other_exclusive = entry.exclusive.map_or(false, |id| id != txn_id);
+        let other_exclusive = entry.exclusive.is_some_and(|id| id != txn_id);
 
         if other_shared || other_exclusive {
             return false;
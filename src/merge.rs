@@ -0,0 +1,86 @@
+use crate::config::MergeConfig;
+use crate::table::Table;
+use dashmap::DashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+
+/// Merges the background merge did since the database was created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MergeStats {
+    /// Base records a merged copy took the place of.
+    pub records: u64,
+    /// Table merges that failed part way. The records merged before the
+    /// failure stay merged; the rest are tried again.
+    pub failed: u64,
+}
+
+#[derive(Default)]
+pub(crate) struct MergeCounters {
+    records: AtomicU64,
+    failed: AtomicU64,
+}
+
+impl MergeCounters {
+    pub(crate) fn stats(&self) -> MergeStats {
+        MergeStats {
+            records: self.records.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn reset(&self) {
+        self.records.store(0, Ordering::Relaxed);
+        self.failed.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Merges every table that has reached one of `MergeConfig`'s thresholds,
+/// every `interval`, on a thread of its own until dropped. Queries keep
+/// running meanwhile: a merge only holds up writers of the record it is
+/// merging.
+pub(crate) struct MergeThread {
+    stop_tx: Option<mpsc::Sender<()>>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl MergeThread {
+    pub(crate) fn spawn(
+        tables: Arc<DashMap<usize, Arc<Table>>>,
+        config: MergeConfig,
+        counters: Arc<MergeCounters>,
+    ) -> Self {
+        let (stop_tx, stop_rx) = mpsc::channel::<()>();
+        let handle = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stop_rx.recv_timeout(config.interval) {
+                // Taken out first, so that tables can be created meanwhile.
+                let due: Vec<Arc<Table>> = tables
+                    .iter()
+                    .filter(|entry| entry.value().needs_merge(&config))
+                    .map(|entry| entry.value().clone())
+                    .collect();
+                for table in due {
+                    match table.merge(&config) {
+                        Ok(merged) => counters.records.fetch_add(merged as u64, Ordering::Relaxed),
+                        Err(_) => counters.failed.fetch_add(1, Ordering::Relaxed),
+                    };
+                }
+            }
+        });
+        Self {
+            stop_tx: Some(stop_tx),
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for MergeThread {
+    /// Waits for a merge that is under way to finish.
+    fn drop(&mut self) {
+        drop(self.stop_tx.take());
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
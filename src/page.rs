@@ -4,14 +4,50 @@ pub enum PageError {
     IndexOutOfBounds(usize),
 }
 
+/// Values are kept dense with a separate bitmap of which slots hold one, the
+/// same shape the page has on disk.
 #[derive(Clone, Debug)]
 pub struct Page {
-    data: [Option<i64>; Page::PAGE_SIZE],
+    values: [i64; Page::PAGE_SIZE],
+    present: [u8; Page::BITMAP_BYTES],
     num_records: usize,
 }
 
 impl Page {
     pub const PAGE_SIZE: usize = 512;
+    pub const BITMAP_BYTES: usize = Page::PAGE_SIZE / 8;
+
+    pub fn from_parts(
+        values: [i64; Page::PAGE_SIZE],
+        present: [u8; Page::BITMAP_BYTES],
+        num_records: usize,
+    ) -> Self {
+        Self { values, present, num_records }
+    }
+
+    pub fn values(&self) -> &[i64; Page::PAGE_SIZE] {
+        &self.values
+    }
+
+    /// Bit `i % 8` of byte `i / 8` is set when slot `i` holds a value.
+    pub fn present(&self) -> &[u8; Page::BITMAP_BYTES] {
+        &self.present
+    }
+
+    #[inline]
+    fn set(&mut self, index: usize, val: Option<i64>) {
+        let bit = 1 << (index % 8);
+        match val {
+            Some(v) => {
+                self.values[index] = v;
+                self.present[index / 8] |= bit;
+            }
+            None => {
+                self.values[index] = 0;
+                self.present[index / 8] &= !bit;
+            }
+        }
+    }
 
     #[inline]
     pub fn has_capacity(&self) -> bool {
@@ -23,17 +59,15 @@ impl Page {
         if offset >= Page::PAGE_SIZE {
             return Err(PageError::Full);
         }
-        self.data[offset] = val;
+        self.set(offset, val);
         self.num_records += 1;
         Ok(())
     }
 
     #[inline]
     pub fn read(&self, index: usize) -> Result<Option<i64>, PageError> {
-        self.data
-            .get(index)
-            .copied()
-            .ok_or(PageError::IndexOutOfBounds(index))
+        let value = *self.values.get(index).ok_or(PageError::IndexOutOfBounds(index))?;
+        Ok((self.present[index / 8] & (1 << (index % 8)) != 0).then_some(value))
     }
 
     #[inline]
@@ -43,10 +77,10 @@ impl Page {
 
     #[inline]
     pub fn update(&mut self, index: usize, val: Option<i64>) -> Result<(), PageError> {
-        if index >= self.data.len() {
+        if index >= Page::PAGE_SIZE {
             return Err(PageError::IndexOutOfBounds(index));
         }
-        self.data[index] = val;
+        self.set(index, val);
         Ok(())
     }
 }
@@ -54,7 +88,8 @@ impl Page {
 impl Default for Page {
     fn default() -> Self {
         Self {
-            data: [None; Page::PAGE_SIZE],
+            values: [0; Page::PAGE_SIZE],
+            present: [0; Page::BITMAP_BYTES],
             num_records: 0,
         }
     }
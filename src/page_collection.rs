@@ -1,15 +1,21 @@
-use crate::bufferpool::{BufferPool, BufferPoolError};
+use crate::bufferpool::{AccessHint, BufferPool, BufferPoolError, PageGuard};
 use crate::iterators::PidRange;
 use crate::table::Table;
-use std::sync::atomic::{AtomicI64, Ordering};
+use std::collections::BTreeSet;
 use std::sync::Arc;
 
+#[derive(Clone, Copy)]
 #[repr(usize)]
 pub enum MetaPage {
     Rid = 0,
     Indirection = 1,
     SchemaEncoding = 2,
-    StartTime = 3,
+    /// In a merged copy of a base record, the snapshot of its oldest version
+    /// the merge kept, which is past every tail record folded into it, or
+    /// the newest of those if it kept none: the chain needs following no
+    /// further than that for the latest values. In a snapshot, the base
+    /// record it is of.
+    Tps = 3,
 }
 
 pub struct PageCollection {
@@ -17,7 +23,6 @@ pub struct PageCollection {
     table_id: usize,
     bufferpool: Arc<BufferPool>,
     num_pages: usize,
-    tps: AtomicI64,
 }
 
 impl PageCollection {
@@ -27,33 +32,57 @@ impl PageCollection {
             pid_range,
             table_id,
             bufferpool,
-            tps: AtomicI64::new(i64::MIN),
         }
     }
 
+    /// Slots a record has pages for here: those there were when the
+    /// collection was cut.
     #[inline]
-    pub fn get_tps(&self) -> i64 {
-        self.tps.load(Ordering::Acquire)
+    pub fn data_width(&self) -> usize {
+        Table::collection_width(self.num_pages)
     }
 
+    /// Schema-encoding words a record has here.
     #[inline]
-    pub fn update_tps(&self, new_tps: i64) {
-        self.tps.fetch_max(new_tps, Ordering::Release);
+    pub fn encoding_words(&self) -> usize {
+        self.num_pages - self.data_width() - Table::NUM_META_PAGES + 1
     }
 
     #[inline]
-    pub fn write_col(&self, col: usize, offset: usize, val: Option<i64>) -> Result<(), BufferPoolError> {
-        self.bufferpool.write(self.make_pid(col), val, offset)
+    pub fn meta_page(&self, col: MetaPage) -> usize {
+        self.data_width() + col as usize
     }
 
-    pub fn write_cols(&self, offset: usize, vals: Vec<Option<i64>>) -> Result<(), BufferPoolError> {
-        (0..self.num_pages)
-            .try_for_each(|i| self.write_col(i, offset, vals[i]))
+    /// Writes a whole record with all of its pages pinned: `data` to the
+    /// page of each slot but those `retired`, which are left without a
+    /// value, and `meta` to the pages after, the meta pages and then the
+    /// schema-encoding words past the first.
+    pub fn write_cols(
+        &self,
+        offset: usize,
+        data: &[Option<i64>],
+        meta: &[Option<i64>],
+        retired: &BTreeSet<usize>,
+    ) -> Result<(), BufferPoolError> {
+        debug_assert!(data.iter().skip(self.data_width()).all(Option::is_none));
+        debug_assert!(meta.iter().skip(self.num_pages - self.data_width()).all(|word| word.is_none_or(|w| w == 0)));
+        let cols: Vec<usize> = (0..self.data_width())
+            .filter(|col| !retired.contains(col))
+            .chain(self.data_width()..self.num_pages)
+            .collect();
+        let vals = cols.iter().map(|&col| match col.checked_sub(self.data_width()) {
+            Some(meta_col) => meta.get(meta_col).copied().flatten(),
+            None => data.get(col).copied().flatten(),
+        });
+        self.fetch_cols(&cols)?
+            .iter()
+            .zip(vals)
+            .try_for_each(|(page, val)| Ok(page.write().write(val, offset)?))
     }
 
     #[inline]
-    pub fn read_col(&self, col: usize, offset: usize) -> Result<Option<i64>, BufferPoolError> {
-        self.bufferpool.read(self.make_pid(col), offset)
+    pub fn read_col(&self, col: usize, offset: usize, hint: AccessHint) -> Result<Option<i64>, BufferPoolError> {
+        self.bufferpool.read_hinted(self.make_pid(col), offset, hint)
     }
 
     #[inline]
@@ -64,29 +93,42 @@ impl PageCollection {
         val: Option<i64>,
     ) -> Result<(), BufferPoolError> {
         match col {
-            MetaPage::Indirection => {
-                let actual_col = self.num_pages - Table::NUM_META_PAGES + col as usize;
-                self.bufferpool.update(self.make_pid(actual_col), offset, val)
-            },
+            MetaPage::Indirection => self.bufferpool.update(self.make_pid(self.meta_page(col)), offset, val),
             MetaPage::SchemaEncoding => panic!("Cannot update schema encoding"),
-            MetaPage::StartTime => panic!("Cannot update start time"),
+            MetaPage::Tps => panic!("Cannot update TPS"),
             MetaPage::Rid => panic!("Cannot update RID"),
         }
     }
 
     #[inline]
-    pub fn read_meta_col(&self, col: MetaPage, offset: usize) -> Result<Option<i64>, BufferPoolError> {
-        self.read_col(self.num_pages - Table::NUM_META_PAGES + col as usize, offset)
+    pub fn read_meta_col(&self, col: MetaPage, offset: usize, hint: AccessHint) -> Result<Option<i64>, BufferPoolError> {
+        self.read_col(self.meta_page(col), offset, hint)
     }
 
-    #[inline]
-    pub fn read_all(&self, offset: usize) -> Result<Vec<Option<i64>>, BufferPoolError> {
-        (0..self.num_pages).map(|i| self.read_col(i, offset)).collect()
+    /// Word `word` of a record's schema encoding, null for a deletion. A
+    /// word past those the record has here has none of its slots.
+    pub fn read_encoding_word(&self, word: usize, offset: usize, hint: AccessHint) -> Result<Option<i64>, BufferPoolError> {
+        match word {
+            0 => self.read_meta_col(MetaPage::SchemaEncoding, offset, hint),
+            _ if word < self.encoding_words() => {
+                let page = self.data_width() + Table::NUM_META_PAGES + word - 1;
+                Ok(Some(self.read_col(page, offset, hint)?.unwrap_or(0)))
+            }
+            _ => Ok(Some(0)),
+        }
     }
 
-    #[inline]
-    pub fn read_data_cols(&self, offset: usize, num_data: usize) -> Result<Vec<Option<i64>>, BufferPoolError> {
-        (0..num_data).map(|i| self.read_col(i, offset)).collect()
+    /// Reads a record's `cols` with all of their pages pinned, so none of
+    /// them is evicted halfway through.
+    pub fn read_cols(&self, cols: &[usize], offset: usize) -> Result<Vec<Option<i64>>, BufferPoolError> {
+        self.fetch_cols(cols)?
+            .iter()
+            .map(|page| Ok(page.read().read(offset)?))
+            .collect()
+    }
+
+    fn fetch_cols(&self, cols: &[usize]) -> Result<Vec<PageGuard>, BufferPoolError> {
+        cols.iter().map(|&col| self.bufferpool.fetch_page(self.make_pid(col))).collect()
     }
 
     #[inline]
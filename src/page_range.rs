@@ -1,31 +1,97 @@
-use crate::bufferpool::{BufferPool, BufferPoolError};
+use crate::bufferpool::{AccessHint, BufferPool, BufferPoolError};
 use crate::iterators::{PhysicalAddress, PhysicalAddressIterator, PidRange, PidRangeIterator};
 use crate::page::Page;
-use crate::page_collection::{MetaPage, PageCollection};
+use crate::page_collection::{MetaPage, PageCollection, PageId};
+use crate::schema::Schema;
+use crate::schema_encoding::SchemaEncoding;
 use crate::table::Table;
 use dashmap::DashMap;
+use parking_lot::RwLock;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
-#[allow(dead_code)] //don't care about pages_per_collection being unused
+/// Where the slots of a table's records stand against the pages of its
+/// collections. A collection has a page for each slot there was when it was
+/// cut, but not for those of columns dropped by then; nothing reads or
+/// writes a retired slot again. A slot added since reads as its `fill`, the
+/// default of the column it was added for, in a base record of a collection
+/// without a page for it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SlotMap {
+    pub(crate) retired: BTreeSet<usize>,
+    pub(crate) fill: BTreeMap<usize, i64>,
+}
+
+/// A collection of a range as the table's counters record it: its number,
+/// its PIDs, and how many of the records in it are not superseded yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CollectionMeta {
+    pub num: usize,
+    pub start: usize,
+    pub end: usize,
+    pub live: usize,
+}
+
+/// A column of every collection, at whichever page each one has it.
+#[derive(Clone, Copy)]
+enum Column {
+    Data(usize),
+    Meta(MetaPage),
+}
+
+impl Column {
+    /// The column's entry in `PageRange::last_scanned`.
+    fn tracker(self) -> usize {
+        match self {
+            Column::Meta(col) => col as usize,
+            Column::Data(col) => Table::NUM_META_PAGES + col,
+        }
+    }
+
+    fn page(self, collection: &PageCollection) -> Option<PageId> {
+        match self {
+            Column::Meta(col) => Some(collection.make_pid(collection.meta_page(col))),
+            Column::Data(col) => (col < collection.data_width()).then(|| collection.make_pid(col)),
+        }
+    }
+}
+
 pub struct PageRange {
     range: DashMap<usize, PageCollection>,
     pub(crate) next_addr: PhysicalAddressIterator,
-    pages_per_collection: usize,
     table_id: usize,
     bufferpool: Arc<BufferPool>,
     pub(crate) pid_iterator: Arc<PidRangeIterator>,
+    /// Shared with the table's other range.
+    slots: Arc<RwLock<SlotMap>>,
+    /// Whether a slot a collection has no page for reads as its fill, as in
+    /// a base record, or as null, as in a tail record, which cannot have
+    /// set it.
+    fills: bool,
+    /// For each column, the collection the last sequential read of it was in.
+    last_scanned: Vec<AtomicUsize>,
+    /// For each collection not retired yet, how many of the records
+    /// appended to it no merge has superseded.
+    live: DashMap<usize, usize>,
 }
 
 impl PageRange {
     pub const PROJECTED_NUM_PAGE_COLLECTIONS: usize =
         Table::PROJECTED_NUM_RECORDS.div_ceil(Page::PAGE_SIZE);
 
+    /// The live count of a collection from counters that did not keep one,
+    /// which is never retired.
+    pub const UNCOUNTED: usize = usize::MAX;
+
     pub fn new(
-        pages_per_collection: usize,
         first_pid: PidRange,
         table_id: usize,
         bufferpool: Arc<BufferPool>,
         pid_iterator: Arc<PidRangeIterator>,
+        slots: Arc<RwLock<SlotMap>>,
+        fills: bool,
     ) -> Self {
         // let mut init_range: DashMap<usize, PageCollection> =
         //     Vec::with_capacity(PageRange::PROJECTED_NUM_PAGE_COLLECTIONS);
@@ -40,45 +106,67 @@ impl PageRange {
         Self {
             range,
             next_addr: PhysicalAddressIterator::default(),
-            pages_per_collection,
             table_id,
             bufferpool,
             pid_iterator,
+            slots,
+            fills,
+            last_scanned: Self::trackers(),
+            live: DashMap::from_iter([(0, 0)]),
         }
     }
 
     pub fn restore(
-        pages_per_collection: usize,
-        collections: Vec<(usize, usize)>,
+        collections: Vec<CollectionMeta>,
         next_addr_val: usize,
         table_id: usize,
         bufferpool: Arc<BufferPool>,
         pid_iterator: Arc<PidRangeIterator>,
+        slots: Arc<RwLock<SlotMap>>,
+        fills: bool,
     ) -> Self {
         let range: DashMap<usize, PageCollection> = DashMap::with_capacity(collections.len());
-        for (i, (start, end)) in collections.into_iter().enumerate() {
+        let live = DashMap::with_capacity(collections.len());
+        for CollectionMeta { num, start, end, live: count } in collections {
             range.insert(
-                i,
+                num,
                 PageCollection::new(PidRange { start, end }, table_id, bufferpool.clone()),
             );
+            live.insert(num, count);
         }
         let next_addr = PhysicalAddressIterator::new();
         next_addr.restore(next_addr_val);
         Self {
             range,
             next_addr,
-            pages_per_collection,
             table_id,
             bufferpool,
             pid_iterator,
+            slots,
+            fills,
+            last_scanned: Self::trackers(),
+            live,
         }
     }
 
-    fn append(&self, all_data: Vec<Option<i64>>) -> Result<PhysicalAddress, BufferPoolError> {
+    fn trackers() -> Vec<AtomicUsize> {
+        (0..Table::NUM_META_PAGES + Schema::MAX_WIDTH).map(|_| AtomicUsize::new(usize::MAX)).collect()
+    }
+
+    fn append(
+        &self,
+        data: &[Option<i64>],
+        meta: &[Option<i64>],
+    ) -> Result<PhysicalAddress, BufferPoolError> {
         let addr = self.next_addr.next();
+        let slots = self.slots.read();
+        {
+            let mut live = self.live.entry(addr.collection_num).or_insert(0);
+            *live = live.saturating_add(1);
+        }
 
         if let Some(collection) = self.range.get(&addr.collection_num) {
-            return collection.write_cols(addr.offset, all_data).map(|_| addr);
+            return collection.write_cols(addr.offset, data, meta, &slots.retired).map(|_| addr);
         }
 
         self.range
@@ -90,23 +178,78 @@ impl PageRange {
                     self.bufferpool.clone(),
                 )
             })
-            .write_cols(addr.offset, all_data)?;
+            .write_cols(addr.offset, data, meta, &slots.retired)?;
 
         Ok(addr)
     }
 
+    /// Has the next record start a collection, unless it would anyway, so
+    /// that it and those after it are cut with a page for every slot there
+    /// is now. A collection nothing was written to yet is cut afresh in
+    /// place. The table's writes have to be held off meanwhile.
+    fn cut(&self) {
+        let next = PhysicalAddress::from_index(self.next_addr.current());
+        if !self.range.contains_key(&next.collection_num) {
+            return;
+        }
+        if next.offset == 0 {
+            let fresh = PageCollection::new(self.pid_iterator.next(), self.table_id, self.bufferpool.clone());
+            self.range.insert(next.collection_num, fresh);
+        } else {
+            self.next_addr.restore((next.collection_num + 1) * Page::PAGE_SIZE);
+        }
+    }
+
+    /// Has the next record start a collection, so that nothing more is
+    /// appended to the one it would have gone in, and returns its number.
+    fn seal(&self) -> usize {
+        let next = PhysicalAddress::from_index(self.next_addr.current());
+        if next.offset == 0 {
+            return next.collection_num;
+        }
+        self.next_addr.restore((next.collection_num + 1) * Page::PAGE_SIZE);
+        next.collection_num + 1
+    }
+
+    /// What slot `col` of a record in `collection` reads as for want of a
+    /// page, if it has none to read.
+    fn without_page(&self, slots: &SlotMap, collection: &PageCollection, col: usize) -> Option<Option<i64>> {
+        if slots.retired.contains(&col) {
+            Some(None)
+        } else if col >= collection.data_width() {
+            Some(slots.fill.get(&col).copied().filter(|_| self.fills))
+        } else {
+            None
+        }
+    }
+
+    /// A record's data and then its meta columns.
     fn read(&self, addr: &PhysicalAddress) -> Result<Vec<Option<i64>>, BufferPoolError> {
-        self.range
-            .get(&addr.collection_num)
-            .ok_or(BufferPoolError::PidNotInFrame)?
-            .read_all(addr.offset)
+        let collection = self.range.get(&addr.collection_num).ok_or(BufferPoolError::PidNotInFrame)?;
+        let meta: Vec<usize> = (collection.data_width()..collection.data_width() + Table::NUM_META_PAGES).collect();
+        let mut record = self.read_data(addr, collection.data_width())?;
+        record.extend(collection.read_cols(&meta, addr.offset)?);
+        Ok(record)
     }
 
+    /// The first `num_data` slots of a record.
     fn read_data(&self, addr: &PhysicalAddress, num_data: usize) -> Result<Vec<Option<i64>>, BufferPoolError> {
-        self.range
-            .get(&addr.collection_num)
-            .ok_or(BufferPoolError::PidNotInFrame)?
-            .read_data_cols(addr.offset, num_data)
+        let collection = self.range.get(&addr.collection_num).ok_or(BufferPoolError::PidNotInFrame)?;
+        let mut data = vec![None; num_data];
+        let mut paged = Vec::with_capacity(num_data);
+        {
+            let slots = self.slots.read();
+            for (col, value) in data.iter_mut().enumerate() {
+                match self.without_page(&slots, &collection, col) {
+                    Some(without) => *value = without,
+                    None => paged.push(col),
+                }
+            }
+        }
+        for (&col, value) in paged.iter().zip(collection.read_cols(&paged, addr.offset)?) {
+            data[col] = value;
+        }
+        Ok(data)
     }
 
     #[inline]
@@ -114,11 +257,14 @@ impl PageRange {
         &self,
         col: usize,
         addr: &PhysicalAddress,
+        hint: AccessHint,
     ) -> Result<Option<i64>, BufferPoolError> {
-        self.range
-            .get(&addr.collection_num)
-            .ok_or(BufferPoolError::PidNotInFrame)?
-            .read_col(col, addr.offset)
+        self.note_read(Column::Data(col), addr, hint);
+        let collection = self.range.get(&addr.collection_num).ok_or(BufferPoolError::PidNotInFrame)?;
+        if let Some(without) = self.without_page(&self.slots.read(), &collection, col) {
+            return Ok(without);
+        }
+        collection.read_col(col, addr.offset, hint)
     }
 
     #[inline]
@@ -138,28 +284,61 @@ impl PageRange {
         &self,
         addr: &PhysicalAddress,
         col: MetaPage,
+        hint: AccessHint,
     ) -> Result<Option<i64>, BufferPoolError> {
+        self.note_read(Column::Meta(col), addr, hint);
         self.range
             .get(&addr.collection_num)
             .ok_or(BufferPoolError::PidNotInFrame)?
-            .read_meta_col(col, addr.offset)
+            .read_meta_col(col, addr.offset, hint)
     }
 
-    /// Returns the TPS watermark for the collection that contains `addr`.
-    #[inline]
-    pub fn get_tps(&self, addr: &PhysicalAddress) -> i64 {
-        self.range
-            .get(&addr.collection_num)
-            .map(|c| c.get_tps())
-            .ok_or(BufferPoolError::PidNotInFrame)
-            .unwrap_or(i64::MIN)
+    /// A record's schema encoding, or `None` for a deletion.
+    fn read_encoding(&self, addr: &PhysicalAddress) -> Result<Option<SchemaEncoding>, BufferPoolError> {
+        let collection = self.range.get(&addr.collection_num).ok_or(BufferPoolError::PidNotInFrame)?;
+        (0..collection.encoding_words())
+            .map(|word| collection.read_encoding_word(word, addr.offset, AccessHint::Normal))
+            .collect::<Result<Option<Vec<i64>>, _>>()
+            .map(|words| words.map(SchemaEncoding::from_words))
     }
 
-    /// Advances the TPS watermark for the collection that contains `addr`.
-    #[inline]
-    pub fn update_tps(&self, addr: &PhysicalAddress, new_tps: i64) {
-        if let Some(collection) = self.range.get(&addr.collection_num) {
-            collection.update_tps(new_tps)
+    /// Whether a record's schema encoding has `slot`, reading only the word
+    /// it falls in.
+    fn encodes(&self, slot: usize, addr: &PhysicalAddress, hint: AccessHint) -> Result<bool, BufferPoolError> {
+        let word = slot / SchemaEncoding::SLOTS_PER_WORD;
+        if word == 0 {
+            self.note_read(Column::Meta(MetaPage::SchemaEncoding), addr, hint);
+        }
+        let collection = self.range.get(&addr.collection_num).ok_or(BufferPoolError::PidNotInFrame)?;
+        let word = collection.read_encoding_word(word, addr.offset, hint)?;
+        Ok(word.is_some_and(|word| SchemaEncoding::word_has(word, slot)))
+    }
+
+    /// A sequential read of `col` that moves on to the collection after the
+    /// last one is taken for a scan of it, and the column's pages in the
+    /// pool's `read_ahead` collections past it are prefetched.
+    fn note_read(&self, col: Column, addr: &PhysicalAddress, hint: AccessHint) {
+        if hint != AccessHint::Sequential {
+            return;
+        }
+        let Some(last_scanned) = self.last_scanned.get(col.tracker()) else {
+            return;
+        };
+        let collection = addr.collection_num;
+        let last = last_scanned.swap(collection, Ordering::Relaxed);
+        if last.wrapping_add(1) == collection {
+            self.prefetch(col, collection + 1..collection + 1 + self.bufferpool.read_ahead());
+        }
+    }
+
+    /// Has the pool load the pages of `col` in `collections` in the
+    /// background.
+    fn prefetch(&self, col: Column, collections: Range<usize>) {
+        let pids: Vec<PageId> = collections
+            .filter_map(|c| self.range.get(&c).and_then(|c| col.page(&c)))
+            .collect();
+        if !pids.is_empty() {
+            self.bufferpool.prefetch(pids);
         }
     }
 
@@ -173,7 +352,7 @@ impl PageRange {
             .enumerate()
             .map(|(col, &flag)| {
                 if flag == 1 {
-                    self.read_single(col, addr)
+                    self.read_single(col, addr, AccessHint::Normal)
                 } else {
                     Ok(None)
                 }
@@ -181,21 +360,112 @@ impl PageRange {
             .collect()
     }
 
-    pub fn collection_pid_ranges(&self) -> Vec<(usize, usize)> {
-        let mut pairs: Vec<(usize, usize, usize)> = self
+    /// The collections not retired yet, in order.
+    pub fn collections(&self) -> Vec<CollectionMeta> {
+        let mut collections: Vec<CollectionMeta> = self
             .range
             .iter()
-            .map(|e| (*e.key(), e.value().pid_range.start, e.value().pid_range.end))
+            .filter_map(|e| {
+                let live = *self.live.get(e.key())?;
+                let range = &e.value().pid_range;
+                Some(CollectionMeta { num: *e.key(), start: range.start, end: range.end, live })
+            })
             .collect();
-        pairs.sort_unstable_by_key(|&(idx, _, _)| idx);
-        pairs.into_iter().map(|(_, s, e)| (s, e)).collect()
+        collections.sort_unstable_by_key(|c| c.num);
+        collections
+    }
+
+    fn has_collection(&self, num: usize) -> bool {
+        self.range.contains_key(&num)
+    }
+
+    /// Takes the record at `addr` off its collection's live count, once a
+    /// newer copy of it, or a merged one, stands in for it.
+    fn supersede(&self, addr: &PhysicalAddress) {
+        if let Some(mut live) = self.live.get_mut(&addr.collection_num)
+            && *live != Self::UNCOUNTED
+        {
+            *live = live.saturating_sub(1);
+        }
+    }
+
+    /// The collections nothing is appended to any longer whose every
+    /// record is superseded.
+    fn superseded(&self) -> Vec<CollectionMeta> {
+        let sealed = self.next_addr.current() / Page::PAGE_SIZE;
+        self.collections().into_iter().filter(|c| c.num < sealed && c.live == 0).collect()
+    }
+
+    /// Counts every record in the collections before `num` as superseded.
+    fn supersede_before(&self, num: usize) {
+        for mut live in self.live.iter_mut() {
+            if *live.key() < num {
+                *live = 0;
+            }
+        }
+    }
+
+    /// Pages of the collections not retired, from collection `num` on.
+    fn pages_from(&self, num: usize) -> usize {
+        self.collections().iter().filter(|c| c.num >= num).map(|c| c.end - c.start).sum()
+    }
+
+    /// Stops counting collection `num`, which the table's counters leave
+    /// out from now on.
+    fn retire(&self, num: usize) {
+        self.live.remove(&num);
+    }
+
+    /// The RID stored in every slot of collection `num`, which is sealed.
+    fn collection_rids(&self, num: usize) -> Result<Vec<(i64, PhysicalAddress)>, BufferPoolError> {
+        let mut pairs = Vec::with_capacity(Page::PAGE_SIZE);
+        for offset in 0..Page::PAGE_SIZE {
+            let addr = PhysicalAddress { collection_num: num, offset };
+            if let Some(rid) = self.read_meta_col(&addr, MetaPage::Rid, AccessHint::Sequential)? {
+                pairs.push((rid, addr));
+            }
+        }
+        Ok(pairs)
+    }
+
+    /// Deletes the pages of retired collection `num` and hands its PIDs out
+    /// again. No reader may be left in it.
+    fn free(&self, num: usize) -> Result<usize, BufferPoolError> {
+        let Some(collection) = self.range.get(&num).map(|c| c.pid_range) else {
+            return Ok(0);
+        };
+        for pid in collection.start..collection.end {
+            self.bufferpool.delete_page(PageId::new(pid, self.table_id))?;
+        }
+        self.range.remove(&num);
+        self.pid_iterator.give_back(collection);
+        Ok(collection.end - collection.start)
     }
 
     pub fn next_addr_value(&self) -> usize {
         self.next_addr.current()
     }
+
+    /// The RID stored in every slot handed out so far, in append order.
+    fn scan_rids(&self) -> Result<Vec<(i64, PhysicalAddress)>, BufferPoolError> {
+        let mut pairs = Vec::with_capacity(self.next_addr.current());
+        // Reads every collection in order, so there is no need to wait for
+        // the second one to start reading ahead.
+        self.prefetch(Column::Meta(MetaPage::Rid), 1..1 + self.bufferpool.read_ahead());
+        for index in 0..self.next_addr.current() {
+            let addr = PhysicalAddress::from_index(index);
+            if !self.has_collection(addr.collection_num) {
+                continue;
+            }
+            if let Some(rid) = self.read_meta_col(&addr, MetaPage::Rid, AccessHint::Sequential)? {
+                pairs.push((rid, addr));
+            }
+        }
+        Ok(pairs)
+    }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum WhichRange {
     Base,
     Tail,
@@ -204,87 +474,112 @@ pub enum WhichRange {
 pub struct PageRanges {
     pub(crate) tail: PageRange,
     pub(crate) base: PageRange,
+    slots: Arc<RwLock<SlotMap>>,
 }
 
 impl PageRanges {
     pub fn new(pages_per_collection: usize, table_id: usize, bufferpool: Arc<BufferPool>) -> Self {
         let pid_range_iter = Arc::new(PidRangeIterator::new(pages_per_collection));
+        let slots = Arc::new(RwLock::new(SlotMap::default()));
         Self {
             tail: PageRange::new(
-                pages_per_collection,
                 pid_range_iter.next(),
                 table_id,
                 bufferpool.clone(),
                 pid_range_iter.clone(),
+                slots.clone(),
+                false,
             ),
             base: PageRange::new(
-                pages_per_collection,
                 pid_range_iter.next(),
                 table_id,
                 bufferpool,
                 pid_range_iter,
+                slots.clone(),
+                true,
             ),
+            slots,
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn restore(
         pages_per_collection: usize,
         table_id: usize,
         bufferpool: Arc<BufferPool>,
-        base_collection: Vec<(usize, usize)>,
-        tail_collection: Vec<(usize, usize)>,
+        base_collection: Vec<CollectionMeta>,
+        tail_collection: Vec<CollectionMeta>,
         base_next_addr: usize,
         tail_next_addr: usize,
         pid_next_start: usize,
+        free_pids: Vec<(usize, usize)>,
+        slots: SlotMap,
     ) -> Self {
-        let pid_iterator = Arc::new(PidRangeIterator::restore(pid_next_start, pages_per_collection, ));
+        let pid_iterator = Arc::new(PidRangeIterator::restore(pid_next_start, pages_per_collection, free_pids));
+        let slots = Arc::new(RwLock::new(slots));
         Self {
             tail: PageRange::restore(
-                pages_per_collection,
                 tail_collection,
                 tail_next_addr,
                 table_id,
                 bufferpool.clone(),
                 pid_iterator.clone(),
+                slots.clone(),
+                false,
             ),
             base: PageRange::restore(
-                pages_per_collection,
                 base_collection,
                 base_next_addr,
                 table_id,
                 bufferpool.clone(),
                 pid_iterator.clone(),
+                slots.clone(),
+                true,
             ),
+            slots,
+        }
+    }
+
+    pub fn slots(&self) -> SlotMap {
+        self.slots.read().clone()
+    }
+
+    /// Cuts the collections of both ranges from now on with a page for each
+    /// of `width` slots, but those `slots` retires, which stop being read
+    /// or written in the older ones too. A record written from now on goes
+    /// in a collection cut after now if `width` changes. The table's writes
+    /// have to be held off meanwhile.
+    pub fn reslot(&self, width: usize, slots: SlotMap) {
+        *self.slots.write() = slots;
+        let pages = Table::collection_pages(width);
+        if pages != self.base.pid_iterator.pages_per_collection() {
+            self.base.pid_iterator.set_pages_per_collection(pages);
+            self.base.cut();
+            self.tail.cut();
         }
     }
 
     pub fn append_base(
         &self,
-        data_cols: &Vec<Option<i64>>,
+        data_cols: &[Option<i64>],
         rid: i64,
     ) -> Result<PhysicalAddress, BufferPoolError> {
-        let mut all_cols = data_cols.clone();
-        all_cols.push(Some(rid));
-        all_cols.push(Some(rid));
-        all_cols.push(Some(0));
-        all_cols.push(None);
-
-        self.base.append(all_cols)
+        self.base.append(data_cols, &Self::meta(rid, rid, Some(SchemaEncoding::default())))
     }
 
+    /// A copy of base record `rid` with the tail records up to `tps`
+    /// folded in.
     pub fn append_base_merged(
         &self,
         data_cols: Vec<Option<i64>>,
         rid: i64,
         indirection: i64,
-        schema_encoding: Option<i64>,
+        tps: i64,
+        schema_encoding: Option<SchemaEncoding>,
     ) -> Result<PhysicalAddress, BufferPoolError> {
-        let mut all_cols = data_cols.clone();
-        all_cols.push(Some(rid));
-        all_cols.push(Some(indirection));
-        all_cols.push(schema_encoding);
-        all_cols.push(None);
-        self.base.append(all_cols)
+        let mut meta = Self::meta(rid, indirection, schema_encoding);
+        meta[MetaPage::Tps as usize] = Some(tps);
+        self.base.append(&data_cols, &meta)
     }
 
     pub fn append_tail(
@@ -292,14 +587,39 @@ impl PageRanges {
         data_cols: Vec<Option<i64>>,
         rid: i64,
         indirection: i64,
-        schema_encoding: Option<i64>,
+        schema_encoding: Option<SchemaEncoding>,
+    ) -> Result<PhysicalAddress, BufferPoolError> {
+        self.tail.append(&data_cols, &Self::meta(rid, indirection, schema_encoding))
+    }
+
+    /// A tail record `rid` holding base record `base_rid`'s values from
+    /// before any of its tail records newer than `folded`, for reads of
+    /// older versions to start from once a merge has written over them. It
+    /// is in no chain; its indirection is `folded`, which is `base_rid` if
+    /// no tail record is folded in, and its TPS says which base record it is
+    /// of.
+    pub fn append_snapshot(
+        &self,
+        data_cols: Vec<Option<i64>>,
+        rid: i64,
+        base_rid: i64,
+        folded: i64,
     ) -> Result<PhysicalAddress, BufferPoolError> {
-        let mut all_cols = data_cols.clone();
-        all_cols.push(Some(rid));
-        all_cols.push(Some(indirection));
-        all_cols.push(schema_encoding);
-        all_cols.push(None);
-        self.tail.append(all_cols)
+        let mut meta = Self::meta(rid, folded, Some(SchemaEncoding::default()));
+        meta[MetaPage::Tps as usize] = Some(base_rid);
+        self.tail.append(&data_cols, &meta)
+    }
+
+    /// What `PageCollection::write_cols` writes past a record's data: the
+    /// meta pages, then the schema encoding's other words. A deletion has
+    /// none.
+    fn meta(rid: i64, indirection: i64, schema_encoding: Option<SchemaEncoding>) -> Vec<Option<i64>> {
+        let mut meta = vec![Some(rid), Some(indirection), None, None];
+        if let Some(encoding) = schema_encoding {
+            meta[MetaPage::SchemaEncoding as usize] = Some(encoding.word(0));
+            meta.extend(encoding.words().skip(1).map(Some));
+        }
+        meta
     }
 
     #[inline]
@@ -308,10 +628,21 @@ impl PageRanges {
         column: usize,
         addr: &PhysicalAddress,
         range: WhichRange,
+    ) -> Result<Option<i64>, BufferPoolError> {
+        self.read_single_hinted(column, addr, range, AccessHint::Normal)
+    }
+
+    #[inline]
+    pub fn read_single_hinted(
+        &self,
+        column: usize,
+        addr: &PhysicalAddress,
+        range: WhichRange,
+        hint: AccessHint,
     ) -> Result<Option<i64>, BufferPoolError> {
         match range {
-            WhichRange::Base => self.base.read_single(column, addr),
-            WhichRange::Tail => self.tail.read_single(column, addr),
+            WhichRange::Base => self.base.read_single(column, addr, hint),
+            WhichRange::Tail => self.tail.read_single(column, addr, hint),
         }
     }
 
@@ -321,7 +652,7 @@ impl PageRanges {
         column: usize,
         addr: &PhysicalAddress,
     ) -> Result<Option<i64>, BufferPoolError> {
-        self.tail.read_single(column, addr)
+        self.tail.read_single(column, addr, AccessHint::Normal)
     }
 
     #[inline]
@@ -351,6 +682,11 @@ impl PageRanges {
         self.base.read_data(addr, num_data)
     }
 
+    #[inline]
+    pub fn read_tail_data(&self, addr: &PhysicalAddress, num_data: usize) -> Result<Vec<Option<i64>>, BufferPoolError> {
+        self.tail.read_data(addr, num_data)
+    }
+
     #[inline]
     pub fn read_projected(
         &self,
@@ -365,29 +701,130 @@ impl PageRanges {
         addr: &PhysicalAddress,
         col_type: MetaPage,
         range: WhichRange,
+    ) -> Result<Option<i64>, BufferPoolError> {
+        self.read_meta_col_hinted(addr, col_type, range, AccessHint::Normal)
+    }
+
+    pub fn read_meta_col_hinted(
+        &self,
+        addr: &PhysicalAddress,
+        col_type: MetaPage,
+        range: WhichRange,
+        hint: AccessHint,
     ) -> Result<Option<i64>, BufferPoolError> {
         match range {
-            WhichRange::Base => self.base.read_meta_col(addr, col_type),
-            WhichRange::Tail => self.tail.read_meta_col(addr, col_type),
+            WhichRange::Base => self.base.read_meta_col(addr, col_type, hint),
+            WhichRange::Tail => self.tail.read_meta_col(addr, col_type, hint),
         }
     }
 
-    #[inline]
-    pub fn get_tps(&self, addr: &PhysicalAddress) -> i64 {
-        self.base.get_tps(addr)
+    /// A record's schema encoding, or `None` for a deletion.
+    pub fn read_schema_encoding(
+        &self,
+        addr: &PhysicalAddress,
+        range: WhichRange,
+    ) -> Result<Option<SchemaEncoding>, BufferPoolError> {
+        match range {
+            WhichRange::Base => self.base.read_encoding(addr),
+            WhichRange::Tail => self.tail.read_encoding(addr),
+        }
     }
 
-    #[inline]
-    pub fn update_tps(&self, addr: &PhysicalAddress, new_tps: i64) {
-        self.base.update_tps(addr, new_tps);
+    /// Whether a record sets `slot`: a tail record that updates it.
+    pub fn encodes_hinted(
+        &self,
+        slot: usize,
+        addr: &PhysicalAddress,
+        range: WhichRange,
+        hint: AccessHint,
+    ) -> Result<bool, BufferPoolError> {
+        match range {
+            WhichRange::Base => self.base.encodes(slot, addr, hint),
+            WhichRange::Tail => self.tail.encodes(slot, addr, hint),
+        }
+    }
+
+    /// The TPS watermark of the base record at `addr`: the newest tail
+    /// record a merge folded into it, or `i64::MIN` if none did.
+    pub fn read_tps(&self, addr: &PhysicalAddress, hint: AccessHint) -> Result<i64, BufferPoolError> {
+        Ok(self.base.read_meta_col(addr, MetaPage::Tps, hint)?.unwrap_or(i64::MIN))
+    }
+
+    fn range(&self, range: WhichRange) -> &PageRange {
+        match range {
+            WhichRange::Base => &self.base,
+            WhichRange::Tail => &self.tail,
+        }
+    }
+
+    pub fn base_collections(&self) -> Vec<CollectionMeta> {
+        self.base.collections()
+    }
+
+    pub fn tail_collections(&self) -> Vec<CollectionMeta> {
+        self.tail.collections()
+    }
+
+    /// Whether the collection `addr` is in still has its pages.
+    pub fn has_collection(&self, addr: &PhysicalAddress, range: WhichRange) -> bool {
+        self.range(range).has_collection(addr.collection_num)
+    }
+
+    pub fn supersede(&self, addr: &PhysicalAddress, range: WhichRange) {
+        self.range(range).supersede(addr)
     }
 
-    pub fn base_collection_pid_ranges(&self) -> Vec<(usize, usize)> {
-        self.base.collection_pid_ranges()
+    pub fn superseded(&self, range: WhichRange) -> Vec<CollectionMeta> {
+        self.range(range).superseded()
     }
 
-    pub fn tail_collection_pid_ranges(&self) -> Vec<(usize, usize)> {
-        self.tail.collection_pid_ranges()
+    pub fn retire(&self, num: usize, range: WhichRange) {
+        self.range(range).retire(num)
+    }
+
+    pub fn collection_rids(&self, num: usize, range: WhichRange) -> Result<Vec<(i64, PhysicalAddress)>, BufferPoolError> {
+        self.range(range).collection_rids(num)
+    }
+
+    /// Seals the collection each range appends to, and returns the numbers
+    /// of the base and tail collections the next records go in.
+    pub fn seal(&self) -> (usize, usize) {
+        (self.base.seal(), self.tail.seal())
+    }
+
+    /// Counts every record before base collection `base` and tail
+    /// collection `tail` as superseded.
+    pub fn supersede_before(&self, base: usize, tail: usize) {
+        self.base.supersede_before(base);
+        self.tail.supersede_before(tail);
+    }
+
+    /// Pages of the collections not retired, from base collection `base`
+    /// and tail collection `tail` on.
+    pub fn pages_from(&self, base: usize, tail: usize) -> usize {
+        self.base.pages_from(base) + self.tail.pages_from(tail)
+    }
+
+    /// Deletes the pages of a retired collection, returning how many it had.
+    pub fn free(&self, num: usize, range: WhichRange) -> Result<usize, BufferPoolError> {
+        self.range(range).free(num)
+    }
+
+    /// The PID ranges there are to hand out again.
+    pub fn free_pid_ranges(&self) -> Vec<(usize, usize)> {
+        self.base.pid_iterator.free_ranges()
+    }
+
+    /// Deletes whatever pages are left in the PID ranges there are to hand
+    /// out again, as a crash between retiring a collection and freeing it
+    /// leaves them.
+    pub fn delete_free_pages(&self) -> Result<(), BufferPoolError> {
+        for (start, end) in self.free_pid_ranges() {
+            for pid in start..end {
+                self.base.bufferpool.delete_page(PageId::new(pid, self.base.table_id))?;
+            }
+        }
+        Ok(())
     }
 
     pub fn base_next_addr(&self) -> usize {
@@ -398,6 +835,14 @@ impl PageRanges {
         self.tail.next_addr_value()
     }
 
+    pub fn scan_base_rids(&self) -> Result<Vec<(i64, PhysicalAddress)>, BufferPoolError> {
+        self.base.scan_rids()
+    }
+
+    pub fn scan_tail_rids(&self) -> Result<Vec<(i64, PhysicalAddress)>, BufferPoolError> {
+        self.tail.scan_rids()
+    }
+
     pub fn pid_next_start(&self) -> usize {
         self.base.pid_iterator.current()
     }
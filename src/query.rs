@@ -1,11 +1,18 @@
+use crate::bufferpool::AccessHint;
 use crate::errors::DbError;
 use crate::page_collection::MetaPage;
 use crate::page_range::WhichRange;
+use crate::schema::{ColumnType, Value};
+use crate::schema_encoding::SchemaEncoding;
 use crate::table::Table;
+use crate::wal::LogRecord;
+use parking_lot::RwLockReadGuard;
 use std::sync::Arc;
 
 pub struct Query {
     pub table: Arc<Table>,
+    /// Transaction the writes are logged under, `None` for auto-commit.
+    txn_id: Option<usize>,
 }
 
 impl Query {
@@ -15,10 +22,28 @@ impl Query {
     pub fn new(table: Arc<Table>) -> Self {
         Self {
             table: table.clone(),
+            txn_id: None,
+        }
+    }
+
+    pub fn with_txn(table: Arc<Table>, txn_id: usize) -> Self {
+        Self {
+            table,
+            txn_id: Some(txn_id),
+        }
+    }
+
+    /// Keeps checkpoints out while a write is half done. A transaction keeps
+    /// them out until it commits, so its queries only nest inside that.
+    fn begin_write(&self) -> RwLockReadGuard<'_, ()> {
+        match self.txn_id {
+            Some(_) => self.table.wal.begin_nested_write(),
+            None => self.table.wal.begin_write(),
         }
     }
 
     pub fn insert(&self, record: Vec<Option<i64>>) -> Result<bool, DbError> {
+        let _writing = self.begin_write();
         let rid = self.table.rid.next();
         let key = record[self.table.key_index].ok_or(DbError::NullValue(self.table.key_index))?;
 
@@ -26,14 +51,34 @@ impl Query {
             return Ok(false);
         }
 
-        let address = self.table.page_ranges.append_base(&record, rid)?;
+        // Written before it is logged, as an update's tail record is; nothing
+        // finds it until it is in the page directory.
+        let address = match self.table.page_ranges.append_base(&record, rid) {
+            Ok(address) => address,
+            Err(e) => {
+                self.table.indices[self.table.key_index].remove(key, rid);
+                return Err(e.into());
+            }
+        };
+        let log = LogRecord::Insert {
+            txn: self.txn_id,
+            table_id: self.table.table_id,
+            rid,
+            cols: record.clone(),
+        };
+        if let Err(e) = self.table.wal.append(&log) {
+            self.table.indices[self.table.key_index].remove(key, rid);
+            return Err(e.into());
+        }
         self.table.page_directory.add(rid, address);
 
-        for (i, _val) in record
+        for (i, val) in record
             .iter()
             .enumerate().filter(|(i,_)| *i != self.table.key_index)
         {
-                self.table.indices[i].insert(i as i64, rid)
+            if let Some(val) = val {
+                self.table.indices[i].insert(*val, rid)
+            }
         }
         Ok(true)
     }
@@ -44,29 +89,34 @@ impl Query {
         search_key_index: usize,
         projected_columns_index: &[i64],
     ) -> Result<Vec<Vec<Option<i64>>>, DbError> {
-        let rids = if search_key_index == self.table.key_index {
-            match self.table.indices[search_key_index].locate(key) {
-                Some(rid) => vec![rid],
-                None => return Ok(vec![]),
-            }
+        // Until the records are read, so that the collections they are in
+        // are not freed meanwhile.
+        let _reading = self.table.pin();
+        self.locate(key, search_key_index)
+            .iter()
+            .map(|&rid| {
+                self.table
+                    .read_latest_projected(projected_columns_index, rid)
+            })
+            .collect()
+    }
+
+    /// RIDs of the records whose slot `search_key_index` holds `key`.
+    fn locate(&self, key: i64, search_key_index: usize) -> Vec<i64> {
+        if search_key_index == self.table.key_index {
+            self.table.indices[search_key_index].locate(key).into_iter().collect()
         } else if self.table.indices[search_key_index].is_enabled() {
             self.table.indices[search_key_index].locate_all(key)
         } else {
+            // Every record is looked at once, so the pages go in cold.
             self.table.indices[self.table.key_index].locate_range(i64::MIN, i64::MAX).into_iter().filter(|&rid| {
                 self.table
-                    .read_latest_single(rid, search_key_index)
+                    .read_latest_single_hinted(rid, search_key_index, AccessHint::Sequential)
                     .ok()
                     .flatten()
                     .is_some_and(|r| r == key)
             }).collect()
-        };
-
-        rids.iter()
-            .map(|&rid| {
-                self.table
-                    .read_latest_projected(projected_columns_index, rid)
-            })
-            .collect()
+        }
     }
 
     pub fn select_version(
@@ -77,6 +127,7 @@ impl Query {
         relative_version: i64,
     ) -> Result<Vec<Vec<Option<i64>>>, DbError> {
         let rid = self.table.rid_for_key(key)?;
+        let _reading = self.table.pin();
 
         Ok(vec![self.table.read_version_projected(
             projected_columns_index,
@@ -88,10 +139,13 @@ impl Query {
         if record[self.table.key_index].is_some() {
             return Ok(false);
         };
+        let _writing = self.begin_write();
         let rid = match self.table.rid_for_key(key) {
             Ok(rid) => rid,
             _ => return Ok(false),
         };
+        // Until the new tail is linked in, so a merge cannot miss it.
+        let _latch = self.table.latch(rid);
 
         let base_addr = self.table.page_directory.get(rid)?;
 
@@ -101,65 +155,70 @@ impl Query {
             .read_meta_col(&base_addr, MetaPage::Indirection, WhichRange::Base)?
             .ok_or(DbError::NullValue(404))?;
 
-        let mut schema_encoding: i64 = 0;
-        for (i, val) in record.iter().enumerate() {
-            if val.is_some() {
-                schema_encoding |= 1 << i;
-            }
-        }
+        let schema_encoding = SchemaEncoding::of(&record);
 
-        let new_key = record[self.table.key_index];
-        if new_key.is_some() {
-            let current_values = self.table.read_latest(rid)?;
-            if let Some(old_key) = current_values[0] {
-                self.table.indices[0].remove(old_key, rid);
-            }
-            self.table.indices[0].insert(new_key.ok_or(DbError::NullValue(0))?, rid);
-        }
+        // Indexed slots the update changes, and the values they move from.
+        let reindexed: Vec<usize> = (0..record.len())
+            .filter(|&i| i != self.table.key_index && record[i].is_some() && self.table.indices[i].is_enabled())
+            .collect();
+        let current_values = if reindexed.is_empty() {
+            Vec::new()
+        } else {
+            self.table.read_latest(rid)?
+        };
 
         let next_rid = self.table.rid.next();
 
+        // Appended before it is logged, so that a write failing here leaves
+        // nothing for recovery to redo. Nothing finds it until the base
+        // record points to it.
         let address = self.table.page_ranges.append_tail(
-            record,
+            record.clone(),
             next_rid,
             current_indirection,
             Some(schema_encoding),
         )?;
-
         self.table.page_directory.add(next_rid, address);
 
+        self.table.wal.append(&LogRecord::Update {
+            txn: self.txn_id,
+            table_id: self.table.table_id,
+            base_rid: rid,
+            tail_rid: next_rid,
+            prev: current_indirection,
+            cols: record.clone(),
+        })?;
+
         self.table
             .page_ranges
             .write_indirection(&base_addr, Some(next_rid), WhichRange::Base)?;
 
+        // Only once the new values are in place.
+        for i in reindexed {
+            if let Some(old) = current_values[i] {
+                self.table.indices[i].remove(old, rid);
+            }
+            if let Some(new) = record[i] {
+                self.table.indices[i].insert(new, rid);
+            }
+        }
+
         // Mark this base RID as having unmerged tail data.
         // DashSet deduplicates automatically so repeated updates to the same
         // record are cheap and don't inflate the dirty set.
-        self.table.dirty_base_rids.insert(rid);
+        self.table.note_tail(rid);
 
         Ok(true)
     }
 
 
     pub fn delete(&self, key: i64) -> Result<bool, DbError> {
+        let _writing = self.begin_write();
         let rid = self.table.rid_for_key(key)?;
+        let _latch = self.table.latch(rid);
 
         let current_val = self.table.read_latest(rid)?;
 
-        self.table.indices[self.table.key_index].remove(key, rid);
-
-        for (i, val) in current_val
-            .iter()
-            .enumerate()
-            .take(self.table.indices.len())
-        {
-            if i != self.table.key_index
-                && let Some(v) = val
-            {
-                self.table.indices[i].remove(*v, rid);
-            }
-        }
-
         let base_addr = self.table.page_directory.get(rid)?;
 
         let current_indirection = self
@@ -169,23 +228,48 @@ impl Query {
             .ok_or(DbError::NullValue(404))?;
 
         let next_rid = self.table.rid.next();
-        let tail_record = vec![None; self.table.num_data_columns];
+
+        // Appended and linked in before the record stops being found, as in
+        // `update`.
+        let tail_record = vec![None; self.table.num_data_columns()];
         let address = self.table.page_ranges.append_tail(
             tail_record,
             next_rid,
             current_indirection,
             None, // schema_encoding = None is the deletion marker
         )?;
-
         self.table.page_directory.add(next_rid, address);
 
+        self.table.wal.append(&LogRecord::Delete {
+            txn: self.txn_id,
+            table_id: self.table.table_id,
+            base_rid: rid,
+            tail_rid: next_rid,
+            prev: current_indirection,
+            key,
+        })?;
+
         self.table
             .page_ranges
             .write_indirection(&base_addr, Some(next_rid), WhichRange::Base)?;
 
+        self.table.indices[self.table.key_index].remove(key, rid);
+
+        for (i, val) in current_val
+            .iter()
+            .enumerate()
+            .take(self.table.indices.len())
+        {
+            if i != self.table.key_index
+                && let Some(v) = val
+            {
+                self.table.indices[i].remove(*v, rid);
+            }
+        }
+
         // Deleted records also need to be merged so the base page reflects
         // the deletion, is_deleted can skip the tail
-        self.table.dirty_base_rids.insert(rid);
+        self.table.note_tail(rid);
 
         Ok(true)
     }
@@ -196,11 +280,12 @@ impl Query {
             return Err(DbError::KeyNotFound(start_range));
         }
         let mut sum: i64 = 0;
+        let _reading = self.table.pin();
 
         for rid in rids {
             sum += self
                 .table
-                .read_latest_single(rid, col)?
+                .read_latest_single_hinted(rid, col, AccessHint::Sequential)?
                 .ok_or(DbError::NullValue(col))?;
         }
         Ok(sum)
@@ -219,6 +304,7 @@ impl Query {
         }
         
         let mut sum: i64 = 0;
+        let _reading = self.table.pin();
 
         for rid in rids {
             sum += self
@@ -230,7 +316,7 @@ impl Query {
     }
 
     pub fn increment(&mut self, key: i64, col: usize) -> Result<bool, DbError> {
-        if col == self.table.key_index || col >= self.table.num_data_columns {
+        if col == self.table.key_index || col >= self.table.num_data_columns() {
             return Ok(false);
         }
 
@@ -238,16 +324,185 @@ impl Query {
             .locate(key)
             .ok_or(DbError::KeyNotFound(key))?;
 
-        let mut record: Vec<Option<i64>> = vec![None; self.table.num_data_columns];
-        let temp = self
-            .table
-            .read_latest_single(rid, col)?
-            .ok_or(DbError::NullValue(col))?
-            + 1;
+        let mut record: Vec<Option<i64>> = vec![None; self.table.num_data_columns()];
+        let temp = {
+            let _reading = self.table.pin();
+            self.table.read_latest_single(rid, col)?.ok_or(DbError::NullValue(col))? + 1
+        };
         record[col] = Some(temp);
 
         self.update(key, record)
 
 
     }
+
+    /// `insert` of one value for each column of the table's schema. A null
+    /// takes the column's default, if it has one.
+    pub fn insert_values(&self, mut values: Vec<Option<Value>>) -> Result<bool, DbError> {
+        if values.len() != self.table.schema().len() {
+            return Err(DbError::ColumnCount(values.len()));
+        }
+        self.table.schema().fill_defaults(&mut values)?;
+        self.insert(self.table.encode(&values)?)
+    }
+
+    /// `select` by the value of column `search_col`, reading back the
+    /// columns `projected` marks. A column of more than one slot has no index
+    /// and is scanned, as is a text or blob column, whose values are compared
+    /// once loaded.
+    pub fn select_values(
+        &self,
+        key: &Value,
+        search_col: usize,
+        projected: &[i64],
+    ) -> Result<Vec<Vec<Option<Value>>>, DbError> {
+        let schema = &self.table.schema();
+        let search = if schema.columns()[search_col].is_out_of_line() {
+            schema.check_column(search_col, key)?;
+            vec![]
+        } else {
+            schema.encode_column(search_col, key)?
+        };
+        let _reading = self.table.pin();
+        let rids = match search[..] {
+            [Some(slot)] => self.locate(slot, schema.slots(search_col).start),
+            [] => {
+                let slot = schema.slots(search_col).start;
+                let mut rids = Vec::new();
+                for rid in self.table.indices[self.table.key_index].locate_range(i64::MIN, i64::MAX) {
+                    let value = self.table.read_latest_single(rid, slot)?;
+                    if self.table.decode_column(search_col, &[value])?.as_ref() == Some(key) {
+                        rids.push(rid);
+                    }
+                }
+                rids
+            }
+            _ => {
+                let mut rids = Vec::new();
+                for rid in self.table.indices[self.table.key_index].locate_range(i64::MIN, i64::MAX) {
+                    if self.table.read_latest(rid)?[schema.slots(search_col)] == search[..] {
+                        rids.push(rid);
+                    }
+                }
+                rids
+            }
+        };
+        let slots = schema.project(projected);
+        rids.iter()
+            .map(|&rid| self.table.decode(&self.table.read_latest_projected(&slots, rid)?))
+            .collect()
+    }
+
+    /// `select_values` by the column named `search`, reading back the columns
+    /// named in `columns` in that order.
+    pub fn select_columns(
+        &self,
+        key: &Value,
+        search: &str,
+        columns: &[&str],
+    ) -> Result<Vec<Vec<Option<Value>>>, DbError> {
+        let schema = &self.table.schema();
+        let cols = columns.iter().map(|name| schema.column(name)).collect::<Result<Vec<_>, _>>()?;
+        let records = self.select_values(key, schema.column(search)?, &schema.mask(columns)?)?;
+        Ok(records
+            .into_iter()
+            .map(|record| cols.iter().map(|&col| record[col].clone()).collect())
+            .collect())
+    }
+
+    /// `select_version` by a primary key of the key column's type.
+    pub fn select_version_values(
+        &self,
+        key: &Value,
+        projected: &[i64],
+        relative_version: i64,
+    ) -> Result<Vec<Vec<Option<Value>>>, DbError> {
+        let schema = &self.table.schema();
+        let key_col = schema.column_of(self.table.key_index);
+        let key = key.as_i64().ok_or(DbError::WrongType(key_col, schema.columns()[key_col]))?;
+        let slots = schema.project(projected);
+        self.select_version(key, self.table.key_index, &slots, relative_version)?
+            .iter()
+            .map(|record| self.table.decode(record))
+            .collect()
+    }
+
+    /// `update` with a value, or `None` to leave it, for each column.
+    pub fn update_values(&self, key: i64, values: Vec<Option<Value>>) -> Result<bool, DbError> {
+        self.update(key, self.table.encode(&values)?)
+    }
+
+    /// `sum` of column `col`: an `I64` for an integer column, an `F64` for a
+    /// float one. Other types cannot be summed.
+    pub fn sum_values(&self, start_range: i64, end_range: i64, col: usize) -> Result<Value, DbError> {
+        self.sum_column(start_range, end_range, col, |rid, slot| {
+            self.table.read_latest_single_hinted(rid, slot, AccessHint::Sequential)
+        })
+    }
+
+    pub fn sum_version_values(
+        &self,
+        start_range: i64,
+        end_range: i64,
+        col: usize,
+        relative_version: i64,
+    ) -> Result<Value, DbError> {
+        self.sum_column(start_range, end_range, col, |rid, slot| {
+            self.table.read_version_single(rid, slot, relative_version)
+        })
+    }
+
+    fn sum_column(
+        &self,
+        start_range: i64,
+        end_range: i64,
+        col: usize,
+        read: impl Fn(i64, usize) -> Result<Option<i64>, DbError>,
+    ) -> Result<Value, DbError> {
+        let schema = &self.table.schema();
+        let ty = schema.columns()[col];
+        if !ty.is_integer() && ty != ColumnType::F64 {
+            return Err(DbError::WrongType(col, ty));
+        }
+        let rids = self.table.indices[self.table.key_index].locate_range(start_range, end_range);
+        if rids.is_empty() {
+            return Err(DbError::KeyNotFound(start_range));
+        }
+        let slot = schema.slots(col).start;
+        let (mut ints, mut floats) = (0i64, 0f64);
+        let _reading = self.table.pin();
+        for rid in rids {
+            let value = read(rid, slot)?;
+            match schema.decode_column(col, &[value])?.ok_or(DbError::NullValue(col))? {
+                Value::F64(v) => floats += v,
+                v => ints += v.as_i64().ok_or(DbError::WrongType(col, ty))?,
+            }
+        }
+        Ok(if ty == ColumnType::F64 { Value::F64(floats) } else { Value::I64(ints) })
+    }
+
+    /// `increment` of integer column `col`. An `I32` column already at
+    /// `i32::MAX` is left as it is.
+    pub fn increment_values(&mut self, key: i64, col: usize) -> Result<bool, DbError> {
+        let schema = &self.table.schema();
+        let Some(&ty) = schema.columns().get(col) else {
+            return Ok(false);
+        };
+        let slot = schema.slots(col).start;
+        match ty {
+            ColumnType::I32 => {
+                let rid = self.table.rid_for_key(key)?;
+                let at_max = {
+                    let _reading = self.table.pin();
+                    self.table.read_latest_single(rid, slot)? == Some(i32::MAX as i64)
+                };
+                if at_max {
+                    return Ok(false);
+                }
+                self.increment(key, slot)
+            }
+            ty if ty.is_integer() => self.increment(key, slot),
+            _ => Ok(false),
+        }
+    }
 }
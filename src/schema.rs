@@ -0,0 +1,705 @@
+use crate::bufferpool::DiskError;
+use crate::disk_manager::read_u64;
+use crate::errors::DbError;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+/// The type of a table column. Pages hold `i64` slots: a number, bool or
+/// timestamp takes one, a string or byte string as many as its longest
+/// value needs eight bytes at a time. Text and blobs take one too, for where
+/// the value sits in the table's `ValueHeap`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    I32,
+    I64,
+    F64,
+    Bool,
+    /// Microseconds since the Unix epoch, in UTC.
+    Timestamp,
+    /// UTF-8 of at most `n` bytes, padded with NULs that reading strips.
+    FixedStr(usize),
+    /// Exactly `n` bytes.
+    FixedBytes(usize),
+    /// UTF-8 of at most `n` bytes.
+    Str(usize),
+    /// At most `n` bytes.
+    Bytes(usize),
+    /// UTF-8 of any length, kept out of line.
+    Text,
+    /// Bytes of any length, kept out of line.
+    Blob,
+}
+
+impl ColumnType {
+    /// Slots a value of this type takes.
+    pub fn width(self) -> usize {
+        match self {
+            ColumnType::FixedStr(n) | ColumnType::FixedBytes(n) => n.div_ceil(8),
+            // The length, then the contents.
+            ColumnType::Str(n) | ColumnType::Bytes(n) => 1 + n.div_ceil(8),
+            _ => 1,
+        }
+    }
+
+    /// Whether the type can be a primary key, be summed as an integer and be
+    /// incremented.
+    pub fn is_integer(self) -> bool {
+        matches!(self, ColumnType::I32 | ColumnType::I64 | ColumnType::Timestamp)
+    }
+
+    /// Whether values of the type live in the table's heap.
+    pub fn is_out_of_line(self) -> bool {
+        matches!(self, ColumnType::Text | ColumnType::Blob)
+    }
+
+    fn tag(self) -> (u8, usize) {
+        match self {
+            ColumnType::I32 => (0, 0),
+            ColumnType::I64 => (1, 0),
+            ColumnType::F64 => (2, 0),
+            ColumnType::Bool => (3, 0),
+            ColumnType::Timestamp => (4, 0),
+            ColumnType::FixedStr(n) => (5, n),
+            ColumnType::FixedBytes(n) => (6, n),
+            ColumnType::Str(n) => (7, n),
+            ColumnType::Bytes(n) => (8, n),
+            ColumnType::Text => (9, 0),
+            ColumnType::Blob => (10, 0),
+        }
+    }
+
+    fn from_tag(tag: u8, n: usize) -> Option<Self> {
+        Some(match tag {
+            0 => ColumnType::I32,
+            1 => ColumnType::I64,
+            2 => ColumnType::F64,
+            3 => ColumnType::Bool,
+            4 => ColumnType::Timestamp,
+            5 => ColumnType::FixedStr(n),
+            6 => ColumnType::FixedBytes(n),
+            7 => ColumnType::Str(n),
+            8 => ColumnType::Bytes(n),
+            9 => ColumnType::Text,
+            10 => ColumnType::Blob,
+            _ => return None,
+        })
+    }
+}
+
+/// `int32`, `int64`, `float64`, `bool`, `timestamp`, `str(n)`,
+/// `fixed_str(n)`, `bytes(n)`, `fixed_bytes(n)`, `text` or `blob`.
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColumnType::I32 => write!(f, "int32"),
+            ColumnType::I64 => write!(f, "int64"),
+            ColumnType::F64 => write!(f, "float64"),
+            ColumnType::Bool => write!(f, "bool"),
+            ColumnType::Timestamp => write!(f, "timestamp"),
+            ColumnType::FixedStr(n) => write!(f, "fixed_str({})", n),
+            ColumnType::FixedBytes(n) => write!(f, "fixed_bytes({})", n),
+            ColumnType::Str(n) => write!(f, "str({})", n),
+            ColumnType::Bytes(n) => write!(f, "bytes({})", n),
+            ColumnType::Text => write!(f, "text"),
+            ColumnType::Blob => write!(f, "blob"),
+        }
+    }
+}
+
+impl FromStr for ColumnType {
+    type Err = DbError;
+
+    fn from_str(s: &str) -> Result<Self, DbError> {
+        let s = s.trim();
+        let unknown = || DbError::InvalidSchema(format!("unknown column type: {}", s));
+        let sized = |prefix: &str| -> Option<usize> {
+            s.strip_prefix(prefix)?.strip_prefix('(')?.strip_suffix(')')?.trim().parse().ok()
+        };
+        Ok(match s {
+            "int32" => ColumnType::I32,
+            "int64" | "int" => ColumnType::I64,
+            "float64" | "float" => ColumnType::F64,
+            "bool" => ColumnType::Bool,
+            "timestamp" => ColumnType::Timestamp,
+            "text" => ColumnType::Text,
+            "blob" => ColumnType::Blob,
+            _ if s.starts_with("fixed_str") => ColumnType::FixedStr(sized("fixed_str").ok_or_else(unknown)?),
+            _ if s.starts_with("fixed_bytes") => ColumnType::FixedBytes(sized("fixed_bytes").ok_or_else(unknown)?),
+            _ if s.starts_with("str") => ColumnType::Str(sized("str").ok_or_else(unknown)?),
+            _ if s.starts_with("bytes") => ColumnType::Bytes(sized("bytes").ok_or_else(unknown)?),
+            _ => return Err(unknown()),
+        })
+    }
+}
+
+/// A value of one of the column types.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    I32(i32),
+    I64(i64),
+    F64(f64),
+    Bool(bool),
+    /// Microseconds since the Unix epoch, in UTC.
+    Timestamp(i64),
+    Str(String),
+    Bytes(Vec<u8>),
+}
+
+impl Value {
+    /// The value of an integer column as the `i64` it is stored as.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Value::I32(v) => Some(v as i64),
+            Value::I64(v) | Value::Timestamp(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+/// A column as a table is made with it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnDef {
+    pub name: String,
+    pub ty: ColumnType,
+    /// Whether an insert may leave the column null.
+    pub nullable: bool,
+    /// What an insert that leaves the column null puts there instead.
+    pub default: Option<Value>,
+}
+
+impl ColumnDef {
+    /// A nullable column without a default.
+    pub fn new(name: impl Into<String>, ty: ColumnType) -> Self {
+        Self { name: name.into(), ty, nullable: true, default: None }
+    }
+}
+
+/// The columns of a table and the slots each one takes. A table made with a
+/// column count alone has `Schema::ints`, one `i64` slot per column, and its
+/// columns and slots are the same thing. Columns made without a name are
+/// named after their number.
+///
+/// `Query` and `Table` work on slots; `Query::insert_values` and the other
+/// `_values` methods take columns and values and go through the schema.
+/// Text and blob values are stored and loaded through the table, with
+/// `Table::encode` and `Table::decode`; `encode` and `decode` here refuse
+/// them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schema {
+    columns: Vec<ColumnType>,
+    names: Vec<String>,
+    nullable: Vec<bool>,
+    defaults: Vec<Option<Value>>,
+    /// The first slot of each column.
+    starts: Vec<usize>,
+    /// Slots a record takes, those of dropped columns included.
+    width: usize,
+}
+
+impl Schema {
+    /// Slots a record may take. Writing one pins a page for each of them,
+    /// so the buffer pool has to be bigger still; `Database` checks that.
+    pub const MAX_WIDTH: usize = 1024;
+
+    pub fn new(columns: Vec<ColumnType>) -> Result<Self, DbError> {
+        Self::with_defs(
+            columns
+                .into_iter()
+                .enumerate()
+                .map(|(col, ty)| ColumnDef::new(col.to_string(), ty))
+                .collect(),
+        )
+    }
+
+    /// Columns with names, and maybe defaults or `NOT NULL`. Names must be
+    /// unique and not empty, and a default has to fit its column.
+    pub fn with_defs(defs: Vec<ColumnDef>) -> Result<Self, DbError> {
+        if defs.is_empty() {
+            return Err(DbError::InvalidSchema("a table needs a column".into()));
+        }
+        let schema = Self::from_defs(defs);
+        schema.validate()?;
+        Ok(schema)
+    }
+
+    /// The schema with `def` added after its last column. It takes slots
+    /// past all the others, dropped ones too, which older records may still
+    /// hold values in.
+    pub fn with_column(&self, def: ColumnDef) -> Result<Self, DbError> {
+        let mut schema = self.clone();
+        schema.push(def);
+        schema.validate()?;
+        Ok(schema)
+    }
+
+    /// The schema without column `col`. Its slots are not given to another.
+    pub fn without_column(&self, col: usize) -> Self {
+        let mut schema = self.clone();
+        schema.columns.remove(col);
+        schema.names.remove(col);
+        schema.nullable.remove(col);
+        schema.defaults.remove(col);
+        schema.starts.remove(col);
+        schema
+    }
+
+    fn validate(&self) -> Result<(), DbError> {
+        for (col, (ty, name)) in self.columns.iter().zip(&self.names).enumerate() {
+            match ty {
+                ColumnType::FixedStr(0) | ColumnType::FixedBytes(0) | ColumnType::Str(0) | ColumnType::Bytes(0) => {
+                    return Err(DbError::InvalidSchema(format!("{} holds nothing", ty)));
+                }
+                _ => {}
+            }
+            if name.is_empty() {
+                return Err(DbError::InvalidSchema(format!("column {} has no name", col)));
+            }
+            if self.names[..col].contains(name) {
+                return Err(DbError::InvalidSchema(format!("two columns are named {}", name)));
+            }
+        }
+        for (col, default) in self.defaults.iter().enumerate() {
+            if let Some(value) = default {
+                self.check_column(col, value)?;
+            }
+        }
+        if self.width() > Self::MAX_WIDTH {
+            return Err(DbError::InvalidSchema(format!(
+                "the columns take {} slots, more than the {} a record has",
+                self.width(),
+                Self::MAX_WIDTH
+            )));
+        }
+        Ok(())
+    }
+
+    /// `n` columns of `ColumnType::I64`.
+    pub fn ints(n: usize) -> Self {
+        Self::from_defs((0..n).map(|col| ColumnDef::new(col.to_string(), ColumnType::I64)).collect())
+    }
+
+    fn from_defs(defs: Vec<ColumnDef>) -> Self {
+        let mut schema = Self {
+            columns: Vec::with_capacity(defs.len()),
+            names: Vec::with_capacity(defs.len()),
+            nullable: Vec::with_capacity(defs.len()),
+            defaults: Vec::with_capacity(defs.len()),
+            starts: Vec::with_capacity(defs.len()),
+            width: 0,
+        };
+        for def in defs {
+            schema.push(def);
+        }
+        schema
+    }
+
+    /// Adds `def` in the slots after the last.
+    fn push(&mut self, def: ColumnDef) {
+        self.starts.push(self.width);
+        self.width += def.ty.width();
+        self.columns.push(def.ty);
+        self.names.push(def.name);
+        self.nullable.push(def.nullable);
+        self.defaults.push(def.default);
+    }
+
+    pub fn columns(&self) -> &[ColumnType] {
+        &self.columns
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// The column named `name`.
+    pub fn column(&self, name: &str) -> Result<usize, DbError> {
+        self.names
+            .iter()
+            .position(|n| n == name)
+            .ok_or_else(|| DbError::UnknownColumn(name.to_string()))
+    }
+
+    pub fn is_nullable(&self, col: usize) -> bool {
+        self.nullable[col]
+    }
+
+    pub fn default(&self, col: usize) -> Option<&Value> {
+        self.defaults[col].as_ref()
+    }
+
+    /// Every column as it was defined.
+    pub fn defs(&self) -> Vec<ColumnDef> {
+        (0..self.columns.len())
+            .map(|col| ColumnDef {
+                name: self.names[col].clone(),
+                ty: self.columns[col],
+                nullable: self.nullable[col],
+                default: self.defaults[col].clone(),
+            })
+            .collect()
+    }
+
+    /// Fills the nulls of a record about to be inserted with their columns'
+    /// defaults. A null left in a column that is not nullable is an error.
+    pub fn fill_defaults(&self, values: &mut [Option<Value>]) -> Result<(), DbError> {
+        for (col, value) in values.iter_mut().enumerate().take(self.columns.len()) {
+            if value.is_none() {
+                *value = self.defaults[col].clone();
+                if value.is_none() && !self.nullable[col] {
+                    return Err(DbError::NullValue(col));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// A `projected` mask of the columns named `names`.
+    pub fn mask(&self, names: &[&str]) -> Result<Vec<i64>, DbError> {
+        let mut mask = vec![0; self.columns.len()];
+        for name in names {
+            mask[self.column(name)?] = 1;
+        }
+        Ok(mask)
+    }
+
+    pub fn len(&self) -> usize {
+        self.columns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    /// Slots a record takes.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn slots(&self, col: usize) -> Range<usize> {
+        self.starts[col]..self.starts[col] + self.columns[col].width()
+    }
+
+    /// The column `slot` belongs to, which has to be one's.
+    pub fn column_of(&self, slot: usize) -> usize {
+        (0..self.columns.len())
+            .find(|&col| self.slots(col).contains(&slot))
+            .expect("the slot of a column")
+    }
+
+    /// Slots that belonged to columns since dropped.
+    pub fn retired_slots(&self) -> Vec<usize> {
+        let mut retired = vec![true; self.width];
+        for col in 0..self.columns.len() {
+            for slot in self.slots(col) {
+                retired[slot] = false;
+            }
+        }
+        (0..self.width).filter(|&slot| retired[slot]).collect()
+    }
+
+    /// The slots of a record of `values`, one for each column. A `None`
+    /// leaves every slot of its column `None`, as are dropped columns' slots.
+    pub fn encode(&self, values: &[Option<Value>]) -> Result<Vec<Option<i64>>, DbError> {
+        self.encode_with(values, &mut no_heap)
+    }
+
+    /// `encode`, handing the bytes of each text and blob value to `store`
+    /// for the slot that refers to them. Nothing is stored unless every value
+    /// fits its column.
+    pub fn encode_with(
+        &self,
+        values: &[Option<Value>],
+        store: &mut dyn FnMut(&[u8]) -> Result<i64, DbError>,
+    ) -> Result<Vec<Option<i64>>, DbError> {
+        if values.len() != self.columns.len() {
+            return Err(DbError::ColumnCount(values.len()));
+        }
+        let mut slots = vec![None; self.width()];
+        let mut out_of_line = Vec::new();
+        for (col, value) in values.iter().enumerate() {
+            if let Some(value) = value {
+                let mut encoded = Vec::with_capacity(self.slots(col).len());
+                if let Some(bytes) = self.encode_into(col, value, &mut encoded)? {
+                    out_of_line.push((self.starts[col], bytes));
+                }
+                for (slot, encoded) in self.slots(col).zip(encoded) {
+                    slots[slot] = encoded;
+                }
+            }
+        }
+        for (slot, bytes) in out_of_line {
+            slots[slot] = Some(store(bytes)?);
+        }
+        Ok(slots)
+    }
+
+    /// The slots `value` takes in column `col`.
+    pub fn encode_column(&self, col: usize, value: &Value) -> Result<Vec<Option<i64>>, DbError> {
+        let mut slots = Vec::with_capacity(self.slots(col).len());
+        if self.encode_into(col, value, &mut slots)?.is_some() {
+            return Err(kept_by_table());
+        }
+        Ok(slots)
+    }
+
+    /// Whether `value` fits column `col`, storing nothing.
+    pub fn check_column(&self, col: usize, value: &Value) -> Result<(), DbError> {
+        self.encode_into(col, value, &mut Vec::new()).map(|_| ())
+    }
+
+    /// Pushes the slots of `value`, or a placeholder and the bytes to store
+    /// in its place for a text or blob.
+    fn encode_into<'v>(
+        &self,
+        col: usize,
+        value: &'v Value,
+        slots: &mut Vec<Option<i64>>,
+    ) -> Result<Option<&'v [u8]>, DbError> {
+        let ty = self.columns[col];
+        let wrong = || DbError::WrongType(col, ty);
+        match (ty, value) {
+            (ColumnType::Text, Value::Str(s)) => {
+                slots.push(None);
+                return Ok(Some(s.as_bytes()));
+            }
+            (ColumnType::Blob, Value::Bytes(b)) => {
+                slots.push(None);
+                return Ok(Some(b));
+            }
+            (ColumnType::I32, Value::I32(v)) => slots.push(Some(*v as i64)),
+            (ColumnType::I64, Value::I64(v)) | (ColumnType::Timestamp, Value::Timestamp(v)) => slots.push(Some(*v)),
+            (ColumnType::F64, Value::F64(v)) => slots.push(Some(ordered_bits(v.to_bits() as i64))),
+            (ColumnType::Bool, Value::Bool(v)) => slots.push(Some(*v as i64)),
+            (ColumnType::FixedStr(n), Value::Str(s)) if s.len() <= n => push_chunks(slots, s.as_bytes(), n),
+            (ColumnType::FixedBytes(n), Value::Bytes(b)) if b.len() == n => push_chunks(slots, b, n),
+            (ColumnType::Str(n), Value::Str(s)) if s.len() <= n => {
+                slots.push(Some(s.len() as i64));
+                push_chunks(slots, s.as_bytes(), n);
+            }
+            (ColumnType::Bytes(n), Value::Bytes(b)) if b.len() <= n => {
+                slots.push(Some(b.len() as i64));
+                push_chunks(slots, b, n);
+            }
+            _ => return Err(wrong()),
+        }
+        Ok(None)
+    }
+
+    /// The values of a record read back as `slots`.
+    pub fn decode(&self, slots: &[Option<i64>]) -> Result<Vec<Option<Value>>, DbError> {
+        self.decode_with(slots, &no_heap_load)
+    }
+
+    /// `decode`, with `load` for the bytes a text or blob slot refers to.
+    /// Columns past the end of `slots`, read before they were added, are
+    /// null.
+    pub fn decode_with(
+        &self,
+        slots: &[Option<i64>],
+        load: &dyn Fn(i64) -> Result<Vec<u8>, DbError>,
+    ) -> Result<Vec<Option<Value>>, DbError> {
+        (0..self.columns.len())
+            .map(|col| self.decode_column_with(col, slots.get(self.slots(col)).unwrap_or_default(), load))
+            .collect()
+    }
+
+    /// The value of column `col` from its own slots. Null if its first slot
+    /// is, which is the case for every column of a record that was not
+    /// projected.
+    pub fn decode_column(&self, col: usize, slots: &[Option<i64>]) -> Result<Option<Value>, DbError> {
+        self.decode_column_with(col, slots, &no_heap_load)
+    }
+
+    pub fn decode_column_with(
+        &self,
+        col: usize,
+        slots: &[Option<i64>],
+        load: &dyn Fn(i64) -> Result<Vec<u8>, DbError>,
+    ) -> Result<Option<Value>, DbError> {
+        let Some(first) = slots.first().copied().flatten() else {
+            return Ok(None);
+        };
+        Ok(Some(match self.columns[col] {
+            ColumnType::I32 => Value::I32(first as i32),
+            ColumnType::I64 => Value::I64(first),
+            ColumnType::F64 => Value::F64(f64::from_bits(ordered_bits(first) as u64)),
+            ColumnType::Bool => Value::Bool(first != 0),
+            ColumnType::Timestamp => Value::Timestamp(first),
+            ColumnType::FixedStr(_) => {
+                let mut bytes = chunk_bytes(slots);
+                let len = bytes.iter().rposition(|&b| b != 0).map_or(0, |last| last + 1);
+                bytes.truncate(len);
+                Value::Str(String::from_utf8_lossy(&bytes).into_owned())
+            }
+            ColumnType::FixedBytes(n) => {
+                let mut bytes = chunk_bytes(slots);
+                bytes.truncate(n);
+                Value::Bytes(bytes)
+            }
+            ColumnType::Str(_) => {
+                let mut bytes = chunk_bytes(&slots[1..]);
+                bytes.truncate(first as usize);
+                Value::Str(String::from_utf8_lossy(&bytes).into_owned())
+            }
+            ColumnType::Bytes(_) => {
+                let mut bytes = chunk_bytes(&slots[1..]);
+                bytes.truncate(first as usize);
+                Value::Bytes(bytes)
+            }
+            ColumnType::Text => Value::Str(String::from_utf8_lossy(&load(first)?).into_owned()),
+            ColumnType::Blob => Value::Bytes(load(first)?),
+        }))
+    }
+
+    /// Which slots to read for a `projected` mask of columns.
+    pub fn project(&self, projected: &[i64]) -> Vec<i64> {
+        let mut slots = vec![0; self.width()];
+        for (col, &on) in projected.iter().enumerate().take(self.columns.len()) {
+            for slot in self.slots(col) {
+                slots[slot] = on;
+            }
+        }
+        slots
+    }
+
+    /// The count, then the type of each column and after it its name,
+    /// whether it is nullable and its default.
+    pub(crate) fn serialize(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&(self.columns.len() as u64).to_be_bytes());
+        for (col, ty) in self.columns.iter().enumerate() {
+            let (tag, n) = ty.tag();
+            buf.push(tag);
+            buf.extend_from_slice(&(n as u64).to_be_bytes());
+            push_bytes(buf, self.names[col].as_bytes());
+            buf.push(self.nullable[col] as u8);
+            match &self.defaults[col] {
+                None => buf.push(0),
+                Some(value) => {
+                    buf.push(1);
+                    match value {
+                        Value::F64(v) => buf.extend_from_slice(&v.to_bits().to_be_bytes()),
+                        Value::Bool(v) => buf.extend_from_slice(&(*v as i64).to_be_bytes()),
+                        Value::Str(s) => push_bytes(buf, s.as_bytes()),
+                        Value::Bytes(b) => push_bytes(buf, b),
+                        v => buf.extend_from_slice(&v.as_i64().unwrap_or_default().to_be_bytes()),
+                    }
+                }
+            }
+        }
+    }
+
+    /// A schema `serialize` wrote, or with `named` false, one written before
+    /// columns had names: types alone.
+    pub(crate) fn deserialize(data: &[u8], offset: &mut usize, named: bool) -> Result<Self, DiskError> {
+        let corrupt = |what: &str| DiskError::CorruptedPage(format!("Invalid schema: {}", what));
+        let byte = |offset: &mut usize| -> Result<u8, DiskError> {
+            let b = *data.get(*offset).ok_or_else(|| corrupt("unexpected end"))?;
+            *offset += 1;
+            Ok(b)
+        };
+        let count = read_u64(data, offset)? as usize;
+        let mut defs = Vec::with_capacity(count.min(Self::MAX_WIDTH));
+        for col in 0..count {
+            let tag = byte(offset)?;
+            let n = read_u64(data, offset)? as usize;
+            let ty = ColumnType::from_tag(tag, n).ok_or_else(|| corrupt(&format!("column type {}", tag)))?;
+            if !named {
+                defs.push(ColumnDef::new(col.to_string(), ty));
+                continue;
+            }
+            let name = String::from_utf8(read_bytes(data, offset)?).map_err(|_| corrupt("column name"))?;
+            let nullable = byte(offset)? != 0;
+            let default = match byte(offset)? {
+                0 => None,
+                _ => Some(match ty {
+                    ColumnType::F64 => Value::F64(f64::from_bits(read_u64(data, offset)?)),
+                    ColumnType::Bool => Value::Bool(read_u64(data, offset)? != 0),
+                    ColumnType::I32 => Value::I32(read_u64(data, offset)? as i32),
+                    ColumnType::I64 => Value::I64(read_u64(data, offset)? as i64),
+                    ColumnType::Timestamp => Value::Timestamp(read_u64(data, offset)? as i64),
+                    ColumnType::FixedStr(_) | ColumnType::Str(_) | ColumnType::Text => Value::Str(
+                        String::from_utf8(read_bytes(data, offset)?).map_err(|_| corrupt("default"))?,
+                    ),
+                    ColumnType::FixedBytes(_) | ColumnType::Bytes(_) | ColumnType::Blob => {
+                        Value::Bytes(read_bytes(data, offset)?)
+                    }
+                }),
+            };
+            defs.push(ColumnDef { name, ty, nullable, default });
+        }
+        Ok(Self::from_defs(defs))
+    }
+
+    /// The width, then the first slot of each column. `serialize` leaves
+    /// them out, since they follow from the types until a column is dropped.
+    pub(crate) fn serialize_slots(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&(self.width as u64).to_be_bytes());
+        for &start in &self.starts {
+            buf.extend_from_slice(&(start as u64).to_be_bytes());
+        }
+    }
+
+    /// Puts the columns where `serialize_slots` wrote they are.
+    pub(crate) fn deserialize_slots(&mut self, data: &[u8], offset: &mut usize) -> Result<(), DiskError> {
+        let width = read_u64(data, offset)? as usize;
+        let mut starts = Vec::with_capacity(self.columns.len());
+        for ty in &self.columns {
+            let start = read_u64(data, offset)? as usize;
+            if start.checked_add(ty.width()).is_none_or(|end| end > width) {
+                return Err(DiskError::CorruptedPage("Invalid schema: column past the width".into()));
+            }
+            starts.push(start);
+        }
+        self.starts = starts;
+        self.width = width;
+        Ok(())
+    }
+}
+
+fn push_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend_from_slice(&(bytes.len() as u64).to_be_bytes());
+    buf.extend_from_slice(bytes);
+}
+
+fn read_bytes(data: &[u8], offset: &mut usize) -> Result<Vec<u8>, DiskError> {
+    let len = read_u64(data, offset)? as usize;
+    let bytes = data
+        .get(*offset..offset.saturating_add(len))
+        .ok_or_else(|| DiskError::CorruptedPage("Unexpected end of schema".into()))?;
+    *offset += len;
+    Ok(bytes.to_vec())
+}
+
+fn kept_by_table() -> DbError {
+    DbError::InvalidSchema("text and blob values are kept by a table".into())
+}
+
+fn no_heap(_: &[u8]) -> Result<i64, DbError> {
+    Err(kept_by_table())
+}
+
+fn no_heap_load(_: i64) -> Result<Vec<u8>, DbError> {
+    Err(kept_by_table())
+}
+
+/// Maps the bits of an `f64` to an `i64` that orders the same way, so that
+/// index ranges over a float column work. Its own inverse.
+fn ordered_bits(bits: i64) -> i64 {
+    bits ^ (((bits >> 63) as u64) >> 1) as i64
+}
+
+/// `bytes` in big-endian chunks of eight, zero-padded to `n` bytes.
+fn push_chunks(slots: &mut Vec<Option<i64>>, bytes: &[u8], n: usize) {
+    for i in 0..n.div_ceil(8) {
+        let mut chunk = [0u8; 8];
+        let part = bytes.get(i * 8..).unwrap_or_default();
+        let len = part.len().min(8);
+        chunk[..len].copy_from_slice(&part[..len]);
+        slots.push(Some(i64::from_be_bytes(chunk)));
+    }
+}
+
+fn chunk_bytes(slots: &[Option<i64>]) -> Vec<u8> {
+    slots.iter().flat_map(|slot| slot.unwrap_or(0).to_be_bytes()).collect()
+}
@@ -0,0 +1,69 @@
+/// Which slots a tail record sets: bit `slot % 64` of word `slot / 64`.
+/// The first word goes on a collection's `MetaPage::SchemaEncoding` page,
+/// where a null marks a deletion; the rest go on pages of their own after
+/// the other meta pages, one for every 64 slots past the first 64.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SchemaEncoding {
+    words: Vec<i64>,
+}
+
+impl SchemaEncoding {
+    pub const SLOTS_PER_WORD: usize = 64;
+
+    /// Words a record of `width` slots needs, one at least.
+    pub fn words_for(width: usize) -> usize {
+        width.div_ceil(Self::SLOTS_PER_WORD).max(1)
+    }
+
+    /// The slots `record` has a value for.
+    pub fn of(record: &[Option<i64>]) -> Self {
+        let mut encoding = Self { words: vec![0; Self::words_for(record.len())] };
+        for (slot, _) in record.iter().enumerate().filter(|(_, val)| val.is_some()) {
+            encoding.words[slot / Self::SLOTS_PER_WORD] |= 1 << (slot % Self::SLOTS_PER_WORD);
+        }
+        encoding
+    }
+
+    pub fn from_words(words: Vec<i64>) -> Self {
+        Self { words }
+    }
+
+    /// The words as the pages have them, the first one first.
+    pub fn words(&self) -> impl Iterator<Item = i64> + '_ {
+        (0..self.words.len().max(1)).map(|word| self.word(word))
+    }
+
+    pub fn word(&self, word: usize) -> i64 {
+        self.words.get(word).copied().unwrap_or(0)
+    }
+
+    pub fn contains(&self, slot: usize) -> bool {
+        Self::word_has(self.word(slot / Self::SLOTS_PER_WORD), slot)
+    }
+
+    /// Whether `word`, the one `slot` falls in, has it.
+    pub fn word_has(word: i64, slot: usize) -> bool {
+        (word as u64 >> (slot % Self::SLOTS_PER_WORD)) & 1 == 1
+    }
+
+    /// The slots here but not in `other`.
+    pub fn without(&self, other: &Self) -> Self {
+        let words = self.words.iter().enumerate().map(|(i, &word)| word & !other.word(i)).collect();
+        Self { words }
+    }
+
+    pub fn add(&mut self, other: &Self) {
+        if self.words.len() < other.words.len() {
+            self.words.resize(other.words.len(), 0);
+        }
+        for (word, &more) in self.words.iter_mut().zip(&other.words) {
+            *word |= more;
+        }
+    }
+
+    /// The slots it has, in order.
+    pub fn slots(&self) -> impl Iterator<Item = usize> + '_ {
+        let width = self.words.len() * Self::SLOTS_PER_WORD;
+        (0..width).filter(|&slot| self.contains(slot))
+    }
+}
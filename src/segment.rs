@@ -0,0 +1,212 @@
+use crate::bufferpool::DiskError;
+use crate::checksum::Crc32c;
+use parking_lot::{Mutex, RwLock};
+use std::fs::{File, OpenOptions};
+use std::io::Read;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+
+/// All pages of one table in a single file of fixed-size slots, page `n`
+/// living in slot `n`.
+///
+/// Which slots hold a page is tracked by an allocation map kept in a file next
+/// to it, one bit per slot. A page is written and synced before its bit is set,
+/// and its bit is cleared before the slot is reused, so a set bit always means
+/// the slot holds a complete page.
+///
+/// A page that has its bit set is overwritten in place only once a copy of it
+/// is synced to a double-write file, which opening the segment writes back
+/// over whatever a crash left of the slot. The copies are dropped whenever
+/// the pages they are of are synced in place.
+pub struct Segment {
+    data: File,
+    map_file: File,
+    map: RwLock<Vec<u8>>,
+    doublewrite: Mutex<Doublewrite>,
+    slot_size: usize,
+}
+
+/// The double-write file: one entry per overwrite since the data file was
+/// last synced, each a big-endian `u64` slot, a CRC-32C of the slot and the
+/// page, and the page.
+struct Doublewrite {
+    file: File,
+    entries: usize,
+}
+
+impl Doublewrite {
+    /// Entries it takes before the data file is synced and they are dropped.
+    const MAX_ENTRIES: usize = 64;
+
+    fn entry_size(slot_size: usize) -> usize {
+        12 + slot_size
+    }
+
+    fn push(&mut self, slot: usize, page: &[u8]) -> Result<(), DiskError> {
+        let slot = (slot as u64).to_be_bytes();
+        let mut entry = Vec::with_capacity(Self::entry_size(page.len()));
+        entry.extend_from_slice(&slot);
+        entry.extend_from_slice(&Crc32c::new().update(&slot).update(page).finish().to_be_bytes());
+        entry.extend_from_slice(page);
+        self.file.write_all_at(&entry, (self.entries * entry.len()) as u64)?;
+        self.file.sync_data()?;
+        self.entries += 1;
+        Ok(())
+    }
+
+    /// The entries up to the first a crash cut short.
+    fn read(&self, slot_size: usize) -> Result<Vec<(usize, Vec<u8>)>, DiskError> {
+        let mut data = Vec::new();
+        (&self.file).read_to_end(&mut data)?;
+        let mut entries = Vec::new();
+        for entry in data.chunks_exact(Self::entry_size(slot_size)) {
+            let (slot, rest) = entry.split_at(8);
+            let (checksum, page) = rest.split_at(4);
+            if Crc32c::new().update(slot).update(page).finish() != u32::from_be_bytes(checksum.try_into().unwrap()) {
+                break;
+            }
+            entries.push((u64::from_be_bytes(slot.try_into().unwrap()) as usize, page.to_vec()));
+        }
+        Ok(entries)
+    }
+
+    /// Drops every entry. Only once the pages they are of are synced in
+    /// place, and synced itself before anything is written after, so that
+    /// no older entry can turn up behind a newer one.
+    fn clear(&mut self) -> Result<(), DiskError> {
+        if self.entries > 0 {
+            self.file.set_len(0)?;
+            self.file.sync_data()?;
+            self.entries = 0;
+        }
+        Ok(())
+    }
+}
+
+impl Segment {
+    pub const DATA_FILE: &str = "pages.seg";
+    pub const MAP_FILE: &str = "pages.map";
+    pub const DOUBLEWRITE_FILE: &str = "pages.dw";
+
+    pub fn open(dir: &Path, slot_size: usize) -> Result<Self, DiskError> {
+        let options = {
+            let mut o = OpenOptions::new();
+            o.read(true).write(true).create(true).truncate(false);
+            o
+        };
+        let data = options.open(dir.join(Self::DATA_FILE))?;
+        let map_file = options.open(dir.join(Self::MAP_FILE))?;
+        let mut map = vec![0; map_file.metadata()?.len() as usize];
+        map_file.read_exact_at(&mut map, 0)?;
+        let file = options.open(dir.join(Self::DOUBLEWRITE_FILE))?;
+        let entries = file.metadata()?.len() as usize / Doublewrite::entry_size(slot_size);
+        let segment = Self {
+            data,
+            map_file,
+            map: RwLock::new(map),
+            doublewrite: Mutex::new(Doublewrite { file, entries }),
+            slot_size,
+        };
+        segment.restore_overwrites()?;
+        Ok(segment)
+    }
+
+    /// Writes the pages the double-write file has copies of back in place,
+    /// the last copy of each winning, in case a crash tore one in the middle
+    /// of its overwrite.
+    fn restore_overwrites(&self) -> Result<(), DiskError> {
+        let mut doublewrite = self.doublewrite.lock();
+        for (slot, page) in doublewrite.read(self.slot_size)? {
+            if self.is_allocated(slot) {
+                self.data.write_all_at(&page, self.offset(slot))?;
+            }
+        }
+        if doublewrite.entries > 0 {
+            self.data.sync_data()?;
+        }
+        doublewrite.clear()
+    }
+
+    pub fn is_allocated(&self, slot: usize) -> bool {
+        self.map
+            .read()
+            .get(slot / 8)
+            .is_some_and(|byte| byte & (1 << (slot % 8)) != 0)
+    }
+
+    /// The slot's contents, or `None` if no page was ever written there.
+    pub fn read(&self, slot: usize) -> Result<Option<Vec<u8>>, DiskError> {
+        if !self.is_allocated(slot) {
+            return Ok(None);
+        }
+        let mut buf = vec![0; self.slot_size];
+        self.data.read_exact_at(&mut buf, self.offset(slot))?;
+        Ok(Some(buf))
+    }
+
+    /// Overwrites the slot with `bytes`, zero-padded to the slot size.
+    pub fn write(&self, slot: usize, bytes: &[u8]) -> Result<(), DiskError> {
+        if bytes.len() > self.slot_size {
+            return Err(DiskError::SerializationError);
+        }
+        let mut buf = bytes.to_vec();
+        buf.resize(self.slot_size, 0);
+        // Held until the page is in place, so that no clear drops its copy
+        // before then.
+        let mut doublewrite = self.doublewrite.lock();
+        if self.is_allocated(slot) {
+            if doublewrite.entries == Doublewrite::MAX_ENTRIES {
+                self.data.sync_data()?;
+                doublewrite.clear()?;
+            }
+            doublewrite.push(slot, &buf)?;
+            self.data.write_all_at(&buf, self.offset(slot))?;
+            return Ok(());
+        }
+        // Synced before the bit is set, or the bit could reach the disk ahead
+        // of the page. That syncs every overwrite so far too, and no copy of
+        // what the slot held before is left to be written back over it.
+        self.data.write_all_at(&buf, self.offset(slot))?;
+        self.data.sync_data()?;
+        doublewrite.clear()?;
+        drop(doublewrite);
+        self.set_allocated(slot, true)
+    }
+
+    /// Releases the slot. Returns whether it held a page.
+    pub fn free(&self, slot: usize) -> Result<bool, DiskError> {
+        if !self.is_allocated(slot) {
+            return Ok(false);
+        }
+        self.set_allocated(slot, false)?;
+        Ok(true)
+    }
+
+    /// Forces both files to stable storage.
+    pub fn sync(&self) -> Result<(), DiskError> {
+        let mut doublewrite = self.doublewrite.lock();
+        self.data.sync_data()?;
+        self.map_file.sync_data()?;
+        doublewrite.clear()
+    }
+
+    fn offset(&self, slot: usize) -> u64 {
+        (slot * self.slot_size) as u64
+    }
+
+    fn set_allocated(&self, slot: usize, allocated: bool) -> Result<(), DiskError> {
+        let mut map = self.map.write();
+        let index = slot / 8;
+        if index >= map.len() {
+            map.resize(index + 1, 0);
+        }
+        if allocated {
+            map[index] |= 1 << (slot % 8);
+        } else {
+            map[index] &= !(1 << (slot % 8));
+        }
+        // A single byte never tears, so the map stays consistent on a crash.
+        self.map_file.write_all_at(&map[index..index + 1], index as u64)?;
+        Ok(())
+    }
+}
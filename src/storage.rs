@@ -0,0 +1,258 @@
+use crate::bufferpool::{Corruption, DiskError};
+use crate::disk_manager::{CorruptionPolicy, IoStats, TableCounters, TableMeta, WriteFence};
+use crate::iterators::PhysicalAddress;
+use crate::page::Page;
+use crate::page_collection::PageId;
+use crate::table::Table;
+use dashmap::DashMap;
+use parking_lot::{Mutex, RwLock};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Where a database keeps its pages and table metadata.
+///
+/// Pages are read and written one at a time and only have to be durable once
+/// `sync_pages` returns. Metadata is written a generation at a time: between
+/// `begin_generation` and `commit_generation` the writes go to a new
+/// generation while the reads still see the current one, and a generation
+/// that is never committed is as if it had never been written.
+///
+/// `DiskManager` keeps everything in files under a directory; `MemoryBackend`
+/// keeps it in memory for tests and ephemeral databases.
+pub trait StorageBackend: Send + Sync {
+    /// Write budget of everything the backend writes, shared with the
+    /// write-ahead log.
+    fn fence(&self) -> Arc<WriteFence>;
+
+    /// Where the write-ahead log goes, or `None` if the storage does not
+    /// outlive the process and there is nothing to recover.
+    fn log_path(&self) -> Option<PathBuf>;
+
+    fn read_page(&self, pid: PageId) -> Result<Page, DiskError>;
+    fn write_page(&self, pid: PageId, page: &Page) -> Result<(), DiskError>;
+    fn delete_page(&self, pid: PageId) -> Result<(), DiskError>;
+    fn page_exists(&self, pid: PageId) -> bool;
+
+    /// Makes every page written so far durable.
+    fn sync_pages(&self) -> Result<(), DiskError>;
+
+    /// Directs the metadata writes that follow to a fresh generation.
+    fn begin_generation(&self);
+
+    /// Makes the generation written since `begin_generation` the current one.
+    fn commit_generation(&self) -> Result<(), DiskError>;
+
+    fn write_tables(&self, tables: &DashMap<usize, Arc<Table>>, next_table_id: usize) -> Result<(), DiskError>;
+    fn read_tables(&self) -> Result<(Vec<TableMeta>, usize), DiskError>;
+    fn write_table_names(&self, table_names: &DashMap<String, usize>) -> Result<(), DiskError>;
+    fn read_table_names(&self) -> Result<Vec<(String, usize)>, DiskError>;
+    fn write_page_directory(&self, table_id: usize, pairs: &[(i64, PhysicalAddress)]) -> Result<(), DiskError>;
+    fn read_page_directory(&self, table_id: usize) -> Result<Vec<(i64, PhysicalAddress)>, DiskError>;
+    fn write_table_counters(&self, table_id: usize, counters: &TableCounters) -> Result<(), DiskError>;
+    fn read_table_counters(&self, table_id: usize) -> Result<TableCounters, DiskError>;
+    fn write_primary_index(&self, table_id: usize, pairs: &[(i64, i64)]) -> Result<(), DiskError>;
+    fn read_primary_index(&self, table_id: usize) -> Result<Vec<(i64, i64)>, DiskError>;
+
+    /// Backends that checksum nothing have nothing to quarantine.
+    fn set_corruption_policy(&self, _policy: CorruptionPolicy) {}
+
+    /// Everything quarantined since the backend was created.
+    fn quarantined(&self) -> Vec<Corruption> {
+        Vec::new()
+    }
+
+    /// Backends that keep everything in memory do no I/O to count.
+    fn io_stats(&self) -> IoStats {
+        IoStats::default()
+    }
+
+    fn reset_io_stats(&self) {}
+
+    /// Under `CorruptionPolicy::Quarantine`, sets the damaged metadata file a
+    /// checksum mismatch names aside and returns `Ok`. Any other error is
+    /// handed back.
+    fn quarantine(&self, err: DiskError) -> Result<(), DiskError> {
+        Err(err)
+    }
+}
+
+impl dyn StorageBackend {
+    /// `Ok(None)` in place of a metadata checksum mismatch that `quarantine`
+    /// accepted, for files the caller can rebuild.
+    pub fn salvage<T>(&self, result: Result<T, DiskError>) -> Result<Option<T>, DiskError> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(err @ DiskError::ChecksumMismatch(_)) => {
+                self.quarantine(err)?;
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Keeps a database in memory, gone once the last reference is dropped.
+pub struct MemoryBackend {
+    fence: Arc<WriteFence>,
+    pages: DashMap<PageId, Page>,
+    current: RwLock<Metadata>,
+    /// The generation being written, if one was begun.
+    pending: Mutex<Option<Metadata>>,
+}
+
+#[derive(Clone, Default)]
+struct Metadata {
+    catalog: Vec<TableMeta>,
+    next_table_id: usize,
+    table_names: Vec<(String, usize)>,
+    page_directories: BTreeMap<usize, Vec<(i64, PhysicalAddress)>>,
+    counters: BTreeMap<usize, TableCounters>,
+    primary_indexes: BTreeMap<usize, Vec<(i64, i64)>>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self {
+            fence: Arc::new(WriteFence::new()),
+            pages: DashMap::new(),
+            current: RwLock::new(Metadata::default()),
+            pending: Mutex::new(None),
+        }
+    }
+
+    /// Applies a metadata write to the pending generation, or to the current
+    /// one if none was begun.
+    fn write_metadata(&self, write: impl FnOnce(&mut Metadata)) -> Result<(), DiskError> {
+        self.fence.check()?;
+        match self.pending.lock().as_mut() {
+            Some(pending) => write(pending),
+            None => write(&mut self.current.write()),
+        }
+        Ok(())
+    }
+}
+
+impl Default for MemoryBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StorageBackend for MemoryBackend {
+    fn fence(&self) -> Arc<WriteFence> {
+        self.fence.clone()
+    }
+
+    fn log_path(&self) -> Option<PathBuf> {
+        None
+    }
+
+    fn read_page(&self, pid: PageId) -> Result<Page, DiskError> {
+        self.pages
+            .get(&pid)
+            .map(|page| page.clone())
+            .ok_or(DiskError::PageNotFound(pid))
+    }
+
+    fn write_page(&self, pid: PageId, page: &Page) -> Result<(), DiskError> {
+        self.fence.check()?;
+        self.pages.insert(pid, page.clone());
+        Ok(())
+    }
+
+    fn delete_page(&self, pid: PageId) -> Result<(), DiskError> {
+        if self.pages.contains_key(&pid) {
+            self.fence.check()?;
+            self.pages.remove(&pid);
+        }
+        Ok(())
+    }
+
+    fn page_exists(&self, pid: PageId) -> bool {
+        self.pages.contains_key(&pid)
+    }
+
+    fn sync_pages(&self) -> Result<(), DiskError> {
+        Ok(())
+    }
+
+    fn begin_generation(&self) {
+        *self.pending.lock() = Some(self.current.read().clone());
+    }
+
+    fn commit_generation(&self) -> Result<(), DiskError> {
+        self.fence.check()?;
+        if let Some(pending) = self.pending.lock().take() {
+            *self.current.write() = pending;
+        }
+        Ok(())
+    }
+
+    fn write_tables(&self, tables: &DashMap<usize, Arc<Table>>, next_table_id: usize) -> Result<(), DiskError> {
+        let catalog = tables
+            .iter()
+            .map(|entry| {
+                let t = entry.value();
+                TableMeta {
+                    table_id: t.table_id,
+                    schema: (*t.schema()).clone(),
+                    key_index: t.key_index,
+                    next_rid: t.rid.current(),
+                    name: String::new(),
+                }
+            })
+            .collect();
+        self.write_metadata(|m| {
+            m.catalog = catalog;
+            m.next_table_id = next_table_id;
+        })
+    }
+
+    fn read_tables(&self) -> Result<(Vec<TableMeta>, usize), DiskError> {
+        let current = self.current.read();
+        Ok((current.catalog.clone(), current.next_table_id))
+    }
+
+    fn write_table_names(&self, table_names: &DashMap<String, usize>) -> Result<(), DiskError> {
+        let names = table_names
+            .iter()
+            .map(|entry| (entry.key().clone(), *entry.value()))
+            .collect();
+        self.write_metadata(|m| m.table_names = names)
+    }
+
+    fn read_table_names(&self) -> Result<Vec<(String, usize)>, DiskError> {
+        Ok(self.current.read().table_names.clone())
+    }
+
+    fn write_page_directory(&self, table_id: usize, pairs: &[(i64, PhysicalAddress)]) -> Result<(), DiskError> {
+        self.write_metadata(|m| {
+            m.page_directories.insert(table_id, pairs.to_vec());
+        })
+    }
+
+    fn read_page_directory(&self, table_id: usize) -> Result<Vec<(i64, PhysicalAddress)>, DiskError> {
+        Ok(self.current.read().page_directories.get(&table_id).cloned().unwrap_or_default())
+    }
+
+    fn write_table_counters(&self, table_id: usize, counters: &TableCounters) -> Result<(), DiskError> {
+        self.write_metadata(|m| {
+            m.counters.insert(table_id, counters.clone());
+        })
+    }
+
+    fn read_table_counters(&self, table_id: usize) -> Result<TableCounters, DiskError> {
+        Ok(self.current.read().counters.get(&table_id).cloned().unwrap_or_default())
+    }
+
+    fn write_primary_index(&self, table_id: usize, pairs: &[(i64, i64)]) -> Result<(), DiskError> {
+        self.write_metadata(|m| {
+            m.primary_indexes.insert(table_id, pairs.to_vec());
+        })
+    }
+
+    fn read_primary_index(&self, table_id: usize) -> Result<Vec<(i64, i64)>, DiskError> {
+        Ok(self.current.read().primary_indexes.get(&table_id).cloned().unwrap_or_default())
+    }
+}
@@ -1,34 +1,94 @@
-use crate::bufferpool::BufferPool;
+use crate::bufferpool::{AccessHint, BufferPool};
 use crate::disk_manager::TableCounters;
+use crate::epoch::{EpochGuard, Epochs};
 use crate::errors::DbError;
-use crate::index::Index;
+use crate::heap::ValueHeap;
+use crate::index::Indices;
 use crate::iterators::{AtomicIterator, PhysicalAddress};
 use crate::page_collection::MetaPage;
 use crate::page_directory::PageDirectory;
-use crate::page_range::{PageRanges, WhichRange};
+use crate::page_range::{CollectionMeta, PageRanges, SlotMap, WhichRange};
 use crate::lock_manager::LockManager;
-use dashmap::DashSet;
-use std::sync::atomic::AtomicI64;
+use crate::schema::{ColumnDef, Schema, Value};
+use crate::schema_encoding::SchemaEncoding;
+use crate::wal::{LogRecord, Wal};
+use crate::config::MergeConfig;
+use crate::vacuum::{VacuumOptions, VacuumStats};
+use dashmap::DashMap;
+use parking_lot::{Mutex, MutexGuard, RwLock};
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
 use std::sync::Arc;
 
+/// A collection whose every record is superseded, kept until no query that
+/// could have looked one of them up is left.
+struct Retired {
+    range: WhichRange,
+    collection: CollectionMeta,
+    /// The epoch it was retired in.
+    epoch: u64,
+    /// The RIDs the page directory still finds in it.
+    rids: Vec<i64>,
+}
+
 pub struct Table {
     pub name: String,
     pub page_ranges: PageRanges,
     pub page_directory: PageDirectory,
     pub rid: AtomicIterator<AtomicI64>,
-    pub num_data_columns: usize,
+    /// Put in place whole by an alter, so that a query keeps the columns it
+    /// started with.
+    schema: RwLock<Arc<Schema>>,
+    /// The slot of the primary key.
     pub key_index: usize,
-    pub indices: Vec<Index>,
+    pub indices: Indices,
+    /// Text and blob values, which their slots refer to.
+    pub heap: ValueHeap,
     pub table_id: usize,
-    pub num_total_cols: usize,
-    pub dirty_base_rids: DashSet<i64>,
+    /// Base records with tail records no merge has folded in yet, and how
+    /// many each has.
+    pub dirty_base_rids: DashMap<i64, usize>,
+    /// The counts in `dirty_base_rids`, summed.
+    unmerged_tails: AtomicUsize,
+    /// Striped by RID. Held from reading a base record's indirection until
+    /// it points past what was appended, so a merge never folds in a chain
+    /// that is still growing.
+    latches: Box<[Mutex<()>]>,
+    /// Pinned by queries, so that a collection is only freed once none of
+    /// them can still be reading it.
+    epochs: Epochs,
+    /// Collections left out of the counters, waiting for their readers.
+    retired: Mutex<Vec<Retired>>,
+    /// Deleted records a merge dropped, and the epoch each went in. The page
+    /// directory keeps them until no query can still be looking one up, and
+    /// checkpoints leave them out.
+    dropped: DashMap<i64, u64>,
+    /// Pages of retired collections deleted so far.
+    freed_pages: AtomicUsize,
     pub lock_manager: Arc<LockManager>,
+    pub wal: Arc<Wal>,
 }
 
 impl Table {
     pub const PROJECTED_NUM_RECORDS: usize = 10001;
     pub const NUM_META_PAGES: usize = 4;
-    
+    const NUM_LATCHES: usize = 64;
+
+    /// Pages a collection of records `width` slots wide takes: one for each
+    /// slot, the meta pages, and one for each schema-encoding word past the
+    /// first.
+    pub fn collection_pages(width: usize) -> usize {
+        width + Self::NUM_META_PAGES + SchemaEncoding::words_for(width) - 1
+    }
+
+    /// The width of records in a collection of `pages` pages, which
+    /// `collection_pages` gave.
+    pub fn collection_width(pages: usize) -> usize {
+        // Past the meta pages, 65 pages for every 64 slots but the first 64.
+        let pages = pages - Self::NUM_META_PAGES;
+        pages - pages.saturating_sub(1) / (SchemaEncoding::SLOTS_PER_WORD + 1)
+    }
+
     pub fn new_no_transaction(
         table_name: String,
         num_columns: usize,
@@ -38,50 +98,54 @@ impl Table {
     ) -> Table {
         Table::new(
             table_name,
-            num_columns,
+            Schema::ints(num_columns),
             key_index,
             table_id,
             bufferpool,
             Arc::new(LockManager::new()),
+            Arc::new(Wal::disabled()),
         )
     }
     
 
     pub fn new(
         table_name: String,
-        num_columns: usize,
+        schema: Schema,
         key_index: usize,
         table_id: usize,
         bufferpool: Arc<BufferPool>,
         lock_manager: Arc<LockManager>,
+        wal: Arc<Wal>,
     ) -> Table {
-        let num_total_cols = num_columns + Table::NUM_META_PAGES;
+        let num_total_cols = Table::collection_pages(schema.width());
+        let page_ranges = PageRanges::new(num_total_cols, table_id, bufferpool.clone());
+        let heap = ValueHeap::new(table_id, bufferpool, page_ranges.base.pid_iterator.clone(), num_total_cols);
         Self {
             name: table_name,
-            page_ranges: PageRanges::new(num_total_cols, table_id, bufferpool),
+            page_ranges,
             page_directory: PageDirectory::default(),
             rid: AtomicIterator::default(),
+            schema: RwLock::new(Arc::new(schema)),
             key_index,
-            num_data_columns: num_columns,
-            indices: (0..num_columns)
-                .map(|i| {
-                    if i == key_index {
-                        Index::new_unique()
-                    } else {
-                        Index::new_non_unique()
-                    }
-                })
-                .collect(),
+            indices: Indices::new(key_index),
+            heap,
             table_id,
-            num_total_cols,
-            dirty_base_rids: DashSet::new(),
+            dirty_base_rids: DashMap::new(),
+            unmerged_tails: AtomicUsize::new(0),
+            latches: (0..Self::NUM_LATCHES).map(|_| Mutex::new(())).collect(),
+            epochs: Epochs::new(),
+            retired: Mutex::new(Vec::new()),
+            dropped: DashMap::new(),
+            freed_pages: AtomicUsize::new(0),
             lock_manager,
+            wal,
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn restore (
         name: String,
-        num_columns: usize,
+        schema: Schema,
         key_index: usize,
         table_id: usize,
         bufferpool: Arc<BufferPool>,
@@ -89,16 +153,11 @@ impl Table {
         counters: TableCounters,
         primary_pairs: Vec<(i64, i64)>,
         lock_manager: Arc<LockManager>,
+        wal: Arc<Wal>,
     ) -> Self {
-        let num_total_cols = num_columns + Table::NUM_META_PAGES;
+        let num_total_cols = Table::collection_pages(schema.width());
 
-        let indices: Vec<Index> = (0..num_columns).map(|i| {
-            if i == key_index {
-                Index::new_unique()
-            } else {
-                Index::new_non_unique()
-            }
-        }).collect();
+        let indices = Indices::new(key_index);
         for (key, rid) in primary_pairs {
             indices[key_index].insert(key, rid);
         }
@@ -106,12 +165,31 @@ impl Table {
         let page_ranges = PageRanges::restore(
             num_total_cols,
             table_id,
-            bufferpool,
+            bufferpool.clone(),
             counters.base_collections,
             counters.tail_collections,
             counters.base_next_addr,
             counters.tail_next_addr,
             counters.pid_next_start,
+            counters.free_pids,
+            SlotMap {
+                retired: schema.retired_slots().into_iter().collect(),
+                fill: counters.fill.into_iter().collect(),
+            },
+        );
+        // Tables from before alters had chunks as big as their collections.
+        let pages_per_chunk = match counters.heap_pages_per_chunk {
+            0 => num_total_cols,
+            pages => pages,
+        };
+        let heap = ValueHeap::restore(
+            table_id,
+            bufferpool,
+            page_ranges.base.pid_iterator.clone(),
+            pages_per_chunk,
+            counters.heap_next_word,
+            counters.heap_chunks,
+            counters.heap_free,
         );
 
         let page_directory = PageDirectory::restore(page_dir_pairs);
@@ -124,16 +202,277 @@ impl Table {
             page_ranges,
             page_directory,
             rid,
+            schema: RwLock::new(Arc::new(schema)),
             key_index,
-            num_data_columns: num_columns,
             indices,
+            heap,
             table_id,
-            num_total_cols,
-            dirty_base_rids: DashSet::new(),
+            dirty_base_rids: DashMap::new(),
+            unmerged_tails: AtomicUsize::new(0),
+            latches: (0..Self::NUM_LATCHES).map(|_| Mutex::new(())).collect(),
+            epochs: Epochs::new(),
+            retired: Mutex::new(Vec::new()),
+            dropped: DashMap::new(),
+            freed_pages: AtomicUsize::new(0),
             lock_manager,
+            wal,
+        }
+    }
+
+    /// The latch for base record `rid`, which writers to it and merges of it
+    /// take after the log's `Wal::begin_write`.
+    pub fn latch(&self, rid: i64) -> MutexGuard<'_, ()> {
+        self.latches[rid.rem_euclid(Self::NUM_LATCHES as i64) as usize].lock()
+    }
+
+    /// Pins the current epoch for a query, which keeps every collection it
+    /// may find a record in until the guard is dropped.
+    pub(crate) fn pin(&self) -> EpochGuard<'_> {
+        self.epochs.pin()
+    }
+
+    /// Takes the collections whose every record a merge superseded out of
+    /// the counters, to be freed once no query can still be in them. A
+    /// checkpoint does it with writes held off, before it writes the
+    /// counters out.
+    pub fn retire_superseded(&self) -> Result<(), DbError> {
+        let epoch = self.epochs.current();
+        let mut retired = self.retired.lock();
+        for range in [WhichRange::Base, WhichRange::Tail] {
+            for collection in self.page_ranges.superseded(range) {
+                // All but base records with a newer copy elsewhere, which
+                // the directory keeps.
+                let rids = self
+                    .page_ranges
+                    .collection_rids(collection.num, range)?
+                    .into_iter()
+                    .filter(|&(rid, addr)| self.page_directory.get(rid).is_ok_and(|at| at == addr))
+                    .map(|(rid, _)| rid)
+                    .collect();
+                self.page_ranges.retire(collection.num, range);
+                retired.push(Retired { range, collection, epoch, rids });
+            }
+        }
+        self.epochs.try_advance();
+        Ok(())
+    }
+
+    /// Deletes the pages of the retired collections no query can still be
+    /// in, hands their PIDs out again, and returns how many pages went.
+    pub fn free_retired(&self) -> Result<usize, DbError> {
+        for _ in 0..2 {
+            if !self.epochs.try_advance() {
+                break;
+            }
+        }
+        let epoch = self.epochs.current();
+        let mut retired = self.retired.lock();
+        let mut pages = 0;
+        while let Some(i) = retired.iter().position(|r| r.epoch + 2 <= epoch) {
+            let r = &retired[i];
+            pages += self.page_ranges.free(r.collection.num, r.range)?;
+            for &rid in &r.rids {
+                let _ = self.page_directory.delete(rid);
+            }
+            retired.swap_remove(i);
         }
+        self.dropped.retain(|&rid, &mut dropped_in| {
+            let gone = dropped_in + 2 <= epoch;
+            if gone {
+                let _ = self.page_directory.delete(rid);
+            }
+            !gone
+        });
+        self.freed_pages.fetch_add(pages, Ordering::Relaxed);
+        Ok(pages)
     }
 
+    /// Pages of retired collections `free_retired` has deleted so far.
+    pub fn freed_pages(&self) -> usize {
+        self.freed_pages.load(Ordering::Relaxed)
+    }
+
+    /// The page directory as a checkpoint writes it out, without the
+    /// records of retired collections or the deleted records dropped.
+    pub fn page_directory_pairs(&self) -> Vec<(i64, PhysicalAddress)> {
+        let mut pairs = self.page_directory.snapshot();
+        let retired = self.retired.lock();
+        let gone: BTreeSet<i64> = retired.iter().flat_map(|r| r.rids.iter().copied()).collect();
+        if !gone.is_empty() || !self.dropped.is_empty() {
+            pairs.retain(|(rid, _)| !gone.contains(rid) && !self.dropped.contains_key(rid));
+        }
+        pairs
+    }
+
+    /// The PID ranges there are to hand out again, counting those of
+    /// retired collections not freed yet.
+    pub fn free_pid_ranges(&self) -> Vec<(usize, usize)> {
+        let mut free = self.page_ranges.free_pid_ranges();
+        free.extend(self.retired.lock().iter().map(|r| (r.collection.start, r.collection.end)));
+        free.sort_unstable();
+        free
+    }
+
+    /// Notes another tail record on base record `base_rid` for a merge to
+    /// fold in.
+    pub fn note_tail(&self, base_rid: i64) {
+        *self.dirty_base_rids.entry(base_rid).or_insert(0) += 1;
+        self.unmerged_tails.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Tail records no merge has folded in yet.
+    pub fn unmerged_tails(&self) -> usize {
+        self.unmerged_tails.load(Ordering::Relaxed)
+    }
+
+    /// Whether the tail records waiting have reached either of `config`'s
+    /// thresholds.
+    pub fn needs_merge(&self, config: &MergeConfig) -> bool {
+        self.unmerged_tails() >= config.tail_records
+            || self.dirty_base_rids.iter().any(|chain| *chain.value() >= config.chain_length)
+    }
+
+    /// The columns as they are now.
+    pub fn schema(&self) -> Arc<Schema> {
+        self.schema.read().clone()
+    }
+
+    /// Slots a record takes, `schema().width()`.
+    pub fn num_data_columns(&self) -> usize {
+        self.schema.read().width()
+    }
+
+    /// Adds column `def` after the last. The records there are read as
+    /// having its default, or null without one; a text or blob default is
+    /// stored once for all of them. The caller holds the log's
+    /// `Wal::quiesce`.
+    pub fn add_column(&self, def: ColumnDef) -> Result<(), DbError> {
+        if !def.nullable && def.default.is_none() {
+            return Err(DbError::InvalidSchema(format!(
+                "{} cannot be added NOT NULL without a default for the records there are",
+                def.name
+            )));
+        }
+        let schema = self.schema().with_column(def)?;
+        let col = schema.len() - 1;
+        let mut values = vec![None; schema.len()];
+        values[col] = schema.default(col).cloned();
+        let slots = schema.encode_with(&values, &mut |bytes| self.write_value(bytes))?;
+        let fill = schema.slots(col).filter_map(|slot| Some((slot, slots[slot]?))).collect();
+        self.log_alter(schema, fill)
+    }
+
+    /// Drops column `col`. Its slots are never read or written again, nor
+    /// given to a column added later, and collections cut from now on have
+    /// no pages for them. Its text and blob values stay in the heap. The
+    /// caller holds the log's `Wal::quiesce`.
+    pub fn drop_column(&self, col: usize) -> Result<(), DbError> {
+        let schema = self.schema();
+        if col >= schema.len() {
+            return Err(DbError::InvalidSchema(format!("there is no column {}", col)));
+        }
+        if schema.slots(col).contains(&self.key_index) {
+            return Err(DbError::InvalidSchema("the key column cannot be dropped".into()));
+        }
+        self.log_alter(schema.without_column(col), Vec::new())
+    }
+
+    fn log_alter(&self, schema: Schema, fill: Vec<(usize, i64)>) -> Result<(), DbError> {
+        self.wal.append(&LogRecord::AlterTable {
+            table_id: self.table_id,
+            schema: schema.clone(),
+            fill: fill.clone(),
+        })?;
+        self.alter(schema, fill);
+        Ok(())
+    }
+
+    /// Puts `schema` in place, which `add_column` or `drop_column` made,
+    /// with `fill` for the slots of a column it adds. Recovery replays a
+    /// logged alter through here.
+    pub fn alter(&self, schema: Schema, fill: Vec<(usize, i64)>) {
+        let mut slots = self.page_ranges.slots();
+        for slot in schema.retired_slots() {
+            if slots.retired.insert(slot) {
+                self.indices[slot].disable();
+                self.indices[slot].clear();
+            }
+        }
+        slots.fill.extend(fill);
+        self.page_ranges.reslot(schema.width(), slots);
+        *self.schema.write() = Arc::new(schema);
+    }
+
+    /// The slots of a record of `values`, with its text and blob values
+    /// stored in the heap.
+    pub fn encode(&self, values: &[Option<Value>]) -> Result<Vec<Option<i64>>, DbError> {
+        self.schema().encode_with(values, &mut |bytes| self.store_value(bytes))
+    }
+
+    /// The values of a record read back as `slots`, with its text and blob
+    /// values loaded from the heap.
+    pub fn decode(&self, slots: &[Option<i64>]) -> Result<Vec<Option<Value>>, DbError> {
+        self.schema().decode_with(slots, &|addr| self.heap.load(addr))
+    }
+
+    /// `decode` of column `col` alone.
+    pub fn decode_column(&self, col: usize, slots: &[Option<i64>]) -> Result<Option<Value>, DbError> {
+        self.schema().decode_column_with(col, slots, &|addr| self.heap.load(addr))
+    }
+
+    /// Puts `bytes` in the heap and logs them, returning the slot that refers
+    /// to them. Recovery writes every logged value back where it went, so a
+    /// record logged after it finds it there.
+    pub fn store_value(&self, bytes: &[u8]) -> Result<i64, DbError> {
+        // A checkpoint in between would count the space as used but lose
+        // the bytes with the log.
+        let _writing = self.wal.begin_nested_write();
+        self.write_value(bytes)
+    }
+
+    /// `store_value` for a caller that keeps checkpoints out itself.
+    fn write_value(&self, bytes: &[u8]) -> Result<i64, DbError> {
+        let words = ValueHeap::words_for(bytes.len());
+        let addr = self.heap.allocate(words);
+        let logged = self.wal.append(&LogRecord::HeapWrite {
+            table_id: self.table_id,
+            addr,
+            bytes: bytes.to_vec(),
+        });
+        if let Err(e) = logged {
+            self.heap.release(addr, words);
+            return Err(e.into());
+        }
+        self.heap.write(addr, bytes)?;
+        Ok(addr as i64)
+    }
+
+    /// Re-applies a logged heap write during recovery.
+    pub fn redo_heap_write(&self, addr: usize, bytes: &[u8]) -> Result<(), DbError> {
+        self.heap.claim(addr, ValueHeap::words_for(bytes.len()));
+        self.heap.write(addr, bytes)
+    }
+
+    /// Gives each text and blob value of `record` a copy of its own, for a
+    /// record written from slots read out of another: a merge of that one
+    /// may free what it refers to.
+    pub fn copy_out_of_line(&self, record: &mut [Option<i64>]) -> Result<(), DbError> {
+        for slot in self.out_of_line_slots() {
+            if let Some(addr) = record[slot] {
+                record[slot] = Some(self.store_value(&self.heap.load(addr)?)?);
+            }
+        }
+        Ok(())
+    }
+
+    /// The slots that refer to the heap.
+    fn out_of_line_slots(&self) -> Vec<usize> {
+        let schema = self.schema();
+        (0..schema.len())
+            .filter(|&col| schema.columns()[col].is_out_of_line())
+            .map(|col| schema.slots(col).start)
+            .collect()
+    }
 
     pub fn read(&self, rid: i64) -> Result<Vec<Option<i64>>, DbError> {
         let addr = self.page_directory.get(rid)?;
@@ -170,35 +509,37 @@ impl Table {
     }
 
     pub fn read_latest_single(&self, rid: i64, col: usize) -> Result<Option<i64>, DbError> {
-        let (base_addr, tps, tail_opt) = self.get_unmerged_tail(rid)?;
+        self.read_latest_single_hinted(rid, col, AccessHint::Normal)
+    }
+
+    /// `read_latest_single`, fetching every page it passes through with
+    /// `hint`.
+    pub fn read_latest_single_hinted(&self, rid: i64, col: usize, hint: AccessHint) -> Result<Option<i64>, DbError> {
+        let (base_addr, tps, tail_opt) = self.get_unmerged_tail(rid, hint)?;
         let mut current_tail_rid = match tail_opt {
             Some(r) => r,
             None => {
                 return self
                     .page_ranges
-                    .read_single(col, &base_addr, WhichRange::Base)
+                    .read_single_hinted(col, &base_addr, WhichRange::Base, hint)
                     .map_err(DbError::Storage);
             }
         };
 
         loop {
             let tail_addr = self.page_directory.get(current_tail_rid)?;
-            let tail_schema = self
-                .page_ranges
-                .read_meta_col(&tail_addr, MetaPage::SchemaEncoding, WhichRange::Tail)?
-                .unwrap_or(0);
-
-            if (tail_schema >> col) & 1 == 1 {
+            if self.page_ranges.encodes_hinted(col, &tail_addr, WhichRange::Tail, hint)? {
                 return self
                     .page_ranges
-                    .read_single(col, &tail_addr, WhichRange::Tail)
+                    .read_single_hinted(col, &tail_addr, WhichRange::Tail, hint)
                     .map_err(DbError::Storage);
             }
 
-            let next_rid = self.page_ranges.read_meta_col(
+            let next_rid = self.page_ranges.read_meta_col_hinted(
                 &tail_addr,
                 MetaPage::Indirection,
                 WhichRange::Tail,
+                hint,
             )?;
             match next_rid {
                 Some(next) if next == rid => break,
@@ -209,7 +550,7 @@ impl Table {
         }
 
         self.page_ranges
-            .read_single(col, &base_addr, WhichRange::Base)
+            .read_single_hinted(col, &base_addr, WhichRange::Base, hint)
             .map_err(DbError::Storage)
     }
 
@@ -219,22 +560,19 @@ impl Table {
         col: usize,
         mut relative_version: i64,
     ) -> Result<Option<i64>, DbError> {
-        let (base_addr, tps, tail_opt) = self.get_unmerged_tail(rid)?;
+        if relative_version == 0 {
+            return self.read_latest_single(rid, col);
+        }
+        let base_addr = self.page_directory.get(rid)?;
+        let head = self.page_ranges.read_meta_col(&base_addr, MetaPage::Indirection, WhichRange::Base)?;
+        let end = self.chain_end(rid, &base_addr)?;
 
-        if let Some(mut current_tail_rid) = tail_opt {
+        // Merged tail records are older versions all the same, so the walk
+        // goes past the watermark.
+        if let Some(mut current_tail_rid) = head.filter(|&head| head > end) {
             loop {
-                // Stop walking once we reach merged region
-                if current_tail_rid <= tps {
-                    break;
-                }
-
                 let tail_addr = self.page_directory.get(current_tail_rid)?;
-                let tail_schema = self
-                    .page_ranges
-                    .read_meta_col(&tail_addr, MetaPage::SchemaEncoding, WhichRange::Tail)?
-                    .unwrap_or(0);
-
-                if (tail_schema >> col) & 1 == 1 {
+                if self.page_ranges.encodes_hinted(col, &tail_addr, WhichRange::Tail, AccessHint::Normal)? {
                     relative_version += 1;
                 }
                 if relative_version > 0 {
@@ -250,15 +588,17 @@ impl Table {
                     WhichRange::Tail,
                 )?;
                 match next_rid {
-                    Some(next) if next == rid => break,
+                    Some(next) if next <= end => break,
                     Some(next) => current_tail_rid = next,
                     None => break,
                 }
             }
         }
 
-        // Fall back to base page (covers no-tail and merged-region cases)
-        self.read_col(col, &base_addr, WhichRange::Base)
+        match self.snapshot_of(rid, &base_addr)? {
+            Some(snapshot) => self.read_col(col, &snapshot, WhichRange::Tail),
+            None => self.read_col(col, &base_addr, WhichRange::Base),
+        }
     }
 
     pub fn read_latest_projected(
@@ -291,7 +631,7 @@ impl Table {
     }
 
     pub fn is_deleted(&self, rid: i64) -> Result<bool, DbError> {
-        let (base_addr, _tps, tail_opt) = self.get_unmerged_tail(rid)?;
+        let (base_addr, _tps, tail_opt) = self.get_unmerged_tail(rid, AccessHint::Normal)?;
 
         if let Some(tail_rid) = tail_opt {
             let tail_addr = self.page_directory.get(tail_rid)?;
@@ -313,15 +653,18 @@ impl Table {
 
     #[inline]
     fn read_record_internal (&self, rid: i64, skip_count: usize) -> Result<Vec<Option<i64>>, DbError> {
-        let (base_addr, tps, tail_opt) = self.get_unmerged_tail(rid)?;
-        let mut result = self.page_ranges.read_data(&base_addr, self.num_data_columns).map_err(DbError::Storage)?;
+        if skip_count > 0 {
+            return self.read_older_version(rid, skip_count);
+        }
+        let (base_addr, tps, tail_opt) = self.get_unmerged_tail(rid, AccessHint::Normal)?;
+        let mut result = self.page_ranges.read_data(&base_addr, self.num_data_columns()).map_err(DbError::Storage)?;
 
         let mut current_tail_rid = match tail_opt {
             Some(tail_rid) => tail_rid,
             None => return Ok(result),
         };
 
-        let mut accumulated_schema: i64 = 0;
+        let mut accumulated_schema = SchemaEncoding::default();
         let mut updates_seen = 0;
 
         loop {
@@ -348,27 +691,96 @@ impl Table {
         Ok(result)
     }
 
+    /// Base record `rid` as it was `skip_count` updates ago, or as the
+    /// oldest version there is if that is fewer. A merge leaves the tail
+    /// records of the versions it keeps where they are, so this walks the
+    /// chain down to its end and starts from the record's oldest values
+    /// rather than from a merged copy.
+    fn read_older_version(&self, rid: i64, skip_count: usize) -> Result<Vec<Option<i64>>, DbError> {
+        let base_addr = self.page_directory.get(rid)?;
+        let mut result = self.original_values(rid, &base_addr)?;
+        let end = self.chain_end(rid, &base_addr)?;
+        let mut accumulated_schema = SchemaEncoding::default();
+        let mut tail_rid = self.page_ranges.read_meta_col(&base_addr, MetaPage::Indirection, WhichRange::Base)?;
+        let mut updates_seen = 0;
+        while let Some(current) = tail_rid.filter(|&t| t > end) {
+            let tail_addr = self.page_directory.get(current)?;
+            if updates_seen >= skip_count {
+                self.apply_tail_update(&tail_addr, &mut result, &mut accumulated_schema)?;
+            }
+            updates_seen += 1;
+            tail_rid = self.page_ranges.read_meta_col(&tail_addr, MetaPage::Indirection, WhichRange::Tail)?;
+        }
+        Ok(result)
+    }
+
+    /// Base record `rid`'s values from before any of the tail records in
+    /// its chain: those of the snapshot a merge wrote of them, or else of
+    /// its copy at `base_addr`.
+    fn original_values(&self, rid: i64, base_addr: &PhysicalAddress) -> Result<Vec<Option<i64>>, DbError> {
+        let width = self.num_data_columns();
+        let values = match self.snapshot_of(rid, base_addr)? {
+            Some(snapshot) => self.page_ranges.read_tail_data(&snapshot, width)?,
+            None => self.page_ranges.read_data(base_addr, width)?,
+        };
+        Ok(values)
+    }
+
+    /// Where the snapshot of base record `rid`'s oldest values is, if its
+    /// copy at `base_addr` is a merged one that kept older versions.
+    fn snapshot_of(&self, rid: i64, base_addr: &PhysicalAddress) -> Result<Option<PhysicalAddress>, DbError> {
+        let tps = self.page_ranges.read_tps(base_addr, AccessHint::Normal)?;
+        // A copy no merge wrote has none, or its own RID after a vacuum.
+        if tps <= rid {
+            return Ok(None);
+        }
+        let Ok(addr) = self.page_directory.get(tps) else {
+            return Ok(None);
+        };
+        // A merge that kept none left the newest tail record it folded in
+        // there instead, as merges from before there were snapshots did.
+        let of = self.page_ranges.read_meta_col(&addr, MetaPage::Tps, WhichRange::Tail)?;
+        Ok((of == Some(rid)).then_some(addr))
+    }
+
+    /// The RID base record `rid`'s chain is followed down to for older
+    /// versions. The tail records past it are folded into the snapshot of
+    /// its copy at `base_addr`, or into the copy itself if a merge kept no
+    /// older versions, and may be freed already.
+    fn chain_end(&self, rid: i64, base_addr: &PhysicalAddress) -> Result<i64, DbError> {
+        let tps = self.page_ranges.read_tps(base_addr, AccessHint::Normal)?;
+        if tps <= rid {
+            return Ok(rid);
+        }
+        match self.snapshot_of(rid, base_addr)? {
+            Some(snapshot) => Ok(self
+                .page_ranges
+                .read_meta_col(&snapshot, MetaPage::Indirection, WhichRange::Tail)?
+                .unwrap_or(rid)),
+            None => Ok(tps),
+        }
+    }
+
     #[inline]
     fn apply_tail_update(
         &self,
         tail_addr: &PhysicalAddress,
-        result: &mut Vec<Option<i64>>,
-        accumulated_schema: &mut i64,
+        result: &mut [Option<i64>],
+        accumulated_schema: &mut SchemaEncoding,
     ) -> Result<(), DbError> {
         let tail_schema = self
             .page_ranges
-            .read_meta_col(&tail_addr, MetaPage::SchemaEncoding, WhichRange::Tail)?
-            .unwrap_or(0);
+            .read_schema_encoding(tail_addr, WhichRange::Tail)?
+            .unwrap_or_default();
 
-        let new_cols = tail_schema & !*accumulated_schema;
-        for (col, schema) in result.iter_mut() .enumerate().take(self.num_data_columns) {
-            if (new_cols >> col) & 1 == 1 {
+        for col in tail_schema.without(accumulated_schema).slots() {
+            if let Some(schema) = result.get_mut(col) {
                 *schema = self
                     .page_ranges
                     .read_single(col, tail_addr, WhichRange::Tail)?;
             }
         }
-        *accumulated_schema |= tail_schema;
+        accumulated_schema.add(&tail_schema);
         Ok(())
     }
 
@@ -378,12 +790,13 @@ impl Table {
     }
 
     #[inline]
-    fn get_unmerged_tail(&self, rid: i64) -> Result<(PhysicalAddress, i64, Option<i64>), DbError> {
+    fn get_unmerged_tail(&self, rid: i64, hint: AccessHint) -> Result<(PhysicalAddress, i64, Option<i64>), DbError> {
         let base_addr = self.page_directory.get(rid)?;
-        let indirection = self.page_ranges.read_meta_col(
+        let indirection = self.page_ranges.read_meta_col_hinted(
             &base_addr,
             MetaPage::Indirection,
             WhichRange::Base,
+            hint,
         )?;
 
         let tail_rid = match indirection {
@@ -391,7 +804,7 @@ impl Table {
             _ => return Ok((base_addr, 0, None)),
         };
 
-        let tps = self.page_ranges.get_tps(&base_addr);
+        let tps = self.page_ranges.read_tps(&base_addr, hint)?;
         if tail_rid <= tps {
             return Ok((base_addr, tps, None));
         }
@@ -399,54 +812,364 @@ impl Table {
         Ok((base_addr, tps, Some(tail_rid)))
     }
 
-    pub fn merge(&mut self) -> Result<(), DbError> {
-        let dirty: Vec<i64> = self.dirty_base_rids.iter().map(|r| *r).collect();
+    /// Makes sure RIDs handed out from now on are past `rid`.
+    #[inline]
+    pub fn reserve_rid(&self, rid: i64) {
+        if self.rid.current() <= rid {
+            self.rid.set(rid + 1);
+        }
+    }
 
-        for &base_rid in &dirty {
-            self.dirty_base_rids.remove(&base_rid);
+    /// Re-applies a logged insert during recovery, keeping the logged RID.
+    pub fn redo_insert(&self, rid: i64, cols: &[Option<i64>]) -> Result<(), DbError> {
+        let key = cols[self.key_index].ok_or(DbError::NullValue(self.key_index))?;
+        let address = self.page_ranges.append_base(cols, rid)?;
+        self.page_directory.add(rid, address);
+        self.indices[self.key_index].insert(key, rid);
+        self.reserve_rid(rid);
+        Ok(())
+    }
 
-            let base_addr = match self.page_directory.get(base_rid) {
-                Ok(addr) => addr,
-                Err(_) => continue,
-            };
+    /// Re-applies a logged update or delete during recovery. The base record's
+    /// indirection is left alone; recovery sets it once the whole log is replayed.
+    pub fn redo_tail(
+        &self,
+        base_rid: i64,
+        tail_rid: i64,
+        prev: i64,
+        cols: Vec<Option<i64>>,
+        schema_encoding: Option<SchemaEncoding>,
+    ) -> Result<(), DbError> {
+        let address = self
+            .page_ranges
+            .append_tail(cols, tail_rid, prev, schema_encoding)?;
+        self.page_directory.add(tail_rid, address);
+        self.note_tail(base_rid);
+        self.reserve_rid(tail_rid);
+        Ok(())
+    }
 
-            // Only process records that have an unmerged tail chain
-            let indirection = match self.page_ranges.read_meta_col(
-                &base_addr,
-                MetaPage::Indirection,
-                WhichRange::Base,
-            )? {
-                Some(ind) if ind != base_rid => ind,
-                _ => continue,
+    /// Rebuilds the page directory from the RID stored in every record slot,
+    /// for when `page_directory.bin` cannot be trusted. A merged copy of a base
+    /// record sits in a later slot than the original, so it wins.
+    pub fn rebuild_page_directory(&self) -> Result<(), DbError> {
+        let base = self.page_ranges.scan_base_rids()?;
+        let tail = self.page_ranges.scan_tail_rids()?;
+        for (rid, addr) in base.into_iter().chain(tail) {
+            self.page_directory.add(rid, addr);
+            self.reserve_rid(rid);
+        }
+        Ok(())
+    }
+
+    /// Re-indexes every base record that is not deleted. Must run after the
+    /// page directory and all indirection chains are consistent.
+    pub fn rebuild_primary_index(&self) -> Result<(), DbError> {
+        for (rid, addr) in self.page_ranges.scan_base_rids()? {
+            // Older copies, and deleted records a merge dropped.
+            if self.page_directory.get(rid).ok() != Some(addr) {
+                continue;
+            }
+            if self.is_deleted(rid)? {
+                continue;
+            }
+            if let Some(key) = self.read_single(rid, self.key_index, WhichRange::Base)? {
+                self.indices[self.key_index].insert(key, rid);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read_base_indirection(&self, base_rid: i64) -> Result<Option<i64>, DbError> {
+        let base_addr = self.page_directory.get(base_rid)?;
+        self.page_ranges
+            .read_meta_col(&base_addr, MetaPage::Indirection, WhichRange::Base)
+            .map_err(DbError::Storage)
+    }
+
+    pub fn write_tail_indirection(&self, tail_rid: i64, indirection: i64) -> Result<(), DbError> {
+        let tail_addr = self.page_directory.get(tail_rid)?;
+        self.page_ranges
+            .write_indirection(&tail_addr, Some(indirection), WhichRange::Tail)
+            .map_err(DbError::Storage)
+    }
+
+    pub fn write_base_indirection(&self, base_rid: i64, indirection: i64) -> Result<(), DbError> {
+        let base_addr = self.page_directory.get(base_rid)?;
+        self.page_ranges
+            .write_indirection(&base_addr, Some(indirection), WhichRange::Base)
+            .map_err(DbError::Storage)
+    }
+
+    /// Folds the tail records of every dirty base record into a merged copy
+    /// of it, which the page directory then points to; the copy's TPS says
+    /// how far its chain is folded in. The newest `config.keep_versions`
+    /// tail records stay, for reads of older versions; the rest, and the
+    /// text and blob values only they had, go once no query can still be
+    /// reading them. One record at a time, so readers go on with the old
+    /// copy or the new and writers only wait on the record being merged.
+    /// Returns how many records were merged.
+    pub fn merge(&self, config: &MergeConfig) -> Result<usize, DbError> {
+        let dirty: Vec<i64> = self.dirty_base_rids.iter().map(|chain| *chain.key()).collect();
+        let mut merged = 0;
+        for base_rid in dirty {
+            // Held off while a checkpoint writes out, so the values a merge
+            // retires are either all in that checkpoint or all left for the
+            // next.
+            let _writing = self.wal.begin_write();
+            let _latch = self.latch(base_rid);
+            let Some((_, tails)) = self.dirty_base_rids.remove(&base_rid) else {
+                continue;
             };
+            self.unmerged_tails.fetch_sub(tails, Ordering::Relaxed);
+            match self.merge_record(base_rid, config.keep_versions) {
+                Ok(true) => merged += 1,
+                Ok(false) => {}
+                Err(e) => {
+                    // Left for the next merge to try again.
+                    self.dirty_base_rids.insert(base_rid, tails);
+                    self.unmerged_tails.fetch_add(tails, Ordering::Relaxed);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(merged)
+    }
+
+    /// Merges base record `base_rid`, holding its latch, keeping
+    /// `keep_versions` older versions of it. False if it has no tail records
+    /// to fold in.
+    fn merge_record(&self, base_rid: i64, keep_versions: usize) -> Result<bool, DbError> {
+        if self.dropped.contains_key(&base_rid) {
+            return Ok(false);
+        }
+        let base_addr = match self.page_directory.get(base_rid) {
+            Ok(addr) => addr,
+            Err(_) => return Ok(false),
+        };
+
+        // Only process records that have an unmerged tail chain
+        let indirection = match self.page_ranges.read_meta_col(
+            &base_addr,
+            MetaPage::Indirection,
+            WhichRange::Base,
+        )? {
+            Some(ind) if ind != base_rid => ind,
+            _ => return Ok(false),
+        };
+        if indirection <= self.page_ranges.read_tps(&base_addr, AccessHint::Normal)? {
+            return Ok(false);
+        }
 
-            let tail_addr = match self.page_directory.get(indirection) {
-                Ok(addr) => addr,
-                Err(_) => continue,
+        let tail_addr = match self.page_directory.get(indirection) {
+            Ok(addr) => addr,
+            Err(_) => return Ok(false),
+        };
+        let latest_schema = self.page_ranges.read_meta_col(
+            &tail_addr,
+            MetaPage::SchemaEncoding,
+            WhichRange::Tail,
+        )?;
+        let snapshot = self.snapshot_of(base_rid, &base_addr)?;
+
+        if latest_schema.is_none() {
+            // Deleted: no version of it is read again, so nothing is written
+            // in its place and the whole of it goes.
+            let mut replaced = self.chain(base_rid, &base_addr)?;
+            replaced.extend(snapshot);
+            self.retire_replaced_values(&base_addr, &replaced, &[], &[])?;
+            self.page_ranges.supersede(&base_addr, WhichRange::Base);
+            for tail_addr in &replaced {
+                self.page_ranges.supersede(tail_addr, WhichRange::Tail);
+            }
+            self.dropped.insert(base_rid, self.epochs.current());
+            return Ok(true);
+        }
+
+        // The merged copy has the latest values. The newest tail records
+        // stay in the chain for the versions kept, which start from a
+        // snapshot of the oldest of them; its RID is past every tail record
+        // folded in, so it does for the watermark too.
+        let chain = self.chain(base_rid, &base_addr)?;
+        let keep = keep_versions.min(chain.len());
+        let latest = self.read_latest(base_rid)?;
+        let mut kept = Vec::new();
+        let tps = if keep == 0 {
+            indirection
+        } else {
+            let folded = match chain.get(keep) {
+                Some(addr) => self
+                    .page_ranges
+                    .read_meta_col(addr, MetaPage::Rid, WhichRange::Tail)?
+                    .ok_or(DbError::RecordNotFound(base_rid))?,
+                None => self.chain_end(base_rid, &base_addr)?,
             };
-            let latest_schema = self.page_ranges.read_meta_col(
-                &tail_addr,
-                MetaPage::SchemaEncoding,
-                WhichRange::Tail,
-            )?;
+            let oldest = self.read_record_internal(base_rid, keep)?;
+            let snapshot_rid = self.rid.next();
+            let snapshot_addr = self.page_ranges.append_snapshot(oldest.clone(), snapshot_rid, base_rid, folded)?;
+            self.page_directory.add(snapshot_rid, snapshot_addr);
+            kept.push(oldest);
+            snapshot_rid
+        };
+        let new_addr = self.page_ranges.append_base_merged(
+            latest.clone(),
+            base_rid,
+            indirection,
+            tps,
+            Some(SchemaEncoding::default()),
+        )?;
+        self.page_directory.add(base_rid, new_addr);
+        kept.push(latest);
+
+        // Nothing looks up the old copy, the snapshot it had, or the tail
+        // records folded into the new one, from now on.
+        let (kept_tails, folded) = chain.split_at(keep);
+        let mut replaced = folded.to_vec();
+        replaced.extend(snapshot);
+        self.retire_replaced_values(&base_addr, &replaced, kept_tails, &kept)?;
+        self.page_ranges.supersede(&base_addr, WhichRange::Base);
+        for tail_addr in &replaced {
+            self.page_ranges.supersede(tail_addr, WhichRange::Tail);
+        }
+        Ok(true)
+    }
 
-            let (consolidated_data, new_schema) = if latest_schema.is_none() {
-                (vec![None; self.num_data_columns], None)
-            } else {
-                let latest = self.read_latest(base_rid)?;
-                (latest[..self.num_data_columns].to_vec(), Some(0i64))
+    /// Where the tail records of base record `base_rid` are, newest first,
+    /// merged or not, down to the end of its chain.
+    fn chain(&self, base_rid: i64, base_addr: &PhysicalAddress) -> Result<Vec<PhysicalAddress>, DbError> {
+        let end = self.chain_end(base_rid, base_addr)?;
+        let mut chain = Vec::new();
+        let mut tail_rid = self.page_ranges.read_meta_col(base_addr, MetaPage::Indirection, WhichRange::Base)?;
+        while let Some(current) = tail_rid.filter(|&t| t > end) {
+            let tail_addr = self.page_directory.get(current)?;
+            chain.push(tail_addr);
+            tail_rid = self.page_ranges.read_meta_col(&tail_addr, MetaPage::Indirection, WhichRange::Tail)?;
+        }
+        Ok(chain)
+    }
+
+    /// Rewrites every record that is not deleted into collections of its
+    /// own, with the newest `options.keep_versions` of its older versions
+    /// as tail records after it and the rest folded in, merged or not. Base RIDs stay as
+    /// they are; the page directory and every index end up with the new
+    /// records only, and the old collections are all superseded, for the
+    /// next checkpoint to retire. The caller holds the log's
+    /// `Wal::quiesce`, and takes that checkpoint after, which puts the new
+    /// collections in place on disk in one go.
+    pub fn vacuum(&self, options: VacuumOptions) -> Result<VacuumStats, DbError> {
+        let ranges = &self.page_ranges;
+        let pages_before = ranges.pages_from(0, 0);
+        let (base_from, tail_from) = ranges.seal();
+        let width = self.num_data_columns();
+        let mut stats = VacuumStats::default();
+        let mut latest = Vec::new();
+        for (rid, addr) in ranges.scan_base_rids()? {
+            // Older copies are superseded already, and so are dropped
+            // records.
+            if self.page_directory.get(rid).ok() != Some(addr) || self.dropped.contains_key(&rid) {
+                continue;
+            }
+            if let Some((_, tails)) = self.dirty_base_rids.remove(&rid) {
+                self.unmerged_tails.fetch_sub(tails, Ordering::Relaxed);
+            }
+            // Merges used to leave a deleted record a copy of its own, with
+            // its values retired already.
+            if ranges.read_meta_col(&addr, MetaPage::SchemaEncoding, WhichRange::Base)?.is_none() {
+                stats.deleted += 1;
+                continue;
+            }
+            let chain = self.chain(rid, &addr)?;
+            let snapshot = self.snapshot_of(rid, &addr)?;
+            let deleted = match chain.first() {
+                Some(newest) => ranges.read_meta_col(newest, MetaPage::SchemaEncoding, WhichRange::Tail)?.is_none(),
+                None => false,
             };
+            if deleted {
+                let replaced: Vec<PhysicalAddress> = chain.iter().copied().chain(snapshot).collect();
+                self.retire_replaced_values(&addr, &replaced, &[], &[])?;
+                stats.deleted += 1;
+                continue;
+            }
 
-            let new_addr = self.page_ranges.append_base_merged(
-                consolidated_data,
-                base_rid,
-                indirection,
-                new_schema,
-            )?;
+            let keep = options.keep_versions.map_or(chain.len(), |versions| versions.min(chain.len()));
+            let data = self.read_record_internal(rid, keep)?;
+            let replaced: Vec<PhysicalAddress> = chain[keep..].iter().copied().chain(snapshot).collect();
+            self.retire_replaced_values(&addr, &replaced, &chain[..keep], std::slice::from_ref(&data))?;
+            // Oldest first, so that the new tail RIDs go up along the chain.
+            let mut indirection = rid;
+            for tail_addr in chain[..keep].iter().rev() {
+                let tail_rid = self.rid.next();
+                let tail_data = ranges.read_tail_data(tail_addr, width)?;
+                let encoding = ranges.read_schema_encoding(tail_addr, WhichRange::Tail)?;
+                let new_addr = ranges.append_tail(tail_data, tail_rid, indirection, encoding)?;
+                self.page_directory.add(tail_rid, new_addr);
+                indirection = tail_rid;
+            }
+            let new_addr = ranges.append_base_merged(data, rid, indirection, rid, Some(SchemaEncoding::default()))?;
+            self.page_directory.add(rid, new_addr);
+            if keep > 0 {
+                self.dirty_base_rids.insert(rid, keep);
+                self.unmerged_tails.fetch_add(keep, Ordering::Relaxed);
+            }
+            latest.push((rid, self.read_latest(rid)?));
+            stats.records += 1;
+            stats.discarded_versions += (chain.len() - keep) as u64;
+        }
+        ranges.supersede_before(base_from, tail_from);
+
+        for (slot, index) in self.indices.enabled() {
+            let pairs: Vec<(i64, i64)> = latest.iter().filter_map(|(rid, record)| Some((record[slot]?, *rid))).collect();
+            index.rebuild(&pairs);
+        }
 
-            self.page_directory.add(base_rid, new_addr);
+        let pages_after = ranges.pages_from(base_from, tail_from);
+        stats.pages_before = pages_before as u64;
+        stats.pages_after = pages_after as u64;
+        Ok(stats)
+    }
 
-            self.page_ranges.update_tps(&new_addr, indirection);
+    /// Retires the heap values of a base record and of the tail records no
+    /// version of it is read from again, all but those of the records `kept`
+    /// written in their place and of the tail records `kept_tails` it goes
+    /// on with.
+    fn retire_replaced_values(
+        &self,
+        base_addr: &PhysicalAddress,
+        tails: &[PhysicalAddress],
+        kept_tails: &[PhysicalAddress],
+        kept: &[Vec<Option<i64>>],
+    ) -> Result<(), DbError> {
+        let slots = self.out_of_line_slots();
+        if slots.is_empty() {
+            return Ok(());
+        }
+        let mut replaced = BTreeSet::new();
+        for &slot in &slots {
+            replaced.extend(self.page_ranges.read_single(slot, base_addr, WhichRange::Base)?);
+        }
+        for tail_addr in tails {
+            for &slot in &slots {
+                replaced.extend(self.page_ranges.read_single(slot, tail_addr, WhichRange::Tail)?);
+            }
+        }
+        for &slot in &slots {
+            for record in kept {
+                if let Some(kept) = record[slot] {
+                    replaced.remove(&kept);
+                }
+            }
+            for tail_addr in kept_tails {
+                if let Some(kept) = self.page_ranges.read_single(slot, tail_addr, WhichRange::Tail)? {
+                    replaced.remove(&kept);
+                }
+            }
+        }
+        // A default stored for an added column is every older record's.
+        for fill in self.page_ranges.slots().fill.values() {
+            replaced.remove(fill);
+        }
+        for addr in replaced {
+            self.heap.retire(addr)?;
         }
         Ok(())
     }
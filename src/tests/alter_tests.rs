@@ -0,0 +1,185 @@
+use crate::bufferpool::BufferPool;
+use crate::check::check;
+use crate::config::{DatabaseConfig, MergeConfig};
+use crate::db::Database;
+use crate::lock_manager::LockManager;
+use crate::query::Query;
+use crate::schema::{ColumnDef, ColumnType, Schema, Value};
+use crate::storage::MemoryBackend;
+use crate::table::Table;
+use crate::wal::Wal;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+fn columns() -> Vec<ColumnDef> {
+    vec![ColumnDef::new("id", ColumnType::I64), ColumnDef::new("score", ColumnType::I32)]
+}
+
+fn defaulted(name: &str, ty: ColumnType, default: Value) -> ColumnDef {
+    ColumnDef { nullable: false, default: Some(default), ..ColumnDef::new(name, ty) }
+}
+
+fn old_row(key: i64) -> Vec<Option<Value>> {
+    vec![Some(Value::I64(key)), Some(Value::I32(key as i32))]
+}
+
+fn select(q: &Query, key: i64) -> Vec<Option<Value>> {
+    let all = vec![1; q.table.schema().len()];
+    q.select_values(&Value::I64(key), 0, &all).unwrap().remove(0)
+}
+
+#[test]
+fn added_columns_read_their_default_in_old_records() {
+    let db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.create_table_with_columns("t".into(), columns(), 0).unwrap();
+    let q = Query::new(db.get_table("t").unwrap());
+    // More than a collection's worth, so the old records span two.
+    for key in 0..600 {
+        q.insert_values(old_row(key)).unwrap();
+    }
+    q.update_values(3, vec![None, Some(Value::I32(-3))]).unwrap();
+
+    assert!(db.alter_table_add_column("t", defaulted("level", ColumnType::I64, Value::I64(7))).unwrap());
+    let note = defaulted("note", ColumnType::Text, Value::Str("none yet".into()));
+    assert!(db.alter_table_add_column("t", note).unwrap());
+    assert!(!db.alter_table_add_column("missing", ColumnDef::new("x", ColumnType::I64)).unwrap());
+    let required = ColumnDef { nullable: false, ..ColumnDef::new("required", ColumnType::I64) };
+    assert!(db.alter_table_add_column("t", required).is_err());
+    assert!(db.alter_table_add_column("t", ColumnDef::new("level", ColumnType::I64)).is_err());
+
+    let t = db.get_table("t").unwrap();
+    assert_eq!(t.schema().names(), ["id", "score", "level", "note"]);
+    let fill = |mut row: Vec<Option<Value>>| {
+        row.extend([Some(Value::I64(7)), Some(Value::Str("none yet".into()))]);
+        row
+    };
+    for key in [0, 3, 511, 512, 599] {
+        let mut expected = fill(old_row(key));
+        if key == 3 {
+            expected[1] = Some(Value::I32(-3));
+        }
+        assert_eq!(select(&q, key), expected);
+    }
+
+    // New records and updates to old ones keep values of their own.
+    let new = vec![Some(Value::I64(1000)), None, Some(Value::I64(1)), Some(Value::Str("new".into()))];
+    q.insert_values(new.clone()).unwrap();
+    assert_eq!(select(&q, 1000), new);
+    q.insert_values(vec![Some(Value::I64(1001)), None, None, None]).unwrap();
+    assert_eq!(select(&q, 1001), fill(vec![Some(Value::I64(1001)), None]));
+    q.update_values(5, vec![None, None, Some(Value::I64(50)), None]).unwrap();
+    let mut updated = fill(old_row(5));
+    updated[2] = Some(Value::I64(50));
+    assert_eq!(select(&q, 5), updated);
+    assert_eq!(q.select_version_values(&Value::I64(5), &[1, 1, 1, 1], -1).unwrap(), vec![fill(old_row(5))]);
+    assert_eq!(q.select_version_values(&Value::I64(3), &[1, 1, 1, 1], -1).unwrap(), vec![fill(old_row(3))]);
+    assert_eq!(q.sum_values(0, 599, 2).unwrap(), Value::I64(7 * 599 + 50));
+}
+
+#[test]
+fn dropped_columns_are_gone() {
+    let db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    let mut defs = columns();
+    defs.push(ColumnDef::new("name", ColumnType::Str(20)));
+    db.create_table_with_columns("t".into(), defs, 0).unwrap();
+    let t = db.get_table("t").unwrap();
+    let q = Query::new(t.clone());
+    t.indices[1].enable();
+    for key in 0..10 {
+        q.insert_values(vec![Some(Value::I64(key)), Some(Value::I32(1)), Some(Value::Str(format!("n{}", key)))])
+            .unwrap();
+    }
+
+    assert!(db.alter_table_drop_column("t", "score").unwrap());
+    assert!(db.alter_table_drop_column("t", "score").is_err());
+    assert!(db.alter_table_drop_column("t", "id").is_err());
+    assert!(!db.alter_table_drop_column("missing", "id").unwrap());
+    assert_eq!(t.schema().names(), ["id", "name"]);
+    assert_eq!(t.schema().width(), 6, "the slot stays");
+    assert_eq!(t.schema().retired_slots(), vec![1]);
+    assert!(t.page_ranges.slots().retired.contains(&1));
+    assert!(!t.indices[1].is_enabled());
+    assert!(t.indices[1].all_pairs().is_empty());
+
+    q.insert_values(vec![Some(Value::I64(10)), Some(Value::Str("after".into()))]).unwrap();
+    q.update_values(2, vec![None, Some(Value::Str("changed".into()))]).unwrap();
+    assert_eq!(select(&q, 4), vec![Some(Value::I64(4)), Some(Value::Str("n4".into()))]);
+    assert_eq!(select(&q, 10), vec![Some(Value::I64(10)), Some(Value::Str("after".into()))]);
+    assert_eq!(select(&q, 2), vec![Some(Value::I64(2)), Some(Value::Str("changed".into()))]);
+
+    // A column added later gets a slot of its own.
+    assert!(db.alter_table_add_column("t", ColumnDef::new("score", ColumnType::I64)).unwrap());
+    assert_eq!(t.schema().slots(2), 6..7);
+    assert_eq!(select(&q, 4), vec![Some(Value::I64(4)), Some(Value::Str("n4".into())), None]);
+}
+
+#[test]
+fn alters_survive_a_checkpoint_and_a_crash() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().to_str().unwrap();
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open(path).unwrap();
+    db.create_table_with_columns("t".into(), columns(), 0).unwrap();
+    let q = Query::new(db.get_table("t").unwrap());
+    for key in 0..20 {
+        q.insert_values(old_row(key)).unwrap();
+    }
+    db.alter_table_add_column("t", defaulted("level", ColumnType::I64, Value::I64(7))).unwrap();
+    q.insert_values(vec![Some(Value::I64(20)), None, Some(Value::I64(2))]).unwrap();
+    db.checkpoint().unwrap();
+
+    // After the checkpoint, from the log alone.
+    db.alter_table_drop_column("t", "score").unwrap();
+    db.alter_table_add_column("t", defaulted("tag", ColumnType::Text, Value::Str("untagged".into()))).unwrap();
+    q.insert_values(vec![Some(Value::I64(21)), None, Some(Value::Str("tagged".into()))]).unwrap();
+    q.update_values(1, vec![None, Some(Value::I64(11)), None]).unwrap();
+    std::mem::forget(db);
+
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open(path).unwrap();
+    let q = Query::new(db.get_table("t").unwrap());
+    let untagged = Some(Value::Str("untagged".into()));
+    assert_eq!(q.table.schema().names(), ["id", "level", "tag"]);
+    assert_eq!(select(&q, 0), vec![Some(Value::I64(0)), Some(Value::I64(7)), untagged.clone()]);
+    assert_eq!(select(&q, 1), vec![Some(Value::I64(1)), Some(Value::I64(11)), untagged.clone()]);
+    assert_eq!(select(&q, 20), vec![Some(Value::I64(20)), Some(Value::I64(2)), untagged.clone()]);
+    assert_eq!(select(&q, 21), vec![Some(Value::I64(21)), Some(Value::I64(7)), Some(Value::Str("tagged".into()))]);
+
+    // And once more from the checkpoint the close takes.
+    db.close().unwrap();
+    let root = db.path.clone().unwrap();
+    assert!(check(&root, false).unwrap().is_consistent());
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open(path).unwrap();
+    let q = Query::new(db.get_table("t").unwrap());
+    assert_eq!(select(&q, 1), vec![Some(Value::I64(1)), Some(Value::I64(11)), untagged]);
+    q.insert_values(vec![Some(Value::I64(22)), None, None]).unwrap();
+    assert_eq!(select(&q, 21)[2], Some(Value::Str("tagged".into())));
+    db.close().unwrap();
+}
+
+#[test]
+fn merges_keep_the_default_of_an_added_column() {
+    let bp = Arc::new(BufferPool::new(Arc::new(MemoryBackend::new())));
+    let schema = Schema::with_defs(columns()).unwrap();
+    let lm = Arc::new(LockManager::new());
+    let t = Arc::new(Table::new("t".into(), schema, 0, 0, bp, lm, Arc::new(Wal::disabled())));
+    let q = Query::new(t.clone());
+    for key in 0..10 {
+        q.insert_values(old_row(key)).unwrap();
+    }
+    t.add_column(defaulted("note", ColumnType::Text, Value::Str("default".into()))).unwrap();
+    q.update_values(1, vec![None, Some(Value::I32(-1)), None]).unwrap();
+    q.update_values(2, vec![None, None, Some(Value::Str("own".into()))]).unwrap();
+
+    t.merge(&MergeConfig::default()).unwrap();
+    let default = Some(Value::Str("default".into()));
+    assert_eq!(select(&q, 1), vec![Some(Value::I64(1)), Some(Value::I32(-1)), default.clone()]);
+    assert_eq!(select(&q, 2), vec![Some(Value::I64(2)), Some(Value::I32(2)), Some(Value::Str("own".into()))]);
+    assert_eq!(select(&q, 3), vec![Some(Value::I64(3)), Some(Value::I32(3)), default.clone()]);
+    // The default is shared, so the merge leaves it be.
+    assert!(t.heap.free_extents().is_empty());
+    t.heap.release_retired();
+    q.insert_values(vec![Some(Value::I64(10)), None, Some(Value::Str("another".into()))]).unwrap();
+    assert_eq!(select(&q, 4)[2], default);
+}
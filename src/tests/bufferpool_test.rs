@@ -1,25 +1,20 @@
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
-    use std::sync::atomic::{AtomicUsize, Ordering};
-    use crate::bufferpool::{BufferPool, DiskManager, BP_CAP};
+    use crate::bufferpool::{BufferPool, BufferPoolError, BP_CAP};
+    use crate::storage::StorageBackend;
     use crate::page_collection::PageId;
+    use crate::storage::MemoryBackend;
 
-    static BP_TEST_CTR: AtomicUsize = AtomicUsize::new(0);
-
-    fn make_bp() -> (Arc<BufferPool>, String) {
-        let id = BP_TEST_CTR.fetch_add(1, Ordering::Relaxed);
-        let dir = format!("./test_tmp/bp_{}_{}", std::process::id(), id);
-        let _ = std::fs::remove_dir_all(&dir);
-        let bp = Arc::new(BufferPool::new(
-            Arc::new(parking_lot::RwLock::new(DiskManager::new(&dir).unwrap()))
-        ));
-        (bp, dir)
+    fn make_bp() -> (Arc<BufferPool>, Arc<MemoryBackend>) {
+        let storage = Arc::new(MemoryBackend::new());
+        let bp = Arc::new(BufferPool::new(storage.clone()));
+        (bp, storage)
     }
 
     #[test]
     fn write_and_read_single_page() {
-        let (bp, _dir) = make_bp();
+        let (bp, _storage) = make_bp();
         let pid = PageId::new(0, 0);
         bp.write(pid, Some(42), 0).unwrap();
         assert_eq!(bp.read(pid, 0).unwrap(), Some(42));
@@ -27,7 +22,7 @@ mod tests {
 
     #[test]
     fn write_none_reads_none() {
-        let (bp, _dir) = make_bp();
+        let (bp, _storage) = make_bp();
         let pid = PageId::new(0, 0);
         bp.write(pid, None, 0).unwrap();
         assert_eq!(bp.read(pid, 0).unwrap(), None);
@@ -35,7 +30,7 @@ mod tests {
 
     #[test]
     fn write_multiple_offsets() {
-        let (bp, _dir) = make_bp();
+        let (bp, _storage) = make_bp();
         let pid = PageId::new(0, 0);
         for i in 0..10usize {
             bp.write(pid, Some(i as i64), i).unwrap();
@@ -47,7 +42,7 @@ mod tests {
 
     #[test]
     fn update_overwrites_value() {
-        let (bp, _dir) = make_bp();
+        let (bp, _storage) = make_bp();
         let pid = PageId::new(0, 0);
         bp.write(pid, Some(10), 0).unwrap();
         bp.update(pid, 0, Some(99)).unwrap();
@@ -56,7 +51,7 @@ mod tests {
 
     #[test]
     fn update_to_none() {
-        let (bp, _dir) = make_bp();
+        let (bp, _storage) = make_bp();
         let pid = PageId::new(0, 0);
         bp.write(pid, Some(10), 0).unwrap();
         bp.update(pid, 0, None).unwrap();
@@ -65,7 +60,7 @@ mod tests {
 
     #[test]
     fn distinct_pages_independent() {
-        let (bp, _dir) = make_bp();
+        let (bp, _storage) = make_bp();
         let p0 = PageId::new(0, 0);
         let p1 = PageId::new(1, 0);
         bp.write(p0, Some(1), 0).unwrap();
@@ -76,7 +71,7 @@ mod tests {
 
     #[test]
     fn eviction_and_reload() {
-        let (bp, _dir) = make_bp();
+        let (bp, _storage) = make_bp();
         // Write to more pages than BP_CAP to force eviction
         for i in 0..(BP_CAP + 10) {
             let pid = PageId::new(i, 0);
@@ -91,14 +86,93 @@ mod tests {
 
     #[test]
     fn evict_all_flushes_dirty_pages() {
-        let (bp, dir) = make_bp();
+        let (bp, storage) = make_bp();
         let pid = PageId::new(0, 0);
         bp.write(pid, Some(123), 0).unwrap();
         bp.evict_all().unwrap();
 
-        // Reload via a fresh pool pointed at the same dir
-        let dm = Arc::new(parking_lot::RwLock::new(DiskManager::new(&dir).unwrap()));
-        let bp2 = BufferPool::new(dm);
+        // Reload via a fresh pool over the same storage
+        let bp2 = BufferPool::new(storage);
         assert_eq!(bp2.read(pid, 0).unwrap(), Some(123));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn pinned_page_is_not_evicted() {
+        let (bp, storage) = make_bp();
+        let pid = PageId::new(0, 0);
+        let page = bp.fetch_page(pid).unwrap();
+        page.write().write(Some(7), 0).unwrap();
+
+        for i in 1..(3 * BP_CAP) {
+            bp.write(PageId::new(i, 0), Some(i as i64), 0).unwrap();
+        }
+        // Never written back, so it never left its frame.
+        assert!(!storage.page_exists(pid));
+        assert_eq!(page.read().read(0).unwrap(), Some(7));
+
+        drop(page);
+        for i in 1..(3 * BP_CAP) {
+            bp.read(PageId::new(i, 0), 0).unwrap();
+        }
+        assert!(storage.page_exists(pid));
+        assert_eq!(bp.read(pid, 0).unwrap(), Some(7));
+    }
+
+    #[test]
+    fn fetch_fails_when_every_frame_is_pinned() {
+        let (bp, _storage) = make_bp();
+        let mut pages: Vec<_> = (0..BP_CAP).map(|i| bp.fetch_page(PageId::new(i, 0)).unwrap()).collect();
+        // Pages already in the pool can still be pinned again.
+        assert_eq!(bp.fetch_page(PageId::new(0, 0)).unwrap().pid(), PageId::new(0, 0));
+
+        let extra = PageId::new(BP_CAP, 0);
+        assert!(matches!(bp.fetch_page(extra), Err(BufferPoolError::AllFramesPinned)));
+        pages.pop();
+        bp.write(extra, Some(1), 0).unwrap();
+        assert_eq!(bp.read(extra, 0).unwrap(), Some(1));
+    }
+
+    #[test]
+    fn resize_keeps_pinned_frames() {
+        let (bp, _storage) = make_bp();
+        let pid = PageId::new(0, 0);
+        // The first page loaded takes the last frame.
+        let page = bp.fetch_page(pid).unwrap();
+        match bp.resize(BP_CAP / 2) {
+            Err(BufferPoolError::PagePinned(p)) => assert_eq!(p, pid),
+            other => panic!("expected the pinned page to stop the resize, got {:?}", other),
+        }
+        assert_eq!(bp.capacity(), BP_CAP);
+
+        drop(page);
+        bp.resize(BP_CAP / 2).unwrap();
+        assert_eq!(bp.capacity(), BP_CAP / 2);
+    }
+
+    #[test]
+    fn write_guard_is_exclusive() {
+        let (bp, _storage) = make_bp();
+        let pid = PageId::new(0, 0);
+        bp.write(pid, Some(0), 0).unwrap();
+
+        let workers: Vec<_> = (0..8)
+            .map(|t| {
+                let bp = bp.clone();
+                std::thread::spawn(move || {
+                    for i in 0..200 {
+                        // Churn the pool so the page would be evicted if it could be.
+                        bp.write(PageId::new(1 + (t * 200 + i) % (2 * BP_CAP), 1), None, 0).unwrap();
+                        let page = bp.fetch_page(pid).unwrap();
+                        let mut page = page.write();
+                        let n = page.read(0).unwrap().unwrap();
+                        page.update(0, Some(n + 1)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert_eq!(bp.read(pid, 0).unwrap(), Some(1600));
+    }
+}
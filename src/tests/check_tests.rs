@@ -0,0 +1,172 @@
+use crate::check::{Problem, check};
+use crate::config::DatabaseConfig;
+use crate::db::Database;
+use crate::query::Query;
+use std::fs;
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+/// A closed database with updates and deletes. `tamper` runs on the open table
+/// right before the close.
+fn build(dir: &TempDir, tamper: impl FnOnce(&crate::table::Table)) -> PathBuf {
+    let mut db = Database::new();
+    db.open(dir.path().to_str().unwrap()).unwrap();
+    db.create_table("t".into(), 2, 0).unwrap();
+    let t = db.get_table("t").unwrap();
+    for key in 0..600 {
+        Query::new(t.clone())
+            .insert(vec![Some(key), Some(key)])
+            .unwrap();
+    }
+    for key in 0..50 {
+        Query::new(t.clone())
+            .update(key, vec![None, Some(-key)])
+            .unwrap();
+    }
+    Query::new(t.clone())
+        .update(7, vec![None, Some(77)])
+        .unwrap();
+    Query::new(t.clone()).delete(9).unwrap();
+    tamper(&t);
+    drop(t);
+    db.close().unwrap();
+    db.path.clone().unwrap()
+}
+
+#[test]
+fn healthy_database_is_consistent() {
+    let dir = TempDir::new().unwrap();
+    let root = build(&dir, |_| {});
+
+    let report = check(&root, false).unwrap();
+    assert!(report.clean_shutdown);
+    assert_eq!(report.problems, vec![]);
+    assert!(report.is_consistent());
+    let summary = &report.tables[0];
+    assert_eq!(
+        (
+            summary.base_records,
+            summary.tail_records,
+            summary.live_records
+        ),
+        (600, 52, 599)
+    );
+    assert!(report.to_string().contains("problems: 0"));
+}
+
+#[test]
+fn index_and_directory_problems_are_repaired() {
+    let dir = TempDir::new().unwrap();
+    let root = build(&dir, |t| {
+        let rid = t.rid_for_key(3).unwrap();
+        t.indices[t.key_index].remove(3, rid);
+        t.indices[t.key_index].insert(9, rid);
+        let tail = t
+            .read_base_indirection(t.rid_for_key(7).unwrap())
+            .unwrap()
+            .unwrap();
+        t.page_directory.delete(tail).unwrap();
+    });
+
+    let report = check(&root, false).unwrap();
+    assert!(
+        report
+            .problems
+            .iter()
+            .any(|p| matches!(p, Problem::MissingIndexEntry { key: 3, .. }))
+    );
+    assert!(
+        report
+            .problems
+            .iter()
+            .any(|p| matches!(p, Problem::BadIndexEntry { key: 9, .. }))
+    );
+    assert!(
+        report
+            .problems
+            .iter()
+            .any(|p| matches!(p, Problem::MissingDirectoryEntry { .. }))
+    );
+    assert!(report.problems.iter().all(Problem::repairable));
+    assert!(!report.is_consistent());
+
+    let report = check(&root, true).unwrap();
+    assert_eq!(report.repaired, vec![0]);
+    assert!(report.is_consistent());
+    assert!(report.to_string().contains("[repaired]"));
+    assert_eq!(check(&root, false).unwrap().problems, vec![]);
+
+    let mut db = Database::new();
+    db.open(dir.path().to_str().unwrap()).unwrap();
+    let t = db.get_table("t").unwrap();
+    let proj = [1i64, 1];
+    assert_eq!(
+        Query::new(t.clone()).select(3, 0, &proj).unwrap(),
+        vec![vec![Some(3), Some(-3)]]
+    );
+    assert_eq!(
+        Query::new(t.clone()).select(7, 0, &proj).unwrap(),
+        vec![vec![Some(7), Some(77)]]
+    );
+    assert_eq!(
+        Query::new(t).select(9, 0, &proj).unwrap(),
+        Vec::<Vec<Option<i64>>>::new()
+    );
+    db.close().unwrap();
+}
+
+#[test]
+fn broken_chain_is_reported_but_not_repaired() {
+    let dir = TempDir::new().unwrap();
+    let root = build(&dir, |t| {
+        t.write_base_indirection(t.rid_for_key(20).unwrap(), 1_000_000)
+            .unwrap();
+    });
+
+    let report = check(&root, true).unwrap();
+    assert!(matches!(
+        report.problems[..],
+        [Problem::BrokenChain { at: 1_000_000, .. }]
+    ));
+    assert!(report.repaired.is_empty());
+    assert!(!report.is_consistent());
+}
+
+#[test]
+fn unreadable_metadata_is_named() {
+    let dir = TempDir::new().unwrap();
+    let root = build(&dir, |_| {});
+    let path = root.join("table").join("0").join("page_directory.1.bin");
+    let mut raw = fs::read(&path).unwrap();
+    raw[10] ^= 1;
+    fs::write(&path, raw).unwrap();
+
+    let report = check(&root, false).unwrap();
+    assert!(report.problems.iter().any(|p| matches!(
+        p,
+        Problem::Unreadable {
+            table_id: Some(0),
+            file: "page_directory",
+            ..
+        }
+    )));
+    assert!(check(&root, true).unwrap().is_consistent());
+    assert_eq!(check(&root, false).unwrap().problems, vec![]);
+}
+
+#[test]
+fn unclean_database_is_not_repaired() {
+    let dir = TempDir::new().unwrap();
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open(dir.path().to_str().unwrap()).unwrap();
+    db.create_table("t".into(), 2, 0).unwrap();
+    Query::new(db.get_table("t").unwrap())
+        .insert(vec![Some(1), Some(1)])
+        .unwrap();
+    let root = db.path.clone().unwrap();
+    std::mem::forget(db);
+
+    let report = check(&root, true).unwrap();
+    assert!(!report.clean_shutdown);
+    assert!(report.repaired.is_empty());
+}
@@ -0,0 +1,188 @@
+use crate::bufferpool::BufferPool;
+use crate::config::{DatabaseConfig, EvictionKind, FlusherConfig, MergeConfig};
+use crate::db::Database;
+use crate::errors::DbError;
+use crate::page_collection::PageId;
+use crate::query::Query;
+use crate::storage::MemoryBackend;
+use std::fs;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+const WRITERS: i64 = 4;
+const ROWS_PER_WRITER: i64 = 300;
+
+fn open(dir: &TempDir, config: DatabaseConfig) -> Database {
+    let mut db = Database::with_config(config).unwrap();
+    db.open(dir.path().to_str().unwrap()).unwrap();
+    db
+}
+
+/// Simulates the process being killed: nothing is flushed and `close` never
+/// runs. Only safe without background work, which would carry on.
+fn crash(db: Database) {
+    std::mem::forget(db);
+}
+
+fn log_len(db: &Database) -> u64 {
+    fs::metadata(db.path.as_ref().unwrap().join("wal.log")).unwrap().len()
+}
+
+fn wait_for(what: &str, mut done: impl FnMut() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !done() {
+        assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+/// Every writer inserts its own keys and then negates every third one.
+fn write_concurrently(db: &Arc<Database>) -> Vec<thread::JoinHandle<()>> {
+    (0..WRITERS)
+        .map(|w| {
+            let db = db.clone();
+            thread::spawn(move || {
+                let t = db.get_table("t").unwrap();
+                let keys = w * ROWS_PER_WRITER..(w + 1) * ROWS_PER_WRITER;
+                for key in keys.clone() {
+                    Query::new(t.clone()).insert(vec![Some(key), Some(key)]).unwrap();
+                }
+                for key in keys.step_by(3) {
+                    Query::new(t.clone()).update(key, vec![None, Some(-key)]).unwrap();
+                }
+            })
+        })
+        .collect()
+}
+
+fn assert_written(db: &Database) {
+    let t = db.get_table("t").unwrap();
+    for key in 0..WRITERS * ROWS_PER_WRITER {
+        let expected = if (key % ROWS_PER_WRITER) % 3 == 0 { -key } else { key };
+        assert_eq!(
+            Query::new(t.clone()).select(key, 0, &[1, 1]).unwrap(),
+            vec![vec![Some(key), Some(expected)]],
+            "key {}",
+            key
+        );
+    }
+}
+
+#[test]
+fn zero_intervals_are_rejected() {
+    let flusher = FlusherConfig { interval: Duration::ZERO, ..FlusherConfig::default() };
+    let no_budget = FlusherConfig { max_pages: 0, ..FlusherConfig::default() };
+    for config in [
+        DatabaseConfig { flusher: Some(flusher), ..DatabaseConfig::default() },
+        DatabaseConfig { flusher: Some(no_budget), ..DatabaseConfig::default() },
+        DatabaseConfig { checkpoint_interval: Some(Duration::ZERO), ..DatabaseConfig::default() },
+        DatabaseConfig { merge: Some(MergeConfig { interval: Duration::ZERO, ..MergeConfig::default() }), ..DatabaseConfig::default() },
+        DatabaseConfig { merge: Some(MergeConfig { chain_length: 0, ..MergeConfig::default() }), ..DatabaseConfig::default() },
+    ] {
+        assert!(matches!(Database::with_config(config), Err(DbError::InvalidConfig(_))), "{:?}", config);
+    }
+}
+
+#[test]
+fn background_writer_keeps_victims_clean() {
+    let flusher = FlusherConfig {
+        interval: Duration::from_millis(1),
+        max_pages: 4,
+        clean_frames: 16,
+    };
+    let bp = BufferPool::with_options(Arc::new(MemoryBackend::new()), 32, EvictionKind::Arc, Some(flusher));
+    for page_num in 0..32 {
+        bp.write(PageId::new(page_num, 0), Some(page_num as i64), 0).unwrap();
+    }
+    // Only the next victims get written, a few at a time.
+    wait_for("the victims to be written", || bp.flush_stats().background_writes >= 16);
+    thread::sleep(Duration::from_millis(20));
+    assert_eq!(bp.flush_stats().background_writes, 16);
+
+    for page_num in 32..48 {
+        bp.read(PageId::new(page_num, 0), 0).unwrap();
+    }
+    assert_eq!(bp.flush_stats().victim_writes, 0);
+    for page_num in 0..32 {
+        assert_eq!(bp.read(PageId::new(page_num, 0), 0).unwrap(), Some(page_num as i64));
+    }
+}
+
+#[test]
+fn checkpoint_cuts_the_log() {
+    let dir = TempDir::new().unwrap();
+    let db = open(&dir, DatabaseConfig::synchronous());
+    db.create_table("t".into(), 2, 0).unwrap();
+    let t = db.get_table("t").unwrap();
+    for key in 0..100 {
+        Query::new(t.clone()).insert(vec![Some(key), Some(key)]).unwrap();
+    }
+    assert!(log_len(&db) > 0);
+
+    db.checkpoint().unwrap();
+    assert_eq!(log_len(&db), 0);
+    assert_eq!(db.checkpoint_stats().taken, 1);
+    Query::new(t.clone()).update(7, vec![None, Some(-7)]).unwrap();
+    drop(t);
+    crash(db);
+
+    // The checkpoint has the rows, what little is left in the log the update.
+    let db = open(&dir, DatabaseConfig::synchronous());
+    let t = db.get_table("t").unwrap();
+    for key in 0..100 {
+        let expected = if key == 7 { -7 } else { key };
+        assert_eq!(Query::new(t.clone()).select(key, 0, &[1, 1]).unwrap(), vec![vec![Some(key), Some(expected)]]);
+    }
+}
+
+#[test]
+fn checkpoints_taken_while_writing_survive_a_crash() {
+    let dir = TempDir::new().unwrap();
+    let db = open(&dir, DatabaseConfig::synchronous());
+    db.create_table("t".into(), 2, 0).unwrap();
+    let db = Arc::new(db);
+
+    let writers = write_concurrently(&db);
+    while !writers.iter().all(|w| w.is_finished()) {
+        db.checkpoint().unwrap();
+    }
+    for writer in writers {
+        writer.join().unwrap();
+    }
+    assert!(db.checkpoint_stats().taken > 0);
+    crash(Arc::into_inner(db).unwrap());
+
+    let db = open(&dir, DatabaseConfig::synchronous());
+    assert_written(&db);
+}
+
+#[test]
+fn background_checkpoints_run_until_close() {
+    let dir = TempDir::new().unwrap();
+    let config = DatabaseConfig {
+        checkpoint_interval: Some(Duration::from_millis(5)),
+        ..DatabaseConfig::default()
+    };
+    let db = open(&dir, config);
+    db.create_table("t".into(), 2, 0).unwrap();
+    let db = Arc::new(db);
+
+    for writer in write_concurrently(&db) {
+        writer.join().unwrap();
+    }
+    let before = db.checkpoint_stats().taken;
+    wait_for("a checkpoint after the writes", || db.checkpoint_stats().taken > before);
+    assert_eq!(log_len(&db), 0);
+    assert_eq!(db.checkpoint_stats().failed, 0);
+
+    db.close().unwrap();
+    let closed = db.checkpoint_stats();
+    thread::sleep(Duration::from_millis(30));
+    assert_eq!(db.checkpoint_stats(), closed);
+    drop(db);
+
+    let db = open(&dir, DatabaseConfig::synchronous());
+    assert_written(&db);
+}
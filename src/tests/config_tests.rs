@@ -0,0 +1,135 @@
+use crate::bufferpool::{BufferPool, BufferPoolError, BP_CAP};
+use crate::config::{DatabaseConfig, PoolSize};
+use crate::db::Database;
+use crate::errors::DbError;
+use crate::page_collection::PageId;
+use crate::query::Query;
+use crate::storage::MemoryBackend;
+use std::sync::Arc;
+
+const ROWS: i64 = 2000;
+
+fn with_pool(size: PoolSize) -> Database {
+    Database::with_config(DatabaseConfig { buffer_pool: size, ..Default::default() }).unwrap()
+}
+
+fn fill(db: &Database) {
+    db.create_table("t".into(), 3, 0).unwrap();
+    let t = db.get_table("t").unwrap();
+    for key in 0..ROWS {
+        Query::new(t.clone()).insert(vec![Some(key), Some(key * 10), None]).unwrap();
+    }
+    for key in (0..ROWS).step_by(7) {
+        Query::new(t.clone()).update(key, vec![None, None, Some(-key)]).unwrap();
+    }
+}
+
+fn assert_rows(db: &Database) {
+    let t = db.get_table("t").unwrap();
+    for key in 0..ROWS {
+        let last = if key % 7 == 0 { Some(-key) } else { None };
+        assert_eq!(
+            Query::new(t.clone()).select(key, 0, &[1, 1, 1]).unwrap(),
+            vec![vec![Some(key), Some(key * 10), last]],
+            "key {}",
+            key
+        );
+    }
+}
+
+#[test]
+fn default_config_keeps_the_old_capacity() {
+    assert_eq!(Database::new().buffer_pool_frames(), BP_CAP);
+    assert!(DatabaseConfig::default().validate().is_ok());
+}
+
+#[test]
+fn byte_budget_rounds_down_to_frames() {
+    let bytes = 100 * BufferPool::FRAME_BYTES + BufferPool::FRAME_BYTES - 1;
+    assert_eq!(PoolSize::Bytes(bytes).frames().unwrap(), 100);
+    assert_eq!(with_pool(PoolSize::Bytes(bytes)).buffer_pool_frames(), 100);
+}
+
+#[test]
+fn too_small_or_too_large_pools_are_rejected() {
+    for size in [
+        PoolSize::Frames(0),
+        PoolSize::Frames(PoolSize::MIN_FRAMES - 1),
+        PoolSize::Bytes(BufferPool::FRAME_BYTES),
+        PoolSize::Frames(usize::MAX),
+    ] {
+        assert!(matches!(
+            Database::with_config(DatabaseConfig { buffer_pool: size, ..Default::default() }),
+            Err(DbError::InvalidConfig(_))
+        ), "{:?}", size);
+    }
+    assert_eq!(PoolSize::Frames(PoolSize::MIN_FRAMES).frames().unwrap(), PoolSize::MIN_FRAMES);
+}
+
+#[test]
+fn small_pool_holds_more_pages_than_frames() {
+    let db = with_pool(PoolSize::Frames(PoolSize::MIN_FRAMES));
+    fill(&db);
+    assert_rows(&db);
+}
+
+#[test]
+fn shrinking_writes_back_dirty_pages() {
+    let mut db = Database::new();
+    fill(&db);
+    db.resize_buffer_pool(PoolSize::Frames(PoolSize::MIN_FRAMES)).unwrap();
+    assert_eq!(db.buffer_pool_frames(), PoolSize::MIN_FRAMES);
+    assert_rows(&db);
+}
+
+#[test]
+fn growing_keeps_cached_pages() {
+    let mut db = with_pool(PoolSize::Frames(PoolSize::MIN_FRAMES));
+    fill(&db);
+    db.resize_buffer_pool(PoolSize::Frames(4 * BP_CAP)).unwrap();
+    assert_eq!(db.buffer_pool_frames(), 4 * BP_CAP);
+    assert_rows(&db);
+
+    // And back down again, past what the first shrink left.
+    db.resize_buffer_pool(PoolSize::Frames(PoolSize::MIN_FRAMES + 1)).unwrap();
+    assert_rows(&db);
+}
+
+#[test]
+fn rejected_resize_leaves_the_pool_alone() {
+    let mut db = Database::new();
+    fill(&db);
+    assert!(matches!(
+        db.resize_buffer_pool(PoolSize::Frames(1)),
+        Err(DbError::InvalidConfig(_))
+    ));
+    assert_eq!(db.buffer_pool_frames(), BP_CAP);
+    assert_rows(&db);
+}
+
+#[test]
+fn resize_while_pages_are_in_use() {
+    let bp = Arc::new(BufferPool::with_capacity(Arc::new(MemoryBackend::new()), BP_CAP));
+    for page_num in 0..BP_CAP {
+        bp.write(PageId::new(page_num, 0), Some(page_num as i64), 0).unwrap();
+    }
+
+    let reader = {
+        let bp = bp.clone();
+        std::thread::spawn(move || {
+            for _ in 0..20 {
+                for page_num in 0..BP_CAP {
+                    assert_eq!(bp.read(PageId::new(page_num, 0), 0).unwrap(), Some(page_num as i64));
+                }
+            }
+        })
+    };
+    for capacity in [PoolSize::MIN_FRAMES, BP_CAP * 2, 40, BP_CAP] {
+        // The reader's page may be pinned in a frame that would go.
+        while let Err(e) = bp.resize(capacity) {
+            assert!(matches!(e, BufferPoolError::PagePinned(_)), "{:?}", e);
+        }
+    }
+    reader.join().unwrap();
+    assert_eq!(bp.capacity(), BP_CAP);
+}
@@ -0,0 +1,333 @@
+use crate::db::Database;
+use crate::disk_manager::DiskManager;
+use crate::bufferpool::DiskError;
+use crate::checksum::crc32c;
+use crate::disk_manager::CorruptionPolicy;
+use crate::page::Page;
+use crate::page_collection::PageId;
+use crate::segment::Segment;
+use crate::storage::StorageBackend;
+use crate::query::Query;
+use dashmap::DashMap;
+use std::fs;
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+fn names(pairs: &[(&str, usize)]) -> DashMap<String, usize> {
+    pairs.iter().map(|(n, id)| (n.to_string(), *id)).collect()
+}
+
+fn sorted(mut pairs: Vec<(String, usize)>) -> Vec<(String, usize)> {
+    pairs.sort();
+    pairs
+}
+
+#[test]
+fn uncommitted_generation_is_invisible() {
+    let dir = TempDir::new().unwrap();
+    let dm = DiskManager::new(dir.path()).unwrap();
+    dm.begin_generation();
+    dm.write_table_names(&names(&[("a", 0)])).unwrap();
+    dm.commit_generation().unwrap();
+
+    // A checkpoint that dies before moving the manifest.
+    dm.begin_generation();
+    dm.write_table_names(&names(&[("a", 0), ("b", 1)])).unwrap();
+    fs::write(dir.path().join("catalog.tmp"), [0u8; 3]).unwrap();
+    drop(dm);
+
+    let dm = DiskManager::new(dir.path()).unwrap();
+    assert_eq!(dm.read_table_names().unwrap(), vec![("a".to_string(), 0)]);
+    assert_eq!(dm.read_tables().unwrap().0.len(), 0);
+}
+
+#[test]
+fn commit_removes_previous_generation() {
+    let dir = TempDir::new().unwrap();
+    let dm = DiskManager::new(dir.path()).unwrap();
+    for round in 0..3 {
+        dm.begin_generation();
+        dm.write_table_names(&names(&[("a", round)])).unwrap();
+        dm.commit_generation().unwrap();
+    }
+
+    let files: Vec<_> = fs::read_dir(dir.path())
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .filter(|n| n.starts_with("table_names"))
+        .collect();
+    assert_eq!(files, vec!["table_names.3.bin".to_string()]);
+    assert_eq!(dm.read_table_names().unwrap(), vec![("a".to_string(), 2)]);
+}
+
+#[test]
+fn legacy_layout_without_manifest_opens() {
+    let dir = TempDir::new().unwrap();
+    let mut db = Database::new();
+    db.open(dir.path().to_str().unwrap()).unwrap();
+    db.create_table("t".into(), 2, 0).unwrap();
+    db.create_table("u".into(), 1, 0).unwrap();
+    Query::new(db.get_table("t").unwrap()).insert(vec![Some(1), Some(10)]).unwrap();
+    db.close().unwrap();
+    let root: PathBuf = db.path.clone().unwrap();
+    drop(db);
+
+    // Rewrite the files the way they were named before generations existed.
+    fs::remove_file(root.join("MANIFEST")).unwrap();
+    let mut dirs = vec![root.clone()];
+    dirs.extend(fs::read_dir(root.join("table")).unwrap().map(|e| e.unwrap().path()));
+    for d in dirs {
+        for entry in fs::read_dir(&d).unwrap() {
+            let name = entry.unwrap().file_name().into_string().unwrap();
+            if let Some(stem) = name.strip_suffix(".1.bin") {
+                fs::rename(d.join(&name), d.join(format!("{stem}.bin"))).unwrap();
+            }
+        }
+    }
+
+    let mut db = Database::new();
+    db.open(dir.path().to_str().unwrap()).unwrap();
+    let proj = [1i64, 1];
+    let rows = Query::new(db.get_table("t").unwrap()).select(1, 0, &proj).unwrap();
+    assert_eq!(rows, vec![vec![Some(1), Some(10)]]);
+    assert!(db.get_table("u").is_some());
+    db.close().unwrap();
+
+    let dm = DiskManager::new(&root).unwrap();
+    assert_eq!(
+        sorted(dm.read_table_names().unwrap()),
+        vec![("t".to_string(), 0), ("u".to_string(), 1)]
+    );
+    assert!(!root.join("catalog.bin").exists());
+}
+
+/// A page in the format of the old one-file-per-page layout.
+fn legacy_page_file(values: &[Option<i64>]) -> Vec<u8> {
+    let mut buf = (values.len() as u64).to_be_bytes().to_vec();
+    for value in values {
+        match value {
+            Some(v) => {
+                buf.push(1);
+                buf.extend_from_slice(&v.to_be_bytes());
+            }
+            None => buf.push(0),
+        }
+    }
+    buf
+}
+
+#[test]
+fn page_files_migrate_into_segment() {
+    let dir = TempDir::new().unwrap();
+    let table_dir = dir.path().join("table").join("2");
+    fs::create_dir_all(&table_dir).unwrap();
+    fs::write(table_dir.join("5"), legacy_page_file(&[Some(7), None, Some(-1)])).unwrap();
+    fs::write(table_dir.join("0"), legacy_page_file(&[Some(1)])).unwrap();
+
+    let dm = DiskManager::new(dir.path()).unwrap();
+    assert_eq!(dm.migrate_page_files().unwrap(), 2);
+    assert!(!table_dir.join("5").exists());
+    assert!(!dm.page_exists(PageId { table_id: 2, page_num: 1 }));
+
+    let page = dm.read_page(PageId { table_id: 2, page_num: 5 }).unwrap();
+    assert_eq!(page.len(), 3);
+    assert_eq!((page.read(0).unwrap(), page.read(1).unwrap(), page.read(2).unwrap()), (Some(7), None, Some(-1)));
+    drop(dm);
+
+    // Nothing left to move, and the pages are still there after a reopen.
+    let dm = DiskManager::new(dir.path()).unwrap();
+    assert_eq!(dm.migrate_page_files().unwrap(), 0);
+    assert_eq!(dm.read_page(PageId { table_id: 2, page_num: 0 }).unwrap().read(0).unwrap(), Some(1));
+}
+
+#[test]
+fn database_with_page_files_opens() {
+    let dir = TempDir::new().unwrap();
+    let mut db = Database::new();
+    db.open(dir.path().to_str().unwrap()).unwrap();
+    db.create_table("t".into(), 2, 0).unwrap();
+    let t = db.get_table("t").unwrap();
+    for key in 0..1500 {
+        Query::new(t.clone()).insert(vec![Some(key), Some(key * 2)]).unwrap();
+    }
+    Query::new(t.clone()).update(3, vec![None, Some(-3)]).unwrap();
+    drop(t);
+    db.close().unwrap();
+    let root: PathBuf = db.path.clone().unwrap();
+    drop(db);
+
+    // Unpack the segment back into one file per page.
+    let table_dir = root.join("table").join("0");
+    let dm = DiskManager::new(&root).unwrap();
+    let mut page_num = 0;
+    while dm.page_exists(PageId { table_id: 0, page_num }) {
+        let page = dm.read_page(PageId { table_id: 0, page_num }).unwrap();
+        let values: Vec<_> = (0..page.len()).map(|i| page.read(i).unwrap()).collect();
+        fs::write(table_dir.join(page_num.to_string()), legacy_page_file(&values)).unwrap();
+        page_num += 1;
+    }
+    drop(dm);
+    assert!(page_num > 2);
+    fs::remove_file(table_dir.join(Segment::DATA_FILE)).unwrap();
+    fs::remove_file(table_dir.join(Segment::MAP_FILE)).unwrap();
+
+    let mut db = Database::new();
+    db.open(dir.path().to_str().unwrap()).unwrap();
+    let t = db.get_table("t").unwrap();
+    let proj = [1i64, 1];
+    assert_eq!(Query::new(t.clone()).select(3, 0, &proj).unwrap(), vec![vec![Some(3), Some(-3)]]);
+    assert_eq!(Query::new(t.clone()).select(1499, 0, &proj).unwrap(), vec![vec![Some(1499), Some(2998)]]);
+    drop(t);
+    db.close().unwrap();
+    assert!(!table_dir.join("0").exists());
+}
+
+#[test]
+fn page_layout_is_fixed_width() {
+    let dir = TempDir::new().unwrap();
+    let dm = DiskManager::new(dir.path()).unwrap();
+    let mut page = Page::default();
+    page.write(Some(-2), 0).unwrap();
+    page.write(None, 1).unwrap();
+    page.write(Some(0x0102), 2).unwrap();
+    dm.write_page(PageId { table_id: 0, page_num: 1 }, &page).unwrap();
+
+    let raw = fs::read(dir.path().join("table").join("0").join(Segment::DATA_FILE)).unwrap();
+    assert_eq!(raw.len(), 2 * DiskManager::PAGE_BYTES);
+    let slot = &raw[DiskManager::PAGE_BYTES..];
+    assert_eq!(slot[0..4], DiskManager::PAGE_FORMAT_VERSION.to_le_bytes());
+    assert_eq!(slot[4..8], 3u32.to_le_bytes());
+    assert_eq!(slot[8..12], crc32c(&[&slot[..8], &[0; 4], &slot[12..]].concat()).to_le_bytes());
+    assert_eq!(slot[16], 0b101);
+    let values = 16 + Page::BITMAP_BYTES;
+    assert_eq!(slot[values..values + 8], (-2i64).to_le_bytes());
+    assert_eq!(slot[values + 16..values + 24], 0x0102i64.to_le_bytes());
+
+    let read = dm.read_page(PageId { table_id: 0, page_num: 1 }).unwrap();
+    assert_eq!(read.len(), 3);
+    assert_eq!((read.read(0).unwrap(), read.read(1).unwrap(), read.read(2).unwrap()), (Some(-2), None, Some(0x0102)));
+}
+
+#[test]
+fn unknown_page_version_is_corruption() {
+    let dir = TempDir::new().unwrap();
+    let dm = DiskManager::new(dir.path()).unwrap();
+    let pid = PageId { table_id: 0, page_num: 0 };
+    dm.write_page(pid, &Page::default()).unwrap();
+    drop(dm);
+
+    let path = dir.path().join("table").join("0").join(Segment::DATA_FILE);
+    // An older version, which has no checksum to check, as much as any other.
+    for version in [9u32, 1] {
+        let mut raw = fs::read(&path).unwrap();
+        raw[0..4].copy_from_slice(&version.to_le_bytes());
+        fs::write(&path, raw).unwrap();
+
+        let dm = DiskManager::new(dir.path()).unwrap();
+        assert!(matches!(dm.read_page(pid), Err(DiskError::ChecksumMismatch(_))));
+        dm.set_corruption_policy(CorruptionPolicy::Quarantine);
+        assert_eq!(dm.read_page(pid).unwrap().len(), 0);
+        assert_eq!(dm.quarantined().len(), 1);
+    }
+}
+
+#[test]
+fn crc32c_matches_reference() {
+    assert_eq!(crc32c(b"123456789"), 0xE306_9283);
+    assert_eq!(crc32c(b""), 0);
+}
+
+fn flip_byte(path: &std::path::Path, offset: usize) {
+    let mut raw = fs::read(path).unwrap();
+    raw[offset] ^= 0x10;
+    fs::write(path, raw).unwrap();
+}
+
+#[test]
+fn corrupted_page_is_detected() {
+    let dir = TempDir::new().unwrap();
+    let dm = DiskManager::new(dir.path()).unwrap();
+    let pid = PageId { table_id: 4, page_num: 2 };
+    let mut page = Page::default();
+    page.write(Some(77), 0).unwrap();
+    dm.write_page(pid, &page).unwrap();
+    drop(dm);
+
+    let seg = dir.path().join("table").join("4").join(Segment::DATA_FILE);
+    flip_byte(&seg, 2 * DiskManager::PAGE_BYTES + 16 + Page::BITMAP_BYTES);
+
+    let dm = DiskManager::new(dir.path()).unwrap();
+    match dm.read_page(pid) {
+        Err(DiskError::ChecksumMismatch(c)) => {
+            assert_eq!((c.table_id, c.page_num), (Some(4), Some(2)));
+            assert_ne!(c.expected, c.actual);
+        }
+        other => panic!("expected a checksum mismatch, got {:?}", other.map(|p| p.len())),
+    }
+
+    dm.set_corruption_policy(CorruptionPolicy::Quarantine);
+    let read = dm.read_page(pid).unwrap();
+    assert_eq!((read.len(), read.read(0).unwrap()), (0, None));
+    assert_eq!(dm.quarantined().len(), 1);
+    assert!(dir.path().join("quarantine").join("table-4-page-2.bin").exists());
+}
+
+fn closed_db_with_rows(dir: &TempDir) -> PathBuf {
+    let mut db = Database::new();
+    db.open(dir.path().to_str().unwrap()).unwrap();
+    db.create_table("t".into(), 2, 0).unwrap();
+    let t = db.get_table("t").unwrap();
+    for key in 0..20 {
+        Query::new(t.clone()).insert(vec![Some(key), Some(-key)]).unwrap();
+    }
+    Query::new(t).update(5, vec![None, Some(55)]).unwrap();
+    db.close().unwrap();
+    db.path.clone().unwrap()
+}
+
+#[test]
+fn corrupted_metadata_fails_open_by_default() {
+    let dir = TempDir::new().unwrap();
+    let root = closed_db_with_rows(&dir);
+    flip_byte(&root.join("table").join("0").join("primary_index.1.bin"), 12);
+
+    let mut db = Database::new();
+    match db.open(dir.path().to_str().unwrap()) {
+        Err(crate::errors::DbError::Disk(DiskError::ChecksumMismatch(c))) => {
+            assert_eq!((c.table_id, c.page_num), (Some(0), None));
+            assert!(c.file.ends_with("primary_index.1.bin"));
+        }
+        other => panic!("expected a checksum mismatch, got {:?}", other),
+    }
+}
+
+#[test]
+fn corrupted_index_and_directory_are_rebuilt_under_quarantine() {
+    let dir = TempDir::new().unwrap();
+    let root = closed_db_with_rows(&dir);
+    let table_dir = root.join("table").join("0");
+    flip_byte(&table_dir.join("primary_index.1.bin"), 12);
+    flip_byte(&table_dir.join("page_directory.1.bin"), 20);
+
+    let mut db = Database::new();
+    db.set_corruption_policy(CorruptionPolicy::Quarantine);
+    db.open(dir.path().to_str().unwrap()).unwrap();
+    assert_eq!(db.quarantined().len(), 2);
+    let t = db.get_table("t").unwrap();
+    let proj = [1i64, 1];
+    assert_eq!(Query::new(t.clone()).select(5, 0, &proj).unwrap(), vec![vec![Some(5), Some(55)]]);
+    assert_eq!(Query::new(t.clone()).select(19, 0, &proj).unwrap(), vec![vec![Some(19), Some(-19)]]);
+    assert_eq!(Query::new(t).sum(0, 19, 1).unwrap(), -(0..20).sum::<i64>() + 5 + 55);
+    db.close().unwrap();
+}
+
+#[test]
+fn corrupted_counters_fail_even_under_quarantine() {
+    let dir = TempDir::new().unwrap();
+    let root = closed_db_with_rows(&dir);
+    flip_byte(&root.join("table").join("0").join("counters.1.bin"), 3);
+
+    let mut db = Database::new();
+    db.set_corruption_policy(CorruptionPolicy::Quarantine);
+    assert!(db.open(dir.path().to_str().unwrap()).is_err());
+}
@@ -0,0 +1,243 @@
+use crate::bufferpool::{AccessHint, AccessStats, BufferPool, BufferPoolError};
+use crate::config::{DatabaseConfig, EvictionKind, PoolSize};
+use crate::db::Database;
+use crate::errors::DbError;
+use crate::page_collection::PageId;
+use crate::query::Query;
+use crate::storage::MemoryBackend;
+use std::sync::Arc;
+use std::thread;
+
+const KINDS: [EvictionKind; 4] = [
+    EvictionKind::Arc,
+    EvictionKind::Clock,
+    EvictionKind::LruK(1),
+    EvictionKind::LruK(2),
+];
+
+fn pool(kind: EvictionKind, frames: usize) -> BufferPool {
+    BufferPool::with_options(Arc::new(MemoryBackend::new()), frames, kind, None)
+}
+
+fn pid(n: usize) -> PageId {
+    PageId::new(n, 0)
+}
+
+#[test]
+fn every_policy_keeps_pages_across_evictions() {
+    for kind in KINDS {
+        let bp = pool(kind, 16);
+        for n in 0..200 {
+            bp.write(pid(n), Some(n as i64), n % 8).unwrap();
+        }
+        for n in (0..200).rev() {
+            assert_eq!(bp.read(pid(n), n % 8).unwrap(), Some(n as i64), "{:?} page {}", kind, n);
+        }
+    }
+}
+
+#[test]
+fn every_policy_passes_over_pinned_frames() {
+    for kind in KINDS {
+        let bp = pool(kind, 16);
+        let pinned: Vec<_> = (0..15).map(|n| bp.fetch_page(pid(n)).unwrap()).collect();
+        for (n, guard) in pinned.iter().enumerate() {
+            guard.write().write(Some(n as i64), 0).unwrap();
+        }
+        // Only one frame is left to cycle through.
+        for n in 100..150 {
+            bp.write(pid(n), Some(n as i64), 0).unwrap();
+        }
+        for (n, guard) in pinned.iter().enumerate() {
+            assert_eq!(guard.read().read(0).unwrap(), Some(n as i64), "{:?}", kind);
+        }
+
+        let last = bp.fetch_page(pid(15)).unwrap();
+        assert!(
+            matches!(bp.fetch_page(pid(16)), Err(BufferPoolError::AllFramesPinned)),
+            "{:?}",
+            kind
+        );
+        drop(last);
+        assert_eq!(bp.read(pid(149), 0).unwrap(), Some(149));
+    }
+}
+
+/// Dirties four hot pages, then reads `scan` pages nobody writes to. Each hot
+/// page evicted along the way costs a victim write.
+fn hot_pages_evicted_by_scan(kind: EvictionKind, touch_every: Option<usize>) -> u64 {
+    let bp = pool(kind, 16);
+    let touch_hot = || {
+        for n in 0..4 {
+            bp.write(pid(n), Some(n as i64), 0).unwrap();
+        }
+    };
+    touch_hot();
+    touch_hot();
+    for n in 100..300 {
+        bp.read(pid(n), 0).unwrap();
+        if touch_every.is_some_and(|every| n % every == 0) {
+            touch_hot();
+        }
+    }
+    bp.flush_stats().victim_writes
+}
+
+#[test]
+fn lru_2_keeps_hot_pages_through_a_scan() {
+    assert_eq!(hot_pages_evicted_by_scan(EvictionKind::LruK(2), None), 0);
+    // Plain LRU lets the scan push them out.
+    assert!(hot_pages_evicted_by_scan(EvictionKind::LruK(1), None) > 0);
+}
+
+#[test]
+fn clock_gives_hot_pages_a_second_chance() {
+    assert_eq!(hot_pages_evicted_by_scan(EvictionKind::Clock, Some(8)), 0);
+}
+
+#[test]
+fn sequential_loads_leave_hot_pages_alone() {
+    for kind in KINDS {
+        let bp = pool(kind, 16);
+        for _ in 0..2 {
+            for n in 0..4 {
+                bp.write(pid(n), Some(n as i64), 0).unwrap();
+            }
+        }
+        for n in 100..300 {
+            bp.read_hinted(pid(n), 0, AccessHint::Sequential).unwrap();
+        }
+        for n in 0..4 {
+            assert_eq!(bp.read(pid(n), 0).unwrap(), Some(n as i64));
+        }
+        assert_eq!(bp.flush_stats().victim_writes, 0, "{:?}", kind);
+        assert_eq!(
+            bp.access_stats(),
+            AccessStats { hits: 8, loads: 204, sequential_hits: 0, sequential_loads: 200, ..Default::default() },
+            "{:?}",
+            kind
+        );
+    }
+}
+
+#[test]
+fn sequential_hits_do_not_make_pages_hot() {
+    // Read over and over by scans, these pages still go before a page that
+    // was used twice.
+    let bp = pool(EvictionKind::LruK(2), 16);
+    for n in 0..15 {
+        bp.read(pid(n), 0).unwrap();
+        for _ in 0..3 {
+            bp.read_hinted(pid(n), 0, AccessHint::Sequential).unwrap();
+        }
+    }
+    bp.write(pid(15), Some(15), 0).unwrap();
+    bp.write(pid(15), Some(15), 0).unwrap();
+    for n in 100..200 {
+        bp.read(pid(n), 0).unwrap();
+    }
+    assert_eq!(bp.flush_stats().victim_writes, 0);
+    assert_eq!(bp.access_stats().sequential_hits, 45);
+}
+
+#[test]
+fn scans_keep_point_lookups_in_the_pool() {
+    // Not LRU-K(2): a lookup reads its record a page at a time, and each page
+    // it loads goes before any used twice, so the record's pages push each
+    // other out before they are ever hot.
+    for kind in [EvictionKind::Arc, EvictionKind::Clock, EvictionKind::LruK(1)] {
+        let db = Database::with_config(DatabaseConfig {
+            buffer_pool: PoolSize::Frames(PoolSize::MIN_FRAMES),
+            eviction: kind,
+            ..DatabaseConfig::synchronous()
+        })
+        .unwrap();
+        db.create_table("t".into(), 3, 0).unwrap();
+        let t = db.get_table("t").unwrap();
+        for key in 0..5000 {
+            Query::new(t.clone()).insert(vec![Some(key), Some(1), Some(key)]).unwrap();
+        }
+        let lookup = || Query::new(t.clone()).select(0, 0, &[1, 1, 1]).unwrap();
+        lookup();
+        lookup();
+        let before = db.access_stats();
+
+        assert_eq!(Query::new(t.clone()).sum(0, 4999, 1).unwrap(), 5000);
+        // Without an index on it, selecting by column 2 scans the table.
+        t.indices[2].disable();
+        assert_eq!(Query::new(t.clone()).select(4999, 2, &[1, 0, 0]).unwrap(), vec![vec![Some(4999), None, None]]);
+        let scanned = db.access_stats();
+        assert!(scanned.sequential_loads > 0, "{:?}", kind);
+        // The scans cycle through a few frames rather than reload every page
+        // they come back to.
+        assert!(scanned.loads - before.loads < 100, "{:?} {:?}", kind, scanned);
+
+        lookup();
+        assert_eq!(db.access_stats().loads, scanned.loads, "{:?} reloaded the looked up record", kind);
+    }
+}
+
+#[test]
+fn concurrent_hits_and_loads_see_their_own_writes() {
+    for kind in KINDS {
+        let bp = Arc::new(pool(kind, 16));
+        let handles: Vec<_> = (0..8)
+            .map(|t| {
+                let bp = Arc::clone(&bp);
+                thread::spawn(move || {
+                    for round in 0..200i64 {
+                        // A hot page of its own, and a cold one it shares
+                        // with nobody.
+                        for n in [t, 8 + t * 32 + (round as usize % 32)] {
+                            let page = bp.fetch_page(pid(n)).unwrap();
+                            page.write().write(Some(round), 0).unwrap();
+                            assert_eq!(page.read().read(0).unwrap(), Some(round));
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        for t in 0..8 {
+            assert_eq!(bp.read(pid(t), 0).unwrap(), Some(199), "{:?}", kind);
+        }
+    }
+}
+
+#[test]
+fn lru_k_needs_k_of_at_least_one() {
+    let config = DatabaseConfig { eviction: EvictionKind::LruK(0), ..Default::default() };
+    assert!(matches!(Database::with_config(config), Err(DbError::InvalidConfig(_))));
+}
+
+#[test]
+fn every_policy_serves_queries_from_a_small_pool() {
+    for kind in KINDS {
+        let db = Database::with_config(DatabaseConfig {
+            buffer_pool: PoolSize::Frames(PoolSize::MIN_FRAMES),
+            eviction: kind,
+            ..DatabaseConfig::synchronous()
+        })
+        .unwrap();
+        db.create_table("t".into(), 3, 0).unwrap();
+        let t = db.get_table("t").unwrap();
+        for key in 0..1000 {
+            Query::new(t.clone()).insert(vec![Some(key), Some(key * 2), None]).unwrap();
+        }
+        for key in (0..1000).step_by(3) {
+            Query::new(t.clone()).update(key, vec![None, None, Some(-key)]).unwrap();
+        }
+        for key in 0..1000 {
+            let last = if key % 3 == 0 { Some(-key) } else { None };
+            assert_eq!(
+                Query::new(t.clone()).select(key, 0, &[1, 1, 1]).unwrap(),
+                vec![vec![Some(key), Some(key * 2), last]],
+                "{:?} key {}",
+                kind,
+                key
+            );
+        }
+    }
+}
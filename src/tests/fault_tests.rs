@@ -0,0 +1,343 @@
+use crate::bufferpool::{BufferPool, BufferPoolError, DiskError, DiskManager, BP_CAP};
+use crate::config::DatabaseConfig;
+use crate::db::Database;
+use crate::errors::DbError;
+use crate::fault::FaultyBackend;
+use crate::page_collection::PageId;
+use crate::query::Query;
+use crate::table::Table;
+use crate::transaction::{QueryOp, Transaction};
+use std::io;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tempfile::TempDir;
+
+const ROWS: i64 = 1000;
+
+fn open_faulty(dir: &TempDir) -> (Database, Arc<FaultyBackend>) {
+    let faulty = Arc::new(FaultyBackend::new(Arc::new(DiskManager::new(dir.path()).unwrap())));
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open_storage(faulty.clone()).unwrap();
+    (db, faulty)
+}
+
+/// A closed table of `ROWS` rows, reopened so every page has to come from disk.
+fn reopened(dir: &TempDir) -> (Database, Arc<FaultyBackend>) {
+    let (db, _) = open_faulty(dir);
+    db.create_table("t".into(), 2, 0).unwrap();
+    let t = db.get_table("t").unwrap();
+    for key in 0..ROWS {
+        Query::new(t.clone()).insert(vec![Some(key), Some(key * 10)]).unwrap();
+    }
+    drop(t);
+    db.close().unwrap();
+    drop(db);
+    open_faulty(dir)
+}
+
+fn select(db: &Database, key: i64) -> Result<Vec<Vec<Option<i64>>>, DbError> {
+    Query::new(db.get_table("t").unwrap()).select(key, 0, &[1, 1])
+}
+
+fn assert_io_error(result: Result<Vec<Vec<Option<i64>>>, DbError>, kind: io::ErrorKind) {
+    match result {
+        Err(DbError::Storage(BufferPoolError::Disk(DiskError::IoError(e)))) => assert_eq!(e.kind(), kind),
+        other => panic!("expected an I/O error, got {:?}", other),
+    }
+}
+
+#[test]
+fn failed_page_read_is_reported_and_retried() {
+    let dir = TempDir::new().unwrap();
+    let (db, faulty) = reopened(&dir);
+
+    faulty.short_read(0);
+    assert_io_error(select(&db, 900), io::ErrorKind::UnexpectedEof);
+    assert_eq!(select(&db, 900).unwrap(), vec![vec![Some(900), Some(9000)]]);
+}
+
+#[test]
+fn failed_loads_do_not_leak_frames() {
+    let dir = TempDir::new().unwrap();
+    let (db, faulty) = reopened(&dir);
+
+    for _ in 0..BP_CAP + 10 {
+        faulty.short_read(0);
+        assert!(select(&db, 900).is_err());
+    }
+    for key in [0, 511, 512, 900] {
+        assert_eq!(select(&db, key).unwrap(), vec![vec![Some(key), Some(key * 10)]]);
+    }
+}
+
+#[test]
+fn failed_eviction_keeps_the_dirty_page() {
+    let dir = TempDir::new().unwrap();
+    let faulty = Arc::new(FaultyBackend::new(Arc::new(DiskManager::new(dir.path()).unwrap())));
+    let bp = BufferPool::new(faulty.clone());
+    for page_num in 0..BP_CAP {
+        bp.write(PageId::new(page_num, 0), Some(page_num as i64), 0).unwrap();
+    }
+
+    // Loading one more page has to write a dirty victim back first.
+    faulty.fail_write(0);
+    match bp.read(PageId::new(BP_CAP, 0), 0) {
+        Err(BufferPoolError::Disk(DiskError::IoError(_))) => {}
+        other => panic!("expected the eviction to fail, got {:?}", other),
+    }
+
+    assert_eq!(bp.read(PageId::new(BP_CAP, 0), 0).unwrap(), None);
+    for page_num in 0..BP_CAP {
+        assert_eq!(bp.read(PageId::new(page_num, 0), 0).unwrap(), Some(page_num as i64));
+    }
+}
+
+#[test]
+fn failed_insert_leaves_the_key_free() {
+    let dir = TempDir::new().unwrap();
+    let (db, faulty) = reopened(&dir);
+    let t = db.get_table("t").unwrap();
+
+    faulty.short_read(0);
+    assert!(Query::new(t.clone()).insert(vec![Some(ROWS), Some(1)]).is_err());
+    assert_eq!(select(&db, ROWS).unwrap(), Vec::<Vec<Option<i64>>>::new());
+
+    assert!(Query::new(t).insert(vec![Some(ROWS), Some(1)]).unwrap());
+    assert_eq!(select(&db, ROWS).unwrap(), vec![vec![Some(ROWS), Some(1)]]);
+}
+
+#[test]
+fn failed_insert_is_not_redone() {
+    let dir = TempDir::new().unwrap();
+    let (db, faulty) = reopened(&dir);
+    let t = db.get_table("t").unwrap();
+
+    faulty.short_read(0);
+    assert!(Query::new(t.clone()).insert(vec![Some(ROWS), Some(1)]).is_err());
+    assert!(Query::new(t.clone()).insert(vec![Some(ROWS + 1), Some(2)]).unwrap());
+
+    // Only the insert that went through was logged.
+    drop(t);
+    std::mem::forget(db);
+    let (db, _) = open_faulty(&dir);
+    assert_eq!(select(&db, ROWS).unwrap(), Vec::<Vec<Option<i64>>>::new());
+    assert_eq!(select(&db, ROWS + 1).unwrap(), vec![vec![Some(ROWS + 1), Some(2)]]);
+}
+
+/// `reopened`, with every row updated to `key * 10 + 1` before the close so
+/// that the tail records come from disk too.
+fn reopened_with_tails(dir: &TempDir) -> (Database, Arc<FaultyBackend>) {
+    let (db, _) = reopened(dir);
+    let t = db.get_table("t").unwrap();
+    for key in 0..ROWS {
+        Query::new(t.clone()).update(key, vec![None, Some(key * 10 + 1)]).unwrap();
+    }
+    drop(t);
+    db.close().unwrap();
+    drop(db);
+    open_faulty(dir)
+}
+
+/// Indexes column 1 for key 1's row, and brings its pages in, so that the
+/// next read from disk is of the tail collection a write to it appends to.
+fn index_and_warm(db: &Database) -> Arc<Table> {
+    let t = db.get_table("t").unwrap();
+    t.indices[1].enable();
+    t.indices[1].insert(11, t.rid_for_key(1).unwrap());
+    assert_eq!(select(db, 1).unwrap(), vec![vec![Some(1), Some(11)]]);
+    t
+}
+
+#[test]
+fn failed_update_changes_nothing() {
+    let dir = TempDir::new().unwrap();
+    let (db, faulty) = reopened_with_tails(&dir);
+    let t = index_and_warm(&db);
+    let rid = t.rid_for_key(1).unwrap();
+
+    faulty.short_read(0);
+    assert!(Query::new(t.clone()).update(1, vec![None, Some(-1)]).is_err());
+    assert_eq!(select(&db, 1).unwrap(), vec![vec![Some(1), Some(11)]]);
+    assert_eq!(t.indices[1].locate_all(11), vec![rid]);
+    assert!(t.indices[1].locate_all(-1).is_empty());
+
+    // Nothing was logged for recovery to redo.
+    drop(t);
+    std::mem::forget(db);
+    let (db, _) = open_faulty(&dir);
+    assert_eq!(select(&db, 1).unwrap(), vec![vec![Some(1), Some(11)]]);
+}
+
+#[test]
+fn failed_delete_leaves_the_record_found() {
+    let dir = TempDir::new().unwrap();
+    let (db, faulty) = reopened_with_tails(&dir);
+    let t = index_and_warm(&db);
+    let rid = t.rid_for_key(1).unwrap();
+
+    faulty.short_read(0);
+    assert!(Query::new(t.clone()).delete(1).is_err());
+    assert_eq!(select(&db, 1).unwrap(), vec![vec![Some(1), Some(11)]]);
+    assert_eq!(t.indices[1].locate_all(11), vec![rid]);
+
+    assert!(Query::new(t.clone()).delete(1).unwrap());
+    assert_eq!(select(&db, 1).unwrap(), Vec::<Vec<Option<i64>>>::new());
+    assert!(t.indices[1].locate_all(11).is_empty());
+
+    // Only the delete that went through is redone.
+    drop(t);
+    std::mem::forget(db);
+    let (db, _) = open_faulty(&dir);
+    assert_eq!(select(&db, 1).unwrap(), Vec::<Vec<Option<i64>>>::new());
+    assert_eq!(select(&db, 2).unwrap(), vec![vec![Some(2), Some(21)]]);
+}
+
+#[test]
+fn torn_page_write_is_undone_on_open() {
+    let dir = TempDir::new().unwrap();
+    let (db, faulty) = reopened_with_tails(&dir);
+    let t = db.get_table("t").unwrap();
+    for key in 300..400 {
+        Query::new(t.clone()).update(key, vec![None, Some(key * 10 + 2)]).unwrap();
+    }
+
+    // The checkpoint writes pages first.
+    faulty.tear_write(0);
+    assert!(db.checkpoint().is_err());
+    drop(t);
+    std::mem::forget(db);
+
+    let (db, _) = open_faulty(&dir);
+    for key in 0..ROWS {
+        let col = if (300..400).contains(&key) { key * 10 + 2 } else { key * 10 + 1 };
+        assert_eq!(select(&db, key).unwrap(), vec![vec![Some(key), Some(col)]]);
+    }
+}
+
+#[test]
+fn transaction_rolls_back_on_read_failure() {
+    let dir = TempDir::new().unwrap();
+    let (db, faulty) = reopened(&dir);
+    let t = db.get_table("t").unwrap();
+    // Bring the first key's pages in, so the select below is what hits disk.
+    assert_eq!(select(&db, 1).unwrap(), vec![vec![Some(1), Some(10)]]);
+
+    let txn = Transaction::from_ops(vec![
+        QueryOp::Update { table: t.clone(), key: 1, cols: vec![None, Some(111)] },
+        QueryOp::Select { table: t.clone(), key: 900, search_col: 0, proj: vec![1, 1] },
+    ]);
+    faulty.short_read(0);
+    assert!(!txn.run());
+    assert_eq!(select(&db, 1).unwrap(), vec![vec![Some(1), Some(10)]]);
+
+    assert!(txn.run());
+    assert_eq!(select(&db, 1).unwrap(), vec![vec![Some(1), Some(111)]]);
+}
+
+#[test]
+fn corrupted_page_keeps_failing() {
+    let dir = TempDir::new().unwrap();
+    let (db, faulty) = reopened(&dir);
+
+    faulty.corrupt_read(0);
+    for _ in 0..2 {
+        match select(&db, 900) {
+            Err(DbError::Storage(BufferPoolError::Disk(DiskError::ChecksumMismatch(c)))) => {
+                assert_eq!(c.table_id, Some(0));
+            }
+            other => panic!("expected a checksum mismatch, got {:?}", other),
+        }
+    }
+    assert_eq!(select(&db, 1).unwrap(), vec![vec![Some(1), Some(10)]]);
+}
+
+/// Writes a few rows, updates some, and tries to close with the `nth` write
+/// failing. Returns `None` once `nth` is past the last write the close does.
+fn close_failing_at(nth: usize) -> Option<TempDir> {
+    let dir = TempDir::new().unwrap();
+    let (db, faulty) = open_faulty(&dir);
+    db.create_table("t".into(), 2, 0).unwrap();
+    let t = db.get_table("t").unwrap();
+    for key in 0..20 {
+        Query::new(t.clone()).insert(vec![Some(key), Some(key * 10)]).unwrap();
+    }
+    for key in 0..5 {
+        Query::new(t.clone()).update(key, vec![None, Some(-key)]).unwrap();
+    }
+
+    faulty.fail_write(nth);
+    if db.close().is_ok() {
+        return None;
+    }
+    // Nothing is half done: closing again finishes the job.
+    db.close().unwrap();
+    Some(dir)
+}
+
+#[test]
+fn close_can_be_retried_after_any_failed_write() {
+    let mut nth = 0;
+    while let Some(dir) = close_failing_at(nth) {
+        let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+        db.open_storage(Arc::new(DiskManager::new(dir.path()).unwrap())).unwrap();
+        for key in 0..20 {
+            let expected = if key < 5 { -key } else { key * 10 };
+            assert_eq!(select(&db, key).unwrap(), vec![vec![Some(key), Some(expected)]], "write {}", nth);
+        }
+        nth += 1;
+    }
+    assert!(nth > 5, "close performed only {} writes", nth);
+}
+
+#[test]
+fn panicking_writer_surfaces_as_dead_worker() {
+    let dir = TempDir::new().unwrap();
+    let (db, faulty) = open_faulty(&dir);
+    db.create_table("t".into(), 2, 0).unwrap();
+    let t = db.get_table("t").unwrap();
+    for key in 0..20 {
+        Query::new(t.clone()).insert(vec![Some(key), Some(key * 10)]).unwrap();
+    }
+    drop(t);
+
+    faulty.panic_on_write(0);
+    for _ in 0..2 {
+        match db.close() {
+            Err(DbError::Storage(BufferPoolError::BackgroundWorkerDead)) => {}
+            other => panic!("expected a dead worker, got {:?}", other),
+        }
+    }
+    drop(db);
+
+    // The log was never truncated, so nothing is lost.
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open_storage(Arc::new(DiskManager::new(dir.path()).unwrap())).unwrap();
+    for key in 0..20 {
+        assert_eq!(select(&db, key).unwrap(), vec![vec![Some(key), Some(key * 10)]]);
+    }
+}
+
+#[test]
+fn stalled_storage_slows_but_does_not_break_queries() {
+    let dir = TempDir::new().unwrap();
+    let (db, faulty) = reopened(&dir);
+    let db = Arc::new(db);
+    faulty.stall(Duration::from_millis(2));
+
+    let readers: Vec<_> = (0..4)
+        .map(|i| {
+            let db = db.clone();
+            thread::spawn(move || {
+                for key in (i..ROWS).step_by(37) {
+                    assert_eq!(select(&db, key).unwrap(), vec![vec![Some(key), Some(key * 10)]]);
+                }
+            })
+        })
+        .collect();
+    for reader in readers {
+        reader.join().unwrap();
+    }
+    faulty.heal();
+    db.close().unwrap();
+}
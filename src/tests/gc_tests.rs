@@ -0,0 +1,315 @@
+use crate::bufferpool::DiskManager;
+use crate::check::check;
+use crate::config::{DatabaseConfig, MergeConfig};
+use crate::db::Database;
+use crate::page::Page;
+use crate::page_collection::PageId;
+use crate::query::Query;
+use crate::storage::{MemoryBackend, StorageBackend};
+use crate::table::Table;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tempfile::TempDir;
+
+/// Two base and two tail collections' worth of records, each updated once.
+const KEYS: i64 = 2 * Page::PAGE_SIZE as i64;
+
+fn fill(db: &Database) -> Arc<Table> {
+    db.create_table("t".into(), 2, 0).unwrap();
+    let t = db.get_table("t").unwrap();
+    let q = Query::new(t.clone());
+    for key in 0..KEYS {
+        q.insert(vec![Some(key), Some(0)]).unwrap();
+    }
+    for key in 0..KEYS {
+        q.update(key, vec![None, Some(key)]).unwrap();
+    }
+    t
+}
+
+/// Every PID the collections of both ranges have.
+fn collection_pids(t: &Table) -> BTreeSet<usize> {
+    let ranges = &t.page_ranges;
+    ranges
+        .base_collections()
+        .into_iter()
+        .chain(ranges.tail_collections())
+        .flat_map(|c| c.start..c.end)
+        .collect()
+}
+
+fn assert_values(db: &Database, value: impl Fn(i64) -> i64) {
+    let q = Query::new(db.get_table("t").unwrap());
+    for key in 0..KEYS {
+        assert_eq!(q.select(key, 0, &[1, 1]).unwrap(), vec![vec![Some(key), Some(value(key))]]);
+    }
+}
+
+#[test]
+fn superseded_collections_are_deleted_and_their_pids_handed_out_again() {
+    let storage = Arc::new(MemoryBackend::new());
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open_storage(storage.clone()).unwrap();
+    let t = fill(&db);
+    db.checkpoint().unwrap();
+    let before = collection_pids(&t);
+
+    assert_eq!(t.merge(&MergeConfig::default()).unwrap(), KEYS as usize);
+    db.checkpoint().unwrap();
+    // The first two base collections hold nothing but superseded copies, and
+    // are gone. The tail records stay for older versions.
+    let gone: Vec<usize> = before.difference(&collection_pids(&t)).copied().collect();
+    let pages = 2 * Table::collection_pages(2);
+    assert_eq!(gone.len(), pages);
+    assert_eq!(db.checkpoint_stats().reclaimed_pages, pages as u64);
+    assert!(gone.iter().all(|&pid| !storage.page_exists(PageId::new(pid, t.table_id))));
+    assert_values(&db, |key| key);
+
+    // New tail collections go where the old base ones were.
+    let next = t.page_ranges.pid_next_start();
+    let q = Query::new(t.clone());
+    for key in 0..KEYS {
+        q.update(key, vec![None, Some(-key)]).unwrap();
+    }
+    assert_eq!(t.page_ranges.pid_next_start(), next);
+    assert!(collection_pids(&t).iter().any(|pid| gone.contains(pid)));
+    assert_values(&db, |key| -key);
+
+    drop(t);
+    db.close().unwrap();
+    drop(db);
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open_storage(storage).unwrap();
+    assert_values(&db, |key| -key);
+}
+
+#[test]
+fn tail_collections_merged_away_are_deleted_and_their_pids_handed_out_again() {
+    let storage = Arc::new(MemoryBackend::new());
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open_storage(storage.clone()).unwrap();
+    let t = fill(&db);
+    let q = Query::new(t.clone());
+    for key in 0..KEYS {
+        q.update(key, vec![None, Some(key + 1)]).unwrap();
+    }
+    db.checkpoint().unwrap();
+    let tails: BTreeSet<usize> = t.page_ranges.tail_collections().into_iter().flat_map(|c| c.start..c.end).collect();
+
+    // Keeping only the newest update, every merge folds the first one in,
+    // so the two tail collections of those hold nothing read any more.
+    let config = MergeConfig { keep_versions: 1, ..MergeConfig::default() };
+    assert_eq!(t.merge(&config).unwrap(), KEYS as usize);
+    db.checkpoint().unwrap();
+    let gone: Vec<usize> = tails.difference(&collection_pids(&t)).copied().collect();
+    assert_eq!(gone.len(), 2 * Table::collection_pages(2));
+    assert!(gone.iter().all(|&pid| !storage.page_exists(PageId::new(pid, t.table_id))));
+    assert_values(&db, |key| key + 1);
+    assert_eq!(q.select_version(3, 0, &[1, 1], -1).unwrap(), vec![vec![Some(3), Some(3)]]);
+
+    // New tail collections go where they were.
+    let next = t.page_ranges.pid_next_start();
+    for round in [-1, -2] {
+        for key in 0..KEYS {
+            q.update(key, vec![None, Some(round * key)]).unwrap();
+        }
+    }
+    assert_eq!(t.page_ranges.pid_next_start(), next);
+    let live = collection_pids(&t);
+    assert!(gone.iter().all(|pid| live.contains(pid)));
+    assert_values(&db, |key| -2 * key);
+
+    drop(q);
+    drop(t);
+    db.close().unwrap();
+    drop(db);
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open_storage(storage).unwrap();
+    assert_values(&db, |key| -2 * key);
+    let q = Query::new(db.get_table("t").unwrap());
+    assert_eq!(q.select_version(3, 0, &[1, 1], -2).unwrap(), vec![vec![Some(3), Some(4)]]);
+}
+
+#[test]
+fn the_collections_of_deleted_records_are_freed() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().to_str().unwrap();
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open(path).unwrap();
+    let t = fill(&db);
+    let q = Query::new(t.clone());
+    for key in 0..KEYS {
+        q.delete(key).unwrap();
+    }
+
+    // Every record went, and the tombstones with them, so nothing is left.
+    assert_eq!(t.merge(&MergeConfig::default()).unwrap(), KEYS as usize);
+    db.checkpoint().unwrap();
+    assert!(collection_pids(&t).is_empty());
+    assert_eq!(db.checkpoint_stats().reclaimed_pages, 6 * Table::collection_pages(2) as u64);
+    assert!(t.page_directory.snapshot().is_empty());
+    for key in 0..KEYS {
+        assert!(q.select(key, 0, &[1, 1]).unwrap().is_empty());
+    }
+
+    drop(q);
+    drop(t);
+    db.close().unwrap();
+    let root = db.path.clone().unwrap();
+    drop(db);
+    assert!(check(&root, false).unwrap().is_consistent());
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open(path).unwrap();
+    let q = Query::new(db.get_table("t").unwrap());
+    assert!(q.select(0, 0, &[1, 1]).unwrap().is_empty());
+    assert!(q.insert(vec![Some(0), Some(1)]).unwrap());
+}
+
+#[test]
+fn a_deleted_record_stays_gone_while_its_collection_is_in_use() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().to_str().unwrap();
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open(path).unwrap();
+    let t = fill(&db);
+    let rid = t.rid_for_key(7).unwrap();
+    t.merge(&MergeConfig::default()).unwrap();
+    // Its merged copy shares a collection with those of live records.
+    Query::new(t.clone()).delete(7).unwrap();
+    assert_eq!(t.merge(&MergeConfig::default()).unwrap(), 1);
+    db.checkpoint().unwrap();
+    assert!(t.page_directory.get(rid).is_err());
+
+    drop(t);
+    db.close().unwrap();
+    let root = db.path.clone().unwrap();
+    drop(db);
+    assert!(check(&root, false).unwrap().is_consistent());
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open(path).unwrap();
+    let t = db.get_table("t").unwrap();
+    assert!(t.page_directory.get(rid).is_err());
+    let q = Query::new(t);
+    for key in 0..KEYS {
+        let expected = if key == 7 { vec![] } else { vec![vec![Some(key), Some(key)]] };
+        assert_eq!(q.select(key, 0, &[1, 1]).unwrap(), expected);
+    }
+}
+
+#[test]
+fn a_query_under_way_holds_off_the_free() {
+    let storage = Arc::new(MemoryBackend::new());
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open_storage(storage.clone()).unwrap();
+    let t = fill(&db);
+    let before = collection_pids(&t);
+
+    let reading = t.pin();
+    t.merge(&MergeConfig::default()).unwrap();
+    db.checkpoint().unwrap();
+    let gone: Vec<usize> = before.difference(&collection_pids(&t)).copied().collect();
+    assert!(!gone.is_empty());
+    assert_eq!(db.checkpoint_stats().reclaimed_pages, 0);
+    assert!(gone.iter().all(|&pid| storage.page_exists(PageId::new(pid, t.table_id))));
+
+    drop(reading);
+    db.checkpoint().unwrap();
+    assert_eq!(db.checkpoint_stats().reclaimed_pages, gone.len() as u64);
+    assert!(gone.iter().all(|&pid| !storage.page_exists(PageId::new(pid, t.table_id))));
+    assert_values(&db, |key| key);
+}
+
+#[test]
+fn retired_collections_are_deleted_after_a_crash() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().to_str().unwrap();
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open(path).unwrap();
+    let t = fill(&db);
+    let before = collection_pids(&t);
+
+    // Retired but never freed, and then updated in the log only.
+    let reading = t.pin();
+    t.merge(&MergeConfig::default()).unwrap();
+    db.checkpoint().unwrap();
+    drop(reading);
+    let gone: Vec<usize> = before.difference(&collection_pids(&t)).copied().collect();
+    let table_id = t.table_id;
+    let q = Query::new(t.clone());
+    for key in 0..KEYS / 2 {
+        q.update(key, vec![None, Some(-key)]).unwrap();
+    }
+    drop(q);
+    drop(t);
+    std::mem::forget(db);
+
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open(path).unwrap();
+    assert_values(&db, |key| if key < KEYS / 2 { -key } else { key });
+    let live = collection_pids(&db.get_table("t").unwrap());
+    db.close().unwrap();
+    let root = db.path.clone().unwrap();
+    drop(db);
+
+    // What is still there belongs to a collection cut since.
+    let dm = DiskManager::new(&root).unwrap();
+    assert!(gone.iter().all(|pid| live.contains(pid) || !dm.page_exists(PageId::new(*pid, table_id))));
+    assert!(check(&root, false).unwrap().is_consistent());
+}
+
+#[test]
+fn queries_read_right_while_space_is_reclaimed() {
+    let dir = TempDir::new().unwrap();
+    let config = DatabaseConfig {
+        checkpoint_interval: Some(Duration::from_millis(10)),
+        merge: Some(MergeConfig { interval: Duration::from_millis(1), tail_records: 64, chain_length: 2, ..MergeConfig::default() }),
+        ..DatabaseConfig::synchronous()
+    };
+    let mut db = Database::with_config(config).unwrap();
+    db.open(dir.path().to_str().unwrap()).unwrap();
+    let t = fill(&db);
+    let db = Arc::new(db);
+
+    // The value of a key only goes up, and always stays a multiple of it.
+    let writer = {
+        let t = t.clone();
+        thread::spawn(move || {
+            let q = Query::new(t);
+            for round in 2..=4 {
+                for key in 0..KEYS {
+                    q.update(key, vec![None, Some(key * round)]).unwrap();
+                }
+            }
+        })
+    };
+    let readers: Vec<_> = (0..2)
+        .map(|_| {
+            let t = t.clone();
+            thread::spawn(move || {
+                let q = Query::new(t);
+                let mut seen = vec![0; KEYS as usize];
+                while seen.iter().enumerate().any(|(key, &v)| v < key as i64 * 4) {
+                    for key in 1..KEYS {
+                        let value = q.select(key, 0, &[0, 1]).unwrap()[0][1].unwrap();
+                        assert_eq!(value % key, 0);
+                        assert!(value >= seen[key as usize]);
+                        seen[key as usize] = value;
+                    }
+                }
+            })
+        })
+        .collect();
+    writer.join().unwrap();
+    for reader in readers {
+        reader.join().unwrap();
+    }
+
+    db.checkpoint().unwrap();
+    db.checkpoint().unwrap();
+    assert!(db.checkpoint_stats().reclaimed_pages > 0);
+    assert_values(&db, |key| key * 4);
+    db.close().unwrap();
+    assert!(check(db.path.as_ref().unwrap(), false).unwrap().is_consistent());
+}
@@ -0,0 +1,135 @@
+use crate::bufferpool::BufferPool;
+use crate::config::{DatabaseConfig, MergeConfig};
+use crate::db::Database;
+use crate::heap::ValueHeap;
+use crate::lock_manager::LockManager;
+use crate::query::Query;
+use crate::schema::{ColumnType, Schema, Value};
+use crate::storage::MemoryBackend;
+use crate::table::Table;
+use crate::wal::Wal;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const COLUMNS: [ColumnType; 3] = [ColumnType::I64, ColumnType::Text, ColumnType::Blob];
+
+/// Long enough for some values to run across pages.
+fn row(key: i64) -> Vec<Option<Value>> {
+    let len = (key as usize * 997) % 6000;
+    vec![
+        Some(Value::I64(key)),
+        Some(Value::Str(format!("user {} ", key).repeat(len / 8))),
+        Some(Value::Bytes((0..len).map(|i| (i + key as usize) as u8).collect())),
+    ]
+}
+
+fn heap_db() -> (Database, Arc<Table>) {
+    let db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.create_typed_table("t".into(), COLUMNS.to_vec(), 0).unwrap();
+    let t = db.get_table("t").unwrap();
+    (db, t)
+}
+
+#[test]
+fn values_of_any_length_come_back() {
+    let (_db, t) = heap_db();
+    let q = Query::new(t.clone());
+    for key in 0..40 {
+        q.insert_values(row(key)).unwrap();
+    }
+    assert_eq!(t.schema().width(), 3, "a heap column takes one slot");
+    for key in 0..40 {
+        assert_eq!(q.select_values(&Value::I64(key), 0, &[1, 1, 1]).unwrap(), vec![row(key)]);
+    }
+    let empty = vec![Some(Value::I64(40)), Some(Value::Str(String::new())), None];
+    q.insert_values(empty.clone()).unwrap();
+    assert_eq!(q.select_values(&Value::I64(40), 0, &[1, 1, 1]).unwrap(), vec![empty]);
+
+    // Text is compared by value, not by where it is.
+    let search = row(7)[1].clone().unwrap();
+    assert_eq!(q.select_values(&search, 1, &[1, 1, 1]).unwrap(), vec![row(7)]);
+    assert!(q.select_values(&Value::Bytes(vec![1]), 1, &[1, 1, 1]).is_err());
+}
+
+#[test]
+fn updates_store_new_values_in_tail_records() {
+    let (_db, t) = heap_db();
+    let q = Query::new(t.clone());
+    q.insert_values(row(3)).unwrap();
+    let mut updated = row(5);
+    updated[0] = None;
+    q.update_values(3, updated.clone()).unwrap();
+
+    let latest = &q.select_values(&Value::I64(3), 0, &[1, 1, 1]).unwrap()[0];
+    assert_eq!(latest[1..], updated[1..]);
+    assert_eq!(q.select_version_values(&Value::I64(3), &[1, 1, 1], -1).unwrap(), vec![row(3)]);
+}
+
+#[test]
+fn heap_values_survive_a_checkpoint_and_a_crash() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().to_str().unwrap();
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open(path).unwrap();
+    db.create_typed_table("t".into(), COLUMNS.to_vec(), 0).unwrap();
+    let q = Query::new(db.get_table("t").unwrap());
+    for key in 0..20 {
+        q.insert_values(row(key)).unwrap();
+    }
+    db.checkpoint().unwrap();
+    for key in 20..40 {
+        q.insert_values(row(key)).unwrap();
+    }
+    let mut updated = row(41);
+    updated[0] = None;
+    q.update_values(2, updated.clone()).unwrap();
+    // Killed: the first half comes back from the checkpoint, the rest from
+    // the log.
+    std::mem::forget(db);
+
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open(path).unwrap();
+    let q = Query::new(db.get_table("t").unwrap());
+    for key in (0..40).filter(|&key| key != 2) {
+        assert_eq!(q.select_values(&Value::I64(key), 0, &[1, 1, 1]).unwrap(), vec![row(key)]);
+    }
+    assert_eq!(q.select_values(&Value::I64(2), 0, &[0, 1, 1]).unwrap()[0][1..], updated[1..]);
+
+    // New values do not land on any of the old ones.
+    q.insert_values(row(100)).unwrap();
+    for key in 0..40 {
+        assert_eq!(q.select_values(&Value::I64(key), 0, &[0, 0, 1]).unwrap().len(), 1);
+    }
+    assert_eq!(q.select_values(&Value::I64(100), 0, &[1, 1, 1]).unwrap(), vec![row(100)]);
+    db.close().unwrap();
+}
+
+#[test]
+fn merged_away_values_are_reused_after_a_checkpoint() {
+    let bp = Arc::new(BufferPool::new(Arc::new(MemoryBackend::new())));
+    let schema = Schema::new(COLUMNS.to_vec()).unwrap();
+    let lm = Arc::new(LockManager::new());
+    let t = Arc::new(Table::new("t".into(), schema, 0, 0, bp, lm, Arc::new(Wal::disabled())));
+    let text = |key: Option<i64>, s: &str| vec![key.map(Value::I64), Some(Value::Str(s.repeat(100))), None];
+    let q = Query::new(t.clone());
+    q.insert_values(text(Some(1), "first")).unwrap();
+    q.update_values(1, text(None, "secnd")).unwrap();
+    q.update_values(1, text(None, "third")).unwrap();
+
+    t.merge(&MergeConfig { keep_versions: 0, ..MergeConfig::default() }).unwrap();
+    assert_eq!(q.select_values(&Value::I64(1), 0, &[1, 1, 1]).unwrap(), vec![text(Some(1), "third")]);
+    // The first two are recorded as free for the next checkpoint...
+    let words = ValueHeap::words_for(500);
+    assert_eq!(t.heap.free_extents().iter().map(|(_, len)| len).sum::<usize>(), 2 * words);
+    // ...which may still need them, so they are not handed out before it.
+    let end = t.heap.next_word();
+    q.insert_values(text(Some(2), "after")).unwrap();
+    assert_eq!(t.heap.next_word(), end + words);
+
+    t.heap.release_retired();
+    q.insert_values(text(Some(3), "reuse")).unwrap();
+    assert_eq!(t.heap.next_word(), end + words);
+    for (key, s) in [(1, "third"), (2, "after"), (3, "reuse")] {
+        assert_eq!(q.select_values(&Value::I64(key), 0, &[1, 1, 1]).unwrap(), vec![text(Some(key), s)]);
+    }
+}
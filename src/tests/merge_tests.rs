@@ -0,0 +1,209 @@
+use crate::check::check;
+use crate::config::{DatabaseConfig, MergeConfig};
+use crate::db::Database;
+use crate::query::Query;
+use crate::table::Table;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+const KEYS: i64 = 200;
+const ROUNDS: i64 = 12;
+
+fn merging(tail_records: usize, chain_length: usize) -> DatabaseConfig {
+    DatabaseConfig {
+        merge: Some(MergeConfig { interval: Duration::from_millis(1), tail_records, chain_length, ..MergeConfig::default() }),
+        ..DatabaseConfig::synchronous()
+    }
+}
+
+fn wait_for(what: &str, mut done: impl FnMut() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !done() {
+        assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+fn select(db: &Database, key: i64) -> Vec<Option<i64>> {
+    Query::new(db.get_table("t").unwrap()).select(key, 0, &[1, 1]).unwrap().remove(0)
+}
+
+#[test]
+fn long_chains_are_merged_while_queries_run() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().to_str().unwrap();
+    let mut db = Database::with_config(merging(usize::MAX, 4)).unwrap();
+    db.open(path).unwrap();
+    db.create_table("t".into(), 2, 0).unwrap();
+    let q = Query::new(db.get_table("t").unwrap());
+    for key in 0..KEYS {
+        q.insert(vec![Some(key), Some(0)]).unwrap();
+    }
+    let db = Arc::new(db);
+
+    // Each writer counts its half of the keys up, round by round, while a
+    // reader checks that no count ever goes back.
+    let writers: Vec<_> = (0..2)
+        .map(|w| {
+            let db = db.clone();
+            thread::spawn(move || {
+                let q = Query::new(db.get_table("t").unwrap());
+                for round in 1..=ROUNDS {
+                    for key in (w..KEYS).step_by(2) {
+                        assert!(q.update(key, vec![None, Some(round)]).unwrap());
+                    }
+                }
+            })
+        })
+        .collect();
+    let reader = {
+        let db = db.clone();
+        thread::spawn(move || {
+            let mut seen = vec![0; KEYS as usize];
+            while seen.iter().any(|&round| round < ROUNDS) {
+                for key in 0..KEYS {
+                    let round = select(&db, key)[1].unwrap();
+                    assert!(round >= seen[key as usize], "key {} went back from {} to {}", key, seen[key as usize], round);
+                    seen[key as usize] = round;
+                }
+            }
+        })
+    };
+    for writer in writers {
+        writer.join().unwrap();
+    }
+    reader.join().unwrap();
+
+    let t = db.get_table("t").unwrap();
+    let config = MergeConfig { chain_length: 4, tail_records: usize::MAX, ..MergeConfig::default() };
+    wait_for("the last chains to be merged", || !t.needs_merge(&config));
+    assert!(db.merge_stats().records >= KEYS as u64);
+    assert_eq!(db.merge_stats().failed, 0);
+    for key in 0..KEYS {
+        assert_eq!(select(&db, key), vec![Some(key), Some(ROUNDS)]);
+    }
+
+    db.close().unwrap();
+    let root = db.path.clone().unwrap();
+    assert!(check(&root, false).unwrap().is_consistent());
+    drop(t);
+    drop(db);
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open(path).unwrap();
+    for key in 0..KEYS {
+        assert_eq!(select(&db, key), vec![Some(key), Some(ROUNDS)]);
+    }
+    db.close().unwrap();
+}
+
+#[test]
+fn enough_tail_records_in_all_trigger_a_merge() {
+    let db = Database::with_config(merging(50, usize::MAX)).unwrap();
+    db.create_table("t".into(), 2, 0).unwrap();
+    let t = db.get_table("t").unwrap();
+    let q = Query::new(t.clone());
+    for key in 0..KEYS {
+        q.insert(vec![Some(key), Some(0)]).unwrap();
+    }
+    for key in 0..49 {
+        q.update(key, vec![None, Some(1)]).unwrap();
+    }
+    thread::sleep(Duration::from_millis(20));
+    assert_eq!(db.merge_stats().records, 0);
+    assert_eq!(t.unmerged_tails(), 49);
+
+    q.update(49, vec![None, Some(1)]).unwrap();
+    wait_for("a merge", || db.merge_stats().records == 50);
+    assert_eq!(t.unmerged_tails(), 0);
+    for key in 0..KEYS {
+        assert_eq!(select(&db, key), vec![Some(key), Some((key < 50) as i64)]);
+    }
+    // Merged records take further updates as before.
+    q.update(0, vec![None, Some(2)]).unwrap();
+    assert_eq!(select(&db, 0), vec![Some(0), Some(2)]);
+}
+
+#[test]
+fn older_versions_read_the_same_after_a_merge() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().to_str().unwrap();
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open(path).unwrap();
+    db.create_table("t".into(), 3, 0).unwrap();
+    let t = db.get_table("t").unwrap();
+    let q = Query::new(t.clone());
+    q.insert(vec![Some(1), Some(10), Some(100)]).unwrap();
+    q.update(1, vec![None, Some(11), None]).unwrap();
+    q.update(1, vec![None, None, Some(101)]).unwrap();
+    let versions = |t: &Arc<Table>| -> Vec<Vec<Option<i64>>> {
+        let q = Query::new(t.clone());
+        (0..4).map(|back| q.select_version(1, 0, &[1, 1, 1], -back).unwrap().remove(0)).collect()
+    };
+    let mut expected = vec![
+        vec![Some(1), Some(11), Some(101)],
+        vec![Some(1), Some(11), Some(100)],
+        vec![Some(1), Some(10), Some(100)],
+        vec![Some(1), Some(10), Some(100)],
+    ];
+    assert_eq!(versions(&t), expected);
+
+    assert_eq!(t.merge(&MergeConfig::default()).unwrap(), 1);
+    assert_eq!(versions(&t), expected);
+    assert_eq!(q.sum_version(1, 1, 2, -1).unwrap(), 100);
+
+    // A second merge starts from the first one's copy.
+    q.update(1, vec![None, Some(12), None]).unwrap();
+    assert_eq!(t.merge(&MergeConfig::default()).unwrap(), 1);
+    expected.insert(0, vec![Some(1), Some(12), Some(101)]);
+    expected.pop();
+    assert_eq!(versions(&t), expected);
+    assert_eq!(select(&db, 1), vec![Some(1), Some(12)]);
+
+    drop(q);
+    drop(t);
+    db.close().unwrap();
+    drop(db);
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open(path).unwrap();
+    assert_eq!(versions(&db.get_table("t").unwrap()), expected);
+}
+
+#[test]
+fn versions_past_those_kept_read_as_the_oldest_kept() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().to_str().unwrap();
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open(path).unwrap();
+    db.create_table("t".into(), 2, 0).unwrap();
+    let t = db.get_table("t").unwrap();
+    let q = Query::new(t.clone());
+    q.insert(vec![Some(1), Some(10)]).unwrap();
+    for value in 11..=14 {
+        q.update(1, vec![None, Some(value)]).unwrap();
+    }
+    let versions = |t: &Arc<Table>| -> Vec<Option<i64>> {
+        let q = Query::new(t.clone());
+        (0..6).map(|back| q.select_version(1, 0, &[0, 1], -back).unwrap()[0][1]).collect()
+    };
+    let config = MergeConfig { keep_versions: 2, ..MergeConfig::default() };
+
+    assert_eq!(t.merge(&config).unwrap(), 1);
+    assert_eq!(versions(&t), [14, 13, 12, 12, 12, 12].map(Some));
+    assert_eq!(q.sum_version(1, 1, 1, -4).unwrap(), 12);
+    // The next merge folds in from where this one stopped.
+    q.update(1, vec![None, Some(15)]).unwrap();
+    assert_eq!(versions(&t), [15, 14, 13, 12, 12, 12].map(Some));
+    assert_eq!(t.merge(&config).unwrap(), 1);
+    assert_eq!(versions(&t), [15, 14, 13, 13, 13, 13].map(Some));
+
+    drop(q);
+    drop(t);
+    db.close().unwrap();
+    assert!(check(db.path.as_ref().unwrap(), false).unwrap().is_consistent());
+    drop(db);
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open(path).unwrap();
+    assert_eq!(versions(&db.get_table("t").unwrap()), [15, 14, 13, 13, 13, 13].map(Some));
+}
@@ -6,3 +6,21 @@ mod page_range_tests;
 mod table_tests;
 mod bufferpool_test;
 mod transaction_tests;
+mod wal_tests;
+mod disk_manager_tests;
+mod recovery_tests;
+mod check_tests;
+mod storage_tests;
+mod fault_tests;
+mod config_tests;
+mod checkpoint_tests;
+mod eviction_tests;
+mod prefetch_tests;
+mod stats_tests;
+mod quota_tests;
+mod schema_tests;
+mod heap_tests;
+mod alter_tests;
+mod merge_tests;
+mod gc_tests;
+mod vacuum_tests;
@@ -0,0 +1,109 @@
+use crate::bufferpool::{AccessStats, BufferPool};
+use crate::config::{DatabaseConfig, EvictionKind, PoolSize};
+use crate::db::Database;
+use crate::page::Page;
+use crate::page_collection::PageId;
+use crate::query::Query;
+use crate::storage::MemoryBackend;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn pool(frames: usize) -> BufferPool {
+    BufferPool::with_options(Arc::new(MemoryBackend::new()), frames, EvictionKind::default(), None)
+}
+
+fn pid(n: usize) -> PageId {
+    PageId::new(n, 0)
+}
+
+fn wait_for(what: &str, mut done: impl FnMut() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !done() {
+        assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn prefetched_pages_are_read_without_loading() {
+    let bp = pool(64);
+    for n in 0..4 {
+        bp.write(pid(n), Some(n as i64), 0).unwrap();
+    }
+    bp.evict_all().unwrap();
+    let before = bp.access_stats();
+
+    bp.prefetch((0..4).map(pid).collect());
+    // The worker takes commands in order, so this waits for the prefetch.
+    bp.flush_all().unwrap();
+    assert_eq!(bp.access_stats().prefetched, 4);
+    for n in 0..4 {
+        assert_eq!(bp.read(pid(n), 0).unwrap(), Some(n as i64));
+    }
+    assert_eq!(
+        bp.access_stats(),
+        AccessStats { hits: before.hits + 4, loads: before.loads, prefetched: 4, prefetch_hits: 4, ..before }
+    );
+    // Only the first read of a prefetched page counts.
+    bp.read(pid(0), 0).unwrap();
+    assert_eq!(bp.access_stats().prefetch_hits, 4);
+}
+
+#[test]
+fn prefetch_skips_resident_pages() {
+    let bp = pool(64);
+    bp.write(pid(0), Some(0), 0).unwrap();
+    bp.prefetch(vec![pid(0), pid(1)]);
+    bp.flush_all().unwrap();
+    assert_eq!(bp.access_stats().prefetched, 1);
+}
+
+#[test]
+fn prefetch_fills_at_most_half_the_cold_ring() {
+    // 64 frames keep a cold ring of 8.
+    let bp = pool(64);
+    bp.prefetch((0..20).map(pid).collect());
+    bp.flush_all().unwrap();
+    assert_eq!(bp.access_stats().prefetched, 4);
+}
+
+fn scanned_db(read_ahead: usize) -> Database {
+    let db = Database::with_config(DatabaseConfig {
+        buffer_pool: PoolSize::Frames(64),
+        read_ahead,
+        ..DatabaseConfig::synchronous()
+    })
+    .unwrap();
+    db.create_table("t".into(), 3, 0).unwrap();
+    let t = db.get_table("t").unwrap();
+    // Far more collections than the pool holds.
+    for key in 0..(20 * Page::PAGE_SIZE) as i64 {
+        Query::new(t.clone()).insert(vec![Some(key), Some(1), Some(key)]).unwrap();
+    }
+    db
+}
+
+#[test]
+fn scans_read_the_next_collections_ahead() {
+    let db = scanned_db(2);
+    let t = db.get_table("t").unwrap();
+    let page = Page::PAGE_SIZE as i64;
+    // Moving on to the second collection has the next two read ahead.
+    assert_eq!(Query::new(t.clone()).sum(0, 2 * page - 1, 1).unwrap(), 2 * page);
+    wait_for("the read-ahead", || db.access_stats().prefetched > 0);
+    let before = db.access_stats();
+
+    assert_eq!(Query::new(t.clone()).sum(2 * page, 3 * page - 1, 1).unwrap(), page);
+    let after = db.access_stats();
+    assert!(after.prefetch_hits > before.prefetch_hits, "{:?}", after);
+}
+
+#[test]
+fn no_read_ahead_prefetches_nothing() {
+    let db = scanned_db(0);
+    let t = db.get_table("t").unwrap();
+    let total = (20 * Page::PAGE_SIZE) as i64;
+    assert_eq!(Query::new(t.clone()).sum(0, total - 1, 1).unwrap(), total);
+    assert_eq!(db.access_stats().prefetched, 0);
+}
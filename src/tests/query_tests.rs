@@ -1,23 +1,16 @@
 use std::sync::Arc;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use parking_lot::RwLock;
-use crate::bufferpool::{BufferPool, DiskManager};
+use crate::bufferpool::BufferPool;
 use crate::errors::DbError;
 use crate::query::Query;
+use crate::storage::MemoryBackend;
 use crate::table::Table;
 
-static TEST_DIR_CTR: AtomicUsize = AtomicUsize::new(0);
-
-fn make_bp(prefix: &str) -> Arc<BufferPool> {
-    let id = TEST_DIR_CTR.fetch_add(1, Ordering::Relaxed);
-    let dir = format!("./test_tmp/{}_{}", prefix, id);
-    let _ = std::fs::remove_dir_all(&dir);
-    let dm = Arc::new(RwLock::new(DiskManager::new(&dir).unwrap()));
-    Arc::new(BufferPool::new(dm))
+fn make_bp() -> Arc<BufferPool> {
+    Arc::new(BufferPool::new(Arc::new(MemoryBackend::new())))
 }
 
 fn setup(num_columns: usize) -> Query {
-    let bp = make_bp("qtest");
+    let bp = make_bp();
     let table = Table::new_no_transaction(String::from("test"), num_columns, 0, 0, bp);
     Query::new(Arc::from(table))
 }
@@ -33,6 +26,23 @@ fn insert_and_select() {
     assert_eq!(result[0], vec![Some(10), Some(20), Some(30)]);
 }
 
+#[test]
+fn secondary_index_finds_inserted_values() {
+    let q = setup(3);
+    q.table.indices[1].enable();
+    q.insert(vec![Some(1), Some(20), Some(30)]).unwrap();
+    q.insert(vec![Some(2), Some(20), None]).unwrap();
+    q.insert(vec![Some(3), Some(21), Some(30)]).unwrap();
+
+    let mask = [1i64, 0, 0];
+    let mut found = q.select(20, 1, &mask).unwrap();
+    found.sort();
+    assert_eq!(found, vec![vec![Some(1), None, None], vec![Some(2), None, None]]);
+    assert_eq!(q.select(21, 1, &mask).unwrap(), vec![vec![Some(3), None, None]]);
+    // Not the slot's position, which is what it used to be indexed under.
+    assert!(q.select(1, 1, &mask).unwrap().is_empty());
+}
+
 #[test]
 fn insert_and_select_version_1() {
     let  q = setup(3);
@@ -140,7 +150,7 @@ fn increment() {
 
 #[test]
 fn quick_test_all() {
-    let bp = make_bp("quick");
+    let bp = make_bp();
     let table: Table = Table::new_no_transaction(String::from("test"), 5, 0, 0, bp);
     let mut query: Query = Query::new(Arc::from(table));
 
@@ -0,0 +1,191 @@
+use crate::bufferpool::{BufferPool, BufferPoolError};
+use crate::config::{DatabaseConfig, EvictionKind, PoolSize, TableQuota};
+use crate::db::Database;
+use crate::errors::DbError;
+use crate::page_collection::PageId;
+use crate::query::Query;
+use crate::storage::MemoryBackend;
+use std::sync::Arc;
+
+const KINDS: [EvictionKind; 3] = [EvictionKind::Arc, EvictionKind::Clock, EvictionKind::LruK(2)];
+
+fn pool(kind: EvictionKind, frames: usize) -> BufferPool {
+    BufferPool::with_options(Arc::new(MemoryBackend::new()), frames, kind, None)
+}
+
+fn load(bp: &BufferPool, table: usize, pages: std::ops::Range<usize>) {
+    for n in pages {
+        bp.write(PageId::new(n, table), Some(n as i64), 0).unwrap();
+    }
+}
+
+#[test]
+fn a_limited_table_evicts_its_own_pages() {
+    for kind in KINDS {
+        let bp = pool(kind, 32);
+        load(&bp, 0, 0..10);
+        bp.set_quota(1, TableQuota { reserved: 0, limit: Some(8) }).unwrap();
+        load(&bp, 1, 0..100);
+        assert_eq!(bp.table_frames(1), 8, "{:?}", kind);
+        assert_eq!(bp.table_frames(0), 10, "{:?}", kind);
+
+        let loads = bp.access_stats().loads;
+        load(&bp, 0, 0..10);
+        assert_eq!(bp.access_stats().loads, loads, "{:?} evicted the other table", kind);
+        for n in 0..100 {
+            assert_eq!(bp.read(PageId::new(n, 1), 0).unwrap(), Some(n as i64));
+        }
+    }
+}
+
+#[test]
+fn other_tables_leave_a_reservation_alone() {
+    for kind in KINDS {
+        let bp = pool(kind, 32);
+        bp.set_quota(0, TableQuota { reserved: 10, limit: None }).unwrap();
+        load(&bp, 0, 0..12);
+        load(&bp, 1, 0..200);
+        // Only the pages past the reservation could go.
+        assert_eq!(bp.table_frames(0), 10, "{:?}", kind);
+
+        // The table itself can still cycle through more pages than it reserved.
+        load(&bp, 0, 0..40);
+        for n in 0..40 {
+            assert_eq!(bp.read(PageId::new(n, 0), 0).unwrap(), Some(n as i64));
+        }
+    }
+}
+
+#[test]
+fn a_new_limit_trims_the_table_at_once() {
+    let bp = pool(EvictionKind::Arc, 32);
+    load(&bp, 1, 0..20);
+    let pinned = bp.fetch_page(PageId::new(0, 1)).unwrap();
+    bp.set_quota(1, TableQuota { reserved: 0, limit: Some(5) }).unwrap();
+    assert_eq!(bp.table_frames(1), 5);
+    assert_eq!(pinned.read().read(0).unwrap(), Some(0));
+
+    bp.set_quota(1, TableQuota::default()).unwrap();
+    assert_eq!(bp.quota(1), TableQuota::default());
+    load(&bp, 1, 20..30);
+    assert_eq!(bp.table_frames(1), 15);
+}
+
+#[test]
+fn reservations_leave_the_rest_a_working_pool() {
+    let bp = pool(EvictionKind::Arc, 32);
+    bp.set_quota(0, TableQuota { reserved: 10, limit: None }).unwrap();
+    assert!(matches!(
+        bp.set_quota(1, TableQuota { reserved: 7, limit: None }),
+        Err(BufferPoolError::Overcommitted { reserved: 17, capacity: 32 })
+    ));
+    // Replacing a table's own reservation only counts the new one.
+    bp.set_quota(0, TableQuota { reserved: 16, limit: None }).unwrap();
+    assert!(matches!(bp.resize(31), Err(BufferPoolError::Overcommitted { .. })));
+    assert_eq!(bp.capacity(), 32);
+    bp.resize(64).unwrap();
+}
+
+fn small_db() -> Database {
+    Database::with_config(DatabaseConfig {
+        buffer_pool: PoolSize::Frames(64),
+        ..DatabaseConfig::synchronous()
+    })
+    .unwrap()
+}
+
+#[test]
+fn a_bulk_load_leaves_a_reserved_table_in_the_pool() {
+    let db = small_db();
+    db.set_table_quota("lookup", TableQuota { reserved: 16, limit: None }).unwrap();
+    db.create_table("lookup".into(), 3, 0).unwrap();
+    db.create_table("bulk".into(), 3, 0).unwrap();
+    let lookup = db.get_table("lookup").unwrap();
+    let bulk = db.get_table("bulk").unwrap();
+    for key in 0..100 {
+        Query::new(lookup.clone()).insert(vec![Some(key), Some(key), Some(key)]).unwrap();
+    }
+    for key in 0..5000 {
+        Query::new(bulk.clone()).insert(vec![Some(key), Some(key), Some(key)]).unwrap();
+    }
+
+    let loads = db.access_stats().loads;
+    for key in 0..100 {
+        assert_eq!(Query::new(lookup.clone()).select(key, 0, &[0, 0, 1]).unwrap(), vec![vec![None, None, Some(key)]]);
+    }
+    assert_eq!(db.access_stats().loads, loads);
+}
+
+#[test]
+fn a_limited_table_leaves_the_rest_of_the_pool_alone() {
+    let db = small_db();
+    db.create_table("lookup".into(), 3, 0).unwrap();
+    db.create_table("bulk".into(), 3, 0).unwrap();
+    db.set_table_quota("bulk", TableQuota { reserved: 0, limit: Some(16) }).unwrap();
+    let bulk = db.get_table("bulk").unwrap();
+    for key in 0..5000 {
+        Query::new(bulk.clone()).insert(vec![Some(key), Some(key), Some(key)]).unwrap();
+    }
+    assert_eq!(db.table_frames("bulk"), Some(16));
+    assert_eq!(Query::new(bulk.clone()).sum(0, 4999, 1).unwrap(), (0..5000).sum::<i64>());
+}
+
+#[test]
+fn quotas_follow_the_table_name() {
+    let db = small_db();
+    let quota = TableQuota { reserved: 8, limit: Some(32) };
+    db.set_table_quota("t", quota).unwrap();
+    assert_eq!(db.table_quota("t"), quota);
+    db.create_table("t".into(), 3, 0).unwrap();
+    let id = db.get_table("t").unwrap().table_id;
+    db.drop_table("t").unwrap();
+    db.create_table("t".into(), 3, 0).unwrap();
+    let table = db.get_table("t").unwrap();
+    assert_ne!(table.table_id, id);
+
+    // The new table is held to the same limit.
+    for key in 0..5000 {
+        Query::new(table.clone()).insert(vec![Some(key), None, None]).unwrap();
+    }
+    assert_eq!(db.table_frames("t"), Some(32));
+}
+
+#[test]
+fn a_limit_must_fit_a_whole_record() {
+    let db = small_db();
+    db.create_table("t".into(), 5, 0).unwrap();
+    // Five columns and the metadata make nine pages per record.
+    let too_small = TableQuota { reserved: 0, limit: Some(8) };
+    assert!(matches!(db.set_table_quota("t", too_small), Err(DbError::InvalidConfig(_))));
+    let inverted = TableQuota { reserved: 20, limit: Some(10) };
+    assert!(matches!(db.set_table_quota("u", inverted), Err(DbError::InvalidConfig(_))));
+    db.set_table_quota("t", TableQuota { reserved: 0, limit: Some(9) }).unwrap();
+}
+
+#[test]
+fn a_table_its_quota_refuses_takes_no_id() {
+    let db = small_db();
+    db.set_table_quota("narrow", TableQuota { reserved: 0, limit: Some(8) }).unwrap();
+    db.set_table_quota("greedy", TableQuota { reserved: 60, limit: None }).unwrap();
+    db.create_table("a".into(), 3, 0).unwrap();
+    let first = db.get_table("a").unwrap().table_id;
+
+    assert!(matches!(db.create_table("narrow".into(), 5, 0), Err(DbError::InvalidConfig(_))));
+    assert!(db.create_table("greedy".into(), 3, 0).is_err());
+    assert!(!db.table_exists("narrow") && !db.table_exists("greedy"));
+    db.create_table("b".into(), 3, 0).unwrap();
+    assert_eq!(db.get_table("b").unwrap().table_id, first + 1);
+}
+
+#[test]
+fn a_taken_name_is_refused() {
+    let db = small_db();
+    db.create_table("t".into(), 3, 0).unwrap();
+    let id = db.get_table("t").unwrap().table_id;
+    Query::new(db.get_table("t").unwrap()).insert(vec![Some(1), Some(2), Some(3)]).unwrap();
+
+    assert!(matches!(db.create_table("t".into(), 5, 0), Err(DbError::TableExists(name)) if name == "t"));
+    let table = db.get_table("t").unwrap();
+    assert_eq!((table.table_id, table.num_data_columns()), (id, 3));
+    assert_eq!(Query::new(table).select(1, 0, &[1, 1, 1]).unwrap(), vec![vec![Some(1), Some(2), Some(3)]]);
+}
@@ -0,0 +1,253 @@
+use crate::config::DatabaseConfig;
+use crate::db::Database;
+use crate::query::Query;
+use crate::transaction::{QueryOp, Transaction};
+use std::collections::BTreeMap;
+use tempfile::TempDir;
+
+/// Simulates the process being killed: nothing is flushed and `close` never runs.
+fn crash(db: Database) {
+    std::mem::forget(db);
+}
+
+/// Opens the database with at most `budget` writes left before every further
+/// write fails as if the process had died right there.
+fn open_armed(dir: &TempDir, budget: usize) -> (Database, bool) {
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.write_fence().arm(budget);
+    let ok = db.open(dir.path().to_str().unwrap()).is_ok();
+    (db, ok)
+}
+
+#[derive(Clone)]
+enum Step {
+    Create(&'static str, usize),
+    Drop(&'static str),
+    Insert(&'static str, Vec<i64>),
+    Update(&'static str, i64, Vec<Option<i64>>),
+    Delete(&'static str, i64),
+    Txn(Vec<Step>),
+    Reopen,
+}
+
+fn workload() -> Vec<Step> {
+    use Step::*;
+    vec![
+        Create("t", 3),
+        Insert("t", vec![1, 10, 100]),
+        Insert("t", vec![2, 20, 200]),
+        Insert("t", vec![3, 30, 300]),
+        Update("t", 2, vec![None, Some(21), None]),
+        Update("t", 2, vec![None, None, Some(202)]),
+        Delete("t", 3),
+        Txn(vec![
+            Insert("t", vec![4, 40, 400]),
+            Update("t", 1, vec![None, Some(11), None]),
+            Delete("t", 2),
+        ]),
+        Reopen,
+        Update("t", 4, vec![None, Some(41), None]),
+        Insert("t", vec![5, 50, 500]),
+        Create("u", 2),
+        Insert("u", vec![1, 1]),
+        Txn(vec![Update("t", 1, vec![None, None, Some(101)]), Insert("u", vec![2, 2])]),
+        Delete("t", 1),
+        Reopen,
+        Drop("u"),
+        Insert("t", vec![3, 33, 333]),
+        Update("t", 5, vec![None, Some(51), Some(501)]),
+    ]
+}
+
+type Model = BTreeMap<String, BTreeMap<i64, Vec<Option<i64>>>>;
+
+fn apply(model: &mut Model, step: &Step) {
+    match step {
+        Step::Create(name, _) => {
+            model.insert(name.to_string(), BTreeMap::new());
+        }
+        Step::Drop(name) => {
+            model.remove(*name);
+        }
+        Step::Insert(name, cols) => {
+            let row = cols.iter().map(|c| Some(*c)).collect();
+            model.get_mut(*name).unwrap().insert(cols[0], row);
+        }
+        Step::Update(name, key, cols) => {
+            let row = model.get_mut(*name).unwrap().get_mut(key).unwrap();
+            for (old, new) in row.iter_mut().zip(cols) {
+                if new.is_some() {
+                    *old = *new;
+                }
+            }
+        }
+        Step::Delete(name, key) => {
+            model.get_mut(*name).unwrap().remove(key);
+        }
+        Step::Txn(steps) => steps.iter().for_each(|s| apply(model, s)),
+        Step::Reopen => {}
+    }
+}
+
+fn to_op(db: &Database, step: &Step) -> QueryOp {
+    match step {
+        Step::Insert(name, cols) => QueryOp::Insert {
+            table: db.get_table(name).unwrap(),
+            args: cols.iter().map(|c| Some(*c)).collect(),
+        },
+        Step::Update(name, key, cols) => QueryOp::Update {
+            table: db.get_table(name).unwrap(),
+            key: *key,
+            cols: cols.clone(),
+        },
+        Step::Delete(name, key) => QueryOp::Delete { table: db.get_table(name).unwrap(), key: *key },
+        _ => unreachable!(),
+    }
+}
+
+/// Runs one step. `false` means a write was refused, i.e. the process "died".
+fn run(db: &mut Database, dir: &TempDir, step: &Step, budget: &mut impl FnMut(&Database) -> usize) -> bool {
+    match step {
+        Step::Create(name, cols) => db.create_table(name.to_string(), *cols, 0).is_ok(),
+        Step::Drop(name) => db.drop_table(name).is_ok(),
+        Step::Insert(name, cols) => {
+            let record = cols.iter().map(|c| Some(*c)).collect();
+            Query::new(db.get_table(name).unwrap()).insert(record).is_ok_and(|ok| ok)
+        }
+        Step::Update(name, key, cols) => {
+            Query::new(db.get_table(name).unwrap()).update(*key, cols.clone()).is_ok_and(|ok| ok)
+        }
+        Step::Delete(name, key) => Query::new(db.get_table(name).unwrap()).delete(*key).is_ok_and(|ok| ok),
+        Step::Txn(steps) => Transaction::from_ops(steps.iter().map(|s| to_op(db, s)).collect()).run(),
+        Step::Reopen => {
+            if db.close().is_err() {
+                return false;
+            }
+            let remaining = budget(db);
+            let (reopened, ok) = open_armed(dir, remaining);
+            let closed = std::mem::replace(db, reopened);
+            drop(closed);
+            ok
+        }
+    }
+}
+
+/// Runs the workload with room for `limit` writes and crashes at the first
+/// refused one. Returns how many steps were acknowledged, whether one was in
+/// flight when the crash hit, and the number of writes performed.
+fn run_until_crash(dir: &TempDir, limit: usize) -> (usize, bool, usize) {
+    let mut used = 0;
+    let (mut db, ok) = open_armed(dir, limit);
+    if !ok {
+        crash(db);
+        return (0, false, limit);
+    }
+    let steps = workload();
+    let mut acked = 0;
+    let mut in_flight = false;
+    for step in &steps {
+        let mut budget = |db: &Database| {
+            used += db.write_fence().performed();
+            limit.saturating_sub(used)
+        };
+        if !run(&mut db, dir, step, &mut budget) {
+            in_flight = true;
+            break;
+        }
+        acked += 1;
+    }
+    used += db.write_fence().performed();
+    crash(db);
+    (acked, in_flight, used)
+}
+
+fn model_after(steps: usize) -> Model {
+    let mut model = Model::new();
+    workload().iter().take(steps).for_each(|s| apply(&mut model, s));
+    model
+}
+
+fn snapshot(db: &Database) -> Model {
+    let mut model = Model::new();
+    for name in ["t", "u"] {
+        let Some(table) = db.get_table(name) else {
+            continue;
+        };
+        let proj = vec![1i64; table.num_data_columns()];
+        let mut rows = BTreeMap::new();
+        for key in 0..10 {
+            let found = Query::new(table.clone()).select(key, 0, &proj).unwrap();
+            if let Some(row) = found.into_iter().next() {
+                rows.insert(key, row);
+            }
+        }
+        model.insert(name.to_string(), rows);
+    }
+    model
+}
+
+fn assert_recovered(dir: &TempDir, acked: usize, in_flight: bool, context: &str) {
+    let (db, ok) = open_armed(dir, usize::MAX);
+    assert!(ok, "{context}: recovery failed");
+    let state = snapshot(&db);
+    let before = model_after(acked);
+    let after = model_after(acked + 1);
+    assert!(
+        state == before || (in_flight && state == after),
+        "{context}: recovered {state:?}, expected {before:?} or {after:?}"
+    );
+
+    // The recovered database keeps working and survives another restart.
+    let mut expected = state;
+    if let Some(t) = db.get_table("t") {
+        assert!(Query::new(t).insert(vec![Some(9), Some(90), Some(900)]).unwrap());
+        expected.get_mut("t").unwrap().insert(9, vec![Some(9), Some(90), Some(900)]);
+    }
+    db.close().unwrap();
+    drop(db);
+    let (db, ok) = open_armed(dir, usize::MAX);
+    assert!(ok, "{context}: reopen after recovery failed");
+    assert_eq!(snapshot(&db), expected, "{context}: state changed across a clean restart");
+    db.close().unwrap();
+}
+
+fn total_writes() -> usize {
+    let dir = TempDir::new().unwrap();
+    let (acked, in_flight, used) = run_until_crash(&dir, usize::MAX);
+    assert_eq!((acked, in_flight), (workload().len(), false));
+    used
+}
+
+#[test]
+fn crash_at_every_write_recovers() {
+    for limit in 0..total_writes() {
+        let dir = TempDir::new().unwrap();
+        let (acked, in_flight, _) = run_until_crash(&dir, limit);
+        assert_recovered(&dir, acked, in_flight, &format!("crash after {limit} writes"));
+    }
+}
+
+#[test]
+fn crash_during_recovery_recovers() {
+    let total = total_writes();
+    for limit in (1..total).step_by(total / 6) {
+        let mut recovery_write = 0;
+        loop {
+            let dir = TempDir::new().unwrap();
+            let (acked, in_flight, _) = run_until_crash(&dir, limit);
+
+            let (db, ok) = open_armed(&dir, recovery_write);
+            crash(db);
+            assert_recovered(
+                &dir,
+                acked,
+                in_flight,
+                &format!("crash after {limit} writes, then after {recovery_write} recovery writes"),
+            );
+            if ok {
+                break;
+            }
+            recovery_write += 1;
+        }
+    }
+}
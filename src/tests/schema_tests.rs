@@ -0,0 +1,322 @@
+use crate::config::DatabaseConfig;
+use crate::db::Database;
+use crate::errors::DbError;
+use crate::query::Query;
+use crate::schema::{ColumnDef, ColumnType, Schema, Value};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const COLUMNS: [ColumnType; 9] = [
+    ColumnType::I64,
+    ColumnType::I32,
+    ColumnType::F64,
+    ColumnType::Bool,
+    ColumnType::Timestamp,
+    ColumnType::FixedStr(5),
+    ColumnType::FixedBytes(3),
+    ColumnType::Str(20),
+    ColumnType::Bytes(10),
+];
+
+fn row(key: i64) -> Vec<Option<Value>> {
+    vec![
+        Some(Value::I64(key)),
+        Some(Value::I32(-(key as i32))),
+        Some(Value::F64(key as f64 / 2.0)),
+        Some(Value::Bool(key % 2 == 0)),
+        Some(Value::Timestamp(1_700_000_000_000_000 + key)),
+        Some(Value::Str(format!("k{}", key % 100))),
+        Some(Value::Bytes(vec![key as u8, 0, 0])),
+        Some(Value::Str(format!("name number {}", key))),
+        Some(Value::Bytes(vec![0; key as usize % 11])),
+    ]
+}
+
+fn typed_db() -> (Database, Arc<crate::table::Table>) {
+    let db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.create_typed_table("t".into(), COLUMNS.to_vec(), 0).unwrap();
+    let t = db.get_table("t").unwrap();
+    (db, t)
+}
+
+#[test]
+fn values_come_back_as_they_went_in() {
+    let schema = Schema::new(COLUMNS.to_vec()).unwrap();
+    assert_eq!(schema.width(), 5 + 1 + 1 + 4 + 3);
+    for key in [0, 1, 7, 10, 255] {
+        let slots = schema.encode(&row(key)).unwrap();
+        assert_eq!(slots.len(), schema.width());
+        assert_eq!(schema.decode(&slots).unwrap(), row(key));
+    }
+    let nulls = vec![None; COLUMNS.len()];
+    assert_eq!(schema.decode(&schema.encode(&nulls).unwrap()).unwrap(), nulls);
+}
+
+#[test]
+fn floats_keep_their_order_in_slots() {
+    let schema = Schema::new(vec![ColumnType::F64]).unwrap();
+    let floats = [f64::NEG_INFINITY, -2.5, -0.0, 0.0, 1e-300, 3.0, f64::INFINITY];
+    let slots: Vec<i64> = floats
+        .iter()
+        .map(|&f| schema.encode(&[Some(Value::F64(f))]).unwrap()[0].unwrap())
+        .collect();
+    assert!(slots.windows(2).all(|w| w[0] < w[1]), "{:?}", slots);
+}
+
+#[test]
+fn values_must_fit_their_column() {
+    let schema = Schema::new(vec![ColumnType::I64, ColumnType::Str(4), ColumnType::FixedBytes(2)]).unwrap();
+    let mismatches = [
+        vec![Some(Value::I32(1)), None, None],
+        vec![None, Some(Value::Str("too long".into())), None],
+        vec![None, None, Some(Value::Bytes(vec![1]))],
+    ];
+    for (col, values) in mismatches.iter().enumerate() {
+        assert!(matches!(schema.encode(values), Err(DbError::WrongType(c, _)) if c == col));
+    }
+    assert!(matches!(schema.encode(&[None]), Err(DbError::ColumnCount(1))));
+}
+
+#[test]
+fn a_schema_must_fit_a_record() {
+    assert!(Schema::new(vec![ColumnType::I64; Schema::MAX_WIDTH]).is_ok());
+    assert!(matches!(Schema::new(vec![ColumnType::I64; Schema::MAX_WIDTH + 1]), Err(DbError::InvalidSchema(_))));
+    assert!(matches!(Schema::new(vec![ColumnType::Str(8 * Schema::MAX_WIDTH)]), Err(DbError::InvalidSchema(_))));
+    assert!(matches!(Schema::new(vec![ColumnType::Str(0)]), Err(DbError::InvalidSchema(_))));
+
+    let db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    let no_int_key = db.create_typed_table("t".into(), vec![ColumnType::Str(8), ColumnType::I64], 0);
+    assert!(matches!(no_int_key, Err(DbError::InvalidSchema(_))));
+    assert!(!db.table_exists("t"));
+}
+
+#[test]
+fn column_types_read_back_as_displayed() {
+    for ty in COLUMNS {
+        assert_eq!(ty.to_string().parse::<ColumnType>().unwrap(), ty);
+    }
+    assert_eq!(" str( 12 )".parse::<ColumnType>().unwrap(), ColumnType::Str(12));
+    assert!("varchar".parse::<ColumnType>().is_err());
+    assert!("str(x)".parse::<ColumnType>().is_err());
+}
+
+#[test]
+fn typed_queries_take_and_return_values() {
+    let (_db, t) = typed_db();
+    let q = Query::new(t.clone());
+    for key in 0..200 {
+        assert!(q.insert_values(row(key)).unwrap());
+    }
+    let all = vec![1; COLUMNS.len()];
+    assert_eq!(q.select_values(&Value::I64(42), 0, &all).unwrap(), vec![row(42)]);
+
+    // By a string, which no index covers, and by a single-slot column.
+    let by_name = q.select_values(&Value::Str("name number 7".into()), 7, &all).unwrap();
+    assert_eq!(by_name, vec![row(7)]);
+    assert_eq!(q.select_values(&Value::Str("k7".into()), 5, &all).unwrap(), vec![row(7), row(107)]);
+    assert_eq!(q.select_values(&Value::I32(-9), 1, &all).unwrap(), vec![row(9)]);
+
+    let mut projected = vec![0; COLUMNS.len()];
+    projected[7] = 1;
+    let names = q.select_values(&Value::I64(3), 0, &projected).unwrap();
+    let mut expected = vec![None; COLUMNS.len()];
+    expected[7] = Some(Value::Str("name number 3".into()));
+    assert_eq!(names, vec![expected]);
+
+    // A shorter string replaces all of the longer one.
+    let mut update = vec![None; COLUMNS.len()];
+    update[7] = Some(Value::Str("x".into()));
+    assert!(q.update_values(150, update).unwrap());
+    let updated = &q.select_values(&Value::I64(150), 0, &all).unwrap()[0];
+    assert_eq!(updated[7], Some(Value::Str("x".into())));
+    assert_eq!(updated[8], row(150)[8]);
+    let previous = q.select_version_values(&Value::I64(150), &all, -1).unwrap();
+    assert_eq!(previous, vec![row(150)]);
+}
+
+#[test]
+fn sums_follow_the_column_type() {
+    let (_db, t) = typed_db();
+    let mut q = Query::new(t.clone());
+    for key in 0..10 {
+        q.insert_values(row(key)).unwrap();
+    }
+    assert_eq!(q.sum_values(0, 9, 1).unwrap(), Value::I64(-45));
+    assert_eq!(q.sum_values(0, 9, 2).unwrap(), Value::F64(22.5));
+    assert!(matches!(q.sum_values(0, 9, 7), Err(DbError::WrongType(7, ColumnType::Str(20)))));
+
+    assert!(q.increment_values(3, 1).unwrap());
+    assert_eq!(q.sum_version_values(0, 9, 1, -1).unwrap(), Value::I64(-45));
+    assert_eq!(q.sum_values(0, 9, 1).unwrap(), Value::I64(-44));
+    assert!(!q.increment_values(3, 2).unwrap(), "floats are not incremented");
+
+    let mut update = vec![None; COLUMNS.len()];
+    update[1] = Some(Value::I32(i32::MAX));
+    q.update_values(4, update).unwrap();
+    assert!(!q.increment_values(4, 1).unwrap(), "an int32 stays in range");
+}
+
+#[test]
+fn typed_tables_survive_a_checkpoint_and_a_crash() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().to_str().unwrap();
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open(path).unwrap();
+    db.create_typed_table("t".into(), COLUMNS.to_vec(), 0).unwrap();
+    let q = Query::new(db.get_table("t").unwrap());
+    for key in 0..50 {
+        q.insert_values(row(key)).unwrap();
+    }
+    db.checkpoint().unwrap();
+    db.create_typed_table("u".into(), vec![ColumnType::Str(8), ColumnType::Timestamp], 1).unwrap();
+    let u = Query::new(db.get_table("u").unwrap());
+    u.insert_values(vec![Some(Value::Str("after".into())), Some(Value::Timestamp(5))]).unwrap();
+    // Killed: `t` comes back from the catalog, `u` from the log.
+    std::mem::forget(db);
+
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open(path).unwrap();
+    let t = db.get_table("t").unwrap();
+    assert_eq!(*t.schema(), Schema::new(COLUMNS.to_vec()).unwrap());
+    let all = vec![1; COLUMNS.len()];
+    for key in 0..50 {
+        assert_eq!(Query::new(t.clone()).select_values(&Value::I64(key), 0, &all).unwrap(), vec![row(key)]);
+    }
+    let u = db.get_table("u").unwrap();
+    assert_eq!(u.key_index, 2, "the key slot comes after the string's two");
+    let found = Query::new(u).select_values(&Value::Timestamp(5), 1, &[1, 1]).unwrap();
+    assert_eq!(found, vec![vec![Some(Value::Str("after".into())), Some(Value::Timestamp(5))]]);
+    db.close().unwrap();
+}
+
+fn accounts() -> Vec<ColumnDef> {
+    vec![
+        ColumnDef { nullable: false, ..ColumnDef::new("id", ColumnType::I64) },
+        ColumnDef::new("owner", ColumnType::Text),
+        ColumnDef { default: Some(Value::F64(0.0)), ..ColumnDef::new("balance", ColumnType::F64) },
+        ColumnDef {
+            nullable: false,
+            default: Some(Value::Str("open".into())),
+            ..ColumnDef::new("status", ColumnType::Str(8))
+        },
+    ]
+}
+
+#[test]
+fn columns_are_found_by_name() {
+    let schema = Schema::with_defs(accounts()).unwrap();
+    assert_eq!(schema.column("balance").unwrap(), 2);
+    assert!(matches!(schema.column("Balance"), Err(DbError::UnknownColumn(name)) if name == "Balance"));
+    assert_eq!(schema.mask(&["status", "id"]).unwrap(), vec![1, 0, 0, 1]);
+    assert_eq!(Schema::ints(3).names(), ["0", "1", "2"]);
+
+    let mut twice = accounts();
+    twice[1].name = "id".into();
+    assert!(matches!(Schema::with_defs(twice), Err(DbError::InvalidSchema(_))));
+    let mut misfit = accounts();
+    misfit[2].default = Some(Value::I64(0));
+    assert!(matches!(Schema::with_defs(misfit), Err(DbError::WrongType(2, ColumnType::F64))));
+}
+
+#[test]
+fn inserts_fill_in_defaults() {
+    let db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.create_table_with_columns("accounts".into(), accounts(), 0).unwrap();
+    let q = Query::new(db.get_table("accounts").unwrap());
+    q.insert_values(vec![Some(Value::I64(1)), None, None, None]).unwrap();
+    let found = q.select_columns(&Value::I64(1), "id", &["status", "balance", "owner"]).unwrap();
+    assert_eq!(found, vec![vec![Some(Value::Str("open".into())), Some(Value::F64(0.0)), None]]);
+
+    // An update's nulls leave the columns alone rather than reset them.
+    let mut update = vec![None; 4];
+    update[2] = Some(Value::F64(9.5));
+    q.update_values(1, update).unwrap();
+    q.update_values(1, vec![None; 4]).unwrap();
+    let found = q.select_columns(&Value::Str("open".into()), "status", &["balance"]).unwrap();
+    assert_eq!(found, vec![vec![Some(Value::F64(9.5))]]);
+    assert!(matches!(q.select_columns(&Value::I64(1), "id", &["nope"]), Err(DbError::UnknownColumn(_))));
+
+
+    // Every record needs a key of its own.
+    let mut defaulted_key = accounts();
+    defaulted_key[0].default = Some(Value::I64(0));
+    let refused = db.create_table_with_columns("bad".into(), defaulted_key, 0);
+    assert!(matches!(refused, Err(DbError::InvalidSchema(_))));
+}
+
+#[test]
+fn a_null_in_a_column_that_is_not_nullable_is_refused() {
+    let db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    let mut defs = accounts();
+    defs[3].default = None;
+    db.create_table_with_columns("accounts".into(), defs, 0).unwrap();
+    let q = Query::new(db.get_table("accounts").unwrap());
+    let no_status = q.insert_values(vec![Some(Value::I64(1)), None, None, None]);
+    assert!(matches!(no_status, Err(DbError::NullValue(3))));
+    assert!(q.select_values(&Value::I64(1), 0, &[1, 1, 1, 1]).unwrap().is_empty());
+}
+
+#[test]
+fn names_and_defaults_survive_a_checkpoint_and_a_crash() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().to_str().unwrap();
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open(path).unwrap();
+    db.create_table_with_columns("checkpointed".into(), accounts(), 0).unwrap();
+    db.checkpoint().unwrap();
+    db.create_table_with_columns("logged".into(), accounts(), 0).unwrap();
+    std::mem::forget(db);
+
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open(path).unwrap();
+    for name in ["checkpointed", "logged"] {
+        let table = db.get_table(name).unwrap();
+        assert_eq!(table.schema().defs(), accounts(), "{}", name);
+        let q = Query::new(table);
+        q.insert_values(vec![Some(Value::I64(7)), None, None, None]).unwrap();
+        let found = q.select_columns(&Value::I64(7), "id", &["status"]).unwrap();
+        assert_eq!(found, vec![vec![Some(Value::Str("open".into()))]]);
+    }
+    db.close().unwrap();
+}
+
+#[test]
+fn wide_tables_are_checked_against_the_pool_and_survive_a_crash() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().to_str().unwrap();
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open(path).unwrap();
+    let frames = db.buffer_pool_frames();
+    assert!(matches!(db.create_table("huge".into(), frames, 0), Err(DbError::InvalidSchema(_))));
+    assert!(matches!(db.create_table("no_key".into(), 3, 3), Err(DbError::InvalidSchema(_))));
+    assert!(!db.table_exists("huge") && !db.table_exists("no_key"));
+
+    let width = 150;
+    db.create_table("wide".into(), width, 0).unwrap();
+    let q = Query::new(db.get_table("wide").unwrap());
+    let update = |col: usize, value: i64| {
+        let mut record = vec![None; width];
+        record[col] = Some(value);
+        record
+    };
+    for key in 0..10 {
+        q.insert((0..width as i64).map(|col| Some(key + col * 100)).collect()).unwrap();
+    }
+    q.update(1, update(149, -1)).unwrap();
+    db.checkpoint().unwrap();
+    q.update(1, update(70, -2)).unwrap();
+    q.update(2, update(128, -3)).unwrap();
+    std::mem::forget(db);
+
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open(path).unwrap();
+    let q = Query::new(db.get_table("wide").unwrap());
+    let all = vec![1; width];
+    let one = &q.select(1, 0, &all).unwrap()[0];
+    assert_eq!((one[69], one[70], one[128], one[149]), (Some(6901), Some(-2), Some(12801), Some(-1)));
+    let two = &q.select(2, 0, &all).unwrap()[0];
+    assert_eq!((two[70], two[128], two[149]), (Some(7002), Some(-3), Some(14902)));
+    assert_eq!(q.select_version(1, 0, &all, -1).unwrap()[0][70], Some(7001));
+    db.close().unwrap();
+    assert!(crate::check::check(&db.path.clone().unwrap(), false).unwrap().is_consistent());
+}
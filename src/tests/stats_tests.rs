@@ -0,0 +1,108 @@
+use crate::bufferpool::{AccessStats, BufferPool};
+use crate::config::{DatabaseConfig, EvictionKind, PoolSize};
+use crate::db::Database;
+use crate::page_collection::PageId;
+use crate::query::Query;
+use crate::storage::MemoryBackend;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+fn pool(kind: EvictionKind, frames: usize) -> BufferPool {
+    BufferPool::with_options(Arc::new(MemoryBackend::new()), frames, kind, None)
+}
+
+fn pid(n: usize) -> PageId {
+    PageId::new(n, 0)
+}
+
+#[test]
+fn pool_stats_count_what_the_pool_did() {
+    let bp = pool(EvictionKind::Arc, 16);
+    for n in 0..40 {
+        bp.write(pid(n), Some(n as i64), 0).unwrap();
+    }
+    let guard = bp.fetch_page(pid(39)).unwrap();
+    let stats = bp.stats();
+    assert_eq!((stats.frames, stats.resident, stats.dirty, stats.pinned), (16, 16, 16, 1));
+    assert_eq!(stats.evictions, 24);
+    assert_eq!(stats.flush.victim_writes, 24);
+    assert_eq!((stats.access.hits, stats.access.loads), (1, 40));
+    assert!(stats.recency_target.is_some());
+    drop(guard);
+
+    bp.flush_all().unwrap();
+    let stats = bp.stats();
+    assert_eq!(stats.dirty, 0);
+    assert_eq!(stats.flush.flush_all_writes, 16);
+}
+
+#[test]
+fn only_arc_has_a_recency_target() {
+    for kind in [EvictionKind::Clock, EvictionKind::LruK(2)] {
+        assert_eq!(pool(kind, 16).stats().recency_target, None, "{:?}", kind);
+    }
+}
+
+#[test]
+fn hit_rate_is_the_share_of_lookups_that_hit() {
+    assert_eq!(AccessStats::default().hit_rate(), None);
+    let stats = AccessStats { hits: 3, loads: 1, ..Default::default() };
+    assert_eq!(stats.hit_rate(), Some(0.75));
+}
+
+#[test]
+fn reset_zeroes_the_counters_but_not_the_pool() {
+    let bp = pool(EvictionKind::Arc, 16);
+    for n in 0..40 {
+        bp.write(pid(n), Some(n as i64), 0).unwrap();
+    }
+    bp.reset_stats();
+    let stats = bp.stats();
+    assert_eq!((stats.evictions, stats.flush.victim_writes), (0, 0));
+    assert_eq!(stats.access, AccessStats::default());
+    assert_eq!((stats.resident, stats.dirty), (16, 16));
+
+    assert_eq!(bp.read(pid(39), 0).unwrap(), Some(39));
+    assert_eq!(bp.stats().access.hits, 1);
+}
+
+#[test]
+fn disk_stats_count_page_and_metadata_io() {
+    let dir = TempDir::new().unwrap();
+    let mut db = Database::with_config(DatabaseConfig {
+        buffer_pool: PoolSize::Frames(PoolSize::MIN_FRAMES),
+        ..DatabaseConfig::synchronous()
+    })
+    .unwrap();
+    assert_eq!(db.stats().io, Default::default(), "nothing is on disk before open");
+    db.open(dir.path().to_str().unwrap()).unwrap();
+    db.create_table("t".into(), 3, 0).unwrap();
+    let t = db.get_table("t").unwrap();
+    for key in 0..2000 {
+        Query::new(t.clone()).insert(vec![Some(key), Some(key), None]).unwrap();
+    }
+    db.checkpoint().unwrap();
+    let stats = db.stats();
+    assert!(stats.io.page_writes > 0);
+    assert!(stats.io.metadata_writes > 0);
+    assert!(stats.io.bytes_written > 0);
+    assert_eq!(stats.io.syncs, 1);
+    assert_eq!(stats.checkpoints.taken, 1);
+    assert!(stats.pool.flush.flush_all_writes > 0);
+
+    db.reset_stats();
+    let stats = db.stats();
+    assert_eq!(stats.io, Default::default());
+    assert_eq!(stats.checkpoints, Default::default());
+    assert_eq!(stats.pool.access, AccessStats::default());
+    assert_eq!(stats.pool.evictions, 0);
+
+    // Everything is on disk by now, so every load is a page read.
+    for key in 0..2000 {
+        assert_eq!(Query::new(t.clone()).select(key, 0, &[0, 1, 0]).unwrap(), vec![vec![None, Some(key), None]]);
+    }
+    let stats = db.stats();
+    assert!(stats.pool.access.loads > 0);
+    assert_eq!(stats.io.page_reads, stats.pool.access.loads);
+    assert!(stats.io.bytes_read > 0);
+}
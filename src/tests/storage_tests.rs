@@ -0,0 +1,88 @@
+use crate::db::Database;
+use crate::page::Page;
+use crate::page_collection::PageId;
+use crate::query::Query;
+use crate::storage::{MemoryBackend, StorageBackend};
+use dashmap::DashMap;
+use std::sync::Arc;
+
+fn fill(db: &Database, rows: i64) {
+    db.create_table("t".into(), 2, 0).unwrap();
+    let t = db.get_table("t").unwrap();
+    for key in 0..rows {
+        Query::new(t.clone())
+            .insert(vec![Some(key), Some(key * 10)])
+            .unwrap();
+    }
+    Query::new(t.clone())
+        .update(3, vec![None, Some(-3)])
+        .unwrap();
+}
+
+fn second_column(db: &Database, key: i64) -> Option<i64> {
+    let t = db.get_table("t").unwrap();
+    let records = Query::new(t).select(key, 0, &[0, 1]).unwrap();
+    records.first().and_then(|r| r[1])
+}
+
+#[test]
+fn new_database_lives_in_memory() {
+    let db = Database::new();
+    fill(&db, 100);
+    assert_eq!(second_column(&db, 3), Some(-3));
+    assert_eq!(second_column(&db, 42), Some(420));
+    db.close().unwrap();
+    assert!(db.path.is_none());
+}
+
+#[test]
+fn memory_backend_survives_reopen() {
+    let storage = Arc::new(MemoryBackend::new());
+    let mut db = Database::new();
+    db.open_storage(storage.clone()).unwrap();
+    fill(&db, 1500);
+    db.close().unwrap();
+    drop(db);
+
+    let mut db = Database::new();
+    db.open_storage(storage).unwrap();
+    assert_eq!(second_column(&db, 3), Some(-3));
+    assert_eq!(second_column(&db, 1499), Some(14990));
+}
+
+#[test]
+fn memory_backend_hides_uncommitted_generation() {
+    let storage = MemoryBackend::new();
+    let names: DashMap<String, usize> = [("a".to_string(), 0)].into_iter().collect();
+    storage.write_table_names(&names).unwrap();
+
+    storage.begin_generation();
+    names.insert("b".to_string(), 1);
+    storage.write_table_names(&names).unwrap();
+    storage.write_primary_index(0, &[(1, 2)]).unwrap();
+    assert_eq!(storage.read_table_names().unwrap(), vec![("a".to_string(), 0)]);
+    assert_eq!(storage.read_primary_index(0).unwrap(), vec![]);
+
+    storage.commit_generation().unwrap();
+    let mut committed = storage.read_table_names().unwrap();
+    committed.sort();
+    assert_eq!(committed, vec![("a".to_string(), 0), ("b".to_string(), 1)]);
+    assert_eq!(storage.read_primary_index(0).unwrap(), vec![(1, 2)]);
+}
+
+#[test]
+fn memory_backend_pages() {
+    let storage = MemoryBackend::new();
+    let pid = PageId::new(4, 1);
+    assert!(!storage.page_exists(pid));
+    assert!(storage.read_page(pid).is_err());
+
+    let mut page = Page::default();
+    page.write(Some(7), 0).unwrap();
+    storage.write_page(pid, &page).unwrap();
+    assert!(storage.page_exists(pid));
+    assert_eq!(storage.read_page(pid).unwrap().read(0).unwrap(), Some(7));
+
+    storage.delete_page(pid).unwrap();
+    assert!(!storage.page_exists(pid));
+}
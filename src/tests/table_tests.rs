@@ -1,22 +1,16 @@
 use std::sync::Arc;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use parking_lot::RwLock;
-use crate::bufferpool::{BufferPool, DiskManager};
+use crate::bufferpool::BufferPool;
+use crate::config::MergeConfig;
 use crate::query::Query;
+use crate::storage::MemoryBackend;
 use crate::table::Table;
 
-static TEST_DIR_CTR: AtomicUsize = AtomicUsize::new(0);
-
-fn make_bp(prefix: &str) -> Arc<BufferPool> {
-    let id = TEST_DIR_CTR.fetch_add(1, Ordering::Relaxed);
-    let dir = format!("./test_tmp/{}_{}", prefix, id);
-    let _ = std::fs::remove_dir_all(&dir);
-    let dm = Arc::new(RwLock::new(DiskManager::new(&dir).unwrap()));
-    Arc::new(BufferPool::new(dm))
+fn make_bp() -> Arc<BufferPool> {
+    Arc::new(BufferPool::new(Arc::new(MemoryBackend::new())))
 }
 
 fn setup(num_columns: usize) -> Query {
-    let bp = make_bp("ttest");
+    let bp = make_bp();
     let table = Table::new_no_transaction(String::from("test"), num_columns, 0, 0, bp);
     Query::new(Arc::from(table))
 }
@@ -87,4 +81,52 @@ fn index_being_weird() {
 
     let rid = q.table.indices[0].locate(5).unwrap();
     assert_eq!(q.table.read_latest_single(rid, 0).unwrap(), Some(5));
-}
\ No newline at end of file
+}
+
+#[test]
+fn updates_past_the_first_64_slots() {
+    let width = 200;
+    let q = setup(width);
+    let row = |key: i64| (0..width as i64).map(|col| Some(key * 1000 + col)).collect::<Vec<_>>();
+    let key = |row: i64| row * 1000;
+    assert!(q.insert(row(1)).unwrap());
+    assert!(q.insert(row(2)).unwrap());
+    let updated = [63, 64, 130, 199];
+    for &col in &updated {
+        let mut update = vec![None; width];
+        update[col] = Some(-(col as i64));
+        q.update(key(1), update).unwrap();
+    }
+
+    let rid = q.table.indices[0].locate(key(1)).unwrap();
+    let mut expected = row(1);
+    for &col in &updated {
+        expected[col] = Some(-(col as i64));
+    }
+    assert_eq!(q.table.read_latest(rid).unwrap(), expected);
+    for col in [0, 62, 63, 64, 65, 127, 128, 130, 199] {
+        assert_eq!(q.table.read_latest_single(rid, col).unwrap(), expected[col]);
+    }
+    // Each update is a version of its own slot only.
+    assert_eq!(q.table.read_version_single(rid, 130, -1).unwrap(), Some(1130));
+    assert_eq!(q.table.read_version_single(rid, 199, -1).unwrap(), Some(1199));
+    assert_eq!(q.table.read_version_single(rid, 64, -1).unwrap(), Some(1064));
+    assert_eq!(q.table.read_version_single(rid, 64, 0).unwrap(), Some(-64));
+    assert_eq!(q.select_version(key(1), 0, &vec![1; width], -1).unwrap()[0][..], [&expected[..199], &[Some(1199)]].concat());
+
+    let other = q.table.indices[0].locate(key(2)).unwrap();
+    assert_eq!(q.table.read_latest(other).unwrap(), row(2));
+
+    q.table.merge(&MergeConfig::default()).unwrap();
+    assert_eq!(q.table.read_latest(rid).unwrap(), expected);
+    assert_eq!(q.table.read_latest(other).unwrap(), row(2));
+}
+
+#[test]
+fn collection_pages_give_back_their_width() {
+    for width in 1..=crate::schema::Schema::MAX_WIDTH {
+        assert_eq!(Table::collection_width(Table::collection_pages(width)), width);
+    }
+    assert_eq!(Table::collection_pages(64), 64 + Table::NUM_META_PAGES);
+    assert_eq!(Table::collection_pages(65), 66 + Table::NUM_META_PAGES);
+}
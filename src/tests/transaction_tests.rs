@@ -14,7 +14,7 @@ fn new_test_db(
     let temp_dir = TempDir::new().unwrap();
     let mut db = Database::new();
     db.open(temp_dir.path().to_str().unwrap()).unwrap();
-    db.create_table(table_name.into(), num_columns, key_index);
+    db.create_table(table_name.into(), num_columns, key_index).unwrap();
     let table = db.get_table(table_name).unwrap();
     (temp_dir, db, table)
 }
@@ -26,7 +26,7 @@ fn seed(table: &Arc<Table>, vals: &[i64]) {
 }
 
 fn read_row(table: &Arc<Table>, key: i64) -> Vec<Option<i64>> {
-    let proj = vec![1i64; table.num_data_columns];
+    let proj = vec![1i64; table.num_data_columns()];
     let q = Query::new(table.clone());
     let rows = q.select(key, table.key_index, &proj).unwrap();
     assert_eq!(rows.len(), 1, "expected 1 row for key {key}");
@@ -34,7 +34,7 @@ fn read_row(table: &Arc<Table>, key: i64) -> Vec<Option<i64>> {
 }
 
 fn key_exists(table: &Arc<Table>, key: i64) -> bool {
-    let proj = vec![1i64; table.num_data_columns];
+    let proj = vec![1i64; table.num_data_columns()];
     let q = Query::new(table.clone());
     q.select(key, table.key_index, &proj).unwrap().len() == 1
 }
@@ -56,7 +56,7 @@ fn select_op(table: &Arc<Table>, key: i64) -> QueryOp {
         table: table.clone(),
         key,
         search_col: table.key_index,
-        proj: vec![1i64; table.num_data_columns],
+        proj: vec![1i64; table.num_data_columns()],
     }
 }
 fn select_version_op(table: &Arc<Table>, key: i64, version: i64) -> QueryOp {
@@ -64,7 +64,7 @@ fn select_version_op(table: &Arc<Table>, key: i64, version: i64) -> QueryOp {
         table: table.clone(),
         key,
         search_col: table.key_index,
-        proj: vec![1i64; table.num_data_columns],
+        proj: vec![1i64; table.num_data_columns()],
         version,
     }
 }
@@ -0,0 +1,205 @@
+use crate::bufferpool::DiskManager;
+use crate::check::check;
+use crate::config::{DatabaseConfig, MergeConfig};
+use crate::db::Database;
+use crate::page::Page;
+use crate::query::Query;
+use crate::schema::{ColumnType, Value};
+use crate::table::Table;
+use crate::vacuum::VacuumOptions;
+use std::sync::Arc;
+use std::thread;
+use tempfile::TempDir;
+
+/// Two base collections' worth of records.
+const KEYS: i64 = 2 * Page::PAGE_SIZE as i64;
+const ROUNDS: i64 = 3;
+
+fn open(dir: &TempDir) -> Database {
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open(dir.path().to_str().unwrap()).unwrap();
+    db
+}
+
+/// Every record updated `ROUNDS` times, to `key * round`, and the odd keys
+/// deleted. Column 1 is indexed.
+fn fill(db: &Database) -> Arc<Table> {
+    db.create_table("t".into(), 2, 0).unwrap();
+    let t = db.get_table("t").unwrap();
+    t.indices[1].enable();
+    let q = Query::new(t.clone());
+    for key in 0..KEYS {
+        q.insert(vec![Some(key), Some(0)]).unwrap();
+    }
+    for round in 1..=ROUNDS {
+        for key in 0..KEYS {
+            q.update(key, vec![None, Some(key * round)]).unwrap();
+        }
+    }
+    for key in (1..KEYS).step_by(2) {
+        q.delete(key).unwrap();
+    }
+    t
+}
+
+fn version(t: &Arc<Table>, key: i64, relative: i64) -> Vec<Vec<Option<i64>>> {
+    Query::new(t.clone()).select_version(key, 0, &[1, 1], relative).unwrap()
+}
+
+fn assert_even_keys(t: &Arc<Table>, round: i64) {
+    let q = Query::new(t.clone());
+    for key in 0..KEYS {
+        let records = q.select(key, 0, &[1, 1]).unwrap();
+        if key % 2 == 0 {
+            assert_eq!(records, vec![vec![Some(key), Some(key * round)]]);
+        } else {
+            assert!(records.is_empty());
+        }
+    }
+}
+
+#[test]
+fn vacuum_leaves_out_deleted_records_and_older_versions() {
+    let dir = TempDir::new().unwrap();
+    let db = open(&dir);
+    let t = fill(&db);
+
+    let stats = db.vacuum("t", VacuumOptions { keep_versions: Some(1) }).unwrap().unwrap();
+    assert_eq!(stats.records, KEYS as u64 / 2);
+    assert_eq!(stats.deleted, KEYS as u64 / 2);
+    assert_eq!(stats.discarded_versions, (ROUNDS as u64 - 1) * KEYS as u64 / 2);
+    assert!(stats.pages_after < stats.pages_before);
+    // Nothing was reading, so the old collections went with the checkpoint.
+    assert_eq!(db.checkpoint_stats().reclaimed_pages, stats.pages_before);
+    assert_eq!(stats.bytes_reclaimed, stats.pages_before * DiskManager::PAGE_BYTES as u64);
+
+    assert_even_keys(&t, ROUNDS);
+    for key in (0..KEYS).step_by(2) {
+        let previous = vec![vec![Some(key), Some(key * (ROUNDS - 1))]];
+        assert_eq!(version(&t, key, -1), previous);
+        // Older ones were folded into the record.
+        assert_eq!(version(&t, key, -2), previous);
+    }
+    // Rebuilt: the deleted records are gone from it, the rest are found.
+    assert!(t.indices[1].locate_all(ROUNDS).is_empty());
+    assert_eq!(t.indices[1].locate_all(2 * ROUNDS), vec![t.rid_for_key(2).unwrap()]);
+
+    // The rewritten records take updates and merges as before.
+    let q = Query::new(t.clone());
+    for key in (0..KEYS).step_by(2) {
+        q.update(key, vec![None, Some(key * (ROUNDS + 1))]).unwrap();
+    }
+    t.merge(&MergeConfig::default()).unwrap();
+    assert_even_keys(&t, ROUNDS + 1);
+
+    drop(q);
+    drop(t);
+    db.close().unwrap();
+    let root = db.path.clone().unwrap();
+    drop(db);
+    assert!(check(&root, false).unwrap().is_consistent());
+    let db = open(&dir);
+    assert_even_keys(&db.get_table("t").unwrap(), ROUNDS + 1);
+}
+
+#[test]
+fn vacuum_counts_only_the_bytes_its_checkpoint_freed() {
+    let dir = TempDir::new().unwrap();
+    let db = open(&dir);
+    let t = fill(&db);
+
+    let reading = t.pin();
+    let stats = db.vacuum("t", VacuumOptions::default()).unwrap().unwrap();
+    assert!(stats.pages_after < stats.pages_before);
+    assert_eq!(stats.bytes_reclaimed, 0);
+
+    drop(reading);
+    db.checkpoint().unwrap();
+    assert_eq!(db.checkpoint_stats().reclaimed_pages, stats.pages_before);
+    assert_even_keys(&t, ROUNDS);
+}
+
+#[test]
+fn vacuum_keeps_every_version_by_default() {
+    let dir = TempDir::new().unwrap();
+    let db = open(&dir);
+    let t = fill(&db);
+    t.merge(&MergeConfig::default()).unwrap();
+
+    let stats = db.vacuum("t", VacuumOptions::default()).unwrap().unwrap();
+    assert_eq!(stats.discarded_versions, 0);
+    for key in (0..KEYS).step_by(2) {
+        for relative in 0..=ROUNDS {
+            assert_eq!(version(&t, key, -relative), vec![vec![Some(key), Some(key * (ROUNDS - relative))]]);
+        }
+    }
+    assert_eq!(t.unmerged_tails(), (ROUNDS * KEYS / 2) as usize);
+    t.merge(&MergeConfig::default()).unwrap();
+    assert_even_keys(&t, ROUNDS);
+
+    // A crash before the next checkpoint leaves the vacuumed table.
+    drop(t);
+    std::mem::forget(db);
+    let db = open(&dir);
+    let t = db.get_table("t").unwrap();
+    assert_even_keys(&t, ROUNDS);
+    assert_eq!(version(&t, 2, -1), vec![vec![Some(2), Some(2 * (ROUNDS - 1))]]);
+}
+
+#[test]
+fn queries_read_right_during_a_vacuum() {
+    let dir = TempDir::new().unwrap();
+    let db = Arc::new(open(&dir));
+    let t = fill(&db);
+
+    let readers: Vec<_> = (0..2)
+        .map(|_| {
+            let t = t.clone();
+            thread::spawn(move || {
+                for _ in 0..3 {
+                    assert_even_keys(&t, ROUNDS);
+                }
+            })
+        })
+        .collect();
+    for _ in 0..3 {
+        db.vacuum("t", VacuumOptions { keep_versions: Some(0) }).unwrap().unwrap();
+    }
+    for reader in readers {
+        reader.join().unwrap();
+    }
+    db.checkpoint().unwrap();
+    assert_even_keys(&t, ROUNDS);
+    assert!(db.vacuum("missing", VacuumOptions::default()).unwrap().is_none());
+}
+
+#[test]
+fn vacuum_copies_text_values_it_keeps() {
+    let dir = TempDir::new().unwrap();
+    let db = open(&dir);
+    db.create_typed_table("t".into(), vec![ColumnType::I64, ColumnType::Text], 0).unwrap();
+    let t = db.get_table("t").unwrap();
+    let q = Query::new(t.clone());
+    let text = |key: i64, round: i64| Some(Value::Str(format!("{} in round {} ", key, round).repeat(40)));
+    for key in 0..64 {
+        q.insert_values(vec![Some(Value::I64(key)), text(key, 0)]).unwrap();
+        q.update_values(key, vec![None, text(key, 1)]).unwrap();
+    }
+    for key in (1..64).step_by(2) {
+        q.delete(key).unwrap();
+    }
+
+    db.vacuum("t", VacuumOptions { keep_versions: Some(0) }).unwrap().unwrap();
+    let read = |t: &Arc<Table>, key: i64| Query::new(t.clone()).select_values(&Value::I64(key), 0, &[1, 1]).unwrap();
+    for key in 0..64 {
+        let expected = if key % 2 == 0 { vec![vec![Some(Value::I64(key)), text(key, 1)]] } else { vec![] };
+        assert_eq!(read(&t, key), expected);
+    }
+
+    drop(q);
+    drop(t);
+    db.close().unwrap();
+    drop(db);
+    let db = open(&dir);
+    assert_eq!(read(&db.get_table("t").unwrap(), 10), vec![vec![Some(Value::I64(10)), text(10, 1)]]);
+}
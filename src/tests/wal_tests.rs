@@ -0,0 +1,259 @@
+use crate::config::DatabaseConfig;
+use crate::db::Database;
+use crate::disk_manager::WriteFence;
+use crate::query::Query;
+use crate::schema::{ColumnDef, ColumnType, Schema};
+use crate::transaction::{QueryOp, Transaction};
+use crate::wal::{LogRecord, Wal};
+use std::io::Write;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+/// Without background work, which would carry on writing after a `crash`.
+fn open_db(dir: &TempDir) -> Database {
+    let mut db = Database::with_config(DatabaseConfig::synchronous()).unwrap();
+    db.open(dir.path().to_str().unwrap()).unwrap();
+    db
+}
+
+/// Simulates the process being killed: nothing is flushed and `close` never runs.
+fn crash(db: Database) {
+    std::mem::forget(db);
+}
+
+fn row(db: &Database, table: &str, key: i64) -> Option<Vec<Option<i64>>> {
+    let t = db.get_table(table).unwrap();
+    let proj = vec![1i64; t.num_data_columns()];
+    Query::new(t).select(key, 0, &proj).unwrap().into_iter().next()
+}
+
+#[test]
+fn records_round_trip() {
+    let dir = TempDir::new().unwrap();
+    let wal = Wal::disabled();
+    wal.open(&dir.path().join("wal.log"), Arc::new(WriteFence::new())).unwrap();
+
+    let altered = Schema::ints(3)
+        .without_column(1)
+        .with_column(ColumnDef::new("added", ColumnType::Str(12)))
+        .unwrap();
+    let records = vec![
+        LogRecord::CreateTable { table_id: 3, name: "Grades".into(), schema: Schema::ints(2), key_index: 0 },
+        LogRecord::Insert { txn: None, table_id: 3, rid: 0, cols: vec![Some(1), None] },
+        LogRecord::Update { txn: Some(7), table_id: 3, base_rid: 0, tail_rid: 1, prev: 0, cols: vec![None, Some(-5)] },
+        LogRecord::Delete { txn: Some(7), table_id: 3, base_rid: 0, tail_rid: 2, prev: 1, key: 1 },
+        LogRecord::Commit { txn: 7 },
+        LogRecord::HeapWrite { table_id: 3, addr: 600, bytes: b"spans\0words".to_vec() },
+        LogRecord::AlterTable { table_id: 3, schema: altered, fill: vec![(3, 42)] },
+        LogRecord::DropTable { table_id: 3 },
+    ];
+    for r in &records {
+        wal.append(r).unwrap();
+    }
+    assert_eq!(wal.read_all().unwrap(), records);
+
+    wal.truncate().unwrap();
+    assert!(wal.read_all().unwrap().is_empty());
+}
+
+#[test]
+fn torn_tail_is_ignored() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("wal.log");
+    let wal = Wal::disabled();
+    wal.open(&path, Arc::new(WriteFence::new())).unwrap();
+    wal.append(&LogRecord::Commit { txn: 1 }).unwrap();
+
+    // Half a frame: a length prefix promising more bytes than follow.
+    let mut f = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+    f.write_all(&[0, 0, 0, 40, 3, 0]).unwrap();
+
+    assert_eq!(wal.read_all().unwrap(), vec![LogRecord::Commit { txn: 1 }]);
+}
+
+fn log_of(dir: &TempDir, records: &[LogRecord]) -> Wal {
+    let wal = Wal::disabled();
+    wal.open(&dir.path().join("wal.log"), Arc::new(WriteFence::new())).unwrap();
+    for r in records {
+        wal.append(r).unwrap();
+    }
+    wal
+}
+
+fn inserts(n: i64) -> Vec<LogRecord> {
+    (0..n).map(|rid| LogRecord::Insert { txn: None, table_id: 0, rid, cols: vec![Some(rid), None] }).collect()
+}
+
+#[test]
+fn damaged_frame_ends_the_log() {
+    let dir = TempDir::new().unwrap();
+    let records = inserts(3);
+    let wal = log_of(&dir, &records);
+    let path = dir.path().join("wal.log");
+    let mut raw = std::fs::read(&path).unwrap();
+    let frame = (raw.len() - 8) / 3;
+    // The second record's RID, which would still parse.
+    raw[8 + frame + 8 + 24] ^= 1;
+    std::fs::write(&path, raw).unwrap();
+
+    assert_eq!(wal.read_all().unwrap(), records[..1]);
+}
+
+#[test]
+fn logs_without_checksums_are_still_read() {
+    let dir = TempDir::new().unwrap();
+    let records = inserts(3);
+    let wal = log_of(&dir, &records);
+    let path = dir.path().join("wal.log");
+    let raw = std::fs::read(&path).unwrap();
+    // The same frames as the old format had them: no marker, no checksums.
+    let mut old = Vec::new();
+    let mut offset = 8;
+    while offset < raw.len() {
+        let len = u32::from_be_bytes(raw[offset..offset + 4].try_into().unwrap()) as usize;
+        old.extend_from_slice(&raw[offset..offset + 4]);
+        old.extend_from_slice(&raw[offset + 8..offset + 8 + len]);
+        offset += 8 + len;
+    }
+    std::fs::write(&path, &old).unwrap();
+    assert_eq!(wal.read_all().unwrap(), records);
+
+    // A column count past any schema's is refused, not allocated.
+    old[4 + 25..4 + 33].copy_from_slice(&u64::MAX.to_be_bytes());
+    std::fs::write(&path, &old).unwrap();
+    assert!(wal.read_all().is_err());
+}
+
+#[test]
+fn unclosed_writes_survive_crash() {
+    let dir = TempDir::new().unwrap();
+    let db = open_db(&dir);
+    db.create_table("t".into(), 3, 0).unwrap();
+    let q = Query::new(db.get_table("t").unwrap());
+    for i in 0..5 {
+        q.insert(vec![Some(i), Some(i * 10), Some(i * 100)]).unwrap();
+    }
+    q.update(1, vec![None, Some(11), None]).unwrap();
+    q.update(1, vec![None, None, Some(111)]).unwrap();
+    q.delete(3).unwrap();
+    drop(q);
+    crash(db);
+
+    let db = open_db(&dir);
+    assert_eq!(row(&db, "t", 0), Some(vec![Some(0), Some(0), Some(0)]));
+    assert_eq!(row(&db, "t", 1), Some(vec![Some(1), Some(11), Some(111)]));
+    assert_eq!(row(&db, "t", 3), None);
+    assert_eq!(row(&db, "t", 4), Some(vec![Some(4), Some(40), Some(400)]));
+}
+
+#[test]
+fn replay_on_top_of_checkpoint() {
+    let dir = TempDir::new().unwrap();
+    let db = open_db(&dir);
+    db.create_table("t".into(), 2, 0).unwrap();
+    let q = Query::new(db.get_table("t").unwrap());
+    q.insert(vec![Some(1), Some(10)]).unwrap();
+    q.update(1, vec![None, Some(20)]).unwrap();
+    db.close().unwrap();
+
+    q.update(1, vec![None, Some(30)]).unwrap();
+    q.insert(vec![Some(2), Some(40)]).unwrap();
+    drop(q);
+    crash(db);
+
+    let db = open_db(&dir);
+    assert_eq!(row(&db, "t", 1), Some(vec![Some(1), Some(30)]));
+    assert_eq!(row(&db, "t", 2), Some(vec![Some(2), Some(40)]));
+
+    // Recovery checkpoints, so new RIDs must not collide with replayed ones.
+    let q = Query::new(db.get_table("t").unwrap());
+    assert!(q.insert(vec![Some(3), Some(50)]).unwrap());
+    assert_eq!(row(&db, "t", 2), Some(vec![Some(2), Some(40)]));
+}
+
+#[test]
+fn committed_transaction_survives_crash() {
+    let dir = TempDir::new().unwrap();
+    let db = open_db(&dir);
+    db.create_table("t".into(), 2, 0).unwrap();
+    let t = db.get_table("t").unwrap();
+    Query::new(t.clone()).insert(vec![Some(1), Some(10)]).unwrap();
+
+    assert!(Transaction::from_ops(vec![
+        QueryOp::Insert { table: t.clone(), args: vec![Some(2), Some(20)] },
+        QueryOp::Update { table: t.clone(), key: 1, cols: vec![None, Some(11)] },
+    ])
+    .run());
+    drop(t);
+    crash(db);
+
+    let db = open_db(&dir);
+    assert_eq!(row(&db, "t", 1), Some(vec![Some(1), Some(11)]));
+    assert_eq!(row(&db, "t", 2), Some(vec![Some(2), Some(20)]));
+}
+
+#[test]
+fn uncommitted_transaction_is_dropped() {
+    let dir = TempDir::new().unwrap();
+    let db = open_db(&dir);
+    db.create_table("t".into(), 2, 0).unwrap();
+    let t = db.get_table("t").unwrap();
+    Query::new(t.clone()).insert(vec![Some(1), Some(10)]).unwrap();
+
+    // Writes logged under a transaction that never reaches its commit record.
+    let in_flight = Query::with_txn(t.clone(), usize::MAX - 1);
+    in_flight.update(1, vec![None, Some(99)]).unwrap();
+    in_flight.insert(vec![Some(2), Some(20)]).unwrap();
+    Query::new(t.clone()).update(1, vec![None, Some(12)]).unwrap();
+    drop((t, in_flight));
+    crash(db);
+
+    let db = open_db(&dir);
+    assert_eq!(row(&db, "t", 1), Some(vec![Some(1), Some(12)]));
+    assert_eq!(row(&db, "t", 2), None);
+
+    // The update chain must skip the dropped tail record.
+    let t = db.get_table("t").unwrap();
+    let proj = [1i64, 1];
+    let prev = Query::new(t).select_version(1, 0, &proj, -1).unwrap();
+    assert_eq!(prev, vec![vec![Some(1), Some(10)]]);
+}
+
+#[test]
+fn commit_that_fails_to_sync_is_cut_off() {
+    let dir = TempDir::new().unwrap();
+    let fence = Arc::new(WriteFence::new());
+    let wal = Wal::disabled();
+    wal.open(&dir.path().join("wal.log"), fence.clone()).unwrap();
+    let insert = LogRecord::Insert { txn: Some(7), table_id: 0, rid: 0, cols: vec![Some(1)] };
+    wal.append(&insert).unwrap();
+
+    // The commit record is written, and then the sync fails.
+    fence.arm(1);
+    assert!(wal.commit(7).is_err());
+    assert_eq!(wal.read_all().unwrap(), vec![insert]);
+}
+
+#[test]
+fn transaction_whose_commit_fails_is_dropped() {
+    let dir = TempDir::new().unwrap();
+    let db = open_db(&dir);
+    db.create_table("t".into(), 2, 0).unwrap();
+    let t = db.get_table("t").unwrap();
+    Query::new(t.clone()).insert(vec![Some(1), Some(10)]).unwrap();
+
+    // Room for the two records and the commit record, not for the sync.
+    db.write_fence().arm(3);
+    assert!(!Transaction::from_ops(vec![
+        QueryOp::Insert { table: t.clone(), args: vec![Some(2), Some(20)] },
+        QueryOp::Update { table: t.clone(), key: 1, cols: vec![None, Some(11)] },
+    ])
+    .run());
+    drop(t);
+    crash(db);
+
+    // Told it failed, so it must not come back.
+    let db = open_db(&dir);
+    assert_eq!(row(&db, "t", 1), Some(vec![Some(1), Some(10)]));
+    assert_eq!(row(&db, "t", 2), None);
+}
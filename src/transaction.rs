@@ -19,6 +19,7 @@ pub enum QueryOp {
     Increment     { table: Arc<Table>, key: i64, col: usize },
 }
 
+#[allow(clippy::enum_variant_names)]
 enum UndoEntry {
     InsertUndo { table: Arc<Table>, key: i64 },
     UpdateUndo { table: Arc<Table>, key: i64, before: Vec<Option<i64>> },
@@ -62,6 +63,8 @@ impl Transaction {
         }
 
         let txn_id = TXN_COUNTER.next();
+        // No checkpoint may cut the log between a transaction's records.
+        let _writing = Self::table_of(&self.ops[0]).wal.begin_write();
         let lm = &Self::table_of(&self.ops[0]).lock_manager;
         let mut undo: Vec<UndoEntry> = Vec::new();
         let mut held_locks: Vec<(usize, i64)> = Vec::new();
@@ -71,16 +74,35 @@ impl Transaction {
                 Self::rollback(undo, txn_id, &held_locks, lm);
                 return false;
             }
-            if !Self::execute_op(op, &mut undo) {
+            if !Self::execute_op(op, txn_id, &mut undo) {
                 Self::rollback(undo, txn_id, &held_locks, lm);
                 return false;
             }
         }
 
+        // The transaction is durable once its commit record is on disk; locks
+        // are held until then so nobody can observe uncommitted writes.
+        if self.ops.iter().any(Self::is_write)
+            && Self::table_of(&self.ops[0]).wal.commit(txn_id).is_err()
+        {
+            Self::rollback(undo, txn_id, &held_locks, lm);
+            return false;
+        }
+
         lm.release_locks(txn_id, &held_locks);
         true
     }
 
+    fn is_write(op: &QueryOp) -> bool {
+        matches!(
+            op,
+            QueryOp::Insert { .. }
+                | QueryOp::Update { .. }
+                | QueryOp::Delete { .. }
+                | QueryOp::Increment { .. }
+        )
+    }
+
     fn acquire_locks(
         lm: &LockManager,
         op: &QueryOp,
@@ -167,10 +189,10 @@ impl Transaction {
         }
     }
 
-    fn execute_op(op: &QueryOp, undo: &mut Vec<UndoEntry>) -> bool {
+    fn execute_op(op: &QueryOp, txn_id: usize, undo: &mut Vec<UndoEntry>) -> bool {
         match op {
             QueryOp::Insert { table, args } => {
-                match Query::new(table.clone()).insert(args.clone()) {
+                match Query::with_txn(table.clone(), txn_id).insert(args.clone()) {
                     Ok(true) => {
                         if let Some(Some(key)) = args.get(table.key_index) {
                             undo.push(UndoEntry::InsertUndo { table: table.clone(), key: *key });
@@ -187,7 +209,7 @@ impl Transaction {
                 update_cols[table.key_index] = None;
 
                 let before = Self::read_before_image(table, *key);
-                match Query::new(table.clone()).update(*key, update_cols) {
+                match Query::with_txn(table.clone(), txn_id).update(*key, update_cols) {
                     Ok(true) => {
                         if let Some(b) = before {
                             undo.push(UndoEntry::UpdateUndo { table: table.clone(), key: *key, before: b });
@@ -199,7 +221,7 @@ impl Transaction {
             }
             QueryOp::Delete { table, key } => {
                 let before = Self::read_before_image(table, *key);
-                match Query::new(table.clone()).delete(*key) {
+                match Query::with_txn(table.clone(), txn_id).delete(*key) {
                     Ok(true) => {
                         if let Some(b) = before {
                             undo.push(UndoEntry::DeleteUndo { table: table.clone(), before: b });
@@ -218,29 +240,33 @@ impl Transaction {
             QueryOp::SumVersion { table, start, end, col, version } =>
                 Query::new(table.clone()).sum_version(*start, *end, *col, *version).is_ok(),
             QueryOp::Increment { table, key, col } =>
-                Query::new(table.clone()).increment(*key, *col).unwrap_or(false),
+                Query::with_txn(table.clone(), txn_id).increment(*key, *col).unwrap_or(false),
         }
     }
 
     fn read_before_image(table: &Arc<Table>, key: i64) -> Option<Vec<Option<i64>>> {
         let rid = table.indices[table.key_index].locate(key)?;
         let full = table.read_latest(rid).ok()?;
-        Some(full[..table.num_data_columns].to_vec())
+        Some(full[..table.num_data_columns()].to_vec())
     }
 
     fn rollback(undo: Vec<UndoEntry>, txn_id: usize, held_locks: &[(usize, i64)], lm: &LockManager) {
         for entry in undo.into_iter().rev() {
             match entry {
                 UndoEntry::InsertUndo { table, key } => {
-                    let _ = Query::new(table).delete(key);
+                    let _ = Query::with_txn(table, txn_id).delete(key);
                 }
                 UndoEntry::UpdateUndo { table, key, before } => {
                     let mut restore = before;
                     restore[table.key_index] = None;
-                    let _ = Query::new(table).update(key, restore);
+                    if table.copy_out_of_line(&mut restore).is_ok() {
+                        let _ = Query::with_txn(table, txn_id).update(key, restore);
+                    }
                 }
-                UndoEntry::DeleteUndo { table, before } => {
-                    let _ = Query::new(table).insert(before);
+                UndoEntry::DeleteUndo { table, mut before } => {
+                    if table.copy_out_of_line(&mut before).is_ok() {
+                        let _ = Query::with_txn(table, txn_id).insert(before);
+                    }
                 }
             }
         }
@@ -0,0 +1,26 @@
+/// How `Database::vacuum` rewrites a table.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VacuumOptions {
+    /// Older versions of each record to keep readable by `select_version`,
+    /// newest first; the rest are folded into the record. `None` keeps them
+    /// all.
+    pub keep_versions: Option<usize>,
+}
+
+/// What a vacuum rewrote and left out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VacuumStats {
+    /// Records rewritten into the new collections.
+    pub records: u64,
+    /// Deleted records left out.
+    pub deleted: u64,
+    /// Older versions of the records rewritten that were left out.
+    pub discarded_versions: u64,
+    /// Pages of the table's base and tail collections before and after.
+    pub pages_before: u64,
+    pub pages_after: u64,
+    /// The table's pages the checkpoint after the rewrite deleted, in bytes
+    /// on disk. Those a query that started before the vacuum still held are
+    /// left to a later checkpoint, and not counted.
+    pub bytes_reclaimed: u64,
+}
@@ -0,0 +1,488 @@
+use crate::bufferpool::DiskError;
+use crate::checksum::Crc32c;
+use crate::disk_manager::{read_i64, read_u64, WriteFence};
+use crate::schema::Schema;
+use dashmap::DashSet;
+use parking_lot::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+/// One entry in the write-ahead log.
+///
+/// Data records carry the RIDs that were handed out when the operation ran, so
+/// replaying them rebuilds exactly the page directory the crashed process had.
+/// `txn` is `None` for auto-committed `Query` calls.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogRecord {
+    CreateTable {
+        table_id: usize,
+        name: String,
+        schema: Schema,
+        key_index: usize,
+    },
+    DropTable {
+        table_id: usize,
+    },
+    Insert {
+        txn: Option<usize>,
+        table_id: usize,
+        rid: i64,
+        cols: Vec<Option<i64>>,
+    },
+    Update {
+        txn: Option<usize>,
+        table_id: usize,
+        base_rid: i64,
+        tail_rid: i64,
+        prev: i64,
+        cols: Vec<Option<i64>>,
+    },
+    Delete {
+        txn: Option<usize>,
+        table_id: usize,
+        base_rid: i64,
+        tail_rid: i64,
+        prev: i64,
+        key: i64,
+    },
+    Commit {
+        txn: usize,
+    },
+    /// A text or blob value put at word `addr` of the table's heap. Logged
+    /// before the record that refers to it, and replayed whatever became of
+    /// that record.
+    HeapWrite {
+        table_id: usize,
+        addr: usize,
+        bytes: Vec<u8>,
+    },
+    /// A column added to or dropped from the table, leaving it `schema`.
+    /// `fill` has the slots of an added column and what the records already
+    /// there read in them.
+    AlterTable {
+        table_id: usize,
+        schema: Schema,
+        fill: Vec<(usize, i64)>,
+    },
+    /// Written by `Database::close` once everything is on disk. A log that
+    /// consists of anything other than this one record means the last process
+    /// did not shut down cleanly.
+    Shutdown,
+}
+
+impl LogRecord {
+    const CREATE_TABLE: u8 = 1;
+    const DROP_TABLE: u8 = 2;
+    const INSERT: u8 = 3;
+    const UPDATE: u8 = 4;
+    const DELETE: u8 = 5;
+    const COMMIT: u8 = 6;
+    const SHUTDOWN: u8 = 7;
+    /// `CREATE_TABLE` with column types. The old tag is still read, as a
+    /// table of `Schema::ints`.
+    const CREATE_TYPED_TABLE: u8 = 8;
+    const HEAP_WRITE: u8 = 9;
+    /// `CREATE_TYPED_TABLE` with column names, nullability and defaults.
+    /// The older tags are still read, with columns named by their number.
+    const CREATE_NAMED_TABLE: u8 = 10;
+    const ALTER_TABLE: u8 = 11;
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        match self {
+            LogRecord::CreateTable { table_id, name, schema, key_index } => {
+                buf.push(Self::CREATE_NAMED_TABLE);
+                buf.extend_from_slice(&(*table_id as u64).to_be_bytes());
+                buf.extend_from_slice(&(name.len() as u64).to_be_bytes());
+                buf.extend_from_slice(name.as_bytes());
+                schema.serialize(&mut buf);
+                buf.extend_from_slice(&(*key_index as u64).to_be_bytes());
+            }
+            LogRecord::DropTable { table_id } => {
+                buf.push(Self::DROP_TABLE);
+                buf.extend_from_slice(&(*table_id as u64).to_be_bytes());
+            }
+            LogRecord::Insert { txn, table_id, rid, cols } => {
+                buf.push(Self::INSERT);
+                push_txn(&mut buf, *txn);
+                buf.extend_from_slice(&(*table_id as u64).to_be_bytes());
+                buf.extend_from_slice(&rid.to_be_bytes());
+                push_cols(&mut buf, cols);
+            }
+            LogRecord::Update { txn, table_id, base_rid, tail_rid, prev, cols } => {
+                buf.push(Self::UPDATE);
+                push_txn(&mut buf, *txn);
+                buf.extend_from_slice(&(*table_id as u64).to_be_bytes());
+                buf.extend_from_slice(&base_rid.to_be_bytes());
+                buf.extend_from_slice(&tail_rid.to_be_bytes());
+                buf.extend_from_slice(&prev.to_be_bytes());
+                push_cols(&mut buf, cols);
+            }
+            LogRecord::Delete { txn, table_id, base_rid, tail_rid, prev, key } => {
+                buf.push(Self::DELETE);
+                push_txn(&mut buf, *txn);
+                buf.extend_from_slice(&(*table_id as u64).to_be_bytes());
+                buf.extend_from_slice(&base_rid.to_be_bytes());
+                buf.extend_from_slice(&tail_rid.to_be_bytes());
+                buf.extend_from_slice(&prev.to_be_bytes());
+                buf.extend_from_slice(&key.to_be_bytes());
+            }
+            LogRecord::Commit { txn } => {
+                buf.push(Self::COMMIT);
+                buf.extend_from_slice(&(*txn as u64).to_be_bytes());
+            }
+            LogRecord::HeapWrite { table_id, addr, bytes } => {
+                buf.push(Self::HEAP_WRITE);
+                buf.extend_from_slice(&(*table_id as u64).to_be_bytes());
+                buf.extend_from_slice(&(*addr as u64).to_be_bytes());
+                buf.extend_from_slice(&(bytes.len() as u64).to_be_bytes());
+                buf.extend_from_slice(bytes);
+            }
+            LogRecord::AlterTable { table_id, schema, fill } => {
+                buf.push(Self::ALTER_TABLE);
+                buf.extend_from_slice(&(*table_id as u64).to_be_bytes());
+                schema.serialize(&mut buf);
+                schema.serialize_slots(&mut buf);
+                buf.extend_from_slice(&(fill.len() as u64).to_be_bytes());
+                for (slot, value) in fill {
+                    buf.extend_from_slice(&(*slot as u64).to_be_bytes());
+                    buf.extend_from_slice(&value.to_be_bytes());
+                }
+            }
+            LogRecord::Shutdown => buf.push(Self::SHUTDOWN),
+        }
+        buf
+    }
+
+    fn deserialize(data: &[u8]) -> Result<LogRecord, DiskError> {
+        let tag = *data
+            .first()
+            .ok_or_else(|| DiskError::CorruptedPage("Empty log record".into()))?;
+        let mut offset = 1;
+        let record = match tag {
+            Self::CREATE_TABLE | Self::CREATE_TYPED_TABLE | Self::CREATE_NAMED_TABLE => {
+                let table_id = read_u64(data, &mut offset)? as usize;
+                let name_len = read_u64(data, &mut offset)? as usize;
+                if offset + name_len > data.len() {
+                    return Err(DiskError::CorruptedPage("Unexpected end of table name".into()));
+                }
+                let name = String::from_utf8(data[offset..offset + name_len].to_vec())
+                    .map_err(|_| DiskError::CorruptedPage("Invalid table name".into()))?;
+                offset += name_len;
+                let schema = match tag {
+                    Self::CREATE_TABLE => Schema::ints(read_u64(data, &mut offset)? as usize),
+                    _ => Schema::deserialize(data, &mut offset, tag == Self::CREATE_NAMED_TABLE)?,
+                };
+                LogRecord::CreateTable {
+                    table_id,
+                    name,
+                    schema,
+                    key_index: read_u64(data, &mut offset)? as usize,
+                }
+            }
+            Self::DROP_TABLE => LogRecord::DropTable {
+                table_id: read_u64(data, &mut offset)? as usize,
+            },
+            Self::INSERT => LogRecord::Insert {
+                txn: read_txn(data, &mut offset)?,
+                table_id: read_u64(data, &mut offset)? as usize,
+                rid: read_i64(data, &mut offset)?,
+                cols: read_cols(data, &mut offset)?,
+            },
+            Self::UPDATE => LogRecord::Update {
+                txn: read_txn(data, &mut offset)?,
+                table_id: read_u64(data, &mut offset)? as usize,
+                base_rid: read_i64(data, &mut offset)?,
+                tail_rid: read_i64(data, &mut offset)?,
+                prev: read_i64(data, &mut offset)?,
+                cols: read_cols(data, &mut offset)?,
+            },
+            Self::DELETE => LogRecord::Delete {
+                txn: read_txn(data, &mut offset)?,
+                table_id: read_u64(data, &mut offset)? as usize,
+                base_rid: read_i64(data, &mut offset)?,
+                tail_rid: read_i64(data, &mut offset)?,
+                prev: read_i64(data, &mut offset)?,
+                key: read_i64(data, &mut offset)?,
+            },
+            Self::COMMIT => LogRecord::Commit {
+                txn: read_u64(data, &mut offset)? as usize,
+            },
+            Self::HEAP_WRITE => {
+                let table_id = read_u64(data, &mut offset)? as usize;
+                let addr = read_u64(data, &mut offset)? as usize;
+                let len = read_u64(data, &mut offset)? as usize;
+                if offset + len > data.len() {
+                    return Err(DiskError::CorruptedPage("Unexpected end of heap value".into()));
+                }
+                LogRecord::HeapWrite {
+                    table_id,
+                    addr,
+                    bytes: data[offset..offset + len].to_vec(),
+                }
+            }
+            Self::ALTER_TABLE => {
+                let table_id = read_u64(data, &mut offset)? as usize;
+                let mut schema = Schema::deserialize(data, &mut offset, true)?;
+                schema.deserialize_slots(data, &mut offset)?;
+                let count = read_u64(data, &mut offset)? as usize;
+                let mut fill = Vec::with_capacity(count.min(Schema::MAX_WIDTH));
+                for _ in 0..count {
+                    let slot = read_u64(data, &mut offset)? as usize;
+                    fill.push((slot, read_i64(data, &mut offset)?));
+                }
+                LogRecord::AlterTable { table_id, schema, fill }
+            }
+            Self::SHUTDOWN => LogRecord::Shutdown,
+            other => {
+                return Err(DiskError::CorruptedPage(format!("Invalid log record tag: {}", other)));
+            }
+        };
+        Ok(record)
+    }
+}
+
+/// Append-only redo log that lives next to the page files.
+///
+/// Every append is handed to the OS before it returns, so a killed process
+/// never loses an acknowledged record. `sync` additionally forces the log to
+/// stable storage and is what makes a transaction commit durable.
+pub struct Wal {
+    log: Mutex<Option<LogFile>>,
+    /// Held shared by every write from its log record to its last page, and
+    /// by a transaction until it commits or rolls back. A checkpoint holds it
+    /// exclusively, so the log it cuts has nothing in flight.
+    writers: RwLock<()>,
+}
+
+struct LogFile {
+    file: File,
+    fence: Arc<WriteFence>,
+    /// Nothing is in the file yet, not even `LOG_MAGIC`.
+    empty: bool,
+}
+
+/// Starts a log whose frames are a big-endian `u32` length, a CRC-32C of
+/// that length and the record, and the record. The logs from before have
+/// neither the marker nor the checksums, and are only ever read.
+const LOG_MAGIC: &[u8; 8] = b"LSTORLOG";
+
+impl LogFile {
+    fn append(&mut self, record: &LogRecord) -> Result<(), DiskError> {
+        self.fence.check()?;
+        let payload = record.serialize();
+        let len = (payload.len() as u32).to_be_bytes();
+        let mut frame = Vec::with_capacity(LOG_MAGIC.len() + payload.len() + 8);
+        if self.empty {
+            frame.extend_from_slice(LOG_MAGIC);
+        }
+        frame.extend_from_slice(&len);
+        frame.extend_from_slice(&Crc32c::new().update(&len).update(&payload).finish().to_be_bytes());
+        frame.extend_from_slice(&payload);
+        self.file.write_all(&frame)?;
+        self.empty = false;
+        Ok(())
+    }
+
+    fn sync(&self) -> Result<(), DiskError> {
+        self.fence.check()?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+}
+
+impl Wal {
+    /// A log that is not backed by a file; appends are no-ops.
+    pub fn disabled() -> Self {
+        Self {
+            log: Mutex::new(None),
+            writers: RwLock::new(()),
+        }
+    }
+
+    /// Opens the log at `path`. Writes to it count against `fence`, the same
+    /// budget the disk manager's page and metadata writes use.
+    pub fn open(&self, path: &Path, fence: Arc<WriteFence>) -> Result<(), DiskError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let empty = file.metadata()?.len() == 0;
+        *self.log.lock() = Some(LogFile { file, fence, empty });
+        Ok(())
+    }
+
+    /// Keeps checkpoints out until the guard is dropped.
+    pub fn begin_write(&self) -> RwLockReadGuard<'_, ()> {
+        self.writers.read()
+    }
+
+    /// `begin_write` for a thread that may hold it already, such as a query
+    /// run by a transaction.
+    pub fn begin_nested_write(&self) -> RwLockReadGuard<'_, ()> {
+        self.writers.read_recursive()
+    }
+
+    /// Waits for the writes under way to finish and keeps new ones out until
+    /// the guard is dropped.
+    pub fn quiesce(&self) -> RwLockWriteGuard<'_, ()> {
+        self.writers.write()
+    }
+
+    pub fn append(&self, record: &LogRecord) -> Result<(), DiskError> {
+        match self.log.lock().as_mut() {
+            Some(log) => log.append(record),
+            None => Ok(()),
+        }
+    }
+
+    pub fn sync(&self) -> Result<(), DiskError> {
+        match self.log.lock().as_ref() {
+            Some(log) => log.sync(),
+            None => Ok(()),
+        }
+    }
+
+    /// Appends a commit record for `txn` and forces the log to disk. If that
+    /// fails, the record is cut off again: the transaction is rolled back,
+    /// and recovery must not take it for a winner. Should that fail too, the
+    /// log takes no more writes, and the database has to be reopened.
+    pub fn commit(&self, txn: usize) -> Result<(), DiskError> {
+        let mut guard = self.log.lock();
+        let Some(log) = guard.as_mut() else {
+            return Ok(());
+        };
+        let start = log.file.metadata()?.len();
+        let result = log.append(&LogRecord::Commit { txn }).and_then(|()| log.sync());
+        if result.is_err() {
+            match log.file.set_len(start).and_then(|()| log.file.sync_data()) {
+                Ok(()) => log.empty = start == 0,
+                Err(_) => log.fence.arm(0),
+            }
+        }
+        result
+    }
+
+    /// Reads every complete record in the log. A torn record at the tail (the
+    /// process died mid-append), or one that fails its checksum, marks the end
+    /// of the log.
+    pub fn read_all(&self) -> Result<Vec<LogRecord>, DiskError> {
+        let mut guard = self.log.lock();
+        let Some(log) = guard.as_mut() else {
+            return Ok(vec![]);
+        };
+        let mut data = Vec::new();
+        log.file.seek(SeekFrom::Start(0))?;
+        log.file.read_to_end(&mut data)?;
+
+        let checksummed = data.starts_with(LOG_MAGIC);
+        let header = if checksummed { 8 } else { 4 };
+        let mut records = Vec::new();
+        let mut offset = if checksummed { LOG_MAGIC.len() } else { 0 };
+        while offset + header <= data.len() {
+            let len_bytes = &data[offset..offset + 4];
+            let len = u32::from_be_bytes(len_bytes.try_into().unwrap()) as usize;
+            let start = offset + header;
+            if len > data.len() - start {
+                break;
+            }
+            let payload = &data[start..start + len];
+            if checksummed {
+                let expected = u32::from_be_bytes(data[offset + 4..offset + 8].try_into().unwrap());
+                if Crc32c::new().update(len_bytes).update(payload).finish() != expected {
+                    break;
+                }
+            }
+            records.push(LogRecord::deserialize(payload)?);
+            offset = start + len;
+        }
+        Ok(records)
+    }
+
+    /// Discards every record. Called once a checkpoint has made them redundant.
+    pub fn truncate(&self) -> Result<(), DiskError> {
+        if let Some(log) = self.log.lock().as_mut() {
+            log.fence.check()?;
+            log.file.set_len(0)?;
+            log.file.sync_all()?;
+            log.empty = true;
+        }
+        Ok(())
+    }
+
+    /// Marks the log as belonging to a cleanly closed database.
+    pub fn shutdown(&self) -> Result<(), DiskError> {
+        self.append(&LogRecord::Shutdown)?;
+        self.sync()
+    }
+}
+
+fn push_txn(buf: &mut Vec<u8>, txn: Option<usize>) {
+    buf.extend_from_slice(&txn.map_or(u64::MAX, |t| t as u64).to_be_bytes());
+}
+
+fn read_txn(data: &[u8], offset: &mut usize) -> Result<Option<usize>, DiskError> {
+    let raw = read_u64(data, offset)?;
+    Ok((raw != u64::MAX).then_some(raw as usize))
+}
+
+fn push_cols(buf: &mut Vec<u8>, cols: &[Option<i64>]) {
+    buf.extend_from_slice(&(cols.len() as u64).to_be_bytes());
+    for col in cols {
+        match col {
+            Some(val) => {
+                buf.push(1);
+                buf.extend_from_slice(&val.to_be_bytes());
+            }
+            None => buf.push(0),
+        }
+    }
+}
+
+fn read_cols(data: &[u8], offset: &mut usize) -> Result<Vec<Option<i64>>, DiskError> {
+    let count = read_u64(data, offset)? as usize;
+    let mut cols = Vec::with_capacity(count.min(Schema::MAX_WIDTH));
+    for _ in 0..count {
+        let tag = *data
+            .get(*offset)
+            .ok_or_else(|| DiskError::CorruptedPage("Unexpected end of log record".into()))?;
+        *offset += 1;
+        cols.push(match tag {
+            1 => Some(read_i64(data, offset)?),
+            0 => None,
+            other => return Err(DiskError::CorruptedPage(format!("Invalid tag: {}", other))),
+        });
+    }
+    Ok(cols)
+}
+
+/// What the analysis pass learns from the log before anything is replayed.
+pub struct LogAnalysis {
+    /// The log holds nothing but the marker a clean `close` leaves behind.
+    pub clean_shutdown: bool,
+    committed: DashSet<usize>,
+}
+
+impl LogAnalysis {
+    pub fn new(records: &[LogRecord]) -> Self {
+        Self {
+            clean_shutdown: matches!(records, [LogRecord::Shutdown]),
+            committed: records
+                .iter()
+                .filter_map(|r| match r {
+                    LogRecord::Commit { txn } => Some(*txn),
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Whether a record written by `txn` must survive recovery. Auto-committed
+    /// records always do, transactional ones only if the commit record made it.
+    pub fn is_winner(&self, txn: Option<usize>) -> bool {
+        txn.is_none_or(|t| self.committed.contains(&t))
+    }
+}